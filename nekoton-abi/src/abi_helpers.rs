@@ -190,6 +190,9 @@ pub mod array_int256_number {
     }
 }
 
+/// Account id of the address.
+///
+/// Workchain is ignored on unpack and is always `0` on pack
 pub mod address_only_hash {
     use super::*;
 
@@ -52,19 +52,20 @@
     clippy::dbg_macro
 )]
 use std::collections::HashMap;
+use std::hash::Hash;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use anyhow::Result;
 use num_traits::ToPrimitive;
 use smallvec::smallvec;
-use ton_abi::{Function, Param, Token, TokenValue};
+use ton_abi::{Function, Param, ParamType, Token, TokenValue};
 use ton_block::{
     Account, AccountStuff, Deserializable, GetRepresentationHash, MsgAddrStd, MsgAddressInt,
     Serializable,
 };
 use ton_executor::{BlockchainConfig, OrdinaryTransactionExecutor, TransactionExecutor};
-use ton_types::{SliceData, UInt256};
+use ton_types::{IBitstring, SliceData, UInt256};
 
 #[cfg(feature = "derive")]
 pub use {
@@ -86,7 +87,7 @@ pub use self::models::*;
 pub use self::token_packer::*;
 pub use self::token_unpacker::*;
 pub use self::tokens_json::*;
-pub use self::tvm::BriefBlockchainConfig;
+pub use self::tvm::{BriefBlockchainConfig, VmStep, VmTrace, MAX_TRACED_STACK_ITEMS};
 pub use transaction_parser::TransactionParser;
 
 mod abi_helpers;
@@ -203,20 +204,27 @@ pub fn create_comment_payload(comment: &str) -> Result<SliceData> {
     .and_then(SliceData::load_builder)
 }
 
+/// Parses comment, encoded either in a separate chain of cells (see [`create_comment_payload`])
+/// or inline right after the prefix, with the rest in the chain of references
 pub fn parse_comment_payload(mut payload: SliceData) -> Option<String> {
     if payload.get_next_u32().ok()? != 0 {
         return None;
     }
 
-    let mut cell = payload.checked_drain_reference().ok()?;
+    let inline_bits = payload.remaining_bits();
+    if inline_bits % 8 != 0 {
+        return None;
+    }
+    let mut data = payload.get_next_bytes(inline_bits / 8).ok()?;
+
+    let mut next = payload.checked_drain_reference().ok();
+    if data.is_empty() && next.is_none() {
+        return None;
+    }
 
-    let mut data = Vec::new();
-    loop {
+    while let Some(cell) = next {
         data.extend_from_slice(cell.data());
-        cell = match cell.reference(0) {
-            Ok(cell) => cell.clone(),
-            Err(_) => break,
-        };
+        next = cell.reference(0).ok();
     }
 
     String::from_utf8(data).ok()
@@ -265,6 +273,76 @@ pub fn unpack_from_cell(
     }
 }
 
+/// Decodes raw `HashmapE` root (e.g. from the contract data) as an ABI `map(K,V)`
+pub fn unpack_map<K, V>(
+    root: Option<ton_types::Cell>,
+    abi_version: ton_abi::contract::AbiVersion,
+) -> Result<HashMap<K, V>>
+where
+    K: KnownParamType + Eq + Hash,
+    V: KnownParamType,
+    TokenValue: UnpackAbi<K> + UnpackAbi<V>,
+{
+    let mut map = ton_types::BuilderData::new();
+    match root {
+        Some(root) => {
+            map.append_bit_one()?.checked_append_reference(root)?;
+        }
+        None => {
+            map.append_bit_zero()?;
+        }
+    }
+
+    let param_type = ParamType::Map(Box::new(K::param_type()), Box::new(V::param_type()));
+    let (value, _) = TokenValue::read_from(
+        &param_type,
+        SliceData::load_builder(map)?,
+        true,
+        &abi_version,
+        false,
+    )?;
+
+    unpack_map_token(value)
+}
+
+/// Decodes ABI `map(K,V)` token (e.g. from the getter output).
+///
+/// Unlike the plain [`UnpackAbi`], key and value types of the map must match `K` and `V`
+pub fn unpack_map_token<K, V>(value: TokenValue) -> Result<HashMap<K, V>>
+where
+    K: KnownParamType + Eq + Hash,
+    V: KnownParamType,
+    TokenValue: UnpackAbi<K> + UnpackAbi<V>,
+{
+    match &value {
+        TokenValue::Map(key_type, value_type, _)
+            if key_type == &K::param_type() && value_type == &V::param_type() =>
+        {
+            Ok(value.unpack()?)
+        }
+        _ => Err(UnpackerError::InvalidAbi.into()),
+    }
+}
+
+/// Encodes ABI `map(K,V)` as a raw `HashmapE` root
+pub fn pack_map<K, V>(
+    map: HashMap<K, V>,
+    abi_version: ton_abi::contract::AbiVersion,
+) -> Result<Option<ton_types::Cell>>
+where
+    K: KnownParamType + BuildMapKeyTokenValue,
+    V: KnownParamType + BuildTokenValue,
+{
+    let cell = pack_into_cell(&[Token::new("map", map.token_value())], abi_version)?;
+
+    let mut map = SliceData::load_cell(cell)?;
+    Ok(if map.get_next_bit()? {
+        Some(map.checked_drain_reference()?)
+    } else {
+        None
+    })
+}
+
 pub fn extract_public_key(
     account: &AccountStuff,
 ) -> Result<ed25519_dalek::PublicKey, ExtractionError> {
@@ -582,6 +660,31 @@ impl<'a> ExecutionContext<'a> {
     ) -> Result<ExecutionOutput> {
         function.run_local_responsible(self.clock, self.account_stuff.clone(), input)
     }
+
+    /// Executes the message in the VM and collects at most `max_steps` steps.
+    ///
+    /// Can be used to find out why the prepared message is aborted
+    pub fn trace_message(
+        &self,
+        message: &ton_block::Message,
+        config: &BriefBlockchainConfig,
+        max_steps: usize,
+    ) -> Result<VmTrace> {
+        let mut account_stuff = self.account_stuff.clone();
+        let BlockStats {
+            gen_utime, gen_lt, ..
+        } = get_block_stats(self.clock, None, account_stuff.storage.last_trans_lt);
+
+        tvm::call_msg_traced(
+            gen_utime,
+            gen_lt,
+            &mut account_stuff,
+            message,
+            config,
+            max_steps,
+        )
+        .map_err(From::from)
+    }
 }
 
 pub trait FunctionExt {
@@ -878,6 +981,7 @@ enum AbiError {
     AnswerIdNotFound,
 }
 
+#[derive(Clone)]
 pub struct Executor {
     config: BlockchainConfig,
     account: Account,
@@ -940,6 +1044,27 @@ impl Executor {
         self.last_transaction_lt.load(Ordering::Acquire)
     }
 
+    /// Executes the message in the VM without mutating the account state
+    /// and collects at most `max_steps` steps.
+    ///
+    /// NOTE: only the compute phase is executed
+    pub fn trace(&self, message: &ton_block::Message, max_steps: usize) -> Result<VmTrace> {
+        let mut account_stuff = match &self.account {
+            Account::Account(account_stuff) => account_stuff.clone(),
+            Account::AccountNone => return Err(ExtractionError::AccountIsNotActive.into()),
+        };
+
+        tvm::call_msg_traced(
+            self.block_utime,
+            self.block_lt,
+            &mut account_stuff,
+            message,
+            &BriefBlockchainConfig::from(&self.config),
+            max_steps,
+        )
+        .map_err(From::from)
+    }
+
     /// Consumes account and executes message without mutating the account state.
     ///
     /// NOTE: produces transaction without state update
@@ -1093,6 +1218,26 @@ mod tests {
         assert_eq!(boc.into_cell(), target_boc);
     }
 
+    #[test]
+    fn inline_text_payload() {
+        let mut tail = ton_types::BuilderData::new();
+        tail.append_raw(b" world", 6 * 8).unwrap();
+
+        let mut builder = ton_types::BuilderData::new();
+        builder.append_u32(0).unwrap();
+        builder.append_raw(b"hello", 5 * 8).unwrap();
+        builder
+            .checked_append_reference(tail.into_cell().unwrap())
+            .unwrap();
+
+        let payload = SliceData::load_builder(builder).unwrap();
+        assert_eq!(parse_comment_payload(payload).unwrap(), "hello world");
+
+        let mut empty = ton_types::BuilderData::new();
+        empty.append_u32(0).unwrap();
+        assert!(parse_comment_payload(SliceData::load_builder(empty).unwrap()).is_none());
+    }
+
     #[test]
     fn test_run_local() {
         let contract = r#####"{
@@ -1216,6 +1361,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_map_round_trip() {
+        let custodians = (0..5u8)
+            .map(|i| (UInt256::from([i; 32]), i))
+            .collect::<HashMap<_, _>>();
+
+        let root = pack_map(custodians.clone(), DEFAULT_ABI_VERSION).unwrap();
+        assert!(root.is_some());
+
+        let decoded: HashMap<UInt256, u8> = unpack_map(root, DEFAULT_ABI_VERSION).unwrap();
+        assert_eq!(decoded, custodians);
+
+        let empty: HashMap<UInt256, u8> = unpack_map(None, DEFAULT_ABI_VERSION).unwrap();
+        assert!(empty.is_empty());
+    }
+
     #[test]
     fn unpack_header() {
         let body = ton_types::deserialize_tree_of_cells(&mut base64::decode("te6ccgEBAwEArAAB4by5SH0Glx7Jnb0imtClvhC4I0DPaT+/su49hM5DQH+xHrEtD9U2dQOJpD2J598bWtYTC4m1Ylxh6MSg9//WKgdEWH2fKWA3SuZNZZ7BBCeDpiGAfwIlOFF981WU06BclcAAAF7d/kbVGEk26dM7mRsgAQFlgBOzHFkFNmE1fX9Dpui0xVFiNtBGdDa6IIntwTxwGs9y4AAAAAAAAAAAAAAAB3NZQAA4AgAA").unwrap().as_slice()).unwrap();
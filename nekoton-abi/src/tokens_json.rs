@@ -134,6 +134,9 @@ pub fn parse_abi_token_value(
 
             match param {
                 ton_abi::ParamType::Uint(_) => {
+                    if number.bits() > size as u64 {
+                        return Err(TokensJsonError::IntegerOverflow);
+                    }
                     ton_abi::TokenValue::Uint(ton_abi::Uint { number, size })
                 }
                 _ => ton_abi::TokenValue::VarUint(size, number),
@@ -162,6 +165,15 @@ pub fn parse_abi_token_value(
 
             match param {
                 ton_abi::ParamType::Int(_) => {
+                    // NOTE: one bit is reserved for the sign
+                    let max_bits = (size as u64).saturating_sub(1);
+                    let fits = match number.sign() {
+                        num_bigint::Sign::Minus => (-&number - 1u8).bits() <= max_bits,
+                        _ => number.bits() <= max_bits,
+                    };
+                    if !fits {
+                        return Err(TokensJsonError::IntegerOverflow);
+                    }
                     ton_abi::TokenValue::Int(ton_abi::Int { number, size })
                 }
                 _ => ton_abi::TokenValue::VarInt(size, number),
@@ -273,8 +285,8 @@ pub fn parse_abi_token_value(
         ton_abi::ParamType::Address => {
             let value = if let Some(value) = value.as_str() {
                 let value = value.trim();
-                ton_block::MsgAddressInt::from_str(value)
-                    .map_err(|_| TokensJsonError::InvalidAddress)
+                // NOTE: packed (user-friendly) addresses are also accepted
+                nekoton_utils::repack_address(value).map_err(|_| TokensJsonError::InvalidAddress)
             } else {
                 Err(TokensJsonError::StringExpected)
             }?;
@@ -460,3 +472,46 @@ pub enum TokensJsonError {
     #[error("Integer overflow")]
     IntegerOverflow,
 }
+
+#[cfg(test)]
+mod tests {
+    use ton_abi::{Param, ParamType};
+
+    use super::*;
+
+    #[test]
+    fn tokens_roundtrip() {
+        let params = [
+            Param::new("amount", ParamType::Uint(128)),
+            Param::new("delta", ParamType::Int(8)),
+            Param::new("payload", ParamType::Cell),
+            Param::new("comment", ParamType::Optional(Box::new(ParamType::String))),
+            Param::new(
+                "values",
+                ParamType::Map(Box::new(ParamType::Uint(32)), Box::new(ParamType::Bool)),
+            ),
+        ];
+
+        let json = serde_json::json!({
+            "amount": "1000000000",
+            "delta": -128,
+            "payload": "te6ccgEBAQEAAgAAAA==",
+            "comment": null,
+            "values": [["1", true], ["2", false]],
+        });
+
+        let tokens = parse_abi_tokens(&params, json.clone()).unwrap();
+        assert_eq!(make_abi_tokens(&tokens).unwrap()["amount"], json["amount"]);
+        assert_eq!(make_abi_tokens(&tokens).unwrap()["values"], json["values"]);
+
+        assert!(matches!(
+            parse_abi_token_value(&ParamType::Uint(8), serde_json::json!(256)),
+            Err(TokensJsonError::IntegerOverflow)
+        ));
+        assert!(matches!(
+            parse_abi_token_value(&ParamType::Int(8), serde_json::json!(-129)),
+            Err(TokensJsonError::IntegerOverflow)
+        ));
+        assert!(parse_abi_token_value(&ParamType::Int(8), serde_json::json!(127)).is_ok());
+    }
+}
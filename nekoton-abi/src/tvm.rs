@@ -1,5 +1,8 @@
+use std::sync::{Arc, Mutex};
+
 use anyhow::Result;
 use nekoton_utils::TrustMe;
+use serde::Serialize;
 use ton_block::{
     AccountStuff, CommonMsgInfo, CurrencyCollection, Deserializable, Message, MsgAddressInt,
     OutAction, OutActions, Serializable,
@@ -9,6 +12,9 @@ use ton_vm::executor::gas::gas_state::Gas;
 use ton_vm::stack::integer::IntegerData;
 use ton_vm::stack::{savelist::SaveList, Stack, StackItem};
 
+/// Max number of the top stack items which are stored in each traced step
+pub const MAX_TRACED_STACK_ITEMS: usize = 16;
+
 #[derive(Debug, Copy, Clone)]
 pub struct BriefBlockchainConfig {
     pub global_id: i32,
@@ -46,6 +52,17 @@ pub fn call(
     account: &mut AccountStuff,
     stack: Stack,
     config: &BriefBlockchainConfig,
+) -> Result<(ton_vm::executor::Engine, i32, bool), ExecutionError> {
+    call_ext(utime, lt, account, stack, config, None)
+}
+
+fn call_ext(
+    utime: u32,
+    lt: u64,
+    account: &mut AccountStuff,
+    stack: Stack,
+    config: &BriefBlockchainConfig,
+    trace: Option<Arc<Mutex<VmTrace>>>,
 ) -> Result<(ton_vm::executor::Engine, i32, bool), ExecutionError> {
     let state = match &mut account.storage.state {
         ton_block::AccountState::AccountActive { state_init, .. } => Ok(state_init),
@@ -86,6 +103,30 @@ pub fn call(
     );
     engine.set_signature_id(config.global_id);
 
+    if let Some(trace) = trace {
+        engine.set_trace(ton_vm::executor::Engine::TRACE_ALL);
+        engine.set_trace_callback(move |_, info| {
+            let mut trace = match trace.lock() {
+                Ok(trace) => trace,
+                Err(_) => return,
+            };
+            if trace.steps.len() >= trace.max_steps {
+                trace.truncated = true;
+                return;
+            }
+
+            let stack = &info.stack.storage;
+            let skip = stack.len().saturating_sub(MAX_TRACED_STACK_ITEMS);
+            trace.steps.push(VmStep {
+                step: info.step,
+                instruction: info.cmd_str.clone(),
+                gas_used: info.gas_used,
+                gas_cmd: info.gas_cmd,
+                stack: stack.iter().skip(skip).map(ToString::to_string).collect(),
+            });
+        });
+    }
+
     let result = engine.execute();
 
     Ok(match result {
@@ -112,6 +153,42 @@ pub fn call_msg(
     account: &mut AccountStuff,
     msg: &Message,
     config: &BriefBlockchainConfig,
+) -> Result<ActionPhaseOutput, ExecutionError> {
+    call_msg_ext(utime, lt, account, msg, config, None)
+}
+
+/// Same as [`call_msg`], but also collects at most `max_steps` VM steps
+pub fn call_msg_traced(
+    utime: u32,
+    lt: u64,
+    account: &mut AccountStuff,
+    msg: &Message,
+    config: &BriefBlockchainConfig,
+    max_steps: usize,
+) -> Result<VmTrace, ExecutionError> {
+    let trace = Arc::new(Mutex::new(VmTrace {
+        exit_code: 0,
+        steps: Vec::new(),
+        truncated: false,
+        max_steps,
+    }));
+    let output = call_msg_ext(utime, lt, account, msg, config, Some(trace.clone()))?;
+
+    let mut trace = match trace.lock() {
+        Ok(mut trace) => std::mem::take(&mut *trace),
+        Err(_) => Default::default(),
+    };
+    trace.exit_code = output.exit_code;
+    Ok(trace)
+}
+
+fn call_msg_ext(
+    utime: u32,
+    lt: u64,
+    account: &mut AccountStuff,
+    msg: &Message,
+    config: &BriefBlockchainConfig,
+    trace: Option<Arc<Mutex<VmTrace>>>,
 ) -> Result<ActionPhaseOutput, ExecutionError> {
     let msg_cell = msg
         .write_to_new_cell()
@@ -135,7 +212,7 @@ pub fn call_msg(
         .push(StackItem::Slice(msg.body().unwrap_or_default())) // message body
         .push(function_selector); // function selector
 
-    let (engine, exit_code, success) = call(utime, lt, account, stack, config)?;
+    let (engine, exit_code, success) = call_ext(utime, lt, account, stack, config, trace)?;
     if !success {
         return Ok(ActionPhaseOutput {
             messages: None,
@@ -187,6 +264,34 @@ fn build_contract_info(
     info
 }
 
+/// VM execution log
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VmTrace {
+    pub exit_code: i32,
+    pub steps: Vec<VmStep>,
+    /// Whether some steps were not collected due to the limit
+    pub truncated: bool,
+    #[serde(skip)]
+    max_steps: usize,
+}
+
+/// Single step of the VM execution
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VmStep {
+    pub step: u32,
+    /// Executed instruction
+    pub instruction: String,
+    /// Total gas used after this step
+    pub gas_used: i64,
+    /// Gas used by this instruction
+    pub gas_cmd: i64,
+    /// Top stack items after this step, from bottom to top.
+    /// At most [`MAX_TRACED_STACK_ITEMS`] items are stored
+    pub stack: Vec<String>,
+}
+
 pub struct ActionPhaseOutput {
     pub messages: Option<Vec<Message>>,
     pub exit_code: i32,
@@ -0,0 +1,310 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+use nekoton_abi::*;
+use ton_abi::{Param, ParamType};
+use ton_block::MsgAddressInt;
+
+use crate::utils::declare_function;
+use crate::RunLocalSimple;
+
+#[derive(Copy, Clone)]
+pub struct DePoolContract<'a>(pub ExecutionContext<'a>);
+
+impl DePoolContract<'_> {
+    pub fn get_depool_info(&self) -> Result<DePoolInfo> {
+        let result = self.0.run_local_simple(get_depool_info(), &[])?.unpack()?;
+        Ok(result)
+    }
+
+    pub fn get_participant_info(&self, address: &MsgAddressInt) -> Result<ParticipantInfo> {
+        let inputs = [address.clone().token_value().named("addr")];
+        let result = self
+            .0
+            .run_local_simple(get_participant_info(), &inputs)?
+            .unpack()?;
+        Ok(result)
+    }
+
+    /// Returns truncated info about the current rounds
+    pub fn get_rounds(&self) -> Result<HashMap<u64, RoundInfo>> {
+        let tokens = self.0.run_local_simple(get_rounds(), &[])?;
+        parse_rounds(tokens)
+    }
+}
+
+fn parse_rounds(tokens: Vec<ton_abi::Token>) -> Result<HashMap<u64, RoundInfo>> {
+    let rounds = tokens.into_iter().next().ok_or(UnpackerError::InvalidAbi)?;
+    unpack_map_token(rounds.value)
+}
+
+/// Adds an ordinary stake to the current pooling round
+///
+/// # Type
+/// Internal method
+///
+/// # Inputs
+/// * `stake: uint64` - stake amount in nano EVER, the rest of the attached value is returned
+///
+pub fn add_ordinary_stake() -> &'static ton_abi::Function {
+    declare_function! {
+        abi: v2_0,
+        header: [time, expire],
+        name: "addOrdinaryStake",
+        inputs: vec![Param::new("stake", ParamType::Uint(64))],
+        outputs: Vec::new(),
+    }
+}
+
+/// Requests the specified part of the stake to be withdrawn after the round completion
+///
+/// # Type
+/// Internal method
+///
+/// # Inputs
+/// * `withdrawValue: uint64` - amount in nano EVER
+///
+pub fn withdraw_part() -> &'static ton_abi::Function {
+    declare_function! {
+        abi: v2_0,
+        header: [time, expire],
+        name: "withdrawPart",
+        inputs: vec![Param::new("withdrawValue", ParamType::Uint(64))],
+        outputs: Vec::new(),
+    }
+}
+
+/// Requests the whole stake to be withdrawn after the round completion
+///
+/// # Type
+/// Internal method
+///
+pub fn withdraw_all() -> &'static ton_abi::Function {
+    declare_function! {
+        abi: v2_0,
+        header: [time, expire],
+        name: "withdrawAll",
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone, UnpackAbiPlain, KnownParamTypePlain)]
+pub struct DePoolInfo {
+    #[abi(bool, name = "poolClosed")]
+    pub pool_closed: bool,
+    #[abi(uint64, name = "minStake")]
+    pub min_stake: u64,
+    #[abi(uint64, name = "validatorAssurance")]
+    pub validator_assurance: u64,
+    #[abi(uint8, name = "participantRewardFraction")]
+    pub participant_reward_fraction: u8,
+    #[abi(uint8, name = "validatorRewardFraction")]
+    pub validator_reward_fraction: u8,
+    #[abi(uint64, name = "balanceThreshold")]
+    pub balance_threshold: u64,
+    #[abi(address, name = "validatorWallet")]
+    pub validator_wallet: MsgAddressInt,
+    #[abi(array, name = "proxies")]
+    pub proxies: Vec<MsgAddressInt>,
+    #[abi(uint64, name = "stakeFee")]
+    pub stake_fee: u64,
+    #[abi(uint64, name = "retOrReinvFee")]
+    pub ret_or_reinv_fee: u64,
+    #[abi(uint64, name = "proxyFee")]
+    pub proxy_fee: u64,
+}
+
+/// Returns the DePool parameters
+///
+/// # Type
+/// Get method
+///
+/// # Outputs
+/// See [`DePoolInfo`]
+///
+pub fn get_depool_info() -> &'static ton_abi::Function {
+    declare_function! {
+        abi: v2_0,
+        header: [time, expire],
+        name: "getDePoolInfo",
+        inputs: Vec::new(),
+        outputs: DePoolInfo::param_type(),
+    }
+}
+
+#[derive(Debug, Clone, UnpackAbi, KnownParamType)]
+pub struct InvestParams {
+    #[abi(uint64, name = "remainingAmount")]
+    pub remaining_amount: u64,
+    #[abi(uint64, name = "lastWithdrawalTime")]
+    pub last_withdrawal_time: u64,
+    #[abi(uint32, name = "withdrawalPeriod")]
+    pub withdrawal_period: u32,
+    #[abi(uint64, name = "withdrawalValue")]
+    pub withdrawal_value: u64,
+    #[abi(address, name = "owner")]
+    pub owner: MsgAddressInt,
+}
+
+#[derive(Debug, Clone, UnpackAbiPlain, KnownParamTypePlain)]
+pub struct ParticipantInfo {
+    /// Total stake of the participant in all rounds
+    #[abi(uint64, name = "total")]
+    pub total: u64,
+    /// Amount which will be withdrawn after the round completion
+    #[abi(uint64, name = "withdrawValue")]
+    pub withdraw_value: u64,
+    #[abi(bool, name = "reinvest")]
+    pub reinvest: bool,
+    /// Total reward of the participant
+    #[abi(uint64, name = "reward")]
+    pub reward: u64,
+    /// Ordinary stakes by round id
+    #[abi(name = "stakes")]
+    pub stakes: BTreeMap<u64, u64>,
+    /// Vesting stakes by round id
+    #[abi(name = "vestings")]
+    pub vestings: BTreeMap<u64, InvestParams>,
+    /// Lock stakes by round id
+    #[abi(name = "locks")]
+    pub locks: BTreeMap<u64, InvestParams>,
+    #[abi(address, name = "vestingDonor")]
+    pub vesting_donor: MsgAddressInt,
+    #[abi(address, name = "lockDonor")]
+    pub lock_donor: MsgAddressInt,
+}
+
+/// Returns the participant stakes
+///
+/// # Type
+/// Get method
+///
+/// # Inputs
+/// * `addr: address` - participant wallet address
+///
+/// # Outputs
+/// See [`ParticipantInfo`]
+///
+pub fn get_participant_info() -> &'static ton_abi::Function {
+    declare_function! {
+        abi: v2_0,
+        header: [time, expire],
+        name: "getParticipantInfo",
+        inputs: vec![Param::new("addr", ParamType::Address)],
+        outputs: ParticipantInfo::param_type(),
+    }
+}
+
+#[derive(Debug, Clone, PackAbi, UnpackAbi, KnownParamType)]
+pub struct RoundInfo {
+    #[abi(uint64, name = "id")]
+    pub id: u64,
+    #[abi(uint32, name = "supposedElectedAt")]
+    pub supposed_elected_at: u32,
+    #[abi(uint32, name = "unfreeze")]
+    pub unfreeze: u32,
+    #[abi(uint32, name = "stakeHeldFor")]
+    pub stake_held_for: u32,
+    #[abi(uint256, name = "vsetHashInElectionPhase")]
+    pub vset_hash_in_election_phase: ton_types::UInt256,
+    #[abi(uint8, name = "step")]
+    pub step: u8,
+    #[abi(uint8, name = "completionReason")]
+    pub completion_reason: u8,
+    #[abi(uint64, name = "stake")]
+    pub stake: u64,
+    #[abi(uint64, name = "recoveredStake")]
+    pub recovered_stake: u64,
+    #[abi(uint64, name = "unused")]
+    pub unused: u64,
+    #[abi(bool, name = "isValidatorStakeCompleted")]
+    pub is_validator_stake_completed: bool,
+    #[abi(uint64, name = "participantReward")]
+    pub participant_reward: u64,
+    #[abi(uint32, name = "participantQty")]
+    pub participant_qty: u32,
+    #[abi(uint64, name = "validatorStake")]
+    pub validator_stake: u64,
+    #[abi(uint64, name = "validatorRemainingStake")]
+    pub validator_remaining_stake: u64,
+    #[abi(uint64, name = "handledStakesAndRewards")]
+    pub handled_stakes_and_rewards: u64,
+}
+
+/// Returns all rounds of the DePool
+///
+/// # Type
+/// Get method
+///
+/// # Outputs
+/// * `rounds: map(uint64, tuple)` - rounds by id
+///
+pub fn get_rounds() -> &'static ton_abi::Function {
+    declare_function! {
+        abi: v2_0,
+        header: [time, expire],
+        name: "getRounds",
+        inputs: Vec::new(),
+        outputs: vec![Param::new(
+            "rounds",
+            ParamType::Map(Box::new(ParamType::Uint(64)), Box::new(RoundInfo::param_type())),
+        )],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn correct_function_ids() {
+        assert_eq!(add_ordinary_stake().input_id, 0x0aac18fd);
+        assert_eq!(withdraw_part().input_id, 0x7b9676c6);
+        assert_eq!(withdraw_all().input_id, 0x12f40370);
+    }
+
+    #[test]
+    fn decode_rounds() {
+        let round = |id| RoundInfo {
+            id,
+            supposed_elected_at: 1650000000,
+            unfreeze: 1650100000,
+            stake_held_for: 32768,
+            vset_hash_in_election_phase: Default::default(),
+            step: 2,
+            completion_reason: 0,
+            stake: 1_000_000_000_000,
+            recovered_stake: 0,
+            unused: 0,
+            is_validator_stake_completed: false,
+            participant_reward: 0,
+            participant_qty: 3,
+            validator_stake: 500_000_000_000,
+            validator_remaining_stake: 0,
+            handled_stakes_and_rewards: 0,
+        };
+
+        let mut rounds = HashMap::new();
+        rounds.insert(1u64, round(1));
+        rounds.insert(2u64, round(2));
+
+        // Getter output
+        let tokens = vec![rounds.clone().token_value().named("rounds")];
+        let parsed = parse_rounds(tokens).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[&2].id, 2);
+        assert_eq!(parsed[&1].validator_stake, 500_000_000_000);
+
+        // Raw map cell
+        let root = pack_map(rounds, ton_abi::contract::ABI_VERSION_2_0).unwrap();
+        let decoded: HashMap<u64, RoundInfo> =
+            unpack_map(root, ton_abi::contract::ABI_VERSION_2_0).unwrap();
+        assert_eq!(decoded[&1].participant_qty, 3);
+
+        // Map with other value type is rejected
+        let mut other = HashMap::new();
+        other.insert(1u64, 1u32);
+        assert!(parse_rounds(vec![other.token_value().named("rounds")]).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+//! Typed definitions of the standard events and notifications
+//! emitted by the bundled contracts
+
+use nekoton_abi::num_bigint::BigUint;
+use nekoton_abi::*;
+use once_cell::race::OnceBox;
+
+use crate::utils::declare_function;
+
+#[derive(Debug, Clone, KnownParamTypePlain, PackAbiPlain, UnpackAbiPlain)]
+pub struct TransferAcceptedEvent {
+    #[abi(bytes)]
+    pub payload: Vec<u8>,
+}
+
+/// Emitted by the multisig wallet when it receives plain transfer
+///
+/// # Type
+/// Event
+///
+/// # Inputs
+/// * `payload: bytes` - message body of the incoming transfer
+///
+pub fn multisig_transfer_accepted() -> &'static ton_abi::Event {
+    static EVENT: OnceBox<ton_abi::Event> = OnceBox::new();
+    EVENT.get_or_init(|| {
+        Box::new(
+            EventBuilder::new("TransferAccepted")
+                .inputs(TransferAcceptedEvent::param_type())
+                .build(),
+        )
+    })
+}
+
+#[derive(Debug, Clone, KnownParamTypePlain, PackAbiPlain, UnpackAbiPlain)]
+pub struct AcceptTokensMintEvent {
+    #[abi(address, name = "tokenRoot")]
+    pub token_root: ton_block::MsgAddressInt,
+    #[abi(with = "uint128_number")]
+    pub amount: BigUint,
+    #[abi(address, name = "remainingGasTo")]
+    pub remaining_gas_to: ton_block::MsgAddressInt,
+    #[abi(cell)]
+    pub payload: ton_types::Cell,
+}
+
+/// Sent by the TIP-3.1 token wallet to its owner after mint with `notify` flag
+///
+/// # Type
+/// Internal method
+///
+/// # Inputs
+/// * `tokenRoot: address` - root token contract
+/// * `amount: uint128` - how much tokens were minted
+/// * `remainingGasTo: address` - remaining gas receiver
+/// * `payload: cell` - arbitrary payload
+///
+pub fn on_accept_tokens_mint() -> &'static ton_abi::Function {
+    declare_function! {
+        name: "onAcceptTokensMint",
+        inputs: AcceptTokensMintEvent::param_type(),
+        outputs: Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone, KnownParamTypePlain, PackAbiPlain, UnpackAbiPlain)]
+pub struct AcceptTokensTransferEvent {
+    #[abi(address, name = "tokenRoot")]
+    pub token_root: ton_block::MsgAddressInt,
+    #[abi(with = "uint128_number")]
+    pub amount: BigUint,
+    #[abi(address)]
+    pub sender: ton_block::MsgAddressInt,
+    #[abi(address, name = "senderWallet")]
+    pub sender_wallet: ton_block::MsgAddressInt,
+    #[abi(address, name = "remainingGasTo")]
+    pub remaining_gas_to: ton_block::MsgAddressInt,
+    #[abi(cell)]
+    pub payload: ton_types::Cell,
+}
+
+/// Sent by the TIP-3.1 token wallet to its owner after incoming transfer with `notify` flag
+///
+/// # Type
+/// Internal method
+///
+/// # Inputs
+/// * `tokenRoot: address` - root token contract
+/// * `amount: uint128` - how much tokens were received
+/// * `sender: address` - owner of the sender token wallet
+/// * `senderWallet: address` - sender token wallet
+/// * `remainingGasTo: address` - remaining gas receiver
+/// * `payload: cell` - arbitrary payload
+///
+pub fn on_accept_tokens_transfer() -> &'static ton_abi::Function {
+    declare_function! {
+        name: "onAcceptTokensTransfer",
+        inputs: AcceptTokensTransferEvent::param_type(),
+        outputs: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_accepted_id() {
+        assert_eq!(multisig_transfer_accepted().id, 0x7d729cc8);
+    }
+}
@@ -56,6 +56,8 @@ use nekoton_abi::{ExecutionContext, ExecutionOutput};
 
 pub mod access;
 pub mod dens;
+pub mod depool;
+pub mod events;
 pub mod old_tip3;
 pub mod tip1155;
 pub mod tip3;
@@ -136,3 +136,34 @@ pub fn deploy_wallet() -> &'static ton_abi::Function {
         outputs: vec![Param::new("address", ParamType::Address)],
     }
 }
+
+/// Transfer root ownership to the new owner
+///
+/// # Type
+/// Internal method
+///
+/// # Inputs
+/// * `newOwner: address` - new root owner address
+/// * `remainingGasTo: address` - address where to send excess gas
+/// * `callbacks: map(address, tuple(uint128, cell))` - notified contracts with attached value and payload
+///
+pub fn transfer_ownership() -> &'static ton_abi::Function {
+    declare_function! {
+        name: "transferOwnership",
+        inputs: vec![
+            Param::new("newOwner", ParamType::Address),
+            Param::new("remainingGasTo", ParamType::Address),
+            Param::new(
+                "callbacks",
+                ParamType::Map(
+                    Box::new(ParamType::Address),
+                    Box::new(ParamType::Tuple(vec![
+                        Param::new("value", ParamType::Uint(128)),
+                        Param::new("payload", ParamType::Cell),
+                    ])),
+                ),
+            ),
+        ],
+        outputs: Vec::new(),
+    }
+}
@@ -3,7 +3,7 @@ use nekoton_utils::*;
 use serde::{Deserialize, Serialize};
 use ton_block::MsgAddressInt;
 
-pub use self::root_token_contract::RootTokenContractState;
+pub use self::root_token_contract::{prepare_transfer_ownership, RootTokenContractState};
 pub use self::token_wallet_contract::TokenWalletContractState;
 
 mod root_token_contract;
@@ -59,4 +59,6 @@ pub enum Tip3Error {
     UnknownVersion,
     #[error("Wallet not deployed")]
     WalletNotDeployed,
+    #[error("Root public key is required for this version")]
+    RootPublicKeyRequired,
 }
@@ -1,11 +1,60 @@
-use nekoton_abi::ExecutionContext;
+use std::collections::BTreeMap;
+
+use nekoton_abi::num_bigint::BigUint;
+use nekoton_abi::*;
+use ton_abi::{Param, ParamType, TokenValue};
 use ton_block::MsgAddressInt;
+use ton_types::SliceData;
 
 use super::{RootTokenContractDetails, Tip3Error, TokenWalletVersion};
 use crate::{old_tip3, tip3, tip3_1, tip6};
 
 pub struct RootTokenContractState<'a>(pub ExecutionContext<'a>);
 
+/// Builds an internal message body for transferring the root ownership.
+///
+/// Message must be sent to the root contract from the current root owner.
+/// `new_owner_public_key` is required for the old version, where the root
+/// can also be owned by the key (use zero key to make `new_owner` the only owner).
+/// `remaining_gas_to` is ignored for the old version
+pub fn prepare_transfer_ownership(
+    version: TokenWalletVersion,
+    new_owner: &MsgAddressInt,
+    new_owner_public_key: Option<ton_types::UInt256>,
+    remaining_gas_to: &MsgAddressInt,
+) -> anyhow::Result<SliceData> {
+    let (function, input) = match version {
+        TokenWalletVersion::OldTip3v4 => {
+            let root_public_key = new_owner_public_key.ok_or(Tip3Error::RootPublicKeyRequired)?;
+            MessageBuilder::new(old_tip3::root_token_contract::transfer_owner())
+                .arg(root_public_key) // root_public_key
+                .arg(new_owner) // root_owner_address
+                .build()
+        }
+        TokenWalletVersion::Tip3 => {
+            let callbacks = TokenValue::Map(
+                ParamType::Address,
+                ParamType::Tuple(vec![
+                    Param::new("value", ParamType::Uint(128)),
+                    Param::new("payload", ParamType::Cell),
+                ]),
+                BTreeMap::new(),
+            );
+
+            MessageBuilder::new(tip3_1::root_token_contract::transfer_ownership())
+                .arg(new_owner) // newOwner
+                .arg(remaining_gas_to) // remainingGasTo
+                .arg(callbacks) // callbacks
+                .build()
+        }
+    };
+
+    let body = function
+        .encode_internal_input(&input)
+        .and_then(SliceData::load_builder)?;
+    Ok(body)
+}
+
 impl RootTokenContractState<'_> {
     /// Calculates token wallet address
     pub fn get_wallet_address(
@@ -21,6 +70,46 @@ impl RootTokenContractState<'_> {
         }
     }
 
+    /// Builds an internal message body for minting tokens.
+    ///
+    /// Message must be sent to the root contract from the root owner.
+    /// `deploy_wallet_value`, `remaining_gas_to`, `notify` and `payload` are
+    /// ignored for the old version, where tokens are minted to the existing token wallet
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare_mint(
+        &self,
+        version: TokenWalletVersion,
+        recipient: &MsgAddressInt,
+        amount: BigUint,
+        deploy_wallet_value: u128,
+        remaining_gas_to: &MsgAddressInt,
+        notify: bool,
+        payload: ton_types::Cell,
+    ) -> anyhow::Result<SliceData> {
+        let (function, input) = match version {
+            TokenWalletVersion::OldTip3v4 => {
+                let token_wallet = self.get_wallet_address(version, recipient)?;
+                MessageBuilder::new(old_tip3::root_token_contract::mint())
+                    .arg(BigUint128(amount)) // tokens
+                    .arg(token_wallet) // to
+                    .build()
+            }
+            TokenWalletVersion::Tip3 => MessageBuilder::new(tip3_1::root_token_contract::mint())
+                .arg(BigUint128(amount)) // amount
+                .arg(recipient) // recipient
+                .arg(deploy_wallet_value) // deployWalletValue
+                .arg(remaining_gas_to) // remainingGasTo
+                .arg(notify) // notify
+                .arg(payload) // payload
+                .build(),
+        };
+
+        let body = function
+            .encode_internal_input(&input)
+            .and_then(SliceData::load_builder)?;
+        Ok(body)
+    }
+
     /// Tries to guess version and retrieve details
     pub fn guess_details(&self) -> anyhow::Result<RootTokenContractDetails> {
         if let Ok(true) = tip6::SidContract(self.0).supports_interfaces(&[
@@ -78,3 +167,51 @@ impl RootTokenContractState<'_> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn owner() -> MsgAddressInt {
+        MsgAddressInt::from_str(
+            "0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn transfer_ownership_old_tip3() {
+        let version = TokenWalletVersion::OldTip3v4;
+        assert!(prepare_transfer_ownership(version, &owner(), None, &owner()).is_err());
+
+        let public_key = ton_types::UInt256::from([1; 32]);
+        let body =
+            prepare_transfer_ownership(version, &owner(), Some(public_key.clone()), &owner())
+                .unwrap();
+
+        let inputs: old_tip3::root_token_contract::TransferOwnerInputs =
+            old_tip3::root_token_contract::transfer_owner()
+                .decode_input(body, true)
+                .unwrap()
+                .unpack()
+                .unwrap();
+        assert_eq!(inputs.root_public_key, public_key);
+        assert_eq!(inputs.root_owner_address, owner());
+    }
+
+    #[test]
+    fn transfer_ownership_tip3() {
+        let body =
+            prepare_transfer_ownership(TokenWalletVersion::Tip3, &owner(), None, &owner()).unwrap();
+
+        let tokens = tip3_1::root_token_contract::transfer_ownership()
+            .decode_input(body, true)
+            .unwrap();
+        assert_eq!(tokens.len(), 3);
+
+        let new_owner: MsgAddressInt = tokens.unpack_first().unwrap();
+        assert_eq!(new_owner, owner());
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
@@ -26,6 +27,22 @@ pub struct GqlNetworkSettings {
     pub endpoint_selection_retry_count: usize,
     /// Gql node type
     pub local: bool,
+    /// Timeout of a single query, long queries are not affected. Default: `60000`
+    #[serde(with = "serde_duration_ms", default = "default_query_timeout")]
+    pub query_timeout: Duration,
+    /// Maximum amount of retries for a failed query. Default: `0`
+    ///
+    /// Failed query is retried on the next best endpoint. Non-idempotent
+    /// requests (e.g. message broadcasts) are never retried
+    #[serde(default)]
+    pub query_retry_count: usize,
+    /// Additional HTTP headers for each request (e.g. API keys)
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+fn default_query_timeout() -> Duration {
+    Duration::from_secs(60)
 }
 
 impl Default for GqlNetworkSettings {
@@ -36,17 +53,24 @@ impl Default for GqlNetworkSettings {
             max_latency: Duration::from_secs(60),
             endpoint_selection_retry_count: 5,
             local: false,
+            query_timeout: default_query_timeout(),
+            query_retry_count: 0,
+            headers: HashMap::new(),
         }
     }
 }
 
 pub struct GqlClient {
     client: reqwest::Client,
+    #[cfg(feature = "gql_subscriptions")]
+    headers: HashMap<String, String>,
     endpoints: Vec<Endpoint>,
     latency_detection_interval: u64,
     max_latency: u32,
     endpoint_selection_retry_count: usize,
     local: bool,
+    query_timeout: Duration,
+    query_retry_count: usize,
     flags: AtomicU64,
     notify: Notify,
 }
@@ -70,6 +94,13 @@ impl GqlClient {
             reqwest::header::CONTENT_TYPE,
             reqwest::header::HeaderValue::from_static("application/json"),
         );
+        for (name, value) in &settings.headers {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("invalid header name: {}", name))?;
+            let value = reqwest::header::HeaderValue::from_str(value)
+                .with_context(|| format!("invalid header value for {}", name))?;
+            headers.insert(name, value);
+        }
 
         let client = reqwest::ClientBuilder::new()
             .default_headers(headers)
@@ -78,23 +109,21 @@ impl GqlClient {
 
         Ok(Arc::new(Self {
             client,
+            #[cfg(feature = "gql_subscriptions")]
+            headers: settings.headers,
             endpoints,
             latency_detection_interval: settings.latency_detection_interval.as_secs(),
             max_latency: settings.max_latency.as_millis() as u32,
             endpoint_selection_retry_count: settings.endpoint_selection_retry_count,
             local: settings.local,
+            query_timeout: settings.query_timeout,
+            query_retry_count: settings.query_retry_count,
             flags: Default::default(),
             notify: Default::default(),
         }))
     }
 
-    async fn select_querying_endpoint(&self) -> Result<&'_ Endpoint> {
-        // Low 4 bytes which are used as endpoint index
-        const INDEX_MASK: u64 = 0x0000_0000_ffff_ffff;
-
-        const INTERMEDIATE: u64 = 0x0000_0000_ffff_fffe;
-        const IN_PROCESS: u64 = 0x0000_0000_ffff_ffff;
-
+    async fn select_querying_endpoint(&self) -> Result<(usize, &'_ Endpoint)> {
         struct Guard<'a> {
             client: &'a GqlClient,
             result: Option<u64>,
@@ -137,15 +166,7 @@ impl GqlClient {
         let mut notify_fut: Option<Notified<'_>> = None;
         loop {
             let state = self.flags.load(Ordering::Acquire);
-            match state >> 32 {
-                // Not detecting yet
-                detection_time if now < detection_time => {
-                    break self
-                        .endpoints
-                        .get((state & INDEX_MASK) as usize)
-                        .ok_or_else(|| GqlClientError::EndpointNotFound.into())
-                }
-
+            match state {
                 // Waiting flags change
                 INTERMEDIATE => continue,
 
@@ -155,6 +176,15 @@ impl GqlClient {
                     None => notify_fut = Some(self.notify.notified()),
                 },
 
+                // Not detecting yet
+                state if now < state >> 32 => {
+                    let index = (state & INDEX_MASK) as usize;
+                    break match self.endpoints.get(index) {
+                        Some(endpoint) => Ok((index, endpoint)),
+                        None => Err(GqlClientError::EndpointNotFound.into()),
+                    };
+                }
+
                 _ => {
                     match self.flags.compare_exchange(
                         state,
@@ -171,7 +201,7 @@ impl GqlClient {
                             let (index, endpoint) = self.find_best_endpoint().await?;
                             guard.set_result(index);
 
-                            break Ok(endpoint);
+                            break Ok((index, endpoint));
                         }
                         // State has already been changed
                         Err(_) => continue,
@@ -185,17 +215,27 @@ impl GqlClient {
         for i in 1..=self.endpoint_selection_retry_count {
             let mut requests = FuturesUnordered::new();
 
+            // Skip recently failed endpoints while there are other candidates
+            let now = now_sec_u64();
+            let has_healthy = self
+                .endpoints
+                .iter()
+                .any(|endpoint| !endpoint.is_failed(now, self.latency_detection_interval));
+
             for (i, endpoint) in self.endpoints.iter().enumerate() {
+                if has_healthy && endpoint.is_failed(now, self.latency_detection_interval) {
+                    continue;
+                }
                 requests.push(async move { (i, endpoint, self.check_latency(endpoint).await) });
             }
 
-            let mut last_latency = None;
+            let mut last_latency: Option<(usize, &Endpoint, u32)> = None;
             while let Some((i, endpoint, response)) = requests.next().await {
                 match response {
                     Ok(latency) if latency <= self.max_latency => return Ok((i, endpoint)),
                     Ok(latency) => {
-                        if matches!(&last_latency, Some((_, _, Some(l))) if latency < *l) {
-                            last_latency = Some((i, endpoint, Some(latency)))
+                        if !matches!(&last_latency, Some((_, _, l)) if latency >= *l) {
+                            last_latency = Some((i, endpoint, latency))
                         }
                     }
                     Err(e) => {
@@ -215,6 +255,21 @@ impl GqlClient {
         Err(GqlClientError::NoEndpointFound.into())
     }
 
+    /// Forces the selection of another endpoint if the failed endpoint is still selected
+    fn invalidate_endpoint(&self, index: usize) {
+        if let Some(endpoint) = self.endpoints.get(index) {
+            endpoint.failed_at.store(now_sec_u64(), Ordering::Release);
+        }
+
+        let state = self.flags.load(Ordering::Acquire);
+        if !matches!(state, INTERMEDIATE | IN_PROCESS) && (state & INDEX_MASK) as usize == index {
+            // NOTE: state could have already been changed by the concurrent query
+            let _ = self
+                .flags
+                .compare_exchange(state, 0, Ordering::Release, Ordering::Relaxed);
+        }
+    }
+
     async fn check_latency(&self, endpoint: &Endpoint) -> Result<u32> {
         #[derive(Deserialize)]
         struct GqlResponse {
@@ -244,21 +299,92 @@ impl nekoton::external::GqlConnection for GqlClient {
         self.local
     }
 
+    #[cfg(feature = "gql_subscriptions")]
+    fn supports_subscriptions(&self) -> bool {
+        true
+    }
+
+    #[cfg(feature = "gql_subscriptions")]
+    async fn subscribe(
+        &self,
+        req: nekoton::external::GqlRequest,
+        handler: Arc<dyn nekoton::external::GqlSubscriptionHandler>,
+    ) -> Result<Box<dyn nekoton::external::GqlSubscription>> {
+        let (index, endpoint) = self.select_querying_endpoint().await?;
+
+        let mut url = endpoint.gql.clone();
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        if url.set_scheme(scheme).is_err() {
+            return Err(GqlClientError::InvalidSubscriptionUrl.into());
+        }
+
+        match subscriptions::subscribe(url, &self.headers, req, handler, self.query_timeout).await {
+            Ok(subscription) => Ok(subscription),
+            Err(e) => {
+                self.invalidate_endpoint(index);
+                Err(e)
+            }
+        }
+    }
+
     async fn post(&self, req: nekoton::external::GqlRequest) -> Result<String> {
-        let endpoint = self.select_querying_endpoint().await?;
-        let response = self
-            .client
-            .post(endpoint.gql.clone())
-            .body(req.data)
-            .send()
-            .await?;
-        Ok(response.text().await?)
+        // NOTE: the message could have been broadcast even if the request failed
+        let retry_count = if req.idempotent {
+            self.query_retry_count
+        } else {
+            0
+        };
+
+        let mut attempt = 0;
+        loop {
+            let (index, endpoint) = self.select_querying_endpoint().await?;
+
+            let mut request = self
+                .client
+                .post(endpoint.gql.clone())
+                .body(req.data.clone());
+            if !req.long_query {
+                request = request.timeout(self.query_timeout);
+            }
+
+            let response = match request.send().await {
+                Ok(response) if response.status().is_server_error() => {
+                    response.error_for_status().map(|_| String::new())
+                }
+                Ok(response) => response.text().await,
+                Err(e) => Err(e),
+            };
+
+            match response {
+                Ok(response) => break Ok(response),
+                Err(e) if attempt < retry_count => {
+                    log::debug!("GQL query error: {:?}", e);
+                    self.invalidate_endpoint(index);
+                    attempt += 1;
+
+                    let interval = std::cmp::min(attempt * 100, 5000);
+                    tokio::time::sleep(Duration::from_millis(interval as u64)).await;
+                }
+                Err(e) => {
+                    self.invalidate_endpoint(index);
+                    break Err(e.into());
+                }
+            }
+        }
     }
 }
 
+// Low 4 bytes which are used as endpoint index
+const INDEX_MASK: u64 = 0x0000_0000_ffff_ffff;
+
+const INTERMEDIATE: u64 = 0x0000_0000_ffff_fffe;
+const IN_PROCESS: u64 = 0x0000_0000_ffff_ffff;
+
 struct Endpoint {
     gql: Url,
     status: Url,
+    /// Time of the last failed query in seconds, `0` if there were no failures
+    failed_at: AtomicU64,
 }
 
 impl Endpoint {
@@ -268,8 +394,14 @@ impl Endpoint {
         Ok(Self {
             gql: gql.as_str().try_into()?,
             status: status.as_str().try_into()?,
+            failed_at: AtomicU64::new(0),
         })
     }
+
+    fn is_failed(&self, now: u64, cooldown: u64) -> bool {
+        let failed_at = self.failed_at.load(Ordering::Acquire);
+        failed_at != 0 && now < failed_at.saturating_add(cooldown)
+    }
 }
 
 fn expand_address(base_url: &str) -> String {
@@ -282,6 +414,287 @@ fn expand_address(base_url: &str) -> String {
     }
 }
 
+#[cfg(feature = "gql_subscriptions")]
+mod subscriptions {
+    use futures_util::SinkExt;
+    use nekoton::external::{GqlRequest, GqlSubscription, GqlSubscriptionHandler};
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::*;
+
+    /// Subprotocol of the `graphql-ws` library
+    const PROTOCOL: &str = "graphql-transport-ws";
+
+    /// Only one operation is started per connection
+    const OPERATION_ID: &str = "1";
+
+    type WsStream = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    /// Connects to the endpoint and starts the subscription.
+    ///
+    /// The connection handshake fails if it is not finished in `timeout`
+    pub async fn subscribe(
+        url: Url,
+        headers: &HashMap<String, String>,
+        req: GqlRequest,
+        handler: Arc<dyn GqlSubscriptionHandler>,
+        timeout: Duration,
+    ) -> Result<Box<dyn GqlSubscription>> {
+        let mut request = url.as_str().into_client_request()?;
+        let request_headers = request.headers_mut();
+        request_headers.insert("Sec-WebSocket-Protocol", HeaderValue::from_static(PROTOCOL));
+        for (name, value) in headers {
+            request_headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+
+        let (mut stream, _) =
+            tokio::time::timeout(timeout, tokio_tungstenite::connect_async(request))
+                .await
+                .map_err(|_| GqlSubscriptionError::HandshakeTimeout)??;
+
+        tokio::time::timeout(timeout, wait_for_ack(&mut stream))
+            .await
+            .map_err(|_| GqlSubscriptionError::HandshakeTimeout)??;
+
+        let payload = serde_json::from_str::<serde_json::Value>(&req.data)?;
+        stream
+            .send(ClientMessage::new("subscribe", Some(payload)).into())
+            .await?;
+
+        let task = tokio::spawn(async move {
+            let error = loop {
+                let event = match stream.next().await {
+                    Some(Ok(Message::Text(text))) => match parse_server_message(&text) {
+                        Ok(event) => event,
+                        Err(e) => break Some(e),
+                    },
+                    Some(Ok(Message::Ping(data))) => {
+                        if let Err(e) = stream.send(Message::Pong(data)).await {
+                            break Some(e.into());
+                        }
+                        continue;
+                    }
+                    Some(Ok(Message::Close(_))) | None => break None,
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => break Some(e.into()),
+                };
+
+                match event {
+                    ServerEvent::Data(data) => handler.on_data(data),
+                    ServerEvent::Complete => break None,
+                    ServerEvent::Ping => {
+                        let pong = ClientMessage::new("pong", None);
+                        if let Err(e) = stream.send(pong.into()).await {
+                            break Some(e.into());
+                        }
+                    }
+                    ServerEvent::Ack | ServerEvent::Other => {}
+                }
+            };
+
+            handler.on_closed(error);
+        });
+
+        Ok(Box::new(WsSubscription(task)))
+    }
+
+    /// Initializes the connection and waits until it is acknowledged by the server
+    async fn wait_for_ack(stream: &mut WsStream) -> Result<()> {
+        stream
+            .send(ClientMessage::new("connection_init", None).into())
+            .await?;
+        loop {
+            let text = match stream.next().await {
+                Some(Ok(Message::Text(text))) => text,
+                Some(Ok(Message::Close(_))) | None => {
+                    return Err(GqlSubscriptionError::ConnectionClosed.into())
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e.into()),
+            };
+            match parse_server_message(&text)? {
+                ServerEvent::Ack => return Ok(()),
+                ServerEvent::Ping => stream.send(ClientMessage::new("pong", None).into()).await?,
+                _ => continue,
+            }
+        }
+    }
+
+    /// Parses the text frame of the `graphql-transport-ws` protocol.
+    ///
+    /// Subscription errors are returned as errors
+    fn parse_server_message(text: &str) -> Result<ServerEvent> {
+        let message = serde_json::from_str::<ServerMessage>(text)?;
+        Ok(match message.ty.as_str() {
+            "connection_ack" => ServerEvent::Ack,
+            "next" => match message.payload {
+                Some(serde_json::Value::Object(mut payload)) => {
+                    if let Some(errors) = payload.remove("errors") {
+                        return Err(GqlSubscriptionError::Server(errors.to_string()).into());
+                    }
+                    match payload.remove("data") {
+                        Some(data) => ServerEvent::Data(data.to_string()),
+                        None => ServerEvent::Other,
+                    }
+                }
+                _ => return Err(GqlSubscriptionError::InvalidPayload.into()),
+            },
+            "error" => {
+                let errors = message.payload.unwrap_or_default();
+                return Err(GqlSubscriptionError::Server(errors.to_string()).into());
+            }
+            "complete" => ServerEvent::Complete,
+            "ping" => ServerEvent::Ping,
+            _ => ServerEvent::Other,
+        })
+    }
+
+    #[derive(Debug, Eq, PartialEq)]
+    enum ServerEvent {
+        Ack,
+        /// JSON encoded `data` field of the subscription event
+        Data(String),
+        Complete,
+        Ping,
+        Other,
+    }
+
+    /// Closes the connection on drop
+    struct WsSubscription(tokio::task::JoinHandle<()>);
+
+    impl Drop for WsSubscription {
+        fn drop(&mut self) {
+            self.0.abort();
+        }
+    }
+
+    impl GqlSubscription for WsSubscription {}
+
+    #[derive(Serialize)]
+    struct ClientMessage {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<&'static str>,
+        #[serde(rename = "type")]
+        ty: &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        payload: Option<serde_json::Value>,
+    }
+
+    impl ClientMessage {
+        fn new(ty: &'static str, payload: Option<serde_json::Value>) -> Self {
+            Self {
+                id: (ty == "subscribe").then_some(OPERATION_ID),
+                ty,
+                payload,
+            }
+        }
+    }
+
+    impl From<ClientMessage> for Message {
+        fn from(message: ClientMessage) -> Self {
+            Message::Text(serde_json::to_string(&message).trust_me())
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct ServerMessage {
+        #[serde(rename = "type")]
+        ty: String,
+        #[serde(default)]
+        payload: Option<serde_json::Value>,
+    }
+
+    #[derive(thiserror::Error, Debug)]
+    enum GqlSubscriptionError {
+        #[error("connection closed")]
+        ConnectionClosed,
+        #[error("invalid subscription payload")]
+        InvalidPayload,
+        #[error("subscription error: {0}")]
+        Server(String),
+        #[error("connection handshake timeout")]
+        HandshakeTimeout,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use tokio::net::TcpListener;
+
+        use super::*;
+
+        #[test]
+        fn parse_server_messages() {
+            let cases = [
+                (r#"{"type":"connection_ack"}"#, ServerEvent::Ack),
+                (r#"{"type":"ping","payload":{}}"#, ServerEvent::Ping),
+                (r#"{"type":"complete","id":"1"}"#, ServerEvent::Complete),
+                (r#"{"type":"pong"}"#, ServerEvent::Other),
+                (
+                    r#"{"type":"next","id":"1","payload":{"data":{"accounts":[]}}}"#,
+                    ServerEvent::Data(r#"{"accounts":[]}"#.to_owned()),
+                ),
+            ];
+            for (text, expected) in cases {
+                assert_eq!(parse_server_message(text).unwrap(), expected, "{text}");
+            }
+
+            for text in [
+                r#"{"type":"next","id":"1","payload":{"errors":[{"message":"oops"}]}}"#,
+                r#"{"type":"next","id":"1","payload":null}"#,
+                r#"{"type":"error","id":"1","payload":[{"message":"oops"}]}"#,
+                r#"not a json"#,
+            ] {
+                assert!(parse_server_message(text).is_err(), "{text}");
+            }
+        }
+
+        #[tokio::test]
+        async fn stalled_handshake_times_out() {
+            struct Handler;
+
+            impl GqlSubscriptionHandler for Handler {
+                fn on_data(&self, _: String) {}
+                fn on_closed(&self, _: Option<anyhow::Error>) {}
+            }
+
+            // Connections are accepted, but the handshake is never answered
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = Url::parse(&format!("ws://{}", listener.local_addr().unwrap())).unwrap();
+            tokio::spawn(async move {
+                let mut sockets = Vec::new();
+                while let Ok((socket, _)) = listener.accept().await {
+                    sockets.push(socket);
+                }
+            });
+
+            let result = subscribe(
+                url,
+                &HashMap::new(),
+                GqlRequest {
+                    data: r#"{"query":"subscription { blocks { id } }"}"#.to_owned(),
+                    long_query: true,
+                    idempotent: true,
+                },
+                Arc::new(Handler),
+                Duration::from_millis(100),
+            )
+            .await;
+            let error = result.err().unwrap();
+            assert!(matches!(
+                error.downcast_ref::<GqlSubscriptionError>(),
+                Some(GqlSubscriptionError::HandshakeTimeout)
+            ));
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 enum GqlClientError {
     #[error("no endpoints specified")]
@@ -290,11 +703,18 @@ enum GqlClientError {
     NoEndpointFound,
     #[error("endpoint not found")]
     EndpointNotFound,
+    #[cfg(feature = "gql_subscriptions")]
+    #[error("invalid subscription url")]
+    InvalidSubscriptionUrl,
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::AtomicUsize;
+
     use nekoton::external::{GqlConnection, GqlRequest};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
 
     use super::*;
 
@@ -315,9 +735,104 @@ mod tests {
             .post(GqlRequest {
                 data: QUERY.to_string(),
                 long_query: false,
+                idempotent: true,
             })
             .await
             .unwrap();
         println!("{}", response);
     }
+
+    #[tokio::test]
+    async fn failover_to_next_endpoint() {
+        const QUERY: &str = r#"{"query":"query { accounts { id } }"}"#;
+
+        let make_request = |idempotent| GqlRequest {
+            data: QUERY.to_string(),
+            long_query: false,
+            idempotent,
+        };
+
+        // The first endpoint is always selected, but fails all queries
+        let (broken, broken_queries) = spawn_endpoint(0, 500).await;
+        let (slow, slow_queries) = spawn_endpoint(100_000, 200).await;
+
+        let make_client = || {
+            GqlClient::new(GqlNetworkSettings {
+                endpoints: vec![broken.clone(), slow.clone()],
+                query_retry_count: 1,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        let client = make_client();
+        client.post(make_request(true)).await.unwrap();
+        assert_eq!(broken_queries.load(Ordering::Acquire), 1);
+        assert_eq!(slow_queries.load(Ordering::Acquire), 1);
+
+        // Failed message broadcast is not repeated on the other endpoint
+        let client = make_client();
+        assert!(client.post(make_request(false)).await.is_err());
+        assert_eq!(broken_queries.load(Ordering::Acquire), 2);
+        assert_eq!(slow_queries.load(Ordering::Acquire), 1);
+    }
+
+    /// Serves the latency query and responds to all other queries with the specified status
+    async fn spawn_endpoint(latency: u32, status: u16) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let queries = Arc::new(AtomicUsize::new(0));
+        tokio::spawn({
+            let queries = queries.clone();
+            async move {
+                loop {
+                    let (mut socket, _) = listener.accept().await.unwrap();
+                    let request = read_request(&mut socket).await;
+
+                    let (code, body) = if request.starts_with(b"GET") {
+                        let body = format!(r#"{{"data":{{"info":{{"latency":{latency}}}}}}}"#);
+                        (200, body)
+                    } else {
+                        queries.fetch_add(1, Ordering::Release);
+                        (status, r#"{"data":{}}"#.to_owned())
+                    };
+
+                    let response = format!(
+                        "HTTP/1.1 {code} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                }
+            }
+        });
+
+        (url, queries)
+    }
+
+    async fn read_request(socket: &mut TcpStream) -> Vec<u8> {
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        loop {
+            let n = socket.read(&mut buffer).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..n]);
+
+            let headers_end = match request.windows(4).position(|item| item == b"\r\n\r\n") {
+                Some(position) => position + 4,
+                None => continue,
+            };
+            let content_length = String::from_utf8_lossy(&request[..headers_end])
+                .to_lowercase()
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:")?.trim().parse().ok())
+                .unwrap_or(0usize);
+            if request.len() >= headers_end + content_length {
+                break;
+            }
+        }
+        request
+    }
 }
@@ -58,3 +58,60 @@ pub mod gql;
 pub mod jrpc;
 #[cfg(feature = "proto_transport")]
 pub mod proto;
+
+#[cfg(any(
+    feature = "gql_transport",
+    feature = "jrpc_transport",
+    feature = "proto_transport"
+))]
+pub use self::config::ConnectionConfig;
+
+#[cfg(any(
+    feature = "gql_transport",
+    feature = "jrpc_transport",
+    feature = "proto_transport"
+))]
+mod config {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use nekoton::transport::Transport;
+    use serde::{Deserialize, Serialize};
+
+    /// Endpoint settings, which allow to select the transport flavor from config
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case", tag = "type", content = "data")]
+    pub enum ConnectionConfig {
+        /// GraphQL endpoints
+        #[cfg(feature = "gql_transport")]
+        Gql(super::gql::GqlNetworkSettings),
+        /// JSON-RPC endpoint
+        #[cfg(feature = "jrpc_transport")]
+        Jrpc { endpoint: String },
+        /// Protobuf RPC endpoint
+        #[cfg(feature = "proto_transport")]
+        Proto { endpoint: String },
+    }
+
+    impl ConnectionConfig {
+        pub fn build_transport(&self) -> Result<Arc<dyn Transport>> {
+            Ok(match self {
+                #[cfg(feature = "gql_transport")]
+                Self::Gql(settings) => {
+                    let client = super::gql::GqlClient::new(settings.clone())?;
+                    Arc::new(nekoton::transport::gql::GqlTransport::new(client))
+                }
+                #[cfg(feature = "jrpc_transport")]
+                Self::Jrpc { endpoint } => {
+                    let client = super::jrpc::JrpcClient::new(endpoint.as_str())?;
+                    Arc::new(nekoton::transport::jrpc::JrpcTransport::new(client))
+                }
+                #[cfg(feature = "proto_transport")]
+                Self::Proto { endpoint } => {
+                    let client = super::proto::ProtoClient::new(endpoint.as_str())?;
+                    Arc::new(nekoton::transport::proto::ProtoTransport::new(client))
+                }
+            })
+        }
+    }
+}
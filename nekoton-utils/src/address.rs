@@ -15,12 +15,7 @@ pub fn pack_std_smc_addr(
     addr: &MsgAddressInt,
     bounceable: bool,
 ) -> Result<String> {
-    let addr = match addr {
-        MsgAddressInt::AddrStd(addr) => addr,
-        MsgAddressInt::AddrVar(_) => {
-            return Err(AddressConversionError::UnsupportedAddressType.into())
-        }
-    };
+    let addr = check_std_address(addr)?;
 
     let testnet = false;
     let mut buffer = [0u8; 36];
@@ -68,6 +63,23 @@ pub fn unpack_std_smc_addr(packed: &str, base64_url: bool) -> Result<MsgAddressI
     }))
 }
 
+/// Checks that the address is a standard address without anycast.
+///
+/// Other kinds of addresses are valid on-chain, but are not supported by wallets
+/// and can't be represented in the user-friendly form. Returns the inner standard address
+pub fn check_std_address(address: &MsgAddressInt) -> Result<&MsgAddrStd, StdAddressError> {
+    match address {
+        MsgAddressInt::AddrStd(MsgAddrStd {
+            anycast: Some(_), ..
+        }) => Err(StdAddressError::AnycastNotSupported),
+        MsgAddressInt::AddrStd(MsgAddrStd { address, .. }) if address.remaining_bits() != 256 => {
+            Err(StdAddressError::InvalidAccountIdLength)
+        }
+        MsgAddressInt::AddrStd(address) => Ok(address),
+        MsgAddressInt::AddrVar(_) => Err(StdAddressError::VarAddressNotSupported),
+    }
+}
+
 pub fn validate_address(address: &str) -> bool {
     MsgAddressInt::from_str(address).is_ok()
         || unpack_std_smc_addr(address, false).is_ok()
@@ -88,10 +100,18 @@ pub fn repack_address(address: &str) -> Result<MsgAddressInt> {
     Err(AddressConversionError::InvalidAddress.into())
 }
 
+#[derive(thiserror::Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StdAddressError {
+    #[error("Anycast addresses are not supported")]
+    AnycastNotSupported,
+    #[error("Variable length addresses are not supported")]
+    VarAddressNotSupported,
+    #[error("Invalid account id length")]
+    InvalidAccountIdLength,
+}
+
 #[derive(thiserror::Error, Debug)]
 enum AddressConversionError {
-    #[error("Unsupported address type")]
-    UnsupportedAddressType,
     #[error("Invalid base64")]
     InvalidBase64,
     #[error("Invalid packed address length")]
@@ -108,7 +128,9 @@ mod tests {
 
     use ton_block::MsgAddressInt;
 
-    use crate::address::{pack_std_smc_addr, unpack_std_smc_addr};
+    use crate::address::{
+        check_std_address, pack_std_smc_addr, unpack_std_smc_addr, StdAddressError,
+    };
 
     fn test_addr() -> MsgAddressInt {
         MsgAddressInt::from_str(
@@ -168,6 +190,39 @@ mod tests {
         assert_eq!(addr, address);
     }
 
+    #[test]
+    fn unpack_masterchain() {
+        let addr = MsgAddressInt::from_str(
+            "-1:3333333333333333333333333333333333333333333333333333333333333333",
+        )
+        .unwrap();
+        for bounceable in [false, true] {
+            let packed = pack_std_smc_addr(true, &addr, bounceable).unwrap();
+            let address = unpack_std_smc_addr(&packed, true).unwrap();
+            assert_eq!(addr, address);
+        }
+    }
+
+    #[test]
+    fn non_std_addresses() {
+        assert!(check_std_address(&test_addr()).is_ok());
+
+        let account_id = ton_types::AccountId::from_raw(vec![0xff; 4], 32);
+
+        let short = MsgAddressInt::with_standart(None, 0, account_id.clone()).unwrap();
+        assert_eq!(
+            check_std_address(&short),
+            Err(StdAddressError::InvalidAccountIdLength)
+        );
+        assert!(pack_std_smc_addr(true, &short, true).is_err());
+
+        let var = MsgAddressInt::with_variant(None, 0, account_id).unwrap();
+        assert_eq!(
+            check_std_address(&var),
+            Err(StdAddressError::VarAddressNotSupported)
+        );
+    }
+
     #[test]
     pub fn repack_b64_safe() {
         let res = super::repack_address("EQAC4_IoTmioEGuCOrnyQE8zzEP8ytjh3oNb3ZZ4klRobFz0")
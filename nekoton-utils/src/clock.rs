@@ -27,50 +27,50 @@ impl Clock for SimpleClock {
     }
 }
 
+/// Clock with the offset from the local time.
+///
+/// It can be shared between transports and subscriptions. The offset is stored
+/// in a single atomic, so all readers observe the value from the last update
 #[derive(Default)]
 pub struct ClockWithOffset {
-    offset_as_sec: AtomicI64,
-    offset_as_ms: AtomicI64,
+    offset_ms: AtomicI64,
 }
 
 impl ClockWithOffset {
     pub fn new(offset_ms: i64) -> Self {
         Self {
-            offset_as_sec: AtomicI64::new(offset_ms / 1000),
-            offset_as_ms: AtomicI64::new(offset_ms),
+            offset_ms: AtomicI64::new(offset_ms),
         }
     }
 
     pub fn update_offset(&self, offset_ms: i64) {
-        self.offset_as_sec
-            .store(offset_ms / 1000, Ordering::Release);
-        self.offset_as_ms.store(offset_ms, Ordering::Release);
+        self.offset_ms.store(offset_ms, Ordering::Release);
+    }
+
+    /// Updates the offset using the time observed in the network
+    pub fn sync_with(&self, chain_time_ms: u64) {
+        self.update_offset(chain_time_ms as i64 - now_ms_u64() as i64);
     }
 
     pub fn offset_ms(&self) -> i64 {
-        self.offset_as_ms.load(Ordering::Acquire)
+        self.offset_ms.load(Ordering::Acquire)
     }
 }
 
 impl Clock for ClockWithOffset {
     #[inline]
     fn now_sec_u64(&self) -> u64 {
-        self.offset_as_sec
-            .load(Ordering::Acquire)
-            .saturating_add(now_sec_u64() as i64)
-            .try_into()
-            .unwrap_or_default()
+        self.now_ms_u64() / 1000
     }
 
     #[inline]
     fn now_ms_f64(&self) -> f64 {
-        self.offset_as_ms.load(Ordering::Acquire) as f64 + now_ms_f64()
+        self.offset_ms() as f64 + now_ms_f64()
     }
 
     #[inline]
     fn now_ms_u64(&self) -> u64 {
-        self.offset_as_ms
-            .load(Ordering::Acquire)
+        self.offset_ms()
             .saturating_add(now_ms_u64() as i64)
             .try_into()
             .unwrap_or_default()
@@ -173,4 +173,16 @@ mod tests {
         println!("{}", now_ms_f64());
         println!("{}", now_ms_u64());
     }
+
+    #[test]
+    fn clock_with_offset() {
+        let clock = ClockWithOffset::new(0);
+
+        let chain_time_ms = now_ms_u64() + 60_000;
+        clock.sync_with(chain_time_ms);
+
+        let offset = clock.offset_ms();
+        assert!((59_000..=60_000).contains(&offset));
+        assert!(clock.now_sec_u64() >= chain_time_ms / 1000 - 1);
+    }
 }
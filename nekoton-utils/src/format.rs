@@ -0,0 +1,199 @@
+//! Locale-independent formatting of token amounts.
+//!
+//! All amounts are integers in the smallest units (e.g. nano EVER),
+//! so no precision is lost on large balances.
+
+/// Number of decimals of the native currency
+pub const EVER_DECIMALS: u8 = 9;
+
+/// Number of nano EVER in one EVER
+pub const ONE_EVER: u64 = 1_000_000_000;
+
+/// Max number of decimals which fits into `u128`
+pub const MAX_DECIMALS: u8 = 38;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Rounding {
+    /// Towards zero
+    Down,
+    /// Away from zero
+    Up,
+    /// To the nearest value, half away from zero
+    HalfUp,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct FormatOptions {
+    /// Max number of fractional digits. All decimals are shown if `None`
+    pub precision: Option<u8>,
+    /// Applied when the amount has more fractional digits than `precision`
+    pub rounding: Rounding,
+    /// Whether to remove trailing zeros of the fractional part
+    pub trim_trailing_zeros: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            precision: None,
+            rounding: Rounding::Down,
+            trim_trailing_zeros: true,
+        }
+    }
+}
+
+/// Formats nano EVER as a decimal string (e.g. `1.5`)
+pub fn format_evers(nano_evers: u64, options: FormatOptions) -> String {
+    format_amount(nano_evers as u128, EVER_DECIMALS, options)
+}
+
+/// Parses a decimal string (e.g. `1.5`) into nano EVER
+pub fn parse_evers(value: &str) -> Result<u64, ParseAmountError> {
+    let amount = parse_amount(value, EVER_DECIMALS)?;
+    u64::try_from(amount).map_err(|_| ParseAmountError::Overflow)
+}
+
+/// Formats an amount in the smallest units as a decimal string.
+///
+/// The decimal separator is always `.`, there are no group separators
+pub fn format_amount(value: u128, decimals: u8, options: FormatOptions) -> String {
+    let decimals = decimals.min(MAX_DECIMALS) as u32;
+    let precision = match options.precision {
+        Some(precision) => (precision as u32).min(decimals),
+        None => decimals,
+    };
+
+    let divisor = 10u128.pow(decimals - precision);
+    let (quotient, remainder) = (value / divisor, value % divisor);
+    let round_up = match options.rounding {
+        Rounding::Down => false,
+        Rounding::Up => remainder > 0,
+        Rounding::HalfUp => remainder > 0 && remainder >= divisor - remainder,
+    };
+    // NOTE: quotient is strictly less than `u128::MAX` when the remainder is not zero
+    let value = quotient + round_up as u128;
+
+    let scale = 10u128.pow(precision);
+    let (integer, fraction) = (value / scale, value % scale);
+
+    let mut result = integer.to_string();
+    if precision > 0 {
+        let fraction = format!("{fraction:0width$}", width = precision as usize);
+        let fraction = if options.trim_trailing_zeros {
+            fraction.trim_end_matches('0')
+        } else {
+            fraction.as_str()
+        };
+        if !fraction.is_empty() {
+            result.push('.');
+            result.push_str(fraction);
+        }
+    }
+    result
+}
+
+/// Parses a decimal string into the amount in the smallest units.
+///
+/// Only digits and a single `.` are accepted, so the result doesn't depend on locale
+pub fn parse_amount(value: &str, decimals: u8) -> Result<u128, ParseAmountError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(ParseAmountError::Empty);
+    }
+
+    let (integer, fraction) = match value.split_once('.') {
+        Some((integer, fraction)) => (integer, fraction),
+        None => (value, ""),
+    };
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(ParseAmountError::Empty);
+    }
+
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > decimals as usize {
+        return Err(ParseAmountError::TooManyDecimals);
+    }
+
+    let mut result: u128 = 0;
+    let fraction_digits = fraction
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(decimals as usize);
+    for digit in integer.bytes().chain(fraction_digits) {
+        if !digit.is_ascii_digit() {
+            return Err(ParseAmountError::InvalidCharacter);
+        }
+        result = result
+            .checked_mul(10)
+            .and_then(|result| result.checked_add((digit - b'0') as u128))
+            .ok_or(ParseAmountError::Overflow)?;
+    }
+
+    Ok(result)
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ParseAmountError {
+    #[error("Empty amount")]
+    Empty,
+    #[error("Invalid character")]
+    InvalidCharacter,
+    #[error("Too many decimals")]
+    TooManyDecimals,
+    #[error("Amount is too large")]
+    Overflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_and_parse() {
+        let default = FormatOptions::default();
+        assert_eq!(format_evers(0, default), "0");
+        assert_eq!(format_evers(1_500_000_000, default), "1.5");
+        assert_eq!(format_evers(1, default), "0.000000001");
+        assert_eq!(format_evers(u64::MAX, default), "18446744073.709551615");
+
+        let rounded = |rounding| FormatOptions {
+            precision: Some(2),
+            rounding,
+            trim_trailing_zeros: false,
+        };
+        assert_eq!(format_evers(1_995_000_000, rounded(Rounding::Down)), "1.99");
+        assert_eq!(
+            format_evers(1_995_000_000, rounded(Rounding::HalfUp)),
+            "2.00"
+        );
+        assert_eq!(format_evers(1_990_000_001, rounded(Rounding::Up)), "2.00");
+        assert_eq!(
+            format_evers(1_994_999_999, rounded(Rounding::HalfUp)),
+            "1.99"
+        );
+        assert_eq!(
+            format_amount(u128::MAX, 0, rounded(Rounding::Up)),
+            u128::MAX.to_string()
+        );
+
+        assert_eq!(parse_evers("1.5"), Ok(1_500_000_000));
+        assert_eq!(parse_evers(".5"), Ok(500_000_000));
+        assert_eq!(parse_evers("10"), Ok(10_000_000_000));
+        assert_eq!(parse_evers("0.0000000010"), Ok(1));
+        assert_eq!(parse_evers("18446744073.709551615"), Ok(u64::MAX));
+        assert_eq!(
+            parse_evers("18446744073.709551616"),
+            Err(ParseAmountError::Overflow)
+        );
+        assert_eq!(
+            parse_evers("0.0000000001"),
+            Err(ParseAmountError::TooManyDecimals)
+        );
+        assert_eq!(parse_evers("1,5"), Err(ParseAmountError::InvalidCharacter));
+        assert_eq!(
+            parse_evers("1.2.3"),
+            Err(ParseAmountError::InvalidCharacter)
+        );
+        assert_eq!(parse_evers("."), Err(ParseAmountError::Empty));
+    }
+}
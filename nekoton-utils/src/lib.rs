@@ -67,6 +67,7 @@ mod clock;
 mod crc;
 #[cfg(feature = "encryption")]
 mod encryption;
+pub mod format;
 mod serde_helpers;
 mod traits;
 mod transaction;
@@ -1,4 +1,3 @@
-use std::borrow::Cow;
 use std::convert::TryInto;
 use std::fmt;
 use std::str::FromStr;
@@ -10,6 +9,10 @@ use serde::{Deserialize, Serialize};
 use ton_block::MsgAddressInt;
 use ton_types::{Cell, SliceData, UInt256};
 
+/// Number which is serialized as string if it doesn't fit into the JS number.
+///
+/// With `string_numbers` feature it is always serialized as string
+/// in human readable formats
 struct StringOrNumber(u64);
 
 impl Serialize for StringOrNumber {
@@ -17,7 +20,10 @@ impl Serialize for StringOrNumber {
     where
         S: serde::Serializer,
     {
-        if self.0 <= 0x1fffffffffffffu64 || !serializer.is_human_readable() {
+        const MAX_SAFE_INTEGER: u64 = 0x1fffffffffffff;
+
+        let is_safe = !cfg!(feature = "string_numbers") && self.0 <= MAX_SAFE_INTEGER;
+        if is_safe || !serializer.is_human_readable() {
             serializer.serialize_u64(self.0)
         } else {
             serializer.serialize_str(&self.0.to_string())
@@ -30,18 +36,37 @@ impl<'de> Deserialize<'de> for StringOrNumber {
     where
         D: serde::Deserializer<'de>,
     {
-        #[derive(Deserialize)]
-        #[serde(untagged)]
-        enum Value<'a> {
-            String(#[serde(borrow)] Cow<'a, str>),
-            Number(u64),
+        struct StringOrNumberVisitor;
+
+        impl<'de> Visitor<'de> for StringOrNumberVisitor {
+            type Value = StringOrNumber;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("unsigned integer or string with it")
+            }
+
+            fn visit_u64<E: Error>(self, value: u64) -> Result<Self::Value, E> {
+                Ok(StringOrNumber(value))
+            }
+
+            fn visit_i64<E: Error>(self, value: i64) -> Result<Self::Value, E> {
+                value
+                    .try_into()
+                    .map(StringOrNumber)
+                    .map_err(|_| E::custom("Invalid number"))
+            }
+
+            fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
+                u64::from_str(value)
+                    .map(StringOrNumber)
+                    .map_err(|_| E::custom("Invalid number"))
+            }
         }
 
-        match Value::deserialize(deserializer)? {
-            Value::String(str) => u64::from_str(str.as_ref())
-                .map(Self)
-                .map_err(|_| D::Error::custom("Invalid number")),
-            Value::Number(value) => Ok(Self(value)),
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(StringOrNumberVisitor)
+        } else {
+            deserializer.deserialize_u64(StringOrNumberVisitor)
         }
     }
 }
@@ -378,6 +403,7 @@ pub mod serde_optional_vec_uint256 {
     }
 }
 
+/// Emits addresses in raw form, accepts both raw and packed forms
 pub mod serde_address {
     use super::*;
 
@@ -393,7 +419,31 @@ pub mod serde_address {
         D: serde::Deserializer<'de>,
     {
         let data = String::deserialize(deserializer)?;
-        MsgAddressInt::from_str(&data).map_err(|_| D::Error::custom("Invalid address"))
+        crate::repack_address(&data).map_err(|_| D::Error::custom("Invalid address"))
+    }
+}
+
+/// Emits addresses in packed bounceable url-safe base64 form, accepts both raw and packed forms.
+///
+/// Intended for API boundaries, persisted formats should use [`serde_address`]
+pub mod serde_packed_address {
+    use super::*;
+
+    pub fn serialize<S>(data: &MsgAddressInt, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::Error;
+
+        let packed = crate::pack_std_smc_addr(true, data, true).map_err(Error::custom)?;
+        serializer.serialize_str(&packed)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<MsgAddressInt, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        serde_address::deserialize(deserializer)
     }
 }
 
@@ -459,7 +509,7 @@ pub mod serde_vec_address {
             {
                 let mut vec = Vec::new();
                 while let Some(elem) = visitor.next_element::<String>()? {
-                    let item = MsgAddressInt::from_str(&elem)
+                    let item = crate::repack_address(&elem)
                         .map_err(|_| V::Error::custom("Invalid address"))?;
                     vec.push(item);
                 }
@@ -672,6 +722,35 @@ pub mod serde_cell {
     }
 }
 
+pub mod serde_optional_cell {
+    use super::*;
+
+    pub fn serialize<S>(data: &Option<Cell>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        #[derive(serde::Serialize)]
+        #[serde(transparent)]
+        struct Wrapper<'a>(#[serde(with = "serde_cell")] &'a Cell);
+
+        match data {
+            Some(data) => serializer.serialize_some(&Wrapper(data)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Cell>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        #[serde(transparent)]
+        struct Wrapper(#[serde(with = "serde_cell")] Cell);
+
+        Option::<Wrapper>::deserialize(deserializer).map(|wrapper| wrapper.map(|data| data.0))
+    }
+}
+
 pub mod serde_ton_block {
     use ton_block::{Deserializable, Serializable};
 
@@ -881,6 +960,24 @@ mod test {
         assert_eq!(bincode::deserialize::<Test>(&data).unwrap(), test);
     }
 
+    #[test]
+    fn test_string_or_number() {
+        #[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
+        struct Test(#[serde(with = "serde_u64")] u64);
+
+        assert_eq!(serde_json::from_str::<Test>("123").unwrap(), Test(123));
+        assert_eq!(serde_json::from_str::<Test>(r#""123""#).unwrap(), Test(123));
+        assert!(serde_json::from_str::<Test>("-1").is_err());
+        assert!(serde_json::from_str::<Test>(r#""abc""#).is_err());
+        assert!(serde_json::from_str::<Test>("1.5").is_err());
+
+        let test = Test(u64::MAX);
+        let data = serde_json::to_string(&test).unwrap();
+        assert_eq!(serde_json::from_str::<Test>(&data).unwrap(), test);
+        let data = bincode::serialize(&test).unwrap();
+        assert_eq!(bincode::deserialize::<Test>(&data).unwrap(), test);
+    }
+
     #[test]
     fn test_optional() {
         #[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
@@ -930,4 +1027,47 @@ mod test {
         let deserialized: Test = serde_json::from_str(&serialized).unwrap();
         assert_eq!(deserialized.field, None);
     }
+
+    #[test]
+    fn test_optional_cell() {
+        #[derive(Serialize, Deserialize)]
+        struct Test {
+            #[serde(with = "serde_optional_cell")]
+            field: Option<Cell>,
+        }
+
+        let mut builder = ton_types::BuilderData::new();
+        builder.append_u32(123).unwrap();
+        let cell = builder.into_cell().unwrap();
+
+        let serialized = serde_json::to_string(&Test {
+            field: Some(cell.clone()),
+        })
+        .unwrap();
+        let deserialized: Test = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.field, Some(cell));
+
+        let serialized = serde_json::to_string(&Test { field: None }).unwrap();
+        assert_eq!(serialized, r#"{"field":null}"#);
+        let deserialized: Test = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.field, None);
+    }
+
+    #[test]
+    fn test_address_forms() {
+        #[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
+        struct Raw(#[serde(with = "serde_address")] MsgAddressInt);
+        #[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
+        struct Packed(#[serde(with = "serde_packed_address")] MsgAddressInt);
+
+        let raw = r#""0:02e3f2284e68a8106b823ab9f2404f33cc43fccad8e1de835bdd96789254686c""#;
+        let packed = r#""EQAC4_IoTmioEGuCOrnyQE8zzEP8ytjh3oNb3ZZ4klRobFz0""#;
+
+        let address = serde_json::from_str::<Raw>(packed).unwrap().0;
+        assert_eq!(serde_json::from_str::<Raw>(raw).unwrap().0, address);
+        assert_eq!(serde_json::to_string(&Raw(address.clone())).unwrap(), raw);
+
+        assert_eq!(serde_json::from_str::<Packed>(raw).unwrap().0, address);
+        assert_eq!(serde_json::to_string(&Packed(address)).unwrap(), packed);
+    }
 }
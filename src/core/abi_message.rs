@@ -0,0 +1,143 @@
+use std::borrow::Cow;
+
+use anyhow::Result;
+use ed25519_dalek::PublicKey;
+use ton_block::MsgAddressInt;
+use ton_types::SliceData;
+
+use nekoton_abi::parse_abi_tokens;
+use nekoton_utils::Clock;
+
+use super::models::Expiration;
+use super::utils::make_labs_unsigned_message;
+use crate::crypto::UnsignedMessage;
+
+/// Prepares an external message for the method of the ABI, loaded at runtime.
+///
+/// `tokens` is a JSON object with the method inputs (see [`parse_abi_tokens`])
+pub fn create_external_message(
+    clock: &dyn Clock,
+    dst: MsgAddressInt,
+    abi: &str,
+    method: &str,
+    tokens: serde_json::Value,
+    public_key: &PublicKey,
+    expiration: Expiration,
+) -> Result<Box<dyn UnsignedMessage>> {
+    let (function, input) = prepare_call(abi, method, tokens)?;
+
+    let message = ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+        dst,
+        ..Default::default()
+    });
+
+    make_labs_unsigned_message(
+        clock,
+        message,
+        expiration,
+        public_key,
+        Cow::Owned(function),
+        input,
+    )
+}
+
+/// Encodes the body of an internal message for the method of the ABI, loaded at runtime
+pub fn create_internal_message_body(
+    abi: &str,
+    method: &str,
+    tokens: serde_json::Value,
+) -> Result<SliceData> {
+    let (function, input) = prepare_call(abi, method, tokens)?;
+    function
+        .encode_internal_input(&input)
+        .and_then(SliceData::load_builder)
+}
+
+fn prepare_call(
+    abi: &str,
+    method: &str,
+    tokens: serde_json::Value,
+) -> Result<(ton_abi::Function, Vec<ton_abi::Token>)> {
+    let contract = ton_abi::Contract::load(abi).map_err(|_| AbiMessageError::InvalidAbi)?;
+    let function = contract
+        .functions
+        .get(method)
+        .cloned()
+        .ok_or(AbiMessageError::MethodNotFound)?;
+    let input = parse_abi_tokens(&function.inputs, tokens)?;
+    Ok((function, input))
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone)]
+enum AbiMessageError {
+    #[error("Invalid ABI")]
+    InvalidAbi,
+    #[error("Method not found")]
+    MethodNotFound,
+}
+
+#[cfg(test)]
+mod tests {
+    use nekoton_abi::read_function_id;
+    use nekoton_utils::ConstClock;
+
+    use super::*;
+
+    const ABI: &str = r#"{
+        "ABI version": 2,
+        "version": "2.2",
+        "header": ["pubkey", "time", "expire"],
+        "functions": [
+            {
+                "name": "setValue",
+                "inputs": [{ "name": "value", "type": "uint128" }],
+                "outputs": []
+            }
+        ],
+        "data": [],
+        "events": [],
+        "fields": []
+    }"#;
+
+    #[test]
+    fn runtime_abi_messages() {
+        let tokens = serde_json::json!({ "value": "123" });
+
+        let body = create_internal_message_body(ABI, "setValue", tokens.clone()).unwrap();
+        let function_id = read_function_id(&body).unwrap();
+
+        let contract = ton_abi::Contract::load(ABI).unwrap();
+        assert_eq!(function_id, contract.function("setValue").unwrap().input_id);
+
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public_key = PublicKey::from(&secret);
+        let message = create_external_message(
+            &ConstClock::from_secs(1650000000),
+            MsgAddressInt::default(),
+            ABI,
+            "setValue",
+            tokens,
+            &public_key,
+            Expiration::Timeout(60),
+        )
+        .unwrap();
+        assert_eq!(message.expire_at(), 1650000060);
+
+        let mut message = create_external_message(
+            &ConstClock::from_secs(1650000000),
+            MsgAddressInt::default(),
+            ABI,
+            "setValue",
+            serde_json::json!({ "value": "123" }),
+            &public_key,
+            Expiration::Timestamp(1650000600),
+        )
+        .unwrap();
+        let hash = message.hash().to_vec();
+        message.refresh_timeout(&ConstClock::from_secs(1650000100));
+        assert_eq!(message.expire_at(), 1650000600);
+        assert_ne!(message.hash(), hash.as_slice());
+
+        assert!(create_internal_message_body(ABI, "unknown", serde_json::json!({})).is_err());
+    }
+}
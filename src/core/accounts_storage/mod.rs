@@ -1,61 +1,82 @@
-use std::collections::btree_map::{self, BTreeMap};
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::sync::Arc;
 
 use anyhow::Result;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use tokio::sync::{RwLock, RwLockReadGuard};
+use tokio::sync::{Mutex, RwLock, RwLockReadGuard};
 use ton_block::MsgAddressInt;
 
 use nekoton_utils::*;
 
 use crate::core::ton_wallet;
-use crate::external::Storage;
+use crate::external::{JournaledStorage, Storage};
 
 pub const ACCOUNTS_STORAGE_KEY: &str = "__core__accounts";
+pub const SPENDINGS_STORAGE_KEY: &str = "__core__spendings";
 
 const DEFAULT_NETWORK_GROUP: &str = "mainnet";
 
 pub struct AccountsStorage {
     storage: Arc<dyn Storage>,
     accounts: RwLock<AssetsMap>,
+    spendings: Mutex<SpendingsMap>,
+    handler: Option<Arc<dyn AccountsStorageHandler>>,
 }
 
+/// Accounts by id
 type AssetsMap = BTreeMap<String, AssetsList>;
 
+/// Outgoing transfers during the current day, by account id
+type SpendingsMap = HashMap<String, DailySpendings>;
+
 impl AccountsStorage {
     /// Decodes data as accounts storage
     pub fn verify(data: &str) -> Result<()> {
         parse_assets_map(data).map(|_| ())
     }
 
-    /// Loads full accounts storage state. Fails on invalid data
+    /// Loads full accounts storage state. Fails on invalid data.
+    ///
+    /// All writes are journaled, so an interrupted write is finished on the next load
     pub async fn load(storage: Arc<dyn Storage>) -> Result<Self> {
-        let data = match storage.get(ACCOUNTS_STORAGE_KEY).await? {
-            Some(data) => parse_assets_map(&data)?,
-            None => Default::default(),
-        };
+        let storage: Arc<dyn Storage> = Arc::new(JournaledStorage::new(storage));
+
+        let data = load_assets_map(storage.as_ref()).await?;
+        let spendings = load_spendings(storage.as_ref()).await;
 
         Ok(Self {
             storage,
             accounts: RwLock::new(data),
+            spendings: Mutex::new(spendings),
+            handler: None,
         })
     }
 
     /// Loads full accounts storage state. Returns empty state on invalid data
     pub async fn load_unchecked(storage: Arc<dyn Storage>) -> Self {
-        Self::load(storage.clone()).await.unwrap_or_else(|_| Self {
+        let storage: Arc<dyn Storage> = Arc::new(JournaledStorage::new(storage));
+
+        let data = load_assets_map(storage.as_ref()).await.unwrap_or_default();
+        let spendings = load_spendings(storage.as_ref()).await;
+
+        Self {
             storage,
-            accounts: Default::default(),
-        })
+            accounts: RwLock::new(data),
+            spendings: Mutex::new(spendings),
+            handler: None,
+        }
     }
 
-    pub async fn reload(&self) -> Result<()> {
-        let data = match self.storage.get(ACCOUNTS_STORAGE_KEY).await? {
-            Some(data) => parse_assets_map(&data)?,
-            None => Default::default(),
-        };
+    /// Sets the handler which is notified about saved changes
+    pub fn with_handler(mut self, handler: Arc<dyn AccountsStorageHandler>) -> Self {
+        self.handler = Some(handler);
+        self
+    }
 
+    pub async fn reload(&self) -> Result<()> {
+        let data = load_assets_map(self.storage.as_ref()).await?;
         *self.accounts.write().await = data;
 
         Ok(())
@@ -63,6 +84,9 @@ impl AccountsStorage {
 
     /// Add account. It can later be fetched by ton wallet address
     ///
+    /// The same address can be added several times with different keys
+    /// or network groups (e.g. multisig wallet for each of its custodians).
+    ///
     /// **NOTE:** If you want to add multiple accounts use [`AccountsStorage::add_accounts`].
     /// Storage is not atomic, so if you add multiple accounts with this method in parallel,
     /// it will overwrite each other.
@@ -76,26 +100,26 @@ impl AccountsStorage {
                 new_account.workchain,
             )
         });
-        let key = address.to_string();
+        if find_duplicate(&accounts, &address, &new_account).is_some() {
+            return Err(AccountsStorageError::AccountAlreadyExists.into());
+        }
 
-        let assets_list = match accounts.entry(key.clone()) {
-            btree_map::Entry::Occupied(_) => {
-                return Err(AccountsStorageError::AccountAlreadyExists.into())
-            }
-            btree_map::Entry::Vacant(entry) => entry
-                .insert(AssetsList {
-                    name: new_account.name,
-                    ton_wallet: TonWalletAsset {
-                        address,
-                        public_key: new_account.public_key,
-                        contract: new_account.contract,
-                    },
-                    additional_assets: Default::default(),
-                })
-                .clone(),
+        let assets_list = AssetsList {
+            id: generate_account_id(),
+            name: new_account.name,
+            ton_wallet: TonWalletAsset {
+                address,
+                public_key: new_account.public_key,
+                contract: new_account.contract,
+            },
+            network_group: new_account.network_group,
+            additional_assets: Default::default(),
+            spending_policy: None,
         };
+        accounts.insert(assets_list.id.clone(), assets_list.clone());
 
         self.save(&accounts).await?;
+        self.notify_updated(std::slice::from_ref(&assets_list));
         Ok(assets_list)
     }
 
@@ -115,34 +139,37 @@ impl AccountsStorage {
                     new_account.workchain,
                 )
             });
-            let key = address.to_string();
-
-            let assets_list = match accounts.entry(key.clone()) {
-                btree_map::Entry::Occupied(_) => continue,
-                btree_map::Entry::Vacant(entry) => entry
-                    .insert(AssetsList {
-                        name: new_account.name,
-                        ton_wallet: TonWalletAsset {
-                            address,
-                            public_key: new_account.public_key,
-                            contract: new_account.contract,
-                        },
-                        additional_assets: Default::default(),
-                    })
-                    .clone(),
+            if find_duplicate(accounts, &address, &new_account).is_some() {
+                continue;
+            }
+
+            let assets_list = AssetsList {
+                id: generate_account_id(),
+                name: new_account.name,
+                ton_wallet: TonWalletAsset {
+                    address,
+                    public_key: new_account.public_key,
+                    contract: new_account.contract,
+                },
+                network_group: new_account.network_group,
+                additional_assets: Default::default(),
+                spending_policy: None,
             };
+            accounts.insert(assets_list.id.clone(), assets_list.clone());
 
             created_accounts.push(assets_list);
         }
 
         self.save(accounts).await?;
+        self.notify_updated(&created_accounts);
         Ok(created_accounts)
     }
 
     pub async fn rename_account(&self, account: &str, name: String) -> Result<AssetsList> {
         let assets = &mut *self.accounts.write().await;
 
-        let (entry, should_save) = match assets.get_mut(account) {
+        let key = normalize_account(assets, account);
+        let (entry, should_save) = match assets.get_mut(&key) {
             Some(entry) => {
                 let should_save = entry.name != name;
                 entry.name = name;
@@ -153,10 +180,113 @@ impl AccountsStorage {
 
         if should_save {
             self.save(assets).await?;
+            self.notify_updated(std::slice::from_ref(&entry));
+        }
+        Ok(entry)
+    }
+
+    /// Sets or removes the spending policy of the account
+    pub async fn set_spending_policy(
+        &self,
+        account: &str,
+        spending_policy: Option<SpendingPolicy>,
+    ) -> Result<AssetsList> {
+        let assets = &mut *self.accounts.write().await;
+
+        let key = normalize_account(assets, account);
+        let (entry, should_save) = match assets.get_mut(&key) {
+            Some(entry) => {
+                let should_save = entry.spending_policy != spending_policy;
+                entry.spending_policy = spending_policy;
+                (entry.clone(), should_save)
+            }
+            None => return Err(AccountsStorageError::AccountNotFound.into()),
+        };
+
+        if should_save {
+            self.save(assets).await?;
+            self.notify_updated(std::slice::from_ref(&entry));
         }
         Ok(entry)
     }
 
+    /// Checks the transfer against the spending policy of the account
+    /// without recording it.
+    ///
+    /// * `account` - account id (or address)
+    /// * `day` - number of the current day since the unix epoch (UTC)
+    pub async fn check_spending(
+        &self,
+        account: &str,
+        recipient: Option<&MsgAddressInt>,
+        amount: Option<u64>,
+        day: u32,
+    ) -> Result<(), SpendingPolicyViolation> {
+        let accounts = self.accounts.read().await;
+        let (key, spending_policy) = match find_spending_policy(&accounts, account) {
+            Some(item) => item,
+            None => return Ok(()),
+        };
+
+        let spent_today = spent_today(&*self.spendings.lock().await, &key, day);
+        spending_policy.check(recipient, amount, spent_today)
+    }
+
+    /// Checks the transfer against the spending policy of the account and
+    /// adds its amount to the daily spendings, if it is allowed.
+    ///
+    /// Check and update are done atomically, so concurrent transfers
+    /// can't exceed the daily limit together.
+    ///
+    /// Nothing is recorded for the accounts without spending policy.
+    ///
+    /// * `account` - account id (or address)
+    /// * `day` - number of the current day since the unix epoch (UTC)
+    pub async fn reserve_spending(
+        &self,
+        account: &str,
+        recipient: Option<&MsgAddressInt>,
+        amount: Option<u64>,
+        day: u32,
+    ) -> Result<(), SpendingPolicyViolation> {
+        let accounts = self.accounts.read().await;
+        let (key, spending_policy) = match find_spending_policy(&accounts, account) {
+            Some(item) => item,
+            None => return Ok(()),
+        };
+
+        let mut spendings = self.spendings.lock().await;
+        spending_policy.check(recipient, amount, spent_today(&spendings, &key, day))?;
+
+        if let Some(amount) = amount {
+            let entry = spendings.entry(key).or_default();
+            if entry.day != day {
+                *entry = DailySpendings { day, amount: 0 };
+            }
+            entry.amount = entry.amount.saturating_add(amount);
+            self.save_spendings(&spendings);
+        }
+
+        Ok(())
+    }
+
+    /// Reverts [`AccountsStorage::reserve_spending`] (e.g. when the message was not signed)
+    pub async fn release_spending(&self, account: &str, amount: Option<u64>, day: u32) {
+        let amount = match amount {
+            Some(amount) => amount,
+            None => return,
+        };
+
+        let key = normalize_account(&*self.accounts.read().await, account);
+        let mut spendings = self.spendings.lock().await;
+        if let Some(entry) = spendings.get_mut(&key) {
+            if entry.day == day {
+                entry.amount = entry.amount.saturating_sub(amount);
+                self.save_spendings(&spendings);
+            }
+        }
+    }
+
     pub async fn add_token_wallet(
         &self,
         account: &str,
@@ -167,7 +297,8 @@ impl AccountsStorage {
 
         let assets = &mut *self.accounts.write().await;
 
-        let (entry, should_save) = match assets.get_mut(account) {
+        let key = normalize_account(assets, account);
+        let (entry, should_save) = match assets.get_mut(&key) {
             Some(entry) => {
                 let should_save = match entry.additional_assets.entry(network_group.to_owned()) {
                     hash_map::Entry::Occupied(mut entry) => {
@@ -186,6 +317,7 @@ impl AccountsStorage {
 
         if should_save {
             self.save(assets).await?;
+            self.notify_updated(std::slice::from_ref(&entry));
         }
         Ok(entry)
     }
@@ -198,7 +330,8 @@ impl AccountsStorage {
     ) -> Result<AssetsList> {
         let assets = &mut *self.accounts.write().await;
 
-        let (entry, should_save) = match assets.get_mut(account) {
+        let key = normalize_account(assets, account);
+        let (entry, should_save) = match assets.get_mut(&key) {
             Some(entry) => {
                 let additional_assets = match entry.additional_assets.get_mut(network_group) {
                     Some(additional_assets) => additional_assets,
@@ -213,6 +346,7 @@ impl AccountsStorage {
 
         if should_save {
             self.save(assets).await?;
+            self.notify_updated(std::slice::from_ref(&entry));
         }
         Ok(entry)
     }
@@ -224,9 +358,13 @@ impl AccountsStorage {
     /// it will overwrite each other.
     pub async fn remove_account(&self, account: &str) -> Result<Option<AssetsList>> {
         let assets = &mut *self.accounts.write().await;
-        let result = assets.remove(account);
+        let key = normalize_account(assets, account);
+        let result = assets.remove(&key);
 
         self.save(assets).await?;
+        if let Some(removed) = &result {
+            self.notify_removed(std::slice::from_ref(removed));
+        }
         Ok(result)
     }
 
@@ -239,10 +377,12 @@ impl AccountsStorage {
 
         let mut result = Vec::new();
         for account in accounts {
-            result.extend(assets.remove(account).into_iter());
+            let key = normalize_account(assets, account);
+            result.extend(assets.remove(&key).into_iter());
         }
 
         self.save(assets).await?;
+        self.notify_removed(&result);
         Ok(result)
     }
 
@@ -250,10 +390,67 @@ impl AccountsStorage {
     pub async fn clear(&self) -> Result<()> {
         self.storage.remove(ACCOUNTS_STORAGE_KEY).await?;
 
+        self.spendings.lock().await.clear();
+        self.storage.remove_unchecked(SPENDINGS_STORAGE_KEY);
+
         let assets = &mut *self.accounts.write().await;
-        assets.clear();
+        let removed = std::mem::take(assets).into_values().collect::<Vec<_>>();
+
+        self.storage.remove(ACCOUNTS_STORAGE_KEY).await?;
+        self.notify_removed(&removed);
+        Ok(())
+    }
 
-        self.storage.remove(ACCOUNTS_STORAGE_KEY).await
+    pub async fn get_account_by_id(&self, id: &str) -> Option<AssetsList> {
+        self.accounts.read().await.get(id).cloned()
+    }
+
+    /// Returns the first account with the specified address.
+    ///
+    /// See [`AccountsStorage::get_accounts_by_address`]
+    pub async fn get_account_by_address(&self, address: &MsgAddressInt) -> Option<AssetsList> {
+        find_by_address(&*self.accounts.read().await, address).cloned()
+    }
+
+    /// Returns all accounts with the specified address (e.g. for different keys or networks)
+    pub async fn get_accounts_by_address(&self, address: &MsgAddressInt) -> Vec<AssetsList> {
+        self.accounts
+            .read()
+            .await
+            .values()
+            .filter(|assets| &assets.ton_wallet.address == address)
+            .cloned()
+            .collect()
+    }
+
+    /// Re-keys data of other stores which reference accounts by address
+    /// (e.g. transaction notes or contacts) by account ids.
+    ///
+    /// Keys which are not addresses of the stored accounts are left as is
+    pub async fn migrate_account_refs<T>(&self, refs: HashMap<String, T>) -> HashMap<String, T> {
+        let accounts = self.accounts.read().await;
+        refs.into_iter()
+            .map(|(key, value)| {
+                let id = repack_address(&key)
+                    .ok()
+                    .and_then(|address| find_by_address(&accounts, &address))
+                    .map(|assets| assets.id.clone());
+                (id.unwrap_or(key), value)
+            })
+            .collect()
+    }
+
+    pub async fn get_accounts_by_public_key(
+        &self,
+        public_key: &ed25519_dalek::PublicKey,
+    ) -> Vec<AssetsList> {
+        self.accounts
+            .read()
+            .await
+            .values()
+            .filter(|assets| &assets.ton_wallet.public_key == public_key)
+            .cloned()
+            .collect()
     }
 
     /// Returns handler to the inner data
@@ -261,6 +458,22 @@ impl AccountsStorage {
         StoredAccountsData(self.accounts.read().await)
     }
 
+    fn notify_updated(&self, accounts: &[AssetsList]) {
+        if let Some(handler) = &self.handler {
+            for account in accounts {
+                handler.on_account_updated(account);
+            }
+        }
+    }
+
+    fn notify_removed(&self, accounts: &[AssetsList]) {
+        if let Some(handler) = &self.handler {
+            for account in accounts {
+                handler.on_account_removed(account);
+            }
+        }
+    }
+
     async fn save(&self, assets: &AssetsMap) -> Result<()> {
         struct StoredAssetsMap<'a>(&'a AssetsMap);
 
@@ -289,6 +502,39 @@ impl AccountsStorage {
         .trust_me();
         self.storage.set(ACCOUNTS_STORAGE_KEY, &data).await
     }
+
+    fn save_spendings(&self, spendings: &SpendingsMap) {
+        let data = serde_json::to_string(spendings).trust_me();
+        self.storage.set_unchecked(SPENDINGS_STORAGE_KEY, &data);
+    }
+}
+
+async fn load_assets_map(storage: &dyn Storage) -> Result<AssetsMap> {
+    match storage.get(ACCOUNTS_STORAGE_KEY).await? {
+        Some(data) => parse_assets_map(&data),
+        None => Ok(Default::default()),
+    }
+}
+
+async fn load_spendings(storage: &dyn Storage) -> SpendingsMap {
+    match storage.get(SPENDINGS_STORAGE_KEY).await {
+        Ok(Some(data)) => serde_json::from_str(&data).unwrap_or_default(),
+        _ => Default::default(),
+    }
+}
+
+fn spent_today(spendings: &SpendingsMap, account: &str, day: u32) -> u64 {
+    match spendings.get(account) {
+        Some(spent) if spent.day == day => spent.amount,
+        _ => 0,
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct DailySpendings {
+    day: u32,
+    #[serde(with = "serde_u64")]
+    amount: u64,
 }
 
 fn parse_assets_map(data: &str) -> Result<AssetsMap> {
@@ -301,13 +547,15 @@ fn parse_assets_map(data: &str) -> Result<AssetsMap> {
         {
             use serde::de::Error;
 
+            // NOTE: accounts were stored by address before, so they are
+            // always re-keyed by id (which is generated for the legacy accounts)
             let accounts = HashMap::<String, String>::deserialize(deserializer)?;
             let accounts = accounts
-                .into_iter()
-                .map(|(address, assets)| {
+                .into_values()
+                .map(|assets| {
                     let assets = serde_json::from_str::<AssetsList>(&assets)
                         .map_err(|_| D::Error::custom("Failed to deserialize AssetsList"))?;
-                    Ok((address, assets))
+                    Ok((assets.id.clone(), assets))
                 })
                 .collect::<Result<_, _>>()?;
             Ok(StoredAssetsMap(accounts))
@@ -322,6 +570,16 @@ fn parse_assets_map(data: &str) -> Result<AssetsMap> {
     Ok(serde_json::from_str::<StoredData>(data)?.assets.0)
 }
 
+pub trait AccountsStorageHandler: Send + Sync {
+    /// Called after the new or changed account was saved.
+    ///
+    /// NOTE: storage is locked during this call, so it must not be accessed here
+    fn on_account_updated(&self, account: &AssetsList);
+
+    /// Called after the account was removed
+    fn on_account_removed(&self, account: &AssetsList);
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AccountToAdd {
@@ -335,26 +593,128 @@ pub struct AccountToAdd {
         skip_serializing_if = "Option::is_none"
     )]
     pub explicit_address: Option<MsgAddressInt>,
+    /// Network group of the account. Account is shown in all networks if not specified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_group: Option<NetworkGroup>,
 }
 
 #[derive(Debug)]
 pub struct StoredAccountsData<'a>(RwLockReadGuard<'a, AssetsMap>);
 
 impl<'a> StoredAccountsData<'a> {
-    pub fn accounts(&self) -> &AssetsMap {
+    /// Accounts by address.
+    ///
+    /// If the address was added several times (e.g. with different keys),
+    /// only the first account by id is returned. See [`StoredAccountsData::accounts_by_id`]
+    pub fn accounts(&self) -> BTreeMap<String, &AssetsList> {
+        let mut accounts = BTreeMap::new();
+        for assets in self.0.values() {
+            accounts
+                .entry(assets.ton_wallet.address.to_string())
+                .or_insert(assets);
+        }
+        accounts
+    }
+
+    /// All accounts by id
+    pub fn accounts_by_id(&self) -> &BTreeMap<String, AssetsList> {
         &self.0
     }
 }
 
 pub type NetworkGroup = String;
 
+/// Accounts are stored by id, but raw and packed addresses are also accepted
+fn normalize_account(assets: &AssetsMap, account: &str) -> String {
+    if assets.contains_key(account) {
+        return account.to_owned();
+    }
+
+    repack_address(account)
+        .ok()
+        .and_then(|address| find_by_address(assets, &address))
+        .map(|assets| assets.id.clone())
+        .unwrap_or_else(|| account.to_owned())
+}
+
+fn find_by_address<'a>(assets: &'a AssetsMap, address: &MsgAddressInt) -> Option<&'a AssetsList> {
+    assets
+        .values()
+        .find(|assets| &assets.ton_wallet.address == address)
+}
+
+/// Returns the account id and its spending policy, if it is set
+fn find_spending_policy<'a>(
+    assets: &'a AssetsMap,
+    account: &str,
+) -> Option<(String, &'a SpendingPolicy)> {
+    let key = normalize_account(assets, account);
+    let spending_policy = assets.get(&key)?.spending_policy.as_ref()?;
+    Some((key, spending_policy))
+}
+
+/// Finds the account with the same address, key and network group
+fn find_duplicate<'a>(
+    assets: &'a AssetsMap,
+    address: &MsgAddressInt,
+    new_account: &AccountToAdd,
+) -> Option<&'a AssetsList> {
+    assets.values().find(|assets| {
+        &assets.ton_wallet.address == address
+            && assets.ton_wallet.public_key == new_account.public_key
+            && assets.network_group == new_account.network_group
+    })
+}
+
+/// Random UUID (v4)
+fn generate_account_id() -> String {
+    let mut bytes = rand::thread_rng().gen::<[u8; 16]>();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    format_uuid(&bytes)
+}
+
+/// Stable id for the accounts which were stored without it
+fn legacy_account_id(address: &MsgAddressInt) -> String {
+    use sha2::Digest;
+
+    let hash = sha2::Sha256::digest(address.to_string().as_bytes());
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    format_uuid(&bytes)
+}
+
+fn format_uuid(bytes: &[u8; 16]) -> String {
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct AssetsList {
+    /// Stable account id, which doesn't change on rename
+    pub id: String,
     pub name: String,
     pub ton_wallet: TonWalletAsset,
 
+    /// Network group of the account. Account is shown in all networks if not specified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_group: Option<NetworkGroup>,
+
     /// Additional assets, grouped by network group
     pub additional_assets: HashMap<NetworkGroup, AdditionalAssets>,
+
+    /// Client-side restrictions for outgoing transfers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spending_policy: Option<SpendingPolicy>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -420,9 +780,15 @@ impl<'de> serde::Deserialize<'de> for AssetsList {
                 depools: Vec<DePoolAsset>,
             },
             New {
+                #[serde(default)]
+                id: Option<String>,
                 name: String,
                 ton_wallet: TonWalletAsset,
+                #[serde(default)]
+                network_group: Option<NetworkGroup>,
                 additional_assets: HashMap<String, AdditionalAssets>,
+                #[serde(default)]
+                spending_policy: Option<SpendingPolicy>,
             },
         }
 
@@ -443,19 +809,28 @@ impl<'de> serde::Deserialize<'de> for AssetsList {
                 );
 
                 AssetsList {
+                    id: legacy_account_id(&ton_wallet.address),
                     name,
                     ton_wallet,
+                    network_group: None,
                     additional_assets,
+                    spending_policy: None,
                 }
             }
             ParsedAssetsList::New {
+                id,
                 name,
                 ton_wallet,
+                network_group,
                 additional_assets,
+                spending_policy,
             } => AssetsList {
+                id: id.unwrap_or_else(|| legacy_account_id(&ton_wallet.address)),
                 name,
                 ton_wallet,
+                network_group,
                 additional_assets,
+                spending_policy,
             },
         })
     }
@@ -482,6 +857,79 @@ pub struct DePoolAsset {
     pub address: MsgAddressInt,
 }
 
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpendingPolicy {
+    /// Max total amount of outgoing transfers during one day (UTC)
+    #[serde(
+        default,
+        with = "serde_optional_u64",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub daily_limit: Option<u64>,
+    /// Max amount of a single outgoing transfer
+    #[serde(
+        default,
+        with = "serde_optional_u64",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub transaction_limit: Option<u64>,
+    /// Allowed recipients. Any recipient is allowed if empty
+    #[serde(
+        default,
+        with = "serde_vec_address",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub whitelist: Vec<MsgAddressInt>,
+}
+
+impl SpendingPolicy {
+    /// # Arguments
+    ///
+    /// * `recipient` - transfer destination, if known
+    /// * `amount` - transfer amount, if known
+    /// * `spent_today` - total amount of transfers during the current day
+    pub fn check(
+        &self,
+        recipient: Option<&MsgAddressInt>,
+        amount: Option<u64>,
+        spent_today: u64,
+    ) -> Result<(), SpendingPolicyViolation> {
+        if !self.whitelist.is_empty() {
+            match recipient {
+                Some(recipient) if self.whitelist.contains(recipient) => {}
+                _ => return Err(SpendingPolicyViolation::RecipientNotWhitelisted),
+            }
+        }
+
+        if self.daily_limit.is_none() && self.transaction_limit.is_none() {
+            return Ok(());
+        }
+
+        let amount = amount.ok_or(SpendingPolicyViolation::UnknownAmount)?;
+        if matches!(self.transaction_limit, Some(limit) if amount > limit) {
+            return Err(SpendingPolicyViolation::TransactionLimitExceeded);
+        }
+        if matches!(self.daily_limit, Some(limit) if spent_today.saturating_add(amount) > limit) {
+            return Err(SpendingPolicyViolation::DailyLimitExceeded);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SpendingPolicyViolation {
+    #[error("Recipient is not whitelisted")]
+    RecipientNotWhitelisted,
+    #[error("Transfer amount is unknown")]
+    UnknownAmount,
+    #[error("Transaction limit exceeded")]
+    TransactionLimitExceeded,
+    #[error("Daily limit exceeded")]
+    DailyLimitExceeded,
+}
+
 #[derive(thiserror::Error, Debug)]
 enum AccountsStorageError {
     #[error("Account already exists")]
@@ -489,3 +937,296 @@ enum AccountsStorageError {
     #[error("Account not found")]
     AccountNotFound,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryStorage;
+
+    fn public_key() -> ed25519_dalek::PublicKey {
+        ed25519_dalek::PublicKey::from(&ed25519_dalek::SecretKey::from_bytes(&[1; 32]).unwrap())
+    }
+
+    #[tokio::test]
+    async fn migrate_legacy_accounts() {
+        let public_key = public_key();
+        let address = ton_wallet::compute_address(&public_key, ton_wallet::WalletType::WalletV3, 0);
+
+        // Account stored by address and without id
+        let legacy = serde_json::json!({
+            "name": "Main",
+            "ton_wallet": TonWalletAsset {
+                address: address.clone(),
+                public_key,
+                contract: ton_wallet::WalletType::WalletV3,
+            },
+            "additional_assets": {},
+        });
+        let mut assets = HashMap::new();
+        assets.insert(address.to_string(), legacy.to_string());
+
+        let storage = Arc::new(MemoryStorage::default());
+        storage
+            .set(
+                ACCOUNTS_STORAGE_KEY,
+                &serde_json::json!({ "assets": assets }).to_string(),
+            )
+            .await
+            .unwrap();
+
+        let accounts_storage = AccountsStorage::load(storage.clone()).await.unwrap();
+        let id = legacy_account_id(&address);
+        assert!(accounts_storage
+            .stored_data()
+            .await
+            .accounts_by_id()
+            .contains_key(&id));
+        assert_eq!(
+            accounts_storage
+                .get_account_by_address(&address)
+                .await
+                .unwrap()
+                .id,
+            id
+        );
+
+        // References by address are re-keyed by id
+        let mut notes = HashMap::new();
+        notes.insert(address.to_string(), "note");
+        notes.insert("unknown".to_owned(), "other");
+        let notes = accounts_storage.migrate_account_refs(notes).await;
+        assert_eq!(notes.get(&id), Some(&"note"));
+        assert_eq!(notes.get("unknown"), Some(&"other"));
+
+        // Accounts are saved by id and the id doesn't change after reload
+        accounts_storage
+            .rename_account(&address.to_string(), "Renamed".to_owned())
+            .await
+            .unwrap();
+        let reloaded = AccountsStorage::load(storage).await.unwrap();
+        let account = reloaded.get_account_by_id(&id).await.unwrap();
+        assert_eq!(account.name, "Renamed");
+        assert_eq!(account.ton_wallet.address, address);
+    }
+
+    #[tokio::test]
+    async fn accounts_round_trip() {
+        let storage = Arc::new(MemoryStorage::default());
+        let accounts_storage = AccountsStorage::load(storage.clone()).await.unwrap();
+
+        let public_key = public_key();
+        let account = accounts_storage
+            .add_account(AccountToAdd {
+                name: "Main".to_owned(),
+                public_key,
+                contract: ton_wallet::WalletType::EverWallet,
+                workchain: 0,
+                explicit_address: None,
+                network_group: None,
+            })
+            .await
+            .unwrap();
+        let address = account.ton_wallet.address.clone();
+
+        let reloaded = AccountsStorage::load(storage).await.unwrap();
+        let stored = reloaded.get_account_by_id(&account.id).await.unwrap();
+        assert_eq!(stored.ton_wallet.address, address);
+        assert_eq!(
+            reloaded.get_account_by_address(&address).await.unwrap().id,
+            account.id
+        );
+        assert_eq!(
+            reloaded.get_accounts_by_public_key(&public_key).await.len(),
+            1
+        );
+
+        // Account can also be removed by address
+        assert!(reloaded
+            .remove_account(&address.to_string())
+            .await
+            .unwrap()
+            .is_some());
+        assert!(reloaded.get_account_by_id(&account.id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn same_address_under_different_keys() {
+        let storage = Arc::new(MemoryStorage::default());
+        let accounts_storage = AccountsStorage::load(storage.clone()).await.unwrap();
+
+        let address =
+            ton_wallet::compute_address(&public_key(), ton_wallet::WalletType::EverWallet, 0);
+        let other_key = ed25519_dalek::PublicKey::from(
+            &ed25519_dalek::SecretKey::from_bytes(&[2; 32]).unwrap(),
+        );
+        let new_account = |public_key, network_group: Option<&str>| AccountToAdd {
+            name: "Multisig".to_owned(),
+            public_key,
+            contract: ton_wallet::WalletType::Multisig(ton_wallet::MultisigType::Multisig2_1),
+            workchain: 0,
+            explicit_address: Some(address.clone()),
+            network_group: network_group.map(str::to_owned),
+        };
+
+        let first = accounts_storage
+            .add_account(new_account(public_key(), None))
+            .await
+            .unwrap();
+        let second = accounts_storage
+            .add_account(new_account(other_key, None))
+            .await
+            .unwrap();
+        let third = accounts_storage
+            .add_account(new_account(public_key(), Some("venom")))
+            .await
+            .unwrap();
+        assert!(accounts_storage
+            .add_account(new_account(public_key(), None))
+            .await
+            .is_err());
+
+        let reloaded = AccountsStorage::load(storage).await.unwrap();
+        let accounts = reloaded.get_accounts_by_address(&address).await;
+        assert_eq!(accounts.len(), 3);
+        for account in [&first, &second, &third] {
+            assert!(accounts.iter().any(|item| item.id == account.id));
+        }
+        assert_eq!(
+            reloaded
+                .get_account_by_id(&third.id)
+                .await
+                .unwrap()
+                .network_group
+                .as_deref(),
+            Some("venom")
+        );
+
+        let data = reloaded.stored_data().await;
+        assert_eq!(data.accounts_by_id().len(), 3);
+        assert_eq!(data.accounts().len(), 1);
+        assert!(data.accounts().contains_key(&address.to_string()));
+    }
+
+    #[tokio::test]
+    async fn spendings_are_tracked_by_account() {
+        let storage = Arc::new(MemoryStorage::default());
+        let accounts_storage = AccountsStorage::load(storage.clone()).await.unwrap();
+
+        let address =
+            ton_wallet::compute_address(&public_key(), ton_wallet::WalletType::EverWallet, 0);
+        let new_account = |byte: u8| AccountToAdd {
+            name: "Multisig".to_owned(),
+            public_key: ed25519_dalek::PublicKey::from(
+                &ed25519_dalek::SecretKey::from_bytes(&[byte; 32]).unwrap(),
+            ),
+            contract: ton_wallet::WalletType::Multisig(ton_wallet::MultisigType::Multisig2_1),
+            workchain: 0,
+            explicit_address: Some(address.clone()),
+            network_group: None,
+        };
+        let limited = accounts_storage.add_account(new_account(1)).await.unwrap();
+        let unlimited = accounts_storage.add_account(new_account(2)).await.unwrap();
+
+        // Nothing is stored for the account without policy
+        accounts_storage
+            .reserve_spending(&unlimited.id, None, Some(100), 1)
+            .await
+            .unwrap();
+        assert!(storage.get(SPENDINGS_STORAGE_KEY).await.unwrap().is_none());
+
+        accounts_storage
+            .set_spending_policy(
+                &limited.id,
+                Some(SpendingPolicy {
+                    daily_limit: Some(100),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .unwrap();
+        accounts_storage
+            .reserve_spending(&limited.id, None, Some(60), 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            accounts_storage
+                .check_spending(&limited.id, None, Some(60), 1)
+                .await,
+            Err(SpendingPolicyViolation::DailyLimitExceeded)
+        );
+
+        // Limits of the account with the same address are not shared
+        accounts_storage
+            .reserve_spending(&unlimited.id, None, Some(1000), 1)
+            .await
+            .unwrap();
+        accounts_storage
+            .release_spending(&limited.id, Some(60), 1)
+            .await;
+        accounts_storage
+            .check_spending(&limited.id, None, Some(60), 1)
+            .await
+            .unwrap();
+    }
+
+    /// Emulates a crash during the write: values are truncated and journal entries are kept
+    struct InterruptedStorage(Arc<MemoryStorage>);
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl Storage for InterruptedStorage {
+        async fn get(&self, key: &str) -> Result<Option<String>> {
+            self.0.get(key).await
+        }
+
+        async fn set(&self, key: &str, value: &str) -> Result<()> {
+            self.set_unchecked(key, value);
+            Ok(())
+        }
+
+        fn set_unchecked(&self, key: &str, value: &str) {
+            if key.ends_with("__journal") {
+                self.0.set_unchecked(key, value);
+            } else {
+                self.0.set_unchecked(key, &value[..value.len() / 2]);
+            }
+        }
+
+        async fn remove(&self, _: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn remove_unchecked(&self, _: &str) {}
+    }
+
+    #[tokio::test]
+    async fn interrupted_write_is_recovered() {
+        let storage = Arc::new(MemoryStorage::default());
+
+        let interrupted = AccountsStorage::load(Arc::new(InterruptedStorage(storage.clone())))
+            .await
+            .unwrap();
+        let account = interrupted
+            .add_account(AccountToAdd {
+                name: "Main".to_owned(),
+                public_key: public_key(),
+                contract: ton_wallet::WalletType::EverWallet,
+                workchain: 0,
+                explicit_address: None,
+                network_group: None,
+            })
+            .await
+            .unwrap();
+        assert!(
+            parse_assets_map(&storage.get(ACCOUNTS_STORAGE_KEY).await.unwrap().unwrap()).is_err()
+        );
+
+        let reloaded = AccountsStorage::load_unchecked(storage.clone()).await;
+        assert!(reloaded.get_account_by_id(&account.id).await.is_some());
+        assert!(storage
+            .get(&format!("{ACCOUNTS_STORAGE_KEY}__journal"))
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -1,21 +1,30 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use anyhow::Result;
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
-use ton_block::MsgAddressInt;
+use ton_block::{GetRepresentationHash, MsgAddressInt};
+use ton_types::UInt256;
 
-use nekoton_abi::{Executor, LastTransactionId};
+use nekoton_abi::{Executor, LastTransactionId, VmTrace};
 use nekoton_utils::*;
 
+use super::dead_letters::{DeadLetter, DeadLetterEmulation, DeadLetters};
 use super::models::{
     ContractState, PendingTransaction, ReliableBehavior, TransactionsBatchInfo,
-    TransactionsBatchType,
+    TransactionsBatchType, TransactionsCursor,
 };
+use super::scheduled_messages::ScheduledMessage;
 use super::{utils, PollingMethod};
 use crate::core::utils::{MessageContext, PendingTransactionsExt};
 use crate::transport::models::{RawContractState, RawTransaction};
-use crate::transport::Transport;
+use crate::transport::{
+    AccountUpdatesHandler, AccountUpdatesSubscription, OperationBounds, Transport,
+};
+
+const DEFAULT_LEGACY_MESSAGE_TTL: u32 = 60;
 
 /// Used as a base object for different listeners implementation
 pub struct ContractSubscription {
@@ -26,6 +35,13 @@ pub struct ContractSubscription {
     latest_known_lt: Option<u64>,
     pending_transactions: Vec<PendingTransaction>,
     transactions_synced: bool,
+    send_safety_margin: u32,
+    legacy_message_ttl: u32,
+    dead_letters: Option<Arc<DeadLetters>>,
+    /// Messages of the pending transactions with their emulation results.
+    /// Stored only when dead letters are enabled
+    in_flight_messages: HashMap<UInt256, (ton_block::Message, DeadLetterEmulation)>,
+    pushed_updates: Option<PushedUpdates>,
 }
 
 impl ContractSubscription {
@@ -36,15 +52,7 @@ impl ContractSubscription {
         on_contract_state: OnContractState<'_>,
         on_transactions_found: Option<OnTransactionsFound<'_>>,
     ) -> Result<Self> {
-        let mut result = Self {
-            clock,
-            transport,
-            address,
-            contract_state: Default::default(),
-            latest_known_lt: None,
-            pending_transactions: Vec::new(),
-            transactions_synced: false,
-        };
+        let mut result = Self::new(clock, transport, address);
 
         result.transactions_synced = !result
             .refresh_contract_state_impl(None, on_contract_state)
@@ -74,6 +82,101 @@ impl ContractSubscription {
         Ok(result)
     }
 
+    /// Subscribes to the contract, which transactions were already processed
+    /// until the cursor (e.g. before the restart).
+    ///
+    /// Only transactions after the cursor are requested, and they are reported as new
+    pub async fn subscribe_from(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        address: MsgAddressInt,
+        cursor: &TransactionsCursor,
+        on_contract_state: OnContractState<'_>,
+        on_transactions_found: OnTransactionsFound<'_>,
+    ) -> Result<Self> {
+        let mut result = Self::new(clock, transport, address);
+        result.latest_known_lt = Some(cursor.lt);
+
+        result
+            .refresh_contract_state_impl(None, on_contract_state)
+            .await?;
+
+        match result.contract_state.last_transaction_id {
+            Some(id) if id.lt() > cursor.lt => {
+                let count = result.transport.info().max_transactions_per_fetch;
+                result
+                    .refresh_latest_transactions(
+                        count,
+                        None,
+                        TransactionsBatchType::New,
+                        on_transactions_found,
+                        &mut |_, _| {},
+                    )
+                    .await?;
+            }
+            _ => result.transactions_synced = true,
+        }
+
+        Ok(result)
+    }
+
+    fn new(clock: Arc<dyn Clock>, transport: Arc<dyn Transport>, address: MsgAddressInt) -> Self {
+        Self {
+            clock,
+            transport,
+            address,
+            contract_state: Default::default(),
+            latest_known_lt: None,
+            pending_transactions: Vec::new(),
+            transactions_synced: false,
+            send_safety_margin: 0,
+            legacy_message_ttl: DEFAULT_LEGACY_MESSAGE_TTL,
+            dead_letters: None,
+            in_flight_messages: Default::default(),
+            pushed_updates: None,
+        }
+    }
+
+    /// Restores the subscription from the snapshot without any requests.
+    ///
+    /// Only new transactions will be requested during the next refresh
+    pub fn restore_state(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        data: &str,
+    ) -> Result<Self> {
+        let snapshot = serde_json::from_str::<ContractSubscriptionSnapshot>(data)?;
+        Ok(Self {
+            clock,
+            transport,
+            address: snapshot.address,
+            contract_state: snapshot.contract_state,
+            latest_known_lt: snapshot.latest_known_lt,
+            pending_transactions: snapshot.pending_transactions,
+            transactions_synced: snapshot.transactions_synced,
+            send_safety_margin: snapshot.send_safety_margin,
+            legacy_message_ttl: snapshot.legacy_message_ttl,
+            dead_letters: None,
+            in_flight_messages: Default::default(),
+            pushed_updates: None,
+        })
+    }
+
+    /// Creates a snapshot of the subscription, which can be used to
+    /// quickly restore it with [`ContractSubscription::restore_state`]
+    pub fn serialize_state(&self) -> String {
+        serde_json::to_string(&ContractSubscriptionSnapshot {
+            address: self.address.clone(),
+            contract_state: self.contract_state,
+            latest_known_lt: self.latest_known_lt,
+            pending_transactions: self.pending_transactions.clone(),
+            transactions_synced: self.transactions_synced,
+            send_safety_margin: self.send_safety_margin,
+            legacy_message_ttl: self.legacy_message_ttl,
+        })
+        .trust_me()
+    }
+
     pub fn transport(&self) -> &Arc<dyn Transport> {
         &self.transport
     }
@@ -114,6 +217,53 @@ impl ContractSubscription {
         }
     }
 
+    /// Messages which expire in less than `seconds` will not be sent,
+    /// because they are unlikely to be included into the block in time
+    pub fn set_send_safety_margin(&mut self, seconds: u32) {
+        self.send_safety_margin = seconds;
+    }
+
+    /// Time in seconds after which the message without `expire` header
+    /// is considered expired if no transaction was found
+    pub fn set_legacy_message_ttl(&mut self, seconds: u32) {
+        self.legacy_message_ttl = seconds;
+    }
+
+    /// Messages which failed to send or expired will be stored there
+    pub fn set_dead_letters(&mut self, dead_letters: Option<Arc<DeadLetters>>) {
+        self.dead_letters = dead_letters;
+    }
+
+    /// Subscribes to the account updates if the transport supports them.
+    ///
+    /// While the subscription is active, [`ContractSubscription::refresh`] doesn't
+    /// request the state until some update is pushed (unless there are pending
+    /// transactions). Regular polling is used again when the subscription is closed.
+    ///
+    /// Returns whether the subscription is active
+    pub async fn subscribe_updates(&mut self) -> Result<bool> {
+        if !self.transport.capabilities().subscriptions {
+            return Ok(false);
+        }
+
+        let state = Arc::new(PushedUpdatesState::default());
+        self.pushed_updates = self
+            .transport
+            .subscribe_account(&self.address, state.clone())
+            .await?
+            .map(|subscription| PushedUpdates {
+                _subscription: subscription,
+                state,
+            });
+
+        Ok(self.pushed_updates.is_some())
+    }
+
+    /// Whether account updates are pushed by the transport
+    pub fn has_pushed_updates(&self) -> bool {
+        matches!(&self.pushed_updates, Some(updates) if !updates.state.is_closed())
+    }
+
     pub fn add_pending_transaction(&mut self, pending_transaction: PendingTransaction) {
         self.pending_transactions.push(pending_transaction);
     }
@@ -123,30 +273,152 @@ impl ContractSubscription {
         message: &ton_block::Message,
         expire_at: u32,
     ) -> Result<PendingTransaction> {
+        self.send_impl(message, expire_at, None).await
+    }
+
+    /// Sends the pre-signed message when its broadcast window starts.
+    ///
+    /// See [`ScheduledMessages`](super::scheduled_messages::ScheduledMessages)
+    pub async fn send_scheduled(
+        &mut self,
+        message: &ScheduledMessage,
+    ) -> Result<PendingTransaction> {
+        if (self.clock.now_sec_u64() as u32) < message.broadcast_not_before {
+            return Err(ContractSubscriptionError::BroadcastWindowNotStarted.into());
+        }
+        self.send_impl(
+            &message.message,
+            message.expire_at,
+            Some(message.broadcast_not_before),
+        )
+        .await
+    }
+
+    async fn send_impl(
+        &mut self,
+        message: &ton_block::Message,
+        expire_at: u32,
+        broadcast_not_before: Option<u32>,
+    ) -> Result<PendingTransaction> {
+        let now = self.clock.now_sec_u64() as u32;
+        if expire_at.saturating_sub(now) < self.send_safety_margin {
+            return Err(ContractSubscriptionError::MessageExpiresTooSoon.into());
+        }
+
         let ctx = MessageContext {
             latest_lt: self
                 .contract_state
                 .last_transaction_id
                 .map(|id| id.lt())
                 .unwrap_or_default(),
-            created_at: self.clock.now_sec_u64() as u32,
+            created_at: now,
             expire_at,
+            broadcast_not_before,
         };
+
+        // NOTE: resending a message which is still pending is a no-op
+        let message_hash = message.hash()?;
+        if let Some(pending_transaction) = self
+            .pending_transactions
+            .iter()
+            .find(|item| item.message_hash == message_hash)
+        {
+            return Ok(pending_transaction.clone());
+        }
+
         let pending_transaction =
             self.pending_transactions
                 .add_message(&self.address, message, ctx)?;
 
-        match self.transport.send_message(message).await {
+        // NOTE: message is emulated before the broadcast, so that the reason
+        // of the failure can be inspected if the message is not delivered
+        let emulation = match &self.dead_letters {
+            Some(_) => Some(
+                match self
+                    .execute_transaction_locally(message, Default::default())
+                    .await
+                {
+                    Ok(transaction) => DeadLetterEmulation::from_result(Ok(&transaction)),
+                    Err(e) => DeadLetterEmulation::from_result(Err(e)),
+                },
+            ),
+            None => None,
+        };
+
+        match super::send_message_once(self.transport.as_ref(), message).await {
+            // message was already delivered, so it is reported on the next refresh
+            // only if the transaction is not known yet
+            Ok(Some(transaction)) => {
+                if transaction.data.lt <= pending_transaction.latest_lt {
+                    self.pending_transactions.cancel(&pending_transaction);
+                }
+                Ok(pending_transaction)
+            }
             // return pending transaction on success
-            Ok(()) => Ok(pending_transaction),
+            Ok(None) => {
+                if let Some(emulation) = emulation {
+                    self.in_flight_messages.insert(
+                        pending_transaction.message_hash,
+                        (message.clone(), emulation),
+                    );
+                }
+                Ok(pending_transaction)
+            }
             // remove pending transaction from queue on error
             Err(e) => {
                 self.pending_transactions.cancel(&pending_transaction);
+                if let Some(dead_letters) = &self.dead_letters {
+                    if let Ok(mut entry) = DeadLetter::send_failed(
+                        self.clock.as_ref(),
+                        self.address.clone(),
+                        message.clone(),
+                        &e,
+                    ) {
+                        entry.emulation = emulation;
+                        dead_letters.add(entry);
+                    }
+                }
                 Err(e)
             }
         }
     }
 
+    /// Sends the message of the contract without `expire` header.
+    ///
+    /// Such message never expires on-chain, so the expiration is tracked
+    /// only on the client side (see [`ContractSubscription::set_legacy_message_ttl`])
+    pub async fn send_legacy(
+        &mut self,
+        message: &ton_block::Message,
+    ) -> Result<PendingTransaction> {
+        let expire_at = (self.clock.now_sec_u64() as u32).saturating_add(self.legacy_message_ttl);
+        self.send(message, expire_at).await
+    }
+
+    /// Same as [`ContractSubscription::refresh`], but fails with
+    /// [`OperationError`](crate::transport::OperationError) when the bounds are exceeded.
+    pub async fn refresh_bounded(
+        &mut self,
+        bounds: &OperationBounds,
+        on_contract_state: OnContractState<'_>,
+        on_transactions_found: OnTransactionsFound<'_>,
+        on_message_sent: OnMessageSent<'_>,
+        on_message_expired: OnMessageExpired<'_>,
+    ) -> Result<()> {
+        let clock = self.clock.clone();
+        bounds
+            .run(
+                clock.as_ref(),
+                self.refresh(
+                    on_contract_state,
+                    on_transactions_found,
+                    on_message_sent,
+                    on_message_expired,
+                ),
+            )
+            .await
+    }
+
     pub async fn refresh(
         &mut self,
         on_contract_state: OnContractState<'_>,
@@ -157,13 +429,53 @@ impl ContractSubscription {
         // optimistic prediction, that there were at most N new transactions
         const INITIAL_TRANSACTION_COUNT: u8 = 4;
 
+        if let Some(updates) = &self.pushed_updates {
+            if updates.state.is_closed() {
+                // Fallback to polling
+                self.pushed_updates = None;
+            } else if !updates.state.take_changed()
+                && self.transactions_synced
+                && self.pending_transactions.is_empty()
+            {
+                // Nothing changed since the last refresh
+                return Ok(());
+            }
+        }
+
+        let result = self
+            .refresh_impl(
+                INITIAL_TRANSACTION_COUNT,
+                on_contract_state,
+                on_transactions_found,
+                on_message_sent,
+                on_message_expired,
+            )
+            .await;
+
+        if result.is_err() {
+            // Make sure that the pushed update is not lost
+            if let Some(updates) = &self.pushed_updates {
+                updates.state.set_changed();
+            }
+        }
+        result
+    }
+
+    async fn refresh_impl(
+        &mut self,
+        initial_transaction_count: u8,
+        on_contract_state: OnContractState<'_>,
+        on_transactions_found: OnTransactionsFound<'_>,
+        on_message_sent: OnMessageSent<'_>,
+        on_message_expired: OnMessageExpired<'_>,
+    ) -> Result<()> {
         // NOTE: refresh transactions every time state changes, or there are
         // new transactions, which we still need to receive (e.g. state has new
         // last_transaction_id, but the last known transaction is not equal to id)
         if self.refresh_contract_state(on_contract_state).await? || !self.transactions_synced {
             let count = u8::min(
                 self.transport.info().max_transactions_per_fetch,
-                INITIAL_TRANSACTION_COUNT,
+                initial_transaction_count,
             );
 
             // get all new transactions until known id
@@ -230,13 +542,7 @@ impl ContractSubscription {
             )
             .await?;
 
-        Ok(
-            if let ton_block::TransactionDescr::Ordinary(descr) = transaction.read_description()? {
-                compute_total_transaction_fees(&transaction, &descr)
-            } else {
-                transaction.total_fees.grams.as_u128()
-            },
-        )
+        total_fees(&transaction)
     }
 
     pub async fn execute_transaction_locally(
@@ -244,6 +550,53 @@ impl ContractSubscription {
         message: &ton_block::Message,
         options: TransactionExecutionOptions,
     ) -> Result<ton_block::Transaction> {
+        self.prepare_executor(options).await?.run_once(message)
+    }
+
+    /// Executes the message against the current contract state.
+    ///
+    /// Unlike [`ContractSubscription::execute_transaction_locally`], also returns
+    /// the resulting account state and total fees.
+    ///
+    /// VM steps of the failed execution are collected if `trace_steps` is specified.
+    /// They are returned in [`LocalExecutionResult::trace`] for aborted transactions,
+    /// or as [`TracedExecutionError`] if the transaction was not produced at all
+    pub async fn execute_local(
+        &self,
+        message: &ton_block::Message,
+        options: TransactionExecutionOptions,
+    ) -> Result<LocalExecutionResult> {
+        let mut executor = self.prepare_executor(options).await?;
+        let initial = options
+            .trace_steps
+            .map(|max_steps| (executor.clone(), max_steps));
+        let trace =
+            |(executor, max_steps): (Executor, usize)| executor.trace(message, max_steps).ok();
+
+        let transaction = match executor.run_mut(message) {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                return Err(match initial.and_then(trace) {
+                    Some(trace) => TracedExecutionError { reason: e, trace }.into(),
+                    None => e,
+                })
+            }
+        };
+
+        let trace = match initial {
+            Some(initial) if transaction.read_description()?.is_aborted() => trace(initial),
+            _ => None,
+        };
+
+        Ok(LocalExecutionResult {
+            total_fees: total_fees(&transaction)?,
+            transaction,
+            account: executor.into_account(),
+            trace,
+        })
+    }
+
+    async fn prepare_executor(&self, options: TransactionExecutionOptions) -> Result<Executor> {
         let blockchain_config = self
             .transport
             .get_blockchain_config(self.clock.as_ref(), true)
@@ -262,7 +615,7 @@ impl ContractSubscription {
             executor.disable_signature_check();
         }
 
-        executor.run_once(message)
+        Ok(executor)
     }
 
     /// Updates contract state. Returns whether the state was changed
@@ -352,19 +705,22 @@ impl ContractSubscription {
         Ok(())
     }
 
-    /// Loads older transactions since specified id and notifies the handler with them
+    /// Loads the page of older transactions starting from the cursor (inclusive)
+    /// and notifies the handler with them.
+    ///
+    /// Returns the cursor of the next page, or `None` if there are no older transactions
     ///
     /// **NOTE: returns transactions, sorted by lt in descending order**
     pub async fn preload_transactions(
         &mut self,
-        from_lt: u64,
+        cursor: &TransactionsCursor,
         on_transactions_found: OnTransactionsFound<'_>,
-    ) -> Result<()> {
-        let transactions = self
+    ) -> Result<Option<TransactionsCursor>> {
+        let (transactions, next) = self
             .transport
-            .get_transactions(
+            .get_transactions_page(
                 &self.address,
-                from_lt,
+                cursor,
                 self.transport.info().max_transactions_per_fetch,
             )
             .await?;
@@ -379,7 +735,7 @@ impl ContractSubscription {
             on_transactions_found(transactions, batch_info);
         }
 
-        Ok(())
+        Ok(next)
     }
 
     async fn refresh_contract_state_impl(
@@ -441,6 +797,13 @@ impl ContractSubscription {
             on_message_sent(pending.clone(), transaction.clone());
             false
         });
+
+        let pending_transactions = &self.pending_transactions;
+        self.in_flight_messages.retain(|message_hash, _| {
+            pending_transactions
+                .iter()
+                .any(|pending| &pending.message_hash == message_hash)
+        });
     }
 
     /// Removes expired transactions and notifies the handler with them
@@ -449,9 +812,23 @@ impl ContractSubscription {
         current_utime: u32,
         on_message_expired: OnMessageExpired<'_>,
     ) {
+        let clock = self.clock.as_ref();
+        let address = &self.address;
+        let dead_letters = self.dead_letters.as_deref();
+        let in_flight_messages = &mut self.in_flight_messages;
+
         self.pending_transactions.retain(|pending| {
             let expired = current_utime > pending.expire_at;
             if expired {
+                let in_flight = in_flight_messages.remove(&pending.message_hash);
+                if let Some(dead_letters) = dead_letters {
+                    let mut entry = DeadLetter::expired(clock, address.clone(), pending);
+                    if let Some((message, emulation)) = in_flight {
+                        entry.message = Some(message);
+                        entry.emulation = Some(emulation);
+                    }
+                    dead_letters.add(entry);
+                }
                 on_message_expired(pending.clone());
             }
             !expired
@@ -459,23 +836,463 @@ impl ContractSubscription {
     }
 }
 
+struct PushedUpdates {
+    _subscription: Box<dyn AccountUpdatesSubscription>,
+    state: Arc<PushedUpdatesState>,
+}
+
+#[derive(Default)]
+struct PushedUpdatesState {
+    changed: AtomicBool,
+    closed: AtomicBool,
+}
+
+impl PushedUpdatesState {
+    fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::AcqRel)
+    }
+
+    fn set_changed(&self) {
+        self.changed.store(true, Ordering::Release);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+}
+
+impl AccountUpdatesHandler for PushedUpdatesState {
+    fn on_state(&self, _: Option<RawContractState>) {
+        // NOTE: state is requested during the refresh along with the transactions
+        self.set_changed();
+    }
+
+    fn on_transaction(&self, _: RawTransaction) {
+        self.set_changed();
+    }
+
+    fn on_closed(&self, error: Option<anyhow::Error>) {
+        if let Some(e) = error {
+            log::warn!("Account subscription closed: {e:?}");
+        }
+        self.closed.store(true, Ordering::Release);
+    }
+}
+
 type OnContractState<'a> = &'a mut (dyn FnMut(&RawContractState) + Send + Sync);
 type OnTransactionsFound<'a> =
     &'a mut (dyn FnMut(Vec<RawTransaction>, TransactionsBatchInfo) + Send + Sync);
 type OnMessageSent<'a> = &'a mut (dyn FnMut(PendingTransaction, RawTransaction) + Send + Sync);
 type OnMessageExpired<'a> = &'a mut (dyn FnMut(PendingTransaction) + Send + Sync);
 
+fn total_fees(transaction: &ton_block::Transaction) -> Result<u128> {
+    Ok(
+        if let ton_block::TransactionDescr::Ordinary(descr) = transaction.read_description()? {
+            compute_total_transaction_fees(transaction, &descr)
+        } else {
+            transaction.total_fees.grams.as_u128()
+        },
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContractSubscriptionSnapshot {
+    #[serde(with = "serde_address")]
+    address: MsgAddressInt,
+    contract_state: ContractState,
+    #[serde(default, with = "serde_optional_u64")]
+    latest_known_lt: Option<u64>,
+    #[serde(default)]
+    pending_transactions: Vec<PendingTransaction>,
+    #[serde(default)]
+    transactions_synced: bool,
+    #[serde(default)]
+    send_safety_margin: u32,
+    #[serde(default = "default_legacy_message_ttl")]
+    legacy_message_ttl: u32,
+}
+
+fn default_legacy_message_ttl() -> u32 {
+    DEFAULT_LEGACY_MESSAGE_TTL
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalExecutionResult {
+    pub transaction: ton_block::Transaction,
+    /// Sum of fees from all execution stages
+    pub total_fees: u128,
+    /// Account state after the transaction
+    pub account: ton_block::Account,
+    /// VM steps of the aborted transaction, if requested
+    pub trace: Option<VmTrace>,
+}
+
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
 pub struct TransactionExecutionOptions {
     pub disable_signature_check: bool,
     #[serde(with = "serde_optional_u64")]
     pub override_balance: Option<u64>,
+    /// Max number of VM steps to collect if the execution fails
+    pub trace_steps: Option<usize>,
+}
+
+/// Local execution failed without producing a transaction
+/// (e.g. the external message was not accepted)
+#[derive(thiserror::Error, Debug)]
+#[error("{reason}")]
+pub struct TracedExecutionError {
+    pub reason: anyhow::Error,
+    /// VM steps of the failed execution
+    pub trace: VmTrace,
+}
+
+#[derive(thiserror::Error, Debug)]
+enum ContractSubscriptionError {
+    #[error("Message expires too soon")]
+    MessageExpiresTooSoon,
+    #[error("Broadcast window has not started yet")]
+    BroadcastWindowNotStarted,
 }
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+    use std::time::Duration;
+
     use super::*;
+    use crate::core::dead_letters::DeadLetterReason;
+    use crate::testing::{ChainSimulator, MemoryStorage};
+
+    fn make_external_message(dst: &MsgAddressInt, id: u32) -> ton_block::Message {
+        let mut message =
+            ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+                dst: dst.clone(),
+                ..Default::default()
+            });
+        let mut body = ton_types::BuilderData::new();
+        body.append_u32(id).unwrap();
+        message.set_body(ton_types::SliceData::load_builder(body).unwrap());
+        message
+    }
+
+    /// Deploys the wallet with a single transaction
+    async fn deploy_wallet(sim: &ChainSimulator) -> MsgAddressInt {
+        use ed25519_dalek::{Keypair, SecretKey, Signer};
+
+        use crate::core::models::Expiration;
+        use crate::core::ton_wallet::wallet_v3;
+        use crate::crypto::UnsignedMessage;
+
+        let secret = SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        let keypair = Keypair { secret, public };
+
+        let address = wallet_v3::compute_contract_address(&public, 0);
+        sim.transport().set_account(
+            address.clone(),
+            ton_block::Account::with_address_and_ballance(
+                &address,
+                &ton_block::CurrencyCollection::with_grams(10_000_000_000),
+            ),
+        );
+
+        let message =
+            wallet_v3::prepare_deploy(sim.clock().as_ref(), &public, 0, Expiration::Timeout(60))
+                .unwrap();
+        let signature = keypair.sign(message.hash()).to_bytes();
+        let message = message.sign(&signature).unwrap().message;
+        sim.transport().send_message(&message).await.unwrap();
+        sim.advance(Duration::from_secs(1)).unwrap();
+
+        address
+    }
+
+    #[tokio::test]
+    async fn restored_state_is_not_refreshed_twice() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let address = deploy_wallet(&sim).await;
+
+        let subscription = ContractSubscription::subscribe(
+            sim.clock().clone(),
+            sim.transport().clone(),
+            address,
+            &mut |_| {},
+            None,
+        )
+        .await
+        .unwrap();
+        let last_lt = subscription.contract_state().last_lt;
+        assert!(last_lt > 0);
+
+        let mut restored = ContractSubscription::restore_state(
+            sim.clock().clone(),
+            sim.transport().clone(),
+            &subscription.serialize_state(),
+        )
+        .unwrap();
+        assert_eq!(restored.contract_state().last_lt, last_lt);
+
+        let mut notified = false;
+        let updated = restored
+            .refresh_contract_state(&mut |_| notified = true)
+            .await
+            .unwrap();
+        assert!(!updated);
+        assert!(!notified);
+    }
+
+    #[tokio::test]
+    async fn preload_transactions_by_cursor() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let address = deploy_wallet(&sim).await;
+
+        let mut subscription = ContractSubscription::subscribe(
+            sim.clock().clone(),
+            sim.transport().clone(),
+            address.clone(),
+            &mut |_| {},
+            None,
+        )
+        .await
+        .unwrap();
+
+        let latest = sim
+            .transport()
+            .get_transactions(&address, u64::MAX, 1)
+            .await
+            .unwrap()
+            .remove(0);
+        let cursor = TransactionsCursor {
+            lt: latest.data.lt,
+            hash: latest.hash,
+        };
+
+        let mut found = Vec::new();
+        let next = subscription
+            .preload_transactions(&cursor, &mut |transactions, batch_info| {
+                assert_eq!(batch_info.batch_type, TransactionsBatchType::Old);
+                found.extend(transactions);
+            })
+            .await
+            .unwrap();
+        assert_eq!(found, [latest]);
+        assert!(next.is_none());
+
+        // Cursor of the unknown transaction is rejected
+        let result = subscription
+            .preload_transactions(
+                &TransactionsCursor {
+                    hash: Default::default(),
+                    ..cursor
+                },
+                &mut |_, _| panic!("transactions must not be found"),
+            )
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn subscribe_from_cursor() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let address = deploy_wallet(&sim).await;
+
+        let latest = sim
+            .transport()
+            .get_transactions(&address, u64::MAX, 1)
+            .await
+            .unwrap()
+            .remove(0);
+
+        // Nothing is reported if all transactions were processed
+        let cursor = TransactionsCursor {
+            lt: latest.data.lt,
+            hash: latest.hash,
+        };
+        ContractSubscription::subscribe_from(
+            sim.clock().clone(),
+            sim.transport().clone(),
+            address.clone(),
+            &cursor,
+            &mut |_| {},
+            &mut |_, _| panic!("transactions must not be found"),
+        )
+        .await
+        .unwrap();
+
+        // Transactions after the cursor are reported as new
+        let cursor = TransactionsCursor {
+            lt: latest.data.lt - 1,
+            hash: Default::default(),
+        };
+        let mut found = Vec::new();
+        ContractSubscription::subscribe_from(
+            sim.clock().clone(),
+            sim.transport().clone(),
+            address,
+            &cursor,
+            &mut |_| {},
+            &mut |transactions, batch_info| {
+                assert_eq!(batch_info.batch_type, TransactionsBatchType::New);
+                found.extend(transactions);
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(found, [latest]);
+    }
+
+    #[tokio::test]
+    async fn repeated_send_is_noop() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let address = deploy_wallet(&sim).await;
+
+        let mut subscription = ContractSubscription::subscribe(
+            sim.clock().clone(),
+            sim.transport().clone(),
+            address.clone(),
+            &mut |_| {},
+            None,
+        )
+        .await
+        .unwrap();
+        let expire_at = sim.clock().now_sec_u64() as u32 + 60;
+
+        // Pending message is not broadcasted twice
+        let message = make_external_message(&address, 1);
+        let first = subscription.send(&message, expire_at).await.unwrap();
+        let second = subscription.send(&message, expire_at).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(subscription.pending_transactions().len(), 1);
+        assert_eq!(sim.transport().pending_messages().len(), 1);
+        sim.transport().drop_pending_messages();
+
+        // Already delivered message is neither broadcasted nor queued
+        let latest = sim
+            .transport()
+            .get_transactions(&address, u64::MAX, 1)
+            .await
+            .unwrap()
+            .remove(0);
+        let delivered = latest.data.in_msg.as_ref().unwrap().read_struct().unwrap();
+        subscription.send(&delivered, expire_at).await.unwrap();
+        assert_eq!(subscription.pending_transactions().len(), 1);
+        assert!(sim.transport().pending_messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn undelivered_messages_are_stored() {
+        const NOW: u64 = 1_700_000_000;
+
+        let sim = ChainSimulator::new(NOW);
+        let address = MsgAddressInt::from_str(
+            "0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb",
+        )
+        .unwrap();
+
+        let mut subscription = ContractSubscription::subscribe(
+            sim.clock().clone(),
+            sim.transport().clone(),
+            address.clone(),
+            &mut |_| {},
+            None,
+        )
+        .await
+        .unwrap();
+
+        let dead_letters = Arc::new(
+            DeadLetters::load_unchecked("test", Arc::new(MemoryStorage::default()), 10).await,
+        );
+        subscription.set_dead_letters(Some(dead_letters.clone()));
+
+        // Broadcast failure
+        let rejected = make_external_message(&address, 1);
+        sim.transport().set_reject_messages(true);
+        assert!(subscription.send(&rejected, NOW as u32 + 60).await.is_err());
+        assert!(subscription.pending_transactions().is_empty());
+
+        let entries = dead_letters.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].message_hash, rejected.hash().unwrap());
+        assert_eq!(entries[0].address, address);
+        assert!(entries[0].message.is_some());
+        assert!(entries[0].emulation.is_some());
+        assert!(matches!(
+            entries[0].reason,
+            DeadLetterReason::SendFailed { .. }
+        ));
+
+        // Expiration
+        let lost = make_external_message(&address, 2);
+        sim.transport().set_reject_messages(false);
+        subscription.send(&lost, NOW as u32 + 60).await.unwrap();
+        sim.transport().drop_pending_messages();
+        sim.clock().advance(Duration::from_secs(120));
+
+        let mut expired = Vec::new();
+        subscription
+            .check_expired_transactions(NOW as u32 + 120, &mut |pending| expired.push(pending));
+        assert_eq!(expired.len(), 1);
+        assert!(subscription.pending_transactions().is_empty());
+
+        let entries = dead_letters.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].message_hash, lost.hash().unwrap());
+        assert_eq!(entries[0].message.as_ref(), Some(&lost));
+        assert!(matches!(
+            entries[0].emulation,
+            Some(DeadLetterEmulation { aborted: true, .. })
+        ));
+        assert!(matches!(
+            entries[0].reason,
+            DeadLetterReason::Expired { expire_at, .. } if expire_at == NOW as u32 + 60
+        ));
+    }
+
+    #[tokio::test]
+    async fn trace_rejected_message() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let address = deploy_wallet(&sim).await;
+
+        let subscription = ContractSubscription::subscribe(
+            sim.clock().clone(),
+            sim.transport().clone(),
+            address.clone(),
+            &mut |_| {},
+            None,
+        )
+        .await
+        .unwrap();
+
+        // Wallet doesn't accept the message without signature
+        let message = make_external_message(&address, 1);
+
+        let error = subscription
+            .execute_local(&message, Default::default())
+            .await
+            .unwrap_err();
+        assert!(error.downcast_ref::<TracedExecutionError>().is_none());
+
+        let error = subscription
+            .execute_local(
+                &message,
+                TransactionExecutionOptions {
+                    trace_steps: Some(4),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap_err();
+        let TracedExecutionError { trace, .. } = error.downcast_ref().unwrap();
+        assert_ne!(trace.exit_code, 0);
+        assert_eq!(trace.steps.len(), 4);
+        assert!(trace.truncated);
+        assert!(trace
+            .steps
+            .iter()
+            .all(|step| step.stack.len() <= nekoton_abi::MAX_TRACED_STACK_ITEMS));
+    }
 
     #[test]
     fn executor_params_serialization() {
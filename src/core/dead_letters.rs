@@ -0,0 +1,264 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use ton_block::{GetRepresentationHash, MsgAddressInt};
+use ton_types::UInt256;
+
+use nekoton_utils::*;
+
+use super::models::PendingTransaction;
+use crate::external::Storage;
+
+pub const DEAD_LETTERS_STORAGE_KEY: &str = "__core__dead_letters";
+
+/// Stores messages which were not delivered, so that they can be
+/// inspected later when diagnosing "missing" transactions
+pub struct DeadLetters {
+    key: String,
+    storage: Arc<dyn Storage>,
+    capacity: usize,
+    entries: parking_lot::RwLock<VecDeque<DeadLetter>>,
+}
+
+impl DeadLetters {
+    pub async fn load(
+        network_group: &str,
+        storage: Arc<dyn Storage>,
+        capacity: usize,
+    ) -> Result<Self> {
+        let key = make_key(network_group);
+
+        let mut entries = match storage.get(&key).await? {
+            Some(data) => serde_json::from_str::<VecDeque<DeadLetter>>(&data)?,
+            None => Default::default(),
+        };
+        entries.truncate(capacity);
+
+        Ok(Self {
+            key,
+            storage,
+            capacity,
+            entries: parking_lot::RwLock::new(entries),
+        })
+    }
+
+    pub async fn load_unchecked(
+        network_group: &str,
+        storage: Arc<dyn Storage>,
+        capacity: usize,
+    ) -> Self {
+        Self::load(network_group, storage.clone(), capacity)
+            .await
+            .unwrap_or_else(|_| Self {
+                key: make_key(network_group),
+                storage,
+                capacity,
+                entries: Default::default(),
+            })
+    }
+
+    /// Adds new entry. The oldest entry is removed when capacity is reached
+    pub fn add(&self, entry: DeadLetter) {
+        let mut entries = self.entries.write();
+        entries.retain(|item| item.message_hash != entry.message_hash);
+        entries.push_front(entry);
+        entries.truncate(self.capacity);
+        self.save(&entries);
+    }
+
+    /// Returns all entries, newest first
+    pub fn entries(&self) -> Vec<DeadLetter> {
+        self.entries.read().iter().cloned().collect()
+    }
+
+    pub fn get(&self, message_hash: &UInt256) -> Option<DeadLetter> {
+        self.entries
+            .read()
+            .iter()
+            .find(|item| &item.message_hash == message_hash)
+            .cloned()
+    }
+
+    pub fn remove(&self, message_hash: &UInt256) -> Option<DeadLetter> {
+        let mut entries = self.entries.write();
+        let index = entries
+            .iter()
+            .position(|item| &item.message_hash == message_hash)?;
+        let entry = entries.remove(index);
+        self.save(&entries);
+        entry
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries.write();
+        entries.clear();
+        self.storage.remove_unchecked(&self.key);
+    }
+
+    fn save(&self, entries: &VecDeque<DeadLetter>) {
+        let data = serde_json::to_string(entries).trust_me();
+        self.storage.set_unchecked(&self.key, &data);
+    }
+}
+
+fn make_key(network_group: &str) -> String {
+    format!("{DEAD_LETTERS_STORAGE_KEY}{network_group}")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetter {
+    #[serde(with = "serde_uint256")]
+    pub message_hash: UInt256,
+    /// Message destination
+    #[serde(with = "serde_address")]
+    pub address: MsgAddressInt,
+    /// Original message, if it is still known
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_optional_message"
+    )]
+    pub message: Option<ton_block::Message>,
+    pub reason: DeadLetterReason,
+    /// Local execution result of the message, if it was performed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emulation: Option<DeadLetterEmulation>,
+    pub created_at: u32,
+}
+
+impl DeadLetter {
+    /// Message was rejected by the transport
+    pub fn send_failed(
+        clock: &dyn Clock,
+        address: MsgAddressInt,
+        message: ton_block::Message,
+        error: &anyhow::Error,
+    ) -> Result<Self> {
+        Ok(Self {
+            message_hash: message.hash()?,
+            address,
+            message: Some(message),
+            reason: DeadLetterReason::SendFailed {
+                error: error.to_string(),
+            },
+            emulation: None,
+            created_at: clock.now_sec_u64() as u32,
+        })
+    }
+
+    /// No transaction was found for the message before its expiration
+    pub fn expired(
+        clock: &dyn Clock,
+        address: MsgAddressInt,
+        pending_transaction: &PendingTransaction,
+    ) -> Self {
+        Self {
+            message_hash: pending_transaction.message_hash,
+            address,
+            message: None,
+            reason: DeadLetterReason::Expired {
+                sent_at: pending_transaction.created_at,
+                expire_at: pending_transaction.expire_at,
+            },
+            emulation: None,
+            created_at: clock.now_sec_u64() as u32,
+        }
+    }
+
+    pub fn with_message(mut self, message: ton_block::Message) -> Self {
+        self.message = Some(message);
+        self
+    }
+
+    /// Attaches the result of the local execution of the message
+    pub fn with_emulation(mut self, emulation: Result<&ton_block::Transaction>) -> Self {
+        self.emulation = Some(DeadLetterEmulation::from_result(emulation));
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+pub enum DeadLetterReason {
+    SendFailed {
+        error: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Expired {
+        sent_at: u32,
+        expire_at: u32,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetterEmulation {
+    pub aborted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DeadLetterEmulation {
+    /// Summarizes the result of the local execution of the message
+    pub fn from_result(result: Result<&ton_block::Transaction>) -> Self {
+        let transaction = match result {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                return Self {
+                    aborted: true,
+                    exit_code: None,
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+
+        match transaction.read_description() {
+            Ok(description) => Self {
+                aborted: description.is_aborted(),
+                exit_code: description
+                    .compute_phase_ref()
+                    .and_then(|phase| match phase {
+                        ton_block::TrComputePhase::Vm(phase) => Some(phase.exit_code),
+                        ton_block::TrComputePhase::Skipped(_) => None,
+                    }),
+                error: None,
+            },
+            Err(e) => Self {
+                aborted: true,
+                exit_code: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+mod serde_optional_message {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use ton_block::Deserializable;
+
+    pub fn serialize<S>(data: &Option<ton_block::Message>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match data {
+            Some(message) => nekoton_utils::serde_ton_block::serialize(message, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<ton_block::Message>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        Option::<String>::deserialize(deserializer)?
+            .map(|data| ton_block::Message::construct_from_base64(&data).map_err(Error::custom))
+            .transpose()
+    }
+}
@@ -15,7 +15,7 @@ use crate::transport::Transport;
 #[derive(Default)]
 pub struct Dens {
     tld: RwLock<HashMap<String, Arc<DensTld>>>,
-    contract_address_cache: Option<Cache<String, MsgAddressInt>>,
+    contract_address_cache: Option<ContractAddressCache>,
 }
 
 impl Dens {
@@ -48,7 +48,7 @@ impl Dens {
 
         if let Some(contract_address_cache) = &self.contract_address_cache {
             if let Some(address) = contract_address_cache.get(path) {
-                return Ok(Some(address.clone()));
+                return Ok(Some(address));
             }
         }
 
@@ -66,6 +66,19 @@ impl Dens {
         Ok(address)
     }
 
+    /// Parses the recipient of the transfer, which can be either
+    /// a raw/packed address or a domain name (e.g. `alice.ever`)
+    pub async fn resolve_recipient(&self, recipient: &str) -> Result<MsgAddressInt> {
+        if let Ok(address) = nekoton_utils::repack_address(recipient) {
+            return Ok(address);
+        }
+
+        match self.try_resolve_contract_address(recipient).await? {
+            Some(address) => Ok(address),
+            None => Err(DensError::AddressNotFound.into()),
+        }
+    }
+
     pub async fn try_resolve(&self, path: &str, record: u32) -> Result<ResolvedValue> {
         if !validate_address(path) {
             return Err(DensError::InvalidPath.into());
@@ -96,6 +109,7 @@ pub struct DensBuilder {
     clock: Arc<dyn Clock>,
     transport: Arc<dyn Transport>,
     dens: Dens,
+    contract_address_cache_ttl: Option<u64>,
 }
 
 impl DensBuilder {
@@ -104,6 +118,7 @@ impl DensBuilder {
             clock,
             transport,
             dens: Default::default(),
+            contract_address_cache_ttl: None,
         }
     }
 
@@ -114,15 +129,59 @@ impl DensBuilder {
     }
 
     pub fn with_contract_address_cache(mut self, capacity: usize) -> Self {
-        self.dens.contract_address_cache = Some(Cache::new(capacity));
+        self.dens.contract_address_cache = Some(ContractAddressCache {
+            clock: self.clock.clone(),
+            entries: Cache::new(capacity),
+            ttl_sec: None,
+        });
         self
     }
 
-    pub fn build(self) -> Dens {
+    /// Resolved addresses are refreshed after the specified number of seconds.
+    ///
+    /// NOTE: has effect only with the contract address cache enabled
+    /// (see [`DensBuilder::with_contract_address_cache`])
+    pub fn with_contract_address_cache_ttl(mut self, ttl_sec: u64) -> Self {
+        self.contract_address_cache_ttl = Some(ttl_sec);
+        self
+    }
+
+    pub fn build(mut self) -> Dens {
+        if let Some(cache) = &mut self.dens.contract_address_cache {
+            cache.ttl_sec = self.contract_address_cache_ttl;
+        }
         self.dens
     }
 }
 
+struct ContractAddressCache {
+    clock: Arc<dyn Clock>,
+    entries: Cache<String, (MsgAddressInt, u64)>,
+    ttl_sec: Option<u64>,
+}
+
+impl ContractAddressCache {
+    fn get(&self, path: &str) -> Option<MsgAddressInt> {
+        let (address, cached_at) = self.entries.get(path)?;
+        match self.ttl_sec {
+            Some(ttl_sec) if cached_at.saturating_add(ttl_sec) <= self.clock.now_sec_u64() => {
+                self.entries.remove(path);
+                None
+            }
+            _ => Some(address),
+        }
+    }
+
+    fn insert(&self, path: String, address: MsgAddressInt) {
+        self.entries
+            .insert(path, (address, self.clock.now_sec_u64()));
+    }
+
+    fn clear(&self) {
+        self.entries.clear();
+    }
+}
+
 /// `DeNS` Top Level Domain
 pub struct DensTld {
     clock: Arc<dyn Clock>,
@@ -233,4 +292,35 @@ enum DensError {
     TldNotFound,
     #[error("Invalid path")]
     InvalidPath,
+    #[error("Domain address not found")]
+    AddressNotFound,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ChainSimulator;
+
+    #[test]
+    fn cache_ttl_does_not_depend_on_order() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let make_builder = || DensBuilder::new(sim.clock().clone(), sim.transport().clone());
+
+        for dens in [
+            make_builder()
+                .with_contract_address_cache(10)
+                .with_contract_address_cache_ttl(60)
+                .build(),
+            make_builder()
+                .with_contract_address_cache_ttl(60)
+                .with_contract_address_cache(10)
+                .build(),
+        ] {
+            let cache = dens.contract_address_cache.as_ref().unwrap();
+            assert_eq!(cache.ttl_sec, Some(60));
+        }
+
+        let dens = make_builder().with_contract_address_cache_ttl(60).build();
+        assert!(dens.contract_address_cache.is_none());
+    }
 }
@@ -0,0 +1,224 @@
+use serde::Serialize;
+use ton_block::AccountState;
+
+use nekoton_utils::*;
+
+use crate::transport::models::ExistingContract;
+
+const SECONDS_PER_DAY: u32 = 86400;
+
+/// Estimates how long the current balance of the account sustains storage fees.
+///
+/// Storage fees are accumulated as a debt once the balance is exhausted. Active
+/// accounts are frozen when the debt exceeds `freeze_due_limit` and all accounts
+/// are deleted when it exceeds `delete_due_limit`
+pub fn estimate_account_lifespan(
+    state: &ExistingContract,
+    config: &ton_executor::BlockchainConfig,
+    now: u32,
+) -> AccountLifespan {
+    let account = &state.account;
+    let is_masterchain = account.addr.workchain_id() == ton_block::MASTERCHAIN_ID;
+
+    // Fee, which is not yet paid at the moment
+    let unpaid_fee = config.calc_storage_fee(&account.storage_stat, is_masterchain, now);
+    let due_payment = account
+        .storage_stat
+        .due_payment
+        .as_ref()
+        .map(|grams| grams.as_u128())
+        .unwrap_or_default()
+        .saturating_add(unpaid_fee);
+
+    let mut storage_stat = account.storage_stat.clone();
+    storage_stat.last_paid = now;
+    let daily_fee = config.calc_storage_fee(
+        &storage_stat,
+        is_masterchain,
+        now.saturating_add(SECONDS_PER_DAY),
+    ) as u64;
+
+    let balance = account.storage.balance.grams.as_u128();
+    let remaining = balance.saturating_sub(due_payment);
+    let debt = due_payment.saturating_sub(balance);
+
+    let gas_config = config.get_gas_config(is_masterchain);
+    let is_active = matches!(account.storage.state, AccountState::AccountActive { .. });
+    let is_frozen = matches!(account.storage.state, AccountState::AccountFrozen { .. });
+
+    let after = |amount: u128| -> Option<u32> {
+        if daily_fee == 0 {
+            return None;
+        }
+        let seconds = amount.saturating_mul(SECONDS_PER_DAY as u128) / daily_fee as u128;
+        Some(
+            u32::try_from(seconds)
+                .unwrap_or(u32::MAX)
+                .saturating_add(now),
+        )
+    };
+
+    let exhausted_at = after(remaining);
+    let frozen_at = if is_active {
+        after((remaining + gas_config.freeze_due_limit as u128).saturating_sub(debt))
+    } else {
+        None
+    };
+    let deleted_at = after((remaining + gas_config.delete_due_limit as u128).saturating_sub(debt));
+
+    AccountLifespan {
+        balance: balance as u64,
+        due_payment: debt as u64,
+        daily_fee,
+        exhausted_at,
+        frozen_at,
+        deleted_at,
+        is_frozen,
+    }
+}
+
+/// Storage fee forecast for the account
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountLifespan {
+    /// Full account balance in nano TON
+    #[serde(with = "serde_string")]
+    pub balance: u64,
+    /// Storage fee debt which was not covered by the balance
+    #[serde(with = "serde_string")]
+    pub due_payment: u64,
+    /// Storage fee for one day in nano TON
+    #[serde(with = "serde_string")]
+    pub daily_fee: u64,
+    /// When the balance will be spent on storage fees. `None` if there are no fees
+    pub exhausted_at: Option<u32>,
+    /// When the account will be frozen. `None` for inactive accounts
+    pub frozen_at: Option<u32>,
+    /// When the account will be deleted
+    pub deleted_at: Option<u32>,
+    /// Whether the account is already frozen
+    pub is_frozen: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+
+    use super::*;
+    use crate::core::models::Expiration;
+    use crate::core::ton_wallet::wallet_v3;
+    use crate::crypto::UnsignedMessage;
+    use crate::testing::ChainSimulator;
+    use crate::transport::models::RawContractState;
+    use crate::transport::Transport;
+
+    async fn deployed_wallet(sim: &ChainSimulator) -> ExistingContract {
+        let secret = SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        let keypair = Keypair { secret, public };
+
+        let address = wallet_v3::compute_contract_address(&public, 0);
+        sim.transport().set_account(
+            address.clone(),
+            ton_block::Account::with_address_and_ballance(
+                &address,
+                &ton_block::CurrencyCollection::with_grams(10_000_000_000),
+            ),
+        );
+
+        let message =
+            wallet_v3::prepare_deploy(sim.clock().as_ref(), &public, 0, Expiration::Timeout(60))
+                .unwrap();
+        let signature = keypair.sign(message.hash()).to_bytes();
+        let message = message.sign(&signature).unwrap().message;
+        sim.transport().send_message(&message).await.unwrap();
+        sim.advance(Duration::from_secs(1)).unwrap();
+
+        match sim.transport().get_contract_state(&address).await.unwrap() {
+            RawContractState::Exists(contract) => contract,
+            RawContractState::NotExists { .. } => panic!("wallet must be deployed"),
+        }
+    }
+
+    fn set_balance(state: &mut ExistingContract, balance: u64) {
+        state.account.storage.balance = ton_block::CurrencyCollection::with_grams(balance);
+    }
+
+    #[tokio::test]
+    async fn lifespan_of_active_account() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let config = nekoton_abi::default_blockchain_config();
+        let gas_config = config.get_gas_config(false);
+
+        let mut state = deployed_wallet(&sim).await;
+        set_balance(&mut state, 1_000_000);
+        let now = state.account.storage_stat.last_paid;
+
+        let lifespan = estimate_account_lifespan(&state, config, now);
+        assert!(lifespan.daily_fee > 0);
+        assert_eq!(lifespan.balance, 1_000_000);
+        assert_eq!(lifespan.due_payment, 0);
+        assert!(!lifespan.is_frozen);
+
+        let after = |amount: u64| {
+            let seconds = amount as u128 * SECONDS_PER_DAY as u128 / lifespan.daily_fee as u128;
+            Some(now + seconds as u32)
+        };
+        assert_eq!(lifespan.exhausted_at, after(1_000_000));
+        assert_eq!(
+            lifespan.frozen_at,
+            after(1_000_000 + gas_config.freeze_due_limit)
+        );
+        assert_eq!(
+            lifespan.deleted_at,
+            after(1_000_000 + gas_config.delete_due_limit)
+        );
+
+        // Timestamps saturate instead of overflowing
+        set_balance(&mut state, u64::MAX);
+        let lifespan = estimate_account_lifespan(&state, config, now);
+        assert_eq!(lifespan.exhausted_at, Some(u32::MAX));
+        assert_eq!(lifespan.deleted_at, Some(u32::MAX));
+    }
+
+    #[tokio::test]
+    async fn lifespan_of_account_with_zero_balance() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let config = nekoton_abi::default_blockchain_config();
+
+        let mut state = deployed_wallet(&sim).await;
+        set_balance(&mut state, 0);
+        let now = state.account.storage_stat.last_paid + 10 * SECONDS_PER_DAY;
+
+        // Unpaid fee since the last payment becomes a debt
+        let lifespan = estimate_account_lifespan(&state, config, now);
+        assert_eq!(lifespan.balance, 0);
+        assert!(lifespan.due_payment > 0);
+        assert_eq!(lifespan.exhausted_at, Some(now));
+
+        let frozen_at = lifespan.frozen_at.unwrap();
+        let deleted_at = lifespan.deleted_at.unwrap();
+        assert!(now < frozen_at && frozen_at < deleted_at);
+    }
+
+    #[tokio::test]
+    async fn lifespan_of_frozen_account() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let config = nekoton_abi::default_blockchain_config();
+
+        let mut state = deployed_wallet(&sim).await;
+        set_balance(&mut state, 0);
+        state.account.storage.state = AccountState::AccountFrozen {
+            state_init_hash: Default::default(),
+        };
+        let now = state.account.storage_stat.last_paid;
+
+        // Frozen accounts can only be deleted
+        let lifespan = estimate_account_lifespan(&state, config, now);
+        assert!(lifespan.is_frozen);
+        assert_eq!(lifespan.frozen_at, None);
+        assert!(lifespan.deleted_at.unwrap() > now);
+    }
+}
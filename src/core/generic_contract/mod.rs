@@ -2,11 +2,17 @@ use std::convert::TryFrom;
 use std::sync::Arc;
 
 use anyhow::Result;
+use parking_lot::Mutex;
 use ton_block::{GetRepresentationHash, MsgAddressInt};
+use ton_types::UInt256;
 
+use nekoton_abi::LastTransactionId;
 use nekoton_utils::Clock;
 
-use super::models::{ContractState, PendingTransaction, Transaction, TransactionsBatchInfo};
+use super::dead_letters::DeadLetters;
+use super::models::{
+    ContractState, PendingTransaction, Transaction, TransactionsBatchInfo, TransactionsCursor,
+};
 use super::{ContractSubscription, PollingMethod, TransactionExecutionOptions};
 use crate::core::utils;
 use crate::transport::models::{RawContractState, RawTransaction};
@@ -15,6 +21,7 @@ use crate::transport::Transport;
 pub struct GenericContract {
     contract_subscription: ContractSubscription,
     handler: Arc<dyn GenericContractSubscriptionHandler>,
+    state_filter: Option<Arc<dyn ContractStateFilter>>,
 }
 
 impl GenericContract {
@@ -24,6 +31,30 @@ impl GenericContract {
         address: MsgAddressInt,
         handler: Arc<dyn GenericContractSubscriptionHandler>,
         preload_transactions: bool,
+    ) -> Result<Self> {
+        Self::subscribe_with_filter(
+            clock,
+            transport,
+            address,
+            handler,
+            None,
+            preload_transactions,
+        )
+        .await
+    }
+
+    /// Same as [`GenericContract::subscribe`], but the handler is notified
+    /// only about the states which pass the filter.
+    ///
+    /// NOTE: filter is applied only to the polled states, states parsed
+    /// from blocks are always reported
+    pub async fn subscribe_with_filter(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        address: MsgAddressInt,
+        handler: Arc<dyn GenericContractSubscriptionHandler>,
+        state_filter: Option<Arc<dyn ContractStateFilter>>,
+        preload_transactions: bool,
     ) -> Result<Self> {
         let contract_subscription = {
             let handler = handler.as_ref();
@@ -47,7 +78,7 @@ impl GenericContract {
                 clock,
                 transport,
                 address,
-                &mut make_contract_state_handler(handler),
+                &mut make_contract_state_handler(handler, state_filter.as_deref()),
                 on_transactions_found,
             )
             .await?
@@ -56,17 +87,59 @@ impl GenericContract {
         Ok(Self {
             contract_subscription,
             handler,
+            state_filter,
         })
     }
 
+    /// Restores the subscription from the snapshot, created by
+    /// [`GenericContract::serialize_state`]. Nothing is requested from the network
+    pub fn restore(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        data: &str,
+        handler: Arc<dyn GenericContractSubscriptionHandler>,
+        state_filter: Option<Arc<dyn ContractStateFilter>>,
+    ) -> Result<Self> {
+        Ok(Self {
+            contract_subscription: ContractSubscription::restore_state(clock, transport, data)?,
+            handler,
+            state_filter,
+        })
+    }
+
+    /// See [`ContractSubscription::serialize_state`]
+    pub fn serialize_state(&self) -> String {
+        self.contract_subscription.serialize_state()
+    }
+
     pub fn address(&self) -> &MsgAddressInt {
         self.contract_subscription.address()
     }
 
+    /// See [`ContractSubscription::set_send_safety_margin`]
+    pub fn set_send_safety_margin(&mut self, seconds: u32) {
+        self.contract_subscription.set_send_safety_margin(seconds);
+    }
+
+    /// See [`ContractSubscription::set_dead_letters`]
+    pub fn set_dead_letters(&mut self, dead_letters: Option<Arc<DeadLetters>>) {
+        self.contract_subscription.set_dead_letters(dead_letters);
+    }
+
+    /// See [`ContractSubscription::subscribe_updates`]
+    pub async fn subscribe_updates(&mut self) -> Result<bool> {
+        self.contract_subscription.subscribe_updates().await
+    }
+
     pub fn contract_state(&self) -> &ContractState {
         self.contract_subscription.contract_state()
     }
 
+    /// Id of the latest known transaction of the contract
+    pub fn last_transaction_id(&self) -> Option<LastTransactionId> {
+        self.contract_state().last_transaction_id
+    }
+
     pub fn pending_transactions(&self) -> &[PendingTransaction] {
         self.contract_subscription.pending_transactions()
     }
@@ -83,11 +156,19 @@ impl GenericContract {
         self.contract_subscription.send(message, expire_at).await
     }
 
+    /// See [`ContractSubscription::send_legacy`]
+    pub async fn send_legacy(
+        &mut self,
+        message: &ton_block::Message,
+    ) -> Result<PendingTransaction> {
+        self.contract_subscription.send_legacy(message).await
+    }
+
     pub async fn refresh(&mut self) -> Result<()> {
         let handler = self.handler.as_ref();
         self.contract_subscription
             .refresh(
-                &mut make_contract_state_handler(handler),
+                &mut make_contract_state_handler(handler, self.state_filter.as_deref()),
                 &mut make_transactions_handler(handler),
                 &mut make_message_sent_handler(handler),
                 &mut make_message_expired_handler(handler),
@@ -113,10 +194,14 @@ impl GenericContract {
         Ok(())
     }
 
-    pub async fn preload_transactions(&mut self, from_lt: u64) -> Result<()> {
+    /// See [`ContractSubscription::preload_transactions`]
+    pub async fn preload_transactions(
+        &mut self,
+        cursor: &TransactionsCursor,
+    ) -> Result<Option<TransactionsCursor>> {
         let handler = self.handler.as_ref();
         self.contract_subscription
-            .preload_transactions(from_lt, &mut make_transactions_handler(handler))
+            .preload_transactions(cursor, &mut make_transactions_handler(handler))
             .await
     }
 
@@ -139,10 +224,16 @@ impl GenericContract {
     }
 }
 
-fn make_contract_state_handler(
-    handler: &dyn GenericContractSubscriptionHandler,
-) -> impl FnMut(&RawContractState) + '_ {
-    move |contract_state| handler.on_state_changed(contract_state.brief())
+fn make_contract_state_handler<'a>(
+    handler: &'a dyn GenericContractSubscriptionHandler,
+    state_filter: Option<&'a dyn ContractStateFilter>,
+) -> impl FnMut(&RawContractState) + 'a {
+    move |contract_state| {
+        if matches!(state_filter, Some(filter) if !filter.is_interesting(contract_state)) {
+            return;
+        }
+        handler.on_state_changed(contract_state.brief())
+    }
 }
 
 fn make_transactions_handler(
@@ -169,6 +260,35 @@ fn make_message_expired_handler(
     move |pending_transaction| handler.on_message_expired(pending_transaction)
 }
 
+pub trait ContractStateFilter: Send + Sync {
+    /// Returns `true` if the handler must be notified about the new state
+    fn is_interesting(&self, new_state: &RawContractState) -> bool;
+}
+
+/// Filter which skips states with the same contract data (e.g. balance changes)
+#[derive(Default)]
+pub struct DataHashFilter {
+    last_hash: Mutex<Option<UInt256>>,
+}
+
+impl ContractStateFilter for DataHashFilter {
+    fn is_interesting(&self, new_state: &RawContractState) -> bool {
+        // NOTE: zero hash is used for the contracts without data
+        let hash = match new_state {
+            RawContractState::Exists(contract) => match &contract.account.storage.state {
+                ton_block::AccountState::AccountActive { state_init, .. } => {
+                    state_init.data.as_ref().map(ton_types::Cell::repr_hash)
+                }
+                _ => None,
+            },
+            RawContractState::NotExists { .. } => None,
+        }
+        .unwrap_or_default();
+
+        self.last_hash.lock().replace(hash) != Some(hash)
+    }
+}
+
 pub trait GenericContractSubscriptionHandler: Send + Sync {
     /// Called when found transaction which is relative with one of the pending transactions
     fn on_message_sent(
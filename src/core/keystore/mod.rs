@@ -14,8 +14,9 @@ use tokio::sync::RwLock;
 use nekoton_utils::*;
 
 use crate::crypto::{
-    EncryptedData, EncryptionAlgorithm, PasswordCache, SharedSecret, Signature, SignatureId,
-    Signer, SignerContext, SignerEntry, SignerStorage,
+    create_encrypted_comment_payload, parse_encrypted_comment_payload, EncryptedData,
+    EncryptionAlgorithm, PasswordCache, SharedSecret, Signature, SignatureId, SignedMessage,
+    Signer, SignerContext, SignerEntry, SignerStorage, UnsignedMessage,
 };
 use crate::external::Storage;
 
@@ -287,6 +288,49 @@ impl KeyStore {
         }
     }
 
+    /// Encrypts the comment for the recipient and encodes it as a message body
+    pub async fn encrypt_comment<T>(
+        &self,
+        comment: &str,
+        recipient_public_key: &ed25519_dalek::PublicKey,
+        input: T::SignInput,
+    ) -> Result<ton_types::SliceData>
+    where
+        T: Signer,
+    {
+        let data = self
+            .encrypt::<T>(
+                comment.as_bytes(),
+                std::slice::from_ref(recipient_public_key),
+                EncryptionAlgorithm::ChaCha20Poly1305,
+                input,
+            )
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(KeyStoreError::SharedSecretError)?;
+        create_encrypted_comment_payload(&data)
+    }
+
+    /// Decrypts the comment from the message body, created by [`KeyStore::encrypt_comment`].
+    ///
+    /// Returns `None` if the body is not an encrypted comment
+    pub async fn decrypt_comment<T>(
+        &self,
+        body: ton_types::SliceData,
+        input: T::SignInput,
+    ) -> Result<Option<String>>
+    where
+        T: Signer,
+    {
+        let data = match parse_encrypted_comment_payload(body) {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let comment = self.decrypt::<T>(&data, input).await?;
+        Ok(Some(String::from_utf8(comment)?))
+    }
+
     pub async fn sign<T>(
         &self,
         data: &[u8],
@@ -307,6 +351,20 @@ impl KeyStore {
             .await
     }
 
+    /// Signs the message hash with the key and fills the signature into the message
+    pub async fn sign_message<T>(
+        &self,
+        message: &dyn UnsignedMessage,
+        signature_id: Option<SignatureId>,
+        input: T::SignInput,
+    ) -> Result<SignedMessage>
+    where
+        T: Signer,
+    {
+        let signature = self.sign::<T>(message.hash(), signature_id, input).await?;
+        message.sign(&signature)
+    }
+
     pub async fn remove_key(&self, public_key: &PublicKey) -> Result<Option<KeyStoreEntry>> {
         let mut state = self.state.write().await;
 
@@ -590,42 +648,12 @@ pub enum KeyStoreError {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::crypto::{
         DerivedKeyCreateInput, DerivedKeyPassword, DerivedKeySigner, EncryptedKeyCreateInput,
         EncryptedKeyPassword, EncryptedKeySigner, MnemonicType, Password, PasswordCacheBehavior,
     };
-    use std::collections::HashMap;
-
-    use super::*;
-
-    #[derive(Default)]
-    struct TestStorage(parking_lot::Mutex<HashMap<String, String>>);
-
-    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
-    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
-    impl Storage for TestStorage {
-        async fn get(&self, key: &str) -> Result<Option<String>> {
-            Ok(self.0.lock().get(key).cloned())
-        }
-
-        async fn set(&self, key: &str, value: &str) -> Result<()> {
-            self.set_unchecked(key, value);
-            Ok(())
-        }
-
-        fn set_unchecked(&self, key: &str, value: &str) {
-            self.0.lock().insert(key.to_string(), value.to_string());
-        }
-
-        async fn remove(&self, key: &str) -> Result<()> {
-            self.remove_unchecked(key);
-            Ok(())
-        }
-
-        fn remove_unchecked(&self, key: &str) {
-            self.0.lock().remove(key);
-        }
-    }
+    use crate::testing::MemoryStorage;
 
     const TEST_MNEMONICS: [&str; 2] = [
         "admit cheap engage ancient audit drink mammal mobile fashion aspect rapid else",
@@ -634,7 +662,7 @@ mod tests {
 
     #[tokio::test]
     async fn correct_encryption() {
-        let storage = Arc::new(TestStorage::default());
+        let storage = Arc::new(MemoryStorage::default());
 
         let keystore = KeyStore::builder()
             .with_signer("master_key", DerivedKeySigner::new())
@@ -709,4 +737,71 @@ mod tests {
             .unwrap();
         assert_eq!(data, TEST_DATA);
     }
+
+    #[tokio::test]
+    async fn sign_message() {
+        use ed25519_dalek::Verifier;
+
+        use crate::core::models::Expiration;
+        use crate::core::ton_wallet::wallet_v3;
+
+        let storage = Arc::new(MemoryStorage::default());
+        let keystore = KeyStore::builder()
+            .with_signer("encrypted_key", EncryptedKeySigner::new())
+            .unwrap()
+            .load(storage)
+            .await
+            .unwrap();
+
+        let key = keystore
+            .add_key::<EncryptedKeySigner>(EncryptedKeyCreateInput {
+                name: None,
+                phrase: TEST_MNEMONICS[1].into(),
+                mnemonic_type: MnemonicType::Labs(0),
+                password: Password::Explicit {
+                    password: "test".into(),
+                    cache_behavior: PasswordCacheBehavior::Store(Duration::from_secs(1000)),
+                },
+            })
+            .await
+            .unwrap();
+        let input = || EncryptedKeyPassword {
+            public_key: key.public_key,
+            password: Password::FromCache,
+        };
+
+        let message =
+            wallet_v3::prepare_deploy(&SimpleClock, &key.public_key, 0, Expiration::Timeout(60))
+                .unwrap();
+        let signed = keystore
+            .sign_message::<EncryptedKeySigner>(message.as_ref(), None, input())
+            .await
+            .unwrap();
+        assert_eq!(signed.expire_at, message.expire_at());
+
+        // Same as signing the hash and attaching the signature manually
+        let signature = keystore
+            .sign::<EncryptedKeySigner>(message.hash(), None, input())
+            .await
+            .unwrap();
+        let expected = message.sign(&signature).unwrap();
+        assert_eq!(signed.message, expected.message);
+
+        let signature = ed25519_dalek::Signature::from_bytes(&signature).unwrap();
+        assert!(key.public_key.verify(message.hash(), &signature).is_ok());
+
+        // Keys of unknown signers are rejected
+        assert!(keystore
+            .sign_message::<DerivedKeySigner>(
+                message.as_ref(),
+                None,
+                DerivedKeyPassword::ByPublicKey {
+                    master_key: key.public_key,
+                    public_key: key.public_key,
+                    password: Password::FromCache,
+                },
+            )
+            .await
+            .is_err());
+    }
 }
@@ -1,24 +1,42 @@
 use anyhow::Result;
 use nekoton_utils::*;
 use serde::{Deserialize, Serialize};
+use ton_block::GetRepresentationHash;
 
-pub use self::contract_subscription::{ContractSubscription, TransactionExecutionOptions};
+pub use self::contract_subscription::{
+    ContractSubscription, LocalExecutionResult, TracedExecutionError, TransactionExecutionOptions,
+};
 use self::models::PollingMethod;
+use crate::transport::models::RawTransaction;
 use crate::transport::Transport;
 
+pub mod abi_message;
 pub mod accounts_storage;
 pub mod contract_subscription;
+pub mod dead_letters;
 pub mod dens;
+pub mod fees;
 pub mod generic_contract;
 pub mod keystore;
+pub mod multisig_tracker;
+pub mod network_manager;
 pub use super::models;
 pub mod nft_wallet;
 pub mod owners_cache;
 pub mod parsing;
+pub mod precheck;
+pub mod prefetcher;
+pub mod receive;
+pub mod reports;
+pub mod scheduled_messages;
+pub mod security;
+pub mod sign_queue;
 pub mod token_wallet;
 pub mod ton_wallet;
+pub mod transactions_history;
 pub mod transactions_tree;
 pub mod utils;
+pub mod validation;
 
 pub struct TonInterface {
     transport: Box<dyn Transport>,
@@ -33,11 +51,44 @@ impl TonInterface {
         self.transport.send_message(message).await
     }
 
+    /// Sends the message only if it was not already processed.
+    ///
+    /// Returns the transaction if the message has already been delivered.
+    /// Must be used when retrying the same message after a timeout,
+    /// e.g. to prevent duplicate multisig submissions
+    pub async fn send_message_once(
+        &self,
+        message: &ton_block::Message,
+    ) -> Result<Option<RawTransaction>> {
+        send_message_once(self.transport.as_ref(), message).await
+    }
+
     pub fn set_transport(&mut self, transport: Box<dyn Transport>) {
         self.transport = transport;
     }
 }
 
+/// Sends the message only if it was not already processed.
+///
+/// Returns the transaction if the message has already been delivered.
+/// The check is skipped for transports without [`message_lookup`] support.
+///
+/// [`message_lookup`]: crate::transport::TransportCapabilities::message_lookup
+pub async fn send_message_once(
+    transport: &dyn Transport,
+    message: &ton_block::Message,
+) -> Result<Option<RawTransaction>> {
+    if transport.capabilities().message_lookup {
+        let message_hash = message.hash()?;
+        if let Some(transaction) = transport.get_dst_transaction(&message_hash).await? {
+            return Ok(Some(transaction));
+        }
+    }
+
+    transport.send_message(message).await?;
+    Ok(None)
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct InternalMessage {
     #[serde(
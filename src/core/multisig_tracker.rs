@@ -0,0 +1,379 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use ed25519_dalek::PublicKey;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use ton_block::MsgAddressInt;
+use ton_types::{Cell, UInt256};
+
+use nekoton_utils::*;
+
+use super::ton_wallet::multisig::offline::SignatureBundle;
+use super::ton_wallet::MultisigType;
+use crate::crypto::Signature;
+use crate::external::Storage;
+
+pub const MULTISIG_TRACKER_STORAGE_KEY: &str = "__core__multisig_tracker";
+
+/// Stores multisig operations which are waiting for confirmations,
+/// so that their state survives application restarts
+pub struct MultisigTracker {
+    key: String,
+    storage: Arc<dyn Storage>,
+    operations: RwLock<Vec<MultisigOperation>>,
+}
+
+impl MultisigTracker {
+    pub async fn load(network_group: &str, storage: Arc<dyn Storage>) -> Result<Self> {
+        let key = make_key(network_group);
+
+        let operations = match storage.get(&key).await? {
+            Some(data) => serde_json::from_str::<Vec<MultisigOperation>>(&data)?,
+            None => Default::default(),
+        };
+
+        Ok(Self {
+            key,
+            storage,
+            operations: RwLock::new(operations),
+        })
+    }
+
+    pub async fn load_unchecked(network_group: &str, storage: Arc<dyn Storage>) -> Self {
+        Self::load(network_group, storage.clone())
+            .await
+            .unwrap_or_else(|_| Self {
+                key: make_key(network_group),
+                storage,
+                operations: Default::default(),
+            })
+    }
+
+    /// Starts tracking the operation. Replaces the operation with the same id
+    pub async fn track(&self, operation: MultisigOperation) {
+        let mut operations = self.operations.write().await;
+        operations.retain(|item| item.id != operation.id);
+        operations.push(operation);
+        self.save(&operations);
+    }
+
+    /// Returns all tracked operations of the multisig wallet
+    pub async fn operations(&self, address: &MsgAddressInt) -> Vec<MultisigOperation> {
+        self.operations
+            .read()
+            .await
+            .iter()
+            .filter(|item| &item.address == address)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn get(&self, id: &UInt256) -> Option<MultisigOperation> {
+        self.operations
+            .read()
+            .await
+            .iter()
+            .find(|item| &item.id == id)
+            .cloned()
+    }
+
+    /// Stores the multisig transaction id, received from the submit transaction
+    pub async fn set_submitted(
+        &self,
+        id: &UInt256,
+        submit_transaction_hash: UInt256,
+        transaction_id: u64,
+    ) -> Result<()> {
+        self.update(id, |operation| {
+            operation.submit_transaction_hash = Some(submit_transaction_hash);
+            operation.transaction_id = Some(transaction_id);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Attaches unsigned confirmations for the co-signers
+    pub async fn set_bundle(&self, id: &UInt256, bundle: SignatureBundle) -> Result<()> {
+        self.update(id, |operation| {
+            if operation.address != bundle.address
+                || operation.multisig_type != bundle.multisig_type
+            {
+                return Err(MultisigTrackerError::WalletMismatch.into());
+            }
+            if operation.transaction_id != Some(bundle.transaction_id) {
+                return Err(MultisigTrackerError::TransactionMismatch.into());
+            }
+            operation.bundle = Some(bundle);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Verifies and stores the signature of the co-signer
+    pub async fn add_signature(
+        &self,
+        id: &UInt256,
+        public_key: &PublicKey,
+        signature: Signature,
+    ) -> Result<()> {
+        self.update(id, |operation| match &mut operation.bundle {
+            Some(bundle) => bundle.add_signature(public_key, signature),
+            None => Err(MultisigTrackerError::BundleNotFound.into()),
+        })
+        .await
+    }
+
+    /// Stops tracking the operation (e.g. when it was executed or expired)
+    pub async fn remove(&self, id: &UInt256) -> Option<MultisigOperation> {
+        let mut operations = self.operations.write().await;
+        let index = operations.iter().position(|item| &item.id == id)?;
+        let operation = operations.remove(index);
+        self.save(&operations);
+        Some(operation)
+    }
+
+    /// Removes all operations which can no longer be confirmed.
+    /// Returns removed operations
+    pub async fn remove_expired(&self, clock: &dyn Clock) -> Vec<MultisigOperation> {
+        let now = clock.now_sec_u64() as u32;
+
+        let mut operations = self.operations.write().await;
+        let (expired, alive) = std::mem::take(&mut *operations)
+            .into_iter()
+            .partition::<Vec<_>, _>(|item| item.expire_at <= now);
+        *operations = alive;
+
+        if !expired.is_empty() {
+            self.save(&operations);
+        }
+        expired
+    }
+
+    pub async fn clear(&self) {
+        let mut operations = self.operations.write().await;
+        operations.clear();
+        self.storage.remove_unchecked(&self.key);
+    }
+
+    async fn update<F>(&self, id: &UInt256, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut MultisigOperation) -> Result<()>,
+    {
+        let mut operations = self.operations.write().await;
+        let operation = operations
+            .iter_mut()
+            .find(|item| &item.id == id)
+            .ok_or(MultisigTrackerError::OperationNotFound)?;
+        f(operation)?;
+        self.save(&operations);
+        Ok(())
+    }
+
+    fn save(&self, operations: &[MultisigOperation]) {
+        let data = serde_json::to_string(operations).trust_me();
+        self.storage.set_unchecked(&self.key, &data);
+    }
+}
+
+fn make_key(network_group: &str) -> String {
+    format!("{MULTISIG_TRACKER_STORAGE_KEY}{network_group}")
+}
+
+/// Multisig transfer which is waiting for confirmations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MultisigOperation {
+    /// Hash of the submit message
+    #[serde(with = "serde_uint256")]
+    pub id: UInt256,
+    /// Multisig wallet address
+    #[serde(with = "serde_address")]
+    pub address: MsgAddressInt,
+    pub multisig_type: MultisigType,
+    #[serde(with = "serde_address")]
+    pub destination: MsgAddressInt,
+    #[serde(with = "serde_string")]
+    pub amount: u64,
+    pub bounce: bool,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_optional_cell"
+    )]
+    pub payload: Option<Cell>,
+    pub required_confirmations: u8,
+    pub created_at: u32,
+    /// Multisig transaction lifetime end
+    pub expire_at: u32,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_optional_uint256"
+    )]
+    pub submit_transaction_hash: Option<UInt256>,
+    /// Multisig transaction id, known after the submit transaction
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_optional_u64"
+    )]
+    pub transaction_id: Option<u64>,
+    /// Confirmations collected from the co-signers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<SignatureBundle>,
+}
+
+impl MultisigOperation {
+    pub fn is_submitted(&self) -> bool {
+        self.transaction_id.is_some()
+    }
+
+    /// Number of collected offline confirmations
+    pub fn signature_count(&self) -> usize {
+        self.bundle
+            .as_ref()
+            .map(SignatureBundle::signature_count)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone)]
+pub enum MultisigTrackerError {
+    #[error("Operation not found")]
+    OperationNotFound,
+    #[error("Bundle belongs to another multisig wallet")]
+    WalletMismatch,
+    #[error("Operation is not submitted or submitted as another transaction")]
+    TransactionMismatch,
+    #[error("Confirmations bundle not found")]
+    BundleNotFound,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+
+    use super::*;
+    use crate::core::models::Expiration;
+    use crate::testing::MemoryStorage;
+
+    fn keypair(byte: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[byte; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    #[tokio::test]
+    async fn operation_survives_reload() {
+        let clock = ConstClock::from_secs(1650000000);
+        let storage = Arc::new(MemoryStorage::default());
+        let tracker = MultisigTracker::load("test", storage.clone())
+            .await
+            .unwrap();
+
+        let custodian = keypair(1);
+        let address = MsgAddressInt::default();
+        let id = UInt256::from([1; 32]);
+
+        let mut payload = ton_types::BuilderData::new();
+        payload.append_u32(123).unwrap();
+        let payload = payload.into_cell().unwrap();
+
+        tracker
+            .track(MultisigOperation {
+                id,
+                address: address.clone(),
+                multisig_type: MultisigType::Multisig2,
+                destination: address.clone(),
+                amount: 1_000_000_000,
+                bounce: false,
+                payload: Some(payload.clone()),
+                required_confirmations: 2,
+                created_at: 1650000000,
+                expire_at: 1650003600,
+                submit_transaction_hash: None,
+                transaction_id: None,
+                bundle: None,
+            })
+            .await;
+
+        // Bundle is accepted only for the submitted transaction
+        let bundle = SignatureBundle::new(
+            &clock,
+            MultisigType::Multisig2,
+            address.clone(),
+            123,
+            &[custodian.public],
+            Expiration::Timeout(60),
+            None,
+        )
+        .unwrap();
+        assert!(tracker.set_bundle(&id, bundle.clone()).await.is_err());
+
+        tracker
+            .set_submitted(&id, UInt256::from([2; 32]), 123)
+            .await
+            .unwrap();
+
+        // Bundle must be prepared for the same wallet
+        let make_bundle = |multisig_type, address| {
+            SignatureBundle::new(
+                &clock,
+                multisig_type,
+                address,
+                123,
+                &[custodian.public],
+                Expiration::Timeout(60),
+                None,
+            )
+            .unwrap()
+        };
+        let other_address = MsgAddressInt::from_str(
+            "-1:3333333333333333333333333333333333333333333333333333333333333333",
+        )
+        .unwrap();
+        assert!(tracker
+            .set_bundle(&id, make_bundle(MultisigType::Multisig2, other_address))
+            .await
+            .is_err());
+        assert!(tracker
+            .set_bundle(&id, make_bundle(MultisigType::Multisig2_1, address.clone()))
+            .await
+            .is_err());
+
+        tracker.set_bundle(&id, bundle).await.unwrap();
+
+        let hash = tracker
+            .get(&id)
+            .await
+            .unwrap()
+            .bundle
+            .unwrap()
+            .entry(&custodian.public)
+            .unwrap()
+            .hash;
+        let signature = custodian.sign(hash.as_slice()).to_bytes();
+        assert!(tracker
+            .add_signature(&id, &keypair(2).public, signature)
+            .await
+            .is_err());
+        tracker
+            .add_signature(&id, &custodian.public, signature)
+            .await
+            .unwrap();
+
+        let reloaded = MultisigTracker::load("test", storage).await.unwrap();
+        let operations = reloaded.operations(&address).await;
+        assert_eq!(operations.len(), 1);
+
+        let operation = &operations[0];
+        assert_eq!(operation.id, id);
+        assert_eq!(operation.payload, Some(payload));
+        assert_eq!(operation.transaction_id, Some(123));
+        assert!(operation.is_submitted());
+        assert_eq!(operation.signature_count(), 1);
+    }
+}
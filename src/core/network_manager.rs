@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Weak};
+
+use anyhow::Result;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+
+use nekoton_utils::*;
+
+use super::dead_letters::DeadLetters;
+use super::multisig_tracker::MultisigTracker;
+use super::owners_cache::OwnersCache;
+use super::ton_wallet::CustomMultisigCodes;
+use crate::external::Storage;
+use crate::transport::Transport;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NetworkCachesConfig {
+    /// Max number of concurrent owner resolutions of the owners cache
+    pub concurrent_resolvers: usize,
+    /// Max number of stored undelivered messages
+    pub dead_letters_capacity: usize,
+    /// Min interval between the owners cache writes in milliseconds
+    pub owners_cache_write_interval_ms: u64,
+}
+
+impl Default for NetworkCachesConfig {
+    fn default() -> Self {
+        Self {
+            concurrent_resolvers: 4,
+            dead_letters_capacity: 100,
+            owners_cache_write_interval_ms: 1000,
+        }
+    }
+}
+
+/// Everything needed to work with a single network
+pub struct Network {
+    name: String,
+    network_group: String,
+    clock: Arc<dyn Clock>,
+    transport: Arc<dyn Transport>,
+    caches: Arc<NetworkCaches>,
+}
+
+impl Network {
+    /// Loads network caches from the storage.
+    ///
+    /// Caches are stored separately for each `network_group`.
+    ///
+    /// NOTE: loaded caches are not shared with other instances, so several networks
+    /// of the same group must be loaded with [`NetworkManager::load_network`]
+    pub async fn load(
+        name: &str,
+        network_group: &str,
+        clock: Arc<dyn Clock>,
+        storage: Arc<dyn Storage>,
+        transport: Arc<dyn Transport>,
+        config: NetworkCachesConfig,
+    ) -> Self {
+        let caches = NetworkCaches::load(
+            network_group,
+            clock.clone(),
+            storage,
+            transport.clone(),
+            config,
+        )
+        .await;
+
+        Self {
+            name: name.to_owned(),
+            network_group: network_group.to_owned(),
+            clock,
+            transport,
+            caches: Arc::new(caches),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn network_group(&self) -> &str {
+        &self.network_group
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    pub fn transport(&self) -> &Arc<dyn Transport> {
+        &self.transport
+    }
+
+    pub fn owners_cache(&self) -> &Arc<OwnersCache> {
+        &self.caches.owners_cache
+    }
+
+    /// Multisig forks, registered for this network group
+    pub fn custom_multisig_codes(&self) -> &Arc<CustomMultisigCodes> {
+        &self.caches.custom_multisig_codes
+    }
+
+    pub fn dead_letters(&self) -> &Arc<DeadLetters> {
+        &self.caches.dead_letters
+    }
+
+    pub fn multisig_tracker(&self) -> &Arc<MultisigTracker> {
+        &self.caches.multisig_tracker
+    }
+}
+
+/// Caches which are stored under the same `network_group`
+struct NetworkCaches {
+    owners_cache: Arc<OwnersCache>,
+    dead_letters: Arc<DeadLetters>,
+    multisig_tracker: Arc<MultisigTracker>,
+    /// Not persisted, forks must be registered after each load
+    custom_multisig_codes: Arc<CustomMultisigCodes>,
+}
+
+impl NetworkCaches {
+    async fn load(
+        network_group: &str,
+        clock: Arc<dyn Clock>,
+        storage: Arc<dyn Storage>,
+        transport: Arc<dyn Transport>,
+        config: NetworkCachesConfig,
+    ) -> Self {
+        let owners_cache = OwnersCache::load_unchecked(
+            network_group,
+            clock,
+            storage.clone(),
+            transport,
+            config.concurrent_resolvers,
+        )
+        .await
+        .with_write_interval(config.owners_cache_write_interval_ms);
+        let dead_letters = DeadLetters::load_unchecked(
+            network_group,
+            storage.clone(),
+            config.dead_letters_capacity,
+        )
+        .await;
+        let multisig_tracker = MultisigTracker::load_unchecked(network_group, storage).await;
+
+        Self {
+            owners_cache: Arc::new(owners_cache),
+            dead_letters: Arc::new(dead_letters),
+            multisig_tracker: Arc::new(multisig_tracker),
+            custom_multisig_codes: Default::default(),
+        }
+    }
+}
+
+/// Owns several named networks (e.g. Everscale, Venom and TON) and tracks
+/// the one which is currently selected by the user
+#[derive(Default)]
+pub struct NetworkManager {
+    state: parking_lot::RwLock<NetworkManagerState>,
+    /// Caches of the loaded networks by `network_group`
+    groups: tokio::sync::Mutex<HashMap<String, Weak<NetworkCaches>>>,
+}
+
+#[derive(Default)]
+struct NetworkManagerState {
+    networks: Vec<Arc<Network>>,
+    active: Option<usize>,
+}
+
+impl NetworkManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the network and adds it to the manager (see [`NetworkManager::add_network`]).
+    ///
+    /// Networks with the same `network_group` (e.g. several endpoints of the same chain)
+    /// share the caches, so they don't overwrite the stored data of each other
+    pub async fn load_network(
+        &self,
+        name: &str,
+        network_group: &str,
+        clock: Arc<dyn Clock>,
+        storage: Arc<dyn Storage>,
+        transport: Arc<dyn Transport>,
+        config: NetworkCachesConfig,
+    ) -> Arc<Network> {
+        let caches = {
+            let mut groups = self.groups.lock().await;
+            match groups.get(network_group).and_then(Weak::upgrade) {
+                Some(caches) => caches,
+                None => {
+                    let caches = Arc::new(
+                        NetworkCaches::load(
+                            network_group,
+                            clock.clone(),
+                            storage,
+                            transport.clone(),
+                            config,
+                        )
+                        .await,
+                    );
+                    groups.insert(network_group.to_owned(), Arc::downgrade(&caches));
+                    caches
+                }
+            }
+        };
+
+        self.add_network(Network {
+            name: name.to_owned(),
+            network_group: network_group.to_owned(),
+            clock,
+            transport,
+            caches,
+        })
+    }
+
+    /// Adds the network or replaces the network with the same name.
+    ///
+    /// The first added network becomes active
+    pub fn add_network(&self, network: Network) -> Arc<Network> {
+        let network = Arc::new(network);
+
+        let mut state = self.state.write();
+        match state.position(network.name()) {
+            Some(index) => state.networks[index] = network.clone(),
+            None => {
+                state.networks.push(network.clone());
+                if state.active.is_none() {
+                    state.active = Some(state.networks.len() - 1);
+                }
+            }
+        }
+        network
+    }
+
+    /// Removes the network. Active network is reset if it was removed
+    pub fn remove_network(&self, name: &str) -> Option<Arc<Network>> {
+        let mut state = self.state.write();
+        let index = state.position(name)?;
+        let network = state.networks.remove(index);
+        state.active = match state.active {
+            Some(active) if active == index => None,
+            Some(active) if active > index => Some(active - 1),
+            active => active,
+        };
+        Some(network)
+    }
+
+    pub fn get_network(&self, name: &str) -> Option<Arc<Network>> {
+        let state = self.state.read();
+        state
+            .position(name)
+            .map(|index| state.networks[index].clone())
+    }
+
+    /// Returns names of all networks in the order they were added
+    pub fn network_names(&self) -> Vec<String> {
+        let state = self.state.read();
+        state
+            .networks
+            .iter()
+            .map(|network| network.name.clone())
+            .collect()
+    }
+
+    pub fn active_network(&self) -> Option<Arc<Network>> {
+        let state = self.state.read();
+        state.active.map(|index| state.networks[index].clone())
+    }
+
+    /// Selects the active network. Returns the selected network
+    pub fn set_active_network(&self, name: &str) -> Result<Arc<Network>> {
+        let mut state = self.state.write();
+        let index = state
+            .position(name)
+            .ok_or(NetworkManagerError::NetworkNotFound)?;
+        state.active = Some(index);
+        Ok(state.networks[index].clone())
+    }
+
+    /// Runs the task (e.g. subscription refresh) on the specified networks simultaneously.
+    ///
+    /// Results are returned in the same order as names. Failure on one
+    /// network doesn't affect the others
+    pub async fn run_on<'a, I, F, R, T>(&self, names: I, mut f: F) -> Vec<(String, Result<T>)>
+    where
+        I: IntoIterator<Item = &'a str>,
+        F: FnMut(Arc<Network>) -> R,
+        R: Future<Output = Result<T>>,
+    {
+        let networks = names
+            .into_iter()
+            .map(|name| (name.to_owned(), self.get_network(name)))
+            .collect::<Vec<_>>();
+
+        let tasks = networks.into_iter().map(|(name, network)| {
+            let task = network.map(&mut f);
+            async move {
+                let result = match task {
+                    Some(task) => task.await,
+                    None => Err(NetworkManagerError::NetworkNotFound.into()),
+                };
+                (name, result)
+            }
+        });
+
+        join_all(tasks).await
+    }
+
+    /// Runs the task on all networks simultaneously
+    pub async fn run_on_all<F, R, T>(&self, f: F) -> Vec<(String, Result<T>)>
+    where
+        F: FnMut(Arc<Network>) -> R,
+        R: Future<Output = Result<T>>,
+    {
+        let names = self.network_names();
+        self.run_on(names.iter().map(String::as_str), f).await
+    }
+}
+
+impl NetworkManagerState {
+    fn position(&self, name: &str) -> Option<usize> {
+        self.networks
+            .iter()
+            .position(|network| network.name == name)
+    }
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone)]
+pub enum NetworkManagerError {
+    #[error("Network not found")]
+    NetworkNotFound,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ton_block::MsgAddressInt;
+
+    use super::*;
+    use crate::testing::{ChainSimulator, MemoryStorage};
+
+    #[tokio::test]
+    async fn networks_of_the_same_group_share_caches() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let storage = Arc::new(MemoryStorage::default());
+        let manager = NetworkManager::new();
+
+        let load = |name: &'static str, network_group: &'static str| {
+            manager.load_network(
+                name,
+                network_group,
+                sim.clock().clone(),
+                storage.clone(),
+                sim.transport().clone(),
+                Default::default(),
+            )
+        };
+
+        let first = load("mainnet", "everscale").await;
+        let second = load("mainnet-gql", "everscale").await;
+        let other = load("testnet", "everscale-testnet").await;
+
+        assert!(Arc::ptr_eq(first.owners_cache(), second.owners_cache()));
+        assert!(Arc::ptr_eq(first.dead_letters(), second.dead_letters()));
+        assert!(Arc::ptr_eq(
+            first.multisig_tracker(),
+            second.multisig_tracker()
+        ));
+        assert!(Arc::ptr_eq(
+            first.custom_multisig_codes(),
+            second.custom_multisig_codes()
+        ));
+        assert!(!Arc::ptr_eq(first.owners_cache(), other.owners_cache()));
+        assert!(!Arc::ptr_eq(
+            first.custom_multisig_codes(),
+            other.custom_multisig_codes()
+        ));
+
+        let token_wallet = MsgAddressInt::from_str(
+            "0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb",
+        )
+        .unwrap();
+        let owner_wallet = MsgAddressInt::from_str(
+            "0:02e3f2284e68a8106b823ab9f2404f33cc43fccad8e1de835bdd96789254686c",
+        )
+        .unwrap();
+        first
+            .owners_cache()
+            .add_entry(token_wallet.clone(), owner_wallet.clone())
+            .await;
+        assert_eq!(
+            second.owners_cache().get_owner(&token_wallet).await,
+            Some(owner_wallet)
+        );
+        assert_eq!(other.owners_cache().get_owner(&token_wallet).await, None);
+
+        // Caches are loaded again when all networks of the group are removed
+        manager.remove_network("mainnet");
+        manager.remove_network("mainnet-gql");
+        drop((first, second));
+        let reloaded = load("mainnet", "everscale").await;
+        assert_eq!(reloaded.owners_cache().stats().await.entries, 1);
+    }
+}
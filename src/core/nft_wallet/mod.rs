@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::sync::Arc;
 
 use anyhow::Result;
@@ -8,11 +8,14 @@ use nekoton_contracts::tip4_1::nft_contract::{self, GetInfoOutputs, NftCallbackP
 use nekoton_contracts::tip4_3::index_contract::IndexGetInfoOutputs;
 use nekoton_contracts::*;
 use nekoton_utils::Clock;
+use serde::{Deserialize, Serialize};
 use ton_block::{MsgAddressInt, Serializable};
 use ton_types::{BuilderData, Cell, UInt256};
 
+use crate::core::dead_letters::DeadLetters;
 use crate::core::models::{
     NftTransaction, PendingTransaction, Transaction, TransactionWithData, TransactionsBatchInfo,
+    TransactionsCursor,
 };
 use crate::core::parsing::parse_nft_transaction;
 use crate::core::{ContractSubscription, InternalMessage};
@@ -21,6 +24,9 @@ use crate::transport::Transport;
 
 const NFT_STAMP: &[u8; 3] = b"nft";
 
+/// Number of index contracts requested at once by [`NftSubscription`]
+const OWNED_NFTS_BATCH_SIZE: u8 = 50;
+
 pub struct NftCollection {
     transport: Arc<dyn Transport>,
     collection_address: MsgAddressInt,
@@ -31,11 +37,49 @@ pub struct NftCollection {
     owner: Option<MsgAddressInt>,
 }
 
+#[derive(Debug, Clone)]
 pub enum JsonInfo {
     Json(String),
     Url(String),
 }
 
+impl JsonInfo {
+    /// Parses TIP-4.2 metadata.
+    ///
+    /// Returns `None` for the metadata which is stored by url (it must be fetched first)
+    pub fn parse_metadata(&self) -> Option<Result<NftMetadata>> {
+        match self {
+            Self::Json(json) => Some(serde_json::from_str(json).map_err(From::from)),
+            Self::Url(_) => None,
+        }
+    }
+}
+
+/// TIP-4.2 JSON metadata. All fields are optional because
+/// the metadata is not validated by the contracts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NftMetadata {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<NftMetadataFile>,
+    pub files: Vec<NftMetadataFile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NftMetadataFile {
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mimetype: Option<String>,
+}
+
 impl NftCollection {
     pub async fn new(
         clock: &dyn Clock,
@@ -111,17 +155,200 @@ impl NftCollection {
         limit: u8,
         continuation: Option<MsgAddressInt>,
     ) -> Result<Vec<MsgAddressInt>> {
+        if !self.transport.capabilities().code_hash_search {
+            return Err(NftError::CodeHashSearchNotSupported.into());
+        }
+
         let code_hash = self.compute_collection_code_hash(owner)?;
         self.transport
             .get_accounts_by_code_hash(&code_hash, limit, &continuation)
             .await
     }
 
+    /// Returns addresses of the collection items, owned by the specified owner.
+    ///
+    /// States of the index contracts are requested concurrently. Indices which point to
+    /// another collection or owner (e.g. stale ones) are skipped
+    pub async fn get_owned_nfts(
+        &self,
+        clock: &dyn Clock,
+        owner: &MsgAddressInt,
+        limit: u8,
+        continuation: Option<MsgAddressInt>,
+    ) -> Result<OwnedNftsBatch> {
+        let indices = self
+            .get_nft_index_contracts(owner, limit, continuation)
+            .await?;
+
+        let continuation = match indices.last() {
+            Some(last) if indices.len() >= limit as usize => Some(last.clone()),
+            _ => None,
+        };
+
+        let states = if self.transport.capabilities().batched_states {
+            self.transport
+                .get_contract_states(&indices)
+                .await?
+                .into_iter()
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            futures_util::future::try_join_all(
+                indices
+                    .iter()
+                    .map(|index| self.transport.get_contract_state(index)),
+            )
+            .await?
+        };
+
+        let nfts = filter_owned_nfts(&self.collection_address, owner, &states, |state| {
+            tip4_3::IndexContract(state.as_context(clock)).get_info()
+        })?;
+
+        Ok(OwnedNftsBatch { nfts, continuation })
+    }
+
     pub fn interfaces(&self) -> &CollectionInterfaces {
         &self.interfaces
     }
 }
 
+/// Page of the owned collection items
+#[derive(Debug, Clone)]
+pub struct OwnedNftsBatch {
+    /// NFT contract addresses
+    pub nfts: Vec<MsgAddressInt>,
+    /// Index contract address to continue from. `None` if there are no more items
+    pub continuation: Option<MsgAddressInt>,
+}
+
+/// Returns items of the indices which point to the specified collection and owner
+fn filter_owned_nfts<F>(
+    collection: &MsgAddressInt,
+    owner: &MsgAddressInt,
+    states: &[RawContractState],
+    mut get_info: F,
+) -> Result<Vec<MsgAddressInt>>
+where
+    F: FnMut(&ExistingContract) -> Result<IndexGetInfoOutputs>,
+{
+    let mut nfts = Vec::with_capacity(states.len());
+    for state in states {
+        let state = match state {
+            RawContractState::Exists(state) => state,
+            RawContractState::NotExists { .. } => continue,
+        };
+
+        let info = get_info(state)?;
+        if &info.collection == collection && &info.owner == owner {
+            nfts.push(info.nft);
+        }
+    }
+    Ok(nfts)
+}
+
+/// Tracks the collection items, owned by the specified owner.
+///
+/// Owned items are enumerated with [`NftCollection::get_owned_nfts`] on each refresh,
+/// so received and transferred items are found without subscribing to each of them
+pub struct NftSubscription {
+    clock: Arc<dyn Clock>,
+    collection: NftCollection,
+    owner: MsgAddressInt,
+    owned_nfts: OwnedNfts,
+    handler: Arc<dyn NftOwnershipHandler>,
+}
+
+impl NftSubscription {
+    /// Enumerates the currently owned items. They are reported
+    /// as received with [`NftOwnershipHandler::on_nfts_received`]
+    pub async fn subscribe(
+        clock: Arc<dyn Clock>,
+        collection: NftCollection,
+        owner: MsgAddressInt,
+        handler: Arc<dyn NftOwnershipHandler>,
+    ) -> Result<Self> {
+        let mut subscription = Self {
+            clock,
+            collection,
+            owner,
+            owned_nfts: Default::default(),
+            handler,
+        };
+        subscription.refresh().await?;
+        Ok(subscription)
+    }
+
+    pub fn collection(&self) -> &NftCollection {
+        &self.collection
+    }
+
+    pub fn owner(&self) -> &MsgAddressInt {
+        &self.owner
+    }
+
+    /// Items which were owned during the last refresh
+    pub fn owned_nfts(&self) -> &HashSet<MsgAddressInt> {
+        &self.owned_nfts.0
+    }
+
+    /// Enumerates owned items again and reports the difference
+    pub async fn refresh(&mut self) -> Result<()> {
+        let mut owned_nfts = HashSet::new();
+        let mut continuation = None;
+        loop {
+            let batch = self
+                .collection
+                .get_owned_nfts(
+                    self.clock.as_ref(),
+                    &self.owner,
+                    OWNED_NFTS_BATCH_SIZE,
+                    continuation,
+                )
+                .await?;
+            owned_nfts.extend(batch.nfts);
+
+            continuation = batch.continuation;
+            if continuation.is_none() {
+                break;
+            }
+        }
+
+        let (received, transferred) = self.owned_nfts.update(owned_nfts);
+        if !received.is_empty() {
+            self.handler.on_nfts_received(received);
+        }
+        if !transferred.is_empty() {
+            self.handler.on_nfts_transferred(transferred);
+        }
+
+        Ok(())
+    }
+}
+
+pub trait NftOwnershipHandler: Send + Sync {
+    /// Called with the items which are now owned by the subscribed owner
+    fn on_nfts_received(&self, nfts: Vec<MsgAddressInt>);
+
+    /// Called with the items which are no longer owned by the subscribed owner
+    fn on_nfts_transferred(&self, nfts: Vec<MsgAddressInt>);
+}
+
+#[derive(Default)]
+struct OwnedNfts(HashSet<MsgAddressInt>);
+
+impl OwnedNfts {
+    /// Replaces owned items. Returns received and transferred items
+    fn update(
+        &mut self,
+        owned: HashSet<MsgAddressInt>,
+    ) -> (Vec<MsgAddressInt>, Vec<MsgAddressInt>) {
+        let received = owned.difference(&self.0).cloned().collect();
+        let transferred = self.0.difference(&owned).cloned().collect();
+        self.0 = owned;
+        (received, transferred)
+    }
+}
+
 pub struct Nft {
     clock: Arc<dyn Clock>,
     address: MsgAddressInt,
@@ -239,6 +466,21 @@ impl Nft {
         &self.contract_subscription
     }
 
+    /// See [`ContractSubscription::set_send_safety_margin`]
+    pub fn set_send_safety_margin(&mut self, seconds: u32) {
+        self.contract_subscription.set_send_safety_margin(seconds);
+    }
+
+    /// See [`ContractSubscription::set_dead_letters`]
+    pub fn set_dead_letters(&mut self, dead_letters: Option<Arc<DeadLetters>>) {
+        self.contract_subscription.set_dead_letters(dead_letters);
+    }
+
+    /// See [`ContractSubscription::subscribe_updates`]
+    pub async fn subscribe_updates(&mut self) -> Result<bool> {
+        self.contract_subscription.subscribe_updates().await
+    }
+
     pub fn address(&self) -> &MsgAddressInt {
         &self.address
     }
@@ -363,10 +605,14 @@ impl Nft {
             .await
     }
 
-    pub async fn preload_transactions(&mut self, from_lt: u64) -> Result<()> {
+    /// See [`ContractSubscription::preload_transactions`]
+    pub async fn preload_transactions(
+        &mut self,
+        cursor: &TransactionsCursor,
+    ) -> Result<Option<TransactionsCursor>> {
         let handler = self.handler.as_ref();
         self.contract_subscription
-            .preload_transactions(from_lt, &mut make_transactions_handler(handler))
+            .preload_transactions(cursor, &mut make_transactions_handler(handler))
             .await
     }
 }
@@ -452,7 +698,11 @@ fn make_transactions_handler(
                         let transaction =
                             Transaction::try_from((transaction.hash, transaction.data)).ok()?;
 
-                        Some(TransactionWithData { transaction, data })
+                        Some(TransactionWithData {
+                            transaction,
+                            data,
+                            labels: Vec::new(),
+                        })
                     }
                     _ => None,
                 },
@@ -609,4 +859,221 @@ enum NftError {
     InvalidNftContact,
     #[error("Contract does not exist")]
     ContractNotExist,
+    #[error("Transport doesn't support searching accounts by code hash")]
+    CodeHashSearchNotSupported,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use nekoton_abi::LastTransactionId;
+
+    use super::*;
+    use crate::testing::ChainSimulator;
+
+    fn address(byte: u8) -> MsgAddressInt {
+        MsgAddressInt::from_str(&format!("0:{}", hex::encode([byte; 32]))).unwrap()
+    }
+
+    /// Collection with the selector-like index code, which is enough to compute index code hashes
+    async fn make_collection(sim: &ChainSimulator) -> NftCollection {
+        let collection_address = address(1);
+        sim.transport().set_account(
+            collection_address.clone(),
+            ton_block::Account::with_address_and_ballance(
+                &collection_address,
+                &ton_block::CurrencyCollection::with_grams(1_000_000_000),
+            ),
+        );
+        let state = match sim
+            .transport()
+            .get_contract_state(&collection_address)
+            .await
+            .unwrap()
+        {
+            RawContractState::Exists(state) => state,
+            RawContractState::NotExists { .. } => panic!("collection must exist"),
+        };
+
+        let mut index_code = BuilderData::new();
+        index_code
+            .append_raw(
+                &[
+                    0xff, 0x00, 0x20, 0xc1, 0x01, 0xf4, 0xa4, 0x20, 0x58, 0x92, 0xf4, 0xa0, 0xe0,
+                    0x5f, 0x02, 0x8a, 0x20, 0xed, 0x53, 0xd9,
+                ],
+                160,
+            )
+            .unwrap();
+
+        NftCollection {
+            transport: sim.transport().clone(),
+            collection_address,
+            state,
+            index_code: index_code.into_cell().unwrap(),
+            interfaces: Default::default(),
+            json_info: None,
+            owner: None,
+        }
+    }
+
+    fn set_index(sim: &ChainSimulator, collection: &NftCollection, index: u8, owner: u8) {
+        // Index code is salted with the collection and owner addresses
+        let mut salt = BuilderData::new();
+        let collection_cell = collection.collection_address.serialize().unwrap();
+        let owner_cell = address(owner).serialize().unwrap();
+        salt.append_raw(collection_cell.data(), collection_cell.bit_length())
+            .unwrap();
+        salt.append_raw(owner_cell.data(), owner_cell.bit_length())
+            .unwrap();
+        let mut nft = BuilderData::new();
+        nft.append_raw(NFT_STAMP, 24).unwrap();
+        salt.checked_append_reference(nft.into_cell().unwrap())
+            .unwrap();
+        let code =
+            nekoton_abi::set_code_salt(collection.index_code.clone(), salt.into_cell().unwrap())
+                .unwrap();
+
+        let index = address(index);
+        let mut account = ton_block::Account::with_address_and_ballance(
+            &index,
+            &ton_block::CurrencyCollection::with_grams(1_000_000_000),
+        );
+        if let ton_block::Account::Account(stuff) = &mut account {
+            stuff.storage.state = ton_block::AccountState::AccountActive {
+                state_init: ton_block::StateInit {
+                    code: Some(code),
+                    ..Default::default()
+                },
+            };
+        }
+        sim.transport().set_account(index, account);
+    }
+
+    #[tokio::test]
+    async fn find_index_contracts_of_owner() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let collection = make_collection(&sim).await;
+
+        set_index(&sim, &collection, 10, 2);
+        set_index(&sim, &collection, 11, 2);
+        set_index(&sim, &collection, 12, 2);
+        set_index(&sim, &collection, 13, 3);
+
+        let owner = address(2);
+        let first = collection
+            .get_nft_index_contracts(&owner, 2, None)
+            .await
+            .unwrap();
+        assert_eq!(first, [address(10), address(11)]);
+
+        let second = collection
+            .get_nft_index_contracts(&owner, 2, first.last().cloned())
+            .await
+            .unwrap();
+        assert_eq!(second, [address(12)]);
+
+        let other = collection
+            .get_nft_index_contracts(&address(3), 10, None)
+            .await
+            .unwrap();
+        assert_eq!(other, [address(13)]);
+    }
+
+    #[test]
+    fn stale_indices_are_skipped() {
+        let collection = address(1);
+        let owner = address(2);
+
+        // Index address -> (collection, owner, nft)
+        let infos = [
+            (10, (1, 2, 20)),
+            (11, (1, 3, 21)),
+            (12, (4, 2, 22)),
+            (13, (1, 2, 23)),
+        ];
+
+        let mut states = infos
+            .iter()
+            .map(|(index, _)| {
+                let account = ton_block::Account::with_address_and_ballance(
+                    &address(*index),
+                    &Default::default(),
+                );
+                RawContractState::Exists(ExistingContract {
+                    account: match account {
+                        ton_block::Account::Account(account) => account,
+                        ton_block::Account::AccountNone => unreachable!(),
+                    },
+                    timings: Default::default(),
+                    last_transaction_id: LastTransactionId::Inexact { latest_lt: 0 },
+                })
+            })
+            .collect::<Vec<_>>();
+        // Removed index
+        states.push(RawContractState::NotExists {
+            timings: Default::default(),
+        });
+
+        let nfts = filter_owned_nfts(&collection, &owner, &states, |state| {
+            let index = state.account.addr.address().get_bytestring(0)[0];
+            let (_, (collection, owner, nft)) =
+                infos.iter().find(|(item, _)| *item == index).unwrap();
+            Ok(IndexGetInfoOutputs {
+                collection: address(*collection),
+                owner: address(*owner),
+                nft: address(*nft),
+            })
+        })
+        .unwrap();
+        assert_eq!(nfts, [address(20), address(23)]);
+    }
+
+    #[test]
+    fn ownership_changes() {
+        let mut owned_nfts = OwnedNfts::default();
+
+        let (received, transferred) = owned_nfts.update([address(1), address(2)].into());
+        assert_eq!(received.len(), 2);
+        assert!(transferred.is_empty());
+
+        let (received, transferred) = owned_nfts.update([address(2), address(3)].into());
+        assert_eq!(received, [address(3)]);
+        assert_eq!(transferred, [address(1)]);
+
+        let (received, transferred) = owned_nfts.update([address(2), address(3)].into());
+        assert!(received.is_empty() && transferred.is_empty());
+    }
+
+    #[test]
+    fn parse_metadata() {
+        let json = JsonInfo::Json(
+            r#"{
+                "type": "Basic NFT",
+                "name": "Sample Name",
+                "description": "Hello world!",
+                "preview": { "source": "https://example.com/preview.png", "mimetype": "image/png" },
+                "files": [{ "source": "https://example.com/image.png", "mimetype": "image/png" }],
+                "external_url": "https://example.com"
+            }"#
+            .to_owned(),
+        );
+
+        let metadata = json.parse_metadata().unwrap().unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("Sample Name"));
+        assert_eq!(metadata.files.len(), 1);
+        assert_eq!(
+            metadata.preview.unwrap().mimetype.as_deref(),
+            Some("image/png")
+        );
+
+        assert!(JsonInfo::Json("{}".to_owned())
+            .parse_metadata()
+            .unwrap()
+            .is_ok());
+        assert!(JsonInfo::Url("https://example.com/1.json".to_owned())
+            .parse_metadata()
+            .is_none());
+    }
 }
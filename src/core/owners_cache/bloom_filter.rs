@@ -0,0 +1,48 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use nekoton_utils::TrustMe;
+
+use super::CompactAddress;
+
+const BITS_PER_ITEM: usize = 10;
+const HASH_COUNT: u64 = 7;
+
+/// Lock-free bloom filter over compact addresses.
+///
+/// False positive rate is about 1% until the expected number of items is reached
+pub struct AddressBloomFilter {
+    bits: Box<[AtomicU64]>,
+}
+
+impl AddressBloomFilter {
+    pub fn new(expected_items: usize) -> Self {
+        let words = (expected_items.max(1) * BITS_PER_ITEM + 63) / 64;
+        Self {
+            bits: (0..words).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    pub fn insert(&self, address: &CompactAddress) {
+        for index in self.indices(address) {
+            self.bits[index / 64].fetch_or(1 << (index % 64), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns `false` if the address was definitely not inserted
+    pub fn contains(&self, address: &CompactAddress) -> bool {
+        self.indices(address)
+            .all(|index| self.bits[index / 64].load(Ordering::Relaxed) & (1 << (index % 64)) != 0)
+    }
+
+    fn indices(
+        &self,
+        CompactAddress(workchain, account_id): &CompactAddress,
+    ) -> impl Iterator<Item = usize> {
+        // NOTE: account id is already a hash, so its parts can be used directly
+        let h1 = u64::from_le_bytes(account_id[0..8].try_into().trust_me()) ^ (*workchain as u64);
+        let h2 = u64::from_le_bytes(account_id[8..16].try_into().trust_me()) | 1;
+        let len = (self.bits.len() * 64) as u64;
+
+        (0..HASH_COUNT).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+}
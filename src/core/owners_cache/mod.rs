@@ -1,33 +1,56 @@
 use std::collections::hash_map::{self, HashMap};
 use std::collections::HashSet;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use anyhow::Result;
 use futures_util::stream::{FuturesUnordered, StreamExt};
-use serde::Deserialize;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
 use tokio::sync::{RwLock, Semaphore};
 use ton_block::MsgAddressInt;
+use ton_types::UInt256;
 
 use nekoton_contracts::tip3_any::{RootTokenContractState, TokenWalletContractState};
 use nekoton_utils::*;
 
+use self::bloom_filter::AddressBloomFilter;
 use super::models::TokenWalletVersion;
-use crate::external::Storage;
-use crate::transport::models::{ExistingContract, RawContractState};
-use crate::transport::Transport;
+use super::token_wallet::verify_ownership;
+use crate::external::{JournaledStorage, Storage};
+use crate::transport::models::{ExistingContract, PollContractState, RawContractState};
+use crate::transport::{OperationBounds, Transport};
+
+mod bloom_filter;
 
 pub const OWNERS_CACHE_STORAGE_KEY: &str = "__core__owners_cache";
+pub const TOKEN_CONTRACT_STATES_STORAGE_KEY: &str = "__core__owners_cache_root_states_v1";
+pub const OWNERS_JOURNAL_STORAGE_KEY: &str = "__core__owners_cache_journal";
+
+/// Max number of changes which can be exported as a delta.
+/// Peers which are further behind receive the full cache
+const MAX_JOURNAL_LEN: usize = 4096;
+
+/// Max number of token wallet states requested at once
+const MAX_STATES_PER_RESOLUTION: usize = 50;
 
 /// Stores a map to resolve owner's wallet address from token wallet address
 pub struct OwnersCache {
     key: String,
+    states_key: String,
+    journal_key: String,
     clock: Arc<dyn Clock>,
-    storage: Arc<dyn Storage>,
+    storage: JournaledStorage,
     transport: Arc<dyn Transport>,
-    owners: RwLock<HashMap<MsgAddressInt, MsgAddressInt>>,
-    token_contract_states: RwLock<HashMap<MsgAddressInt, (ExistingContract, TokenWalletVersion)>>,
+    owners: RwLock<OwnersMap>,
+    bloom_filter: Option<AddressBloomFilter>,
+    handler: Option<Arc<dyn OwnersCacheHandler>>,
+    counters: OwnersCacheCounters,
+    token_contract_states: RwLock<HashMap<MsgAddressInt, CachedRootState>>,
+    token_contract_states_ttl: Option<u64>,
     resolver_semaphore: Semaphore,
+    journal: parking_lot::Mutex<OwnersJournal>,
 }
 
 impl OwnersCache {
@@ -38,35 +61,22 @@ impl OwnersCache {
         transport: Arc<dyn Transport>,
         concurrent_resolvers: usize,
     ) -> Result<Self> {
-        #[derive(Deserialize)]
-        #[serde(transparent)]
-        struct OwnersMap(Vec<OwnersMapItem>);
-        #[derive(Deserialize)]
-        struct OwnersMapItem(String, String);
-
-        let key = make_key(network_group);
-
-        let data = match storage.get(&key).await? {
-            Some(data) => serde_json::from_str::<OwnersMap>(&data)?.0,
-            None => Default::default(),
-        }
-        .into_iter()
-        .map(|OwnersMapItem(token_wallet, owner_wallet)| {
-            let token_wallet = MsgAddressInt::from_str(&token_wallet)?;
-            let owner_wallet = MsgAddressInt::from_str(&owner_wallet)?;
-            Result::<_, anyhow::Error>::Ok((token_wallet, owner_wallet))
-        })
-        .collect::<Result<HashMap<_, _>, _>>()?;
+        let storage = JournaledStorage::new(storage);
+        let (data, persisted_bytes) = load_entries(network_group, &storage).await?;
 
-        Ok(Self {
-            key,
+        Ok(Self::with_entries(
+            network_group,
             clock,
             storage,
             transport,
-            owners: RwLock::new(data),
-            token_contract_states: Default::default(),
-            resolver_semaphore: Semaphore::new(concurrent_resolvers),
-        })
+            concurrent_resolvers,
+            data,
+            persisted_bytes,
+        )
+        .with_stored_token_contract_states()
+        .await
+        .with_stored_journal()
+        .await)
     }
 
     pub async fn load_unchecked(
@@ -76,23 +86,226 @@ impl OwnersCache {
         transport: Arc<dyn Transport>,
         concurrent_resolvers: usize,
     ) -> Self {
-        Self::load(
+        let storage = JournaledStorage::new(storage);
+        let (data, persisted_bytes) = load_entries(network_name, &storage)
+            .await
+            .unwrap_or_default();
+
+        Self::with_entries(
             network_name,
-            clock.clone(),
-            storage.clone(),
-            transport.clone(),
+            clock,
+            storage,
+            transport,
             concurrent_resolvers,
+            data,
+            persisted_bytes,
         )
+        .with_stored_token_contract_states()
+        .await
+        .with_stored_journal()
         .await
-        .unwrap_or_else(|_| Self {
+    }
+
+    /// Same as [`OwnersCache::load_unchecked`], but instead of discarding the whole
+    /// cache on parse error, keeps all entries which could still be read.
+    ///
+    /// Corrupted data is backed up under the separate key for inspection,
+    /// and the salvaged entries are saved back
+    pub async fn load_with_recovery(
+        network_name: &str,
+        clock: Arc<dyn Clock>,
+        storage: Arc<dyn Storage>,
+        transport: Arc<dyn Transport>,
+        concurrent_resolvers: usize,
+    ) -> (Self, OwnersCacheRecovery) {
+        let storage = JournaledStorage::new(storage);
+        let key = make_key(network_name);
+
+        let data = storage.get(&key).await.ok().flatten().unwrap_or_default();
+        let persisted_bytes = data.len();
+
+        let parsed = if data.is_empty() {
+            Ok(Vec::new())
+        } else {
+            serde_json::from_str::<StoredOwnersMap>(&data)
+                .map_err(anyhow::Error::from)
+                .and_then(parse_entries)
+        };
+
+        let (entries, recovery) = match parsed {
+            Ok(entries) => {
+                let recovery = OwnersCacheRecovery {
+                    recovered: entries.len(),
+                    ..Default::default()
+                };
+                (entries, recovery)
+            }
+            Err(_) => {
+                let backup_key = make_backup_key(&key);
+                storage.set_unchecked(&backup_key, &data);
+
+                let (entries, dropped) = salvage_entries(&data);
+                let recovery = OwnersCacheRecovery {
+                    recovered: entries.len(),
+                    dropped,
+                    backup_key: Some(backup_key),
+                };
+                (entries, recovery)
+            }
+        };
+
+        let cache = Self::with_entries(
+            network_name,
+            clock,
+            storage,
+            transport,
+            concurrent_resolvers,
+            entries,
+            persisted_bytes,
+        )
+        .with_stored_token_contract_states()
+        .await
+        .with_stored_journal()
+        .await;
+
+        if recovery.backup_key.is_some() {
+            let owners = cache.owners.read().await;
+            cache.save(&owners);
+        }
+
+        (cache, recovery)
+    }
+
+    fn with_entries(
+        network_name: &str,
+        clock: Arc<dyn Clock>,
+        storage: JournaledStorage,
+        transport: Arc<dyn Transport>,
+        concurrent_resolvers: usize,
+        entries: Vec<(CompactAddress, CompactAddress)>,
+        persisted_bytes: usize,
+    ) -> Self {
+        let epoch = clock.now_ms_u64();
+        Self {
             key: make_key(network_name),
+            states_key: make_states_key(network_name),
+            journal_key: make_journal_key(network_name),
             clock,
             storage,
             transport,
-            owners: Default::default(),
+            owners: RwLock::new(entries.into_iter().collect()),
+            bloom_filter: None,
+            handler: None,
+            counters: OwnersCacheCounters {
+                persisted_bytes: AtomicUsize::new(persisted_bytes),
+                ..Default::default()
+            },
             token_contract_states: Default::default(),
+            token_contract_states_ttl: None,
             resolver_semaphore: Semaphore::new(concurrent_resolvers),
-        })
+            journal: parking_lot::Mutex::new(OwnersJournal {
+                epoch,
+                base_version: 0,
+                changes: Default::default(),
+            }),
+        }
+    }
+
+    /// Restores root token contract states, saved by the previous session.
+    ///
+    /// Restored states are checked for updates on the first access
+    async fn with_stored_token_contract_states(mut self) -> Self {
+        let stored = match self.storage.get(&self.states_key).await {
+            Ok(Some(data)) => serde_json::from_str::<StoredTokenContractStates>(&data).ok(),
+            _ => None,
+        };
+
+        if let Some(stored) = stored {
+            let token_contract_states = self.token_contract_states.get_mut();
+            for (root_token_contract, mut state) in stored {
+                if let Ok(root_token_contract) = MsgAddressInt::from_str(&root_token_contract) {
+                    state.verified = false;
+                    token_contract_states.insert(root_token_contract, state);
+                }
+            }
+        }
+
+        self
+    }
+
+    /// Restores the journal of changes, saved by the previous session,
+    /// so that cursors exported before the restart remain valid.
+    ///
+    /// Starts a new epoch if there is no stored journal
+    async fn with_stored_journal(mut self) -> Self {
+        let stored = match self.storage.get(&self.journal_key).await {
+            Ok(Some(data)) => serde_json::from_str::<StoredOwnersJournal>(&data)
+                .ok()
+                .and_then(|stored| OwnersJournal::try_from(stored).ok()),
+            _ => None,
+        };
+
+        match stored {
+            Some(journal) => *self.journal.get_mut() = journal,
+            None => self.save_journal(),
+        }
+
+        self
+    }
+
+    /// Enables bloom filter over known token wallets, so that lookups of
+    /// not cached addresses don't need to acquire the lock.
+    ///
+    /// `expected_items` is the expected number of cached entries
+    pub fn with_bloom_filter(mut self, expected_items: usize) -> Self {
+        let bloom_filter = AddressBloomFilter::new(expected_items);
+        for token_wallet in self.owners.get_mut().keys() {
+            bloom_filter.insert(token_wallet);
+        }
+        self.bloom_filter = Some(bloom_filter);
+        self
+    }
+
+    /// Sets the handler which is notified about new entries
+    pub fn with_handler(mut self, handler: Arc<dyn OwnersCacheHandler>) -> Self {
+        self.handler = Some(handler);
+        self
+    }
+
+    /// Sets the lifetime of the cached root token contract states in seconds.
+    ///
+    /// Expired states are requested again on the next access
+    pub fn with_token_contract_states_ttl(mut self, ttl: u64) -> Self {
+        self.token_contract_states_ttl = Some(ttl);
+        self
+    }
+
+    /// Writes the cache into the storage at most once per `interval_ms`.
+    ///
+    /// Only the latest state is written, the rest is written on [`OwnersCache::flush`]
+    /// or when the cache is dropped
+    pub fn with_write_interval(mut self, interval_ms: u64) -> Self {
+        self.storage = self
+            .storage
+            .with_throttling(self.clock.clone(), interval_ms);
+        self
+    }
+
+    /// Writes all delayed changes into the storage
+    pub async fn flush(&self) -> Result<()> {
+        self.storage.flush().await
+    }
+
+    /// Returns current cache statistics
+    pub async fn stats(&self) -> OwnersCacheStats {
+        let counters = &self.counters;
+        OwnersCacheStats {
+            entries: self.owners.read().await.len(),
+            hits: counters.hits.load(Ordering::Relaxed),
+            misses: counters.misses.load(Ordering::Relaxed),
+            in_flight_resolutions: counters.in_flight_resolutions.load(Ordering::Relaxed),
+            persisted_bytes: counters.persisted_bytes.load(Ordering::Relaxed),
+        }
     }
 
     pub async fn check_recipient_wallet(
@@ -101,43 +314,40 @@ impl OwnersCache {
         owner_wallet: &MsgAddressInt,
     ) -> Result<RecipientWallet> {
         let mut token_contract_states = self.token_contract_states.write().await;
-        match token_contract_states.entry(root_token_contract.clone()) {
-            hash_map::Entry::Occupied(entry) => {
-                check_token_wallet(
-                    self.clock.as_ref(),
-                    self.transport.as_ref(),
-                    &self.owners,
-                    entry.get(),
-                    owner_wallet,
-                )
-                .await
-            }
-            hash_map::Entry::Vacant(entry) => {
-                let state = match self
-                    .transport
-                    .get_contract_state(root_token_contract)
-                    .await?
-                {
-                    RawContractState::Exists(state) => state,
-                    RawContractState::NotExists { .. } => {
-                        return Err(OwnersCacheError::InvalidRootTokenContract.into())
-                    }
-                };
+        let state = self
+            .get_root_token_state(&mut token_contract_states, root_token_contract)
+            .await?;
+        self.check_token_wallet(state, owner_wallet).await
+    }
 
-                let version = RootTokenContractState(state.as_context(self.clock.as_ref()))
-                    .guess_details()?
-                    .version;
-
-                check_token_wallet(
-                    self.clock.as_ref(),
-                    self.transport.as_ref(),
-                    &self.owners,
-                    entry.insert((state, version)),
-                    owner_wallet,
-                )
-                .await
-            }
+    /// Adds the entry only if the token wallet address matches the one
+    /// computed from the root token contract state.
+    ///
+    /// Returns `false` if the owner address was spoofed
+    pub async fn add_verified_entry(
+        &self,
+        root_token_contract: &MsgAddressInt,
+        token_wallet: MsgAddressInt,
+        owner_wallet: MsgAddressInt,
+    ) -> Result<bool> {
+        let verified = {
+            let mut token_contract_states = self.token_contract_states.write().await;
+            let CachedRootState { state, version, .. } = self
+                .get_root_token_state(&mut token_contract_states, root_token_contract)
+                .await?;
+
+            verify_ownership(
+                &RootTokenContractState(state.as_context(self.clock.as_ref())),
+                *version,
+                &owner_wallet,
+                &token_wallet,
+            )?
+        };
+
+        if verified {
+            self.add_entry(token_wallet, owner_wallet).await;
         }
+        Ok(verified)
     }
 
     /// Returns map with token wallet as key and its owner as value.
@@ -146,67 +356,480 @@ impl OwnersCache {
         &self,
         token_wallets: &[MsgAddressInt],
     ) -> HashMap<MsgAddressInt, MsgAddressInt> {
-        let semaphore = &self.resolver_semaphore;
-        let clock = self.clock.as_ref();
-        let transport = self.transport.as_ref();
-        let owners = &self.owners;
+        self.resolve_owners_bounded(token_wallets, &OperationBounds::unbounded())
+            .await
+    }
 
+    /// Same as [`OwnersCache::resolve_owners`], but stops requesting new states
+    /// when the bounds are exceeded. Returns only the resolved part in that case
+    pub async fn resolve_owners_bounded(
+        &self,
+        token_wallets: &[MsgAddressInt],
+        bounds: &OperationBounds,
+    ) -> HashMap<MsgAddressInt, MsgAddressInt> {
         let token_wallets = token_wallets.iter().collect::<HashSet<_>>();
 
-        token_wallets
-            .into_iter()
-            .map(|token_wallet| async move {
-                if let Some(owner) = owners.read().await.get(token_wallet) {
-                    return Some((token_wallet.clone(), owner.clone()));
+        let mut result = HashMap::with_capacity(token_wallets.len());
+        let mut unresolved = Vec::new();
+        for token_wallet in token_wallets {
+            if let Some(key) = CompactAddress::new(token_wallet) {
+                if let Some(owner) = self.get_cached(&key).await {
+                    result.insert(token_wallet.clone(), owner);
+                    continue;
                 }
+            }
+            unresolved.push(token_wallet.clone());
+        }
 
-                let contract_state = {
-                    let _permit = semaphore.acquire().await.ok()?;
-                    match transport.get_contract_state(token_wallet).await.ok()? {
-                        RawContractState::Exists(state) => state,
-                        RawContractState::NotExists { .. } => return None,
-                    }
+        // NOTE: states are requested separately if the transport can't batch them,
+        // so that the requests are still performed concurrently
+        let chunk_size = if self.transport.capabilities().batched_states {
+            MAX_STATES_PER_RESOLUTION
+        } else {
+            1
+        };
+
+        let mut resolved = unresolved
+            .chunks(chunk_size)
+            .map(|chunk| async move {
+                let states = {
+                    let _permit = self.resolver_semaphore.acquire().await.ok()?;
+                    let _in_flight = InFlightGuard::new(&self.counters.in_flight_resolutions);
+                    bounds
+                        .run(
+                            self.clock.as_ref(),
+                            self.transport.get_contract_states(chunk),
+                        )
+                        .await
+                        .ok()?
                 };
 
-                let state = TokenWalletContractState(contract_state.as_context(clock));
-                let version = state.get_version().ok()?;
-                let details = state.get_details(version).ok()?;
+                let mut owners = Vec::with_capacity(chunk.len());
+                for (token_wallet, state) in chunk.iter().zip(states) {
+                    if let Ok(RawContractState::Exists(state)) = state {
+                        if let Some(owner) = self.parse_owner(token_wallet, &state).await {
+                            owners.push((token_wallet.clone(), owner));
+                        }
+                    }
+                }
+                Some(owners)
+            })
+            .collect::<FuturesUnordered<_>>();
 
-                owners
-                    .write()
-                    .await
-                    .insert(token_wallet.clone(), details.owner_address.clone());
+        while let Some(owners) = resolved.next().await {
+            result.extend(owners.into_iter().flatten());
+        }
 
-                Some((token_wallet.clone(), details.owner_address))
-            })
-            .collect::<FuturesUnordered<_>>()
-            .filter_map(|value| async move { value })
-            .collect()
-            .await
+        result
+    }
+
+    /// Reads the owner from the token wallet state and caches it
+    async fn parse_owner(
+        &self,
+        token_wallet: &MsgAddressInt,
+        contract_state: &ExistingContract,
+    ) -> Option<MsgAddressInt> {
+        let state = TokenWalletContractState(contract_state.as_context(self.clock.as_ref()));
+        let version = state.get_version().ok()?;
+        let details = state.get_details(version).ok()?;
+
+        if let (Some(key), Some(owner)) = (
+            CompactAddress::new(token_wallet),
+            CompactAddress::new(&details.owner_address),
+        ) {
+            let mut owners = self.owners.write().await;
+            self.insert_entry(&mut owners, key, owner);
+        }
+
+        Some(details.owner_address)
     }
 
     pub async fn get_owner(&self, token_wallet: &MsgAddressInt) -> Option<MsgAddressInt> {
-        self.owners.read().await.get(token_wallet).cloned()
+        let key = CompactAddress::new(token_wallet)?;
+        self.get_cached(&key).await
     }
 
-    pub async fn add_entry(&self, token_wallet: MsgAddressInt, owner_wallet: MsgAddressInt) {
+    /// Caches the owner of the token wallet.
+    ///
+    /// Returns `false` if the same entry already exists (nothing is saved in that case)
+    pub async fn add_entry(
+        &self,
+        token_wallet: MsgAddressInt,
+        owner_wallet: MsgAddressInt,
+    ) -> bool {
+        let (token_wallet, owner_wallet) = match compact_entry(&token_wallet, &owner_wallet) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
         let mut owners = self.owners.write().await;
-        owners.insert(token_wallet, owner_wallet);
-        self.save(&owners);
+        let changed = self.insert_entry(&mut owners, token_wallet, owner_wallet);
+        if changed {
+            self.save(&owners);
+        }
+        changed
     }
 
-    pub async fn add_owners_list<I>(&self, new_owners: I)
+    /// Returns the number of new or changed entries
+    pub async fn add_owners_list<I>(&self, new_owners: I) -> usize
     where
         I: Iterator<Item = (MsgAddressInt, MsgAddressInt)>,
     {
         let mut owners = self.owners.write().await;
-        owners.extend(new_owners);
+        let mut changed = 0;
+        for (token_wallet, owner_wallet) in new_owners {
+            if let Some((token_wallet, owner_wallet)) = compact_entry(&token_wallet, &owner_wallet)
+            {
+                if self.insert_entry(&mut owners, token_wallet, owner_wallet) {
+                    changed += 1;
+                }
+            }
+        }
+        if changed > 0 {
+            self.save(&owners);
+        }
+        changed
+    }
+
+    /// Removes the cached owner of the token wallet.
+    ///
+    /// Returns `false` if there was no such entry
+    pub async fn remove_entry(&self, token_wallet: &MsgAddressInt) -> bool {
+        let token_wallet = match CompactAddress::new(token_wallet) {
+            Some(token_wallet) => token_wallet,
+            None => return false,
+        };
+
+        let mut owners = self.owners.write().await;
+        let removed = owners.remove(&token_wallet).is_some();
+        if removed {
+            self.save(&owners);
+        }
+        removed
+    }
+
+    /// Removes all entries and cached root token contract states
+    pub async fn clear(&self) {
+        // NOTE: same lock order as in `check_recipient_wallet`
+        let mut token_contract_states = self.token_contract_states.write().await;
+        let mut owners = self.owners.write().await;
+        owners.clear();
+        token_contract_states.clear();
         self.save(&owners);
+        self.save_token_contract_states(&token_contract_states);
+    }
+
+    /// Removes all entries which don't belong to the specified root token contracts.
+    ///
+    /// NOTE: token wallet addresses are computed for each known owner and root, so
+    /// this operation is quite expensive for large caches.
+    ///
+    /// Returns the number of removed entries
+    pub async fn retain_roots(&self, root_token_contracts: &[MsgAddressInt]) -> Result<usize> {
+        let mut token_contract_states = self.token_contract_states.write().await;
+        token_contract_states.retain(|root, _| root_token_contracts.contains(root));
+        self.save_token_contract_states(&token_contract_states);
+
+        let mut owners = self.owners.write().await;
+        let known_owners = owners.values().copied().collect::<HashSet<_>>();
+
+        let clock = self.clock.as_ref();
+        let mut retained = HashSet::with_capacity(owners.len());
+        for root_token_contract in root_token_contracts {
+            let CachedRootState { state, version, .. } = self
+                .get_root_token_state(&mut token_contract_states, root_token_contract)
+                .await?;
+            let state = RootTokenContractState(state.as_context(clock));
+
+            for owner in &known_owners {
+                let token_wallet = state.get_wallet_address(*version, &owner.expand())?;
+                if let Some(token_wallet) = CompactAddress::new(&token_wallet) {
+                    retained.insert(token_wallet);
+                }
+            }
+        }
+
+        let total = owners.len();
+        owners.retain(|token_wallet, _| retained.contains(token_wallet));
+        let removed = total - owners.len();
+        if removed > 0 {
+            self.save(&owners);
+        }
+
+        Ok(removed)
+    }
+
+    /// Adds entries from the prebuilt snapshot (e.g. bundled with the application).
+    /// Snapshot has the same format as the persisted cache, existing entries are not changed.
+    ///
+    /// Returns the number of new entries
+    pub async fn merge_snapshot<R>(&self, reader: R) -> Result<usize>
+    where
+        R: std::io::Read,
+    {
+        let entries = parse_entries(serde_json::from_reader::<_, StoredOwnersMap>(reader)?)?;
+
+        let mut owners = self.owners.write().await;
+        let mut added = 0;
+        for (token_wallet, owner_wallet) in entries {
+            if !owners.contains_key(&token_wallet) {
+                self.insert_entry(&mut owners, token_wallet, owner_wallet);
+                added += 1;
+            }
+        }
+        if added > 0 {
+            self.save(&owners);
+        }
+
+        Ok(added)
+    }
+
+    /// Exports entries which were added or changed after the specified cursor,
+    /// e.g. to send them to another device with the same accounts.
+    ///
+    /// Returns all entries if the cursor is `None`, belongs to the previous session
+    /// or is too old
+    pub async fn export_delta(&self, since: Option<OwnersCacheCursor>) -> OwnersCacheDelta {
+        let owners = self.owners.read().await;
+        let journal = self.journal.lock();
+
+        let cursor = journal.cursor();
+        let changes = since.and_then(|since| journal.changes_since(since));
+
+        let (full, entries) = match changes {
+            Some(changes) => (false, changes.iter().map(expand_entry).collect()),
+            None => (true, owners.iter().map(expand_entry).collect()),
+        };
+
+        OwnersCacheDelta {
+            cursor,
+            full,
+            entries,
+        }
+    }
+
+    /// Applies entries, exported by [`OwnersCache::export_delta`] on another device.
+    ///
+    /// Each entry is verified against the specified root token contracts, entries
+    /// which don't belong to any of them are skipped. Imported entries are not
+    /// added to the journal, so they are not exported back to the sender.
+    ///
+    /// Returns the number of new or changed entries
+    pub async fn import_delta(
+        &self,
+        delta: OwnersCacheDelta,
+        root_token_contracts: &[MsgAddressInt],
+    ) -> Result<usize> {
+        let entries = parse_entries(delta.entries)?;
+
+        // NOTE: same lock order as in `check_recipient_wallet`
+        let mut token_contract_states = self.token_contract_states.write().await;
+        for root_token_contract in root_token_contracts {
+            self.get_root_token_state(&mut token_contract_states, root_token_contract)
+                .await?;
+        }
+
+        let clock = self.clock.as_ref();
+        let roots = root_token_contracts
+            .iter()
+            .filter_map(|root_token_contract| token_contract_states.get(root_token_contract))
+            .collect::<Vec<_>>();
+
+        let mut verified = Vec::with_capacity(entries.len());
+        for (token_wallet, owner_wallet) in entries {
+            let (token_wallet_address, owner_wallet_address) =
+                (token_wallet.expand(), owner_wallet.expand());
+            for CachedRootState { state, version, .. } in &roots {
+                if verify_ownership(
+                    &RootTokenContractState(state.as_context(clock)),
+                    *version,
+                    &owner_wallet_address,
+                    &token_wallet_address,
+                )? {
+                    verified.push((token_wallet, owner_wallet));
+                    break;
+                }
+            }
+        }
+
+        let mut owners = self.owners.write().await;
+        let mut changed = 0;
+        for (token_wallet, owner_wallet) in verified {
+            if self.insert_entry_impl(&mut owners, token_wallet, owner_wallet, false) {
+                changed += 1;
+            }
+        }
+        if changed > 0 {
+            self.save(&owners);
+        }
+
+        Ok(changed)
     }
 
-    fn save(&self, owners: &HashMap<MsgAddressInt, MsgAddressInt>) {
-        struct OwnersMap<'a>(&'a HashMap<MsgAddressInt, MsgAddressInt>);
-        struct OwnersMapItem<'a>(&'a MsgAddressInt, &'a MsgAddressInt);
+    /// Returns cached root token contract state or fetches it if it is missing or expired
+    async fn get_root_token_state<'a>(
+        &self,
+        token_contract_states: &'a mut HashMap<MsgAddressInt, CachedRootState>,
+        root_token_contract: &MsgAddressInt,
+    ) -> Result<&'a CachedRootState> {
+        let now = self.clock.now_sec_u64();
+
+        let refresh = match token_contract_states.get_mut(root_token_contract) {
+            Some(cached) if !cached.verified => {
+                // NOTE: restored states are reused only if there were no new transactions
+                let last_lt = cached.state.last_transaction_id.lt();
+                match self
+                    .transport
+                    .poll_contract_state(root_token_contract, last_lt)
+                    .await?
+                {
+                    PollContractState::Unchanged { .. } => {
+                        cached.verified = true;
+                        cached.fetched_at = now;
+                        false
+                    }
+                    _ => true,
+                }
+            }
+            Some(cached) => matches!(
+                self.token_contract_states_ttl,
+                Some(ttl) if cached.fetched_at.saturating_add(ttl) <= now
+            ),
+            None => true,
+        };
+
+        if refresh {
+            let state = self.fetch_root_token_state(root_token_contract).await?;
+            token_contract_states.insert(root_token_contract.clone(), state);
+            self.save_token_contract_states(token_contract_states);
+        }
+
+        Ok(token_contract_states.get(root_token_contract).trust_me())
+    }
+
+    async fn fetch_root_token_state(
+        &self,
+        root_token_contract: &MsgAddressInt,
+    ) -> Result<CachedRootState> {
+        let state = match self
+            .transport
+            .get_contract_state(root_token_contract)
+            .await?
+        {
+            RawContractState::Exists(state) => state,
+            RawContractState::NotExists { .. } => {
+                return Err(OwnersCacheError::InvalidRootTokenContract.into())
+            }
+        };
+
+        let version = RootTokenContractState(state.as_context(self.clock.as_ref()))
+            .guess_details()?
+            .version;
+
+        Ok(CachedRootState {
+            state,
+            version,
+            fetched_at: self.clock.now_sec_u64(),
+            verified: true,
+        })
+    }
+
+    async fn check_token_wallet(
+        &self,
+        CachedRootState { state, version, .. }: &CachedRootState,
+        owner_wallet: &MsgAddressInt,
+    ) -> Result<RecipientWallet> {
+        let clock = self.clock.as_ref();
+        let token_wallet = RootTokenContractState(state.as_context(clock))
+            .get_wallet_address(*version, owner_wallet)?;
+
+        if let Some((key, owner)) = compact_entry(&token_wallet, owner_wallet) {
+            let mut owners = self.owners.write().await;
+            self.insert_entry(&mut owners, key, owner);
+        }
+
+        Ok(
+            match self.transport.get_contract_state(&token_wallet).await? {
+                RawContractState::NotExists { .. } => RecipientWallet::NotExists,
+                RawContractState::Exists(contract) => {
+                    let state = TokenWalletContractState(contract.as_context(clock));
+                    let version = state.get_version()?;
+                    let balance = state.get_balance(version)?;
+
+                    RecipientWallet::Exists {
+                        address: token_wallet,
+                        version,
+                        balance,
+                    }
+                }
+            },
+        )
+    }
+
+    async fn get_cached(&self, key: &CompactAddress) -> Option<MsgAddressInt> {
+        // NOTE: bloom filter allows to skip the lock for unknown addresses
+        let maybe_cached = match &self.bloom_filter {
+            Some(bloom_filter) => bloom_filter.contains(key),
+            None => true,
+        };
+
+        let owner = if maybe_cached {
+            self.owners
+                .read()
+                .await
+                .get(key)
+                .map(CompactAddress::expand)
+        } else {
+            None
+        };
+
+        let counter = match owner {
+            Some(_) => &self.counters.hits,
+            None => &self.counters.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        owner
+    }
+
+    /// Returns `true` if the entry was added or changed
+    fn insert_entry(
+        &self,
+        owners: &mut OwnersMap,
+        token_wallet: CompactAddress,
+        owner_wallet: CompactAddress,
+    ) -> bool {
+        self.insert_entry_impl(owners, token_wallet, owner_wallet, true)
+    }
+
+    fn insert_entry_impl(
+        &self,
+        owners: &mut OwnersMap,
+        token_wallet: CompactAddress,
+        owner_wallet: CompactAddress,
+        journaled: bool,
+    ) -> bool {
+        if let Some(bloom_filter) = &self.bloom_filter {
+            bloom_filter.insert(&token_wallet);
+        }
+
+        let changed = owners.insert(token_wallet, owner_wallet) != Some(owner_wallet);
+        if !changed {
+            return false;
+        }
+
+        if journaled {
+            self.journal.lock().push(token_wallet, owner_wallet);
+        }
+        if let Some(handler) = &self.handler {
+            handler.on_entry_inserted(&token_wallet.expand(), &owner_wallet.expand());
+        }
+        true
+    }
+
+    fn save(&self, owners: &OwnersMap) {
+        struct OwnersMap<'a>(&'a HashMap<CompactAddress, CompactAddress>);
+        struct OwnersMapItem<'a>(&'a CompactAddress, &'a CompactAddress);
 
         impl<'a> serde::Serialize for OwnersMapItem<'a> {
             fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -216,8 +839,8 @@ impl OwnersCache {
                 use serde::ser::SerializeTuple;
 
                 let mut tuple = serializer.serialize_tuple(2)?;
-                tuple.serialize_element(&self.0.to_string())?;
-                tuple.serialize_element(&self.1.to_string())?;
+                tuple.serialize_element(&self.0.expand().to_string())?;
+                tuple.serialize_element(&self.1.expand().to_string())?;
                 tuple.end()
             }
         }
@@ -237,39 +860,301 @@ impl OwnersCache {
         }
 
         let data = serde_json::to_string(&OwnersMap(owners)).trust_me();
+        self.counters
+            .persisted_bytes
+            .store(data.len(), Ordering::Relaxed);
         self.storage.set_unchecked(&self.key, &data);
+        self.save_journal();
+    }
+
+    fn save_journal(&self) {
+        let data = StoredOwnersJournal::from(&*self.journal.lock());
+        self.storage
+            .set_unchecked(&self.journal_key, &serde_json::to_string(&data).trust_me());
+    }
+
+    fn save_token_contract_states(&self, states: &HashMap<MsgAddressInt, CachedRootState>) {
+        let data = states
+            .iter()
+            .map(|(root_token_contract, state)| (root_token_contract.to_string(), state))
+            .collect::<Vec<_>>();
+
+        self.storage
+            .set_unchecked(&self.states_key, &serde_json::to_string(&data).trust_me());
     }
 }
 
-async fn check_token_wallet(
-    clock: &dyn Clock,
-    transport: &dyn Transport,
-    owners: &RwLock<OwnersMap>,
-    (state, version): &(ExistingContract, TokenWalletVersion),
-    owner_wallet: &MsgAddressInt,
-) -> Result<RecipientWallet> {
-    let token_wallet = RootTokenContractState(state.as_context(clock))
-        .get_wallet_address(*version, owner_wallet)?;
+/// Persisted list of `(root token contract, state)` pairs
+type StoredTokenContractStates = Vec<(String, CachedRootState)>;
 
-    {
-        let mut owners = owners.write().await;
-        owners.insert(token_wallet.clone(), owner_wallet.clone());
+/// Persisted list of `(token wallet, owner wallet)` pairs
+type StoredOwnersMap = Vec<(String, String)>;
+
+/// Persisted journal of changes
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredOwnersJournal {
+    #[serde(with = "serde_u64")]
+    epoch: u64,
+    #[serde(with = "serde_u64")]
+    base_version: u64,
+    changes: StoredOwnersMap,
+}
+
+impl From<&OwnersJournal> for StoredOwnersJournal {
+    fn from(journal: &OwnersJournal) -> Self {
+        Self {
+            epoch: journal.epoch,
+            base_version: journal.base_version,
+            changes: journal
+                .changes
+                .iter()
+                .map(|(token_wallet, owner_wallet)| expand_entry((token_wallet, owner_wallet)))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<StoredOwnersJournal> for OwnersJournal {
+    type Error = anyhow::Error;
+
+    fn try_from(stored: StoredOwnersJournal) -> Result<Self, Self::Error> {
+        Ok(Self {
+            epoch: stored.epoch,
+            base_version: stored.base_version,
+            changes: parse_entries(stored.changes)?.into_iter().collect(),
+        })
+    }
+}
+
+/// Reads stored entries. Returns entries with the size of the stored data
+async fn load_entries(
+    network_name: &str,
+    storage: &dyn Storage,
+) -> Result<(Vec<(CompactAddress, CompactAddress)>, usize)> {
+    match storage.get(&make_key(network_name)).await? {
+        Some(data) => Ok((
+            parse_entries(serde_json::from_str::<StoredOwnersMap>(&data)?)?,
+            data.len(),
+        )),
+        None => Ok(Default::default()),
+    }
+}
+
+fn parse_entries(data: StoredOwnersMap) -> Result<Vec<(CompactAddress, CompactAddress)>> {
+    data.into_iter()
+        .map(|(token_wallet, owner_wallet)| {
+            let token_wallet = repack_address(&token_wallet)?;
+            let owner_wallet = repack_address(&owner_wallet)?;
+            Ok(compact_entry(&token_wallet, &owner_wallet))
+        })
+        .filter_map(Result::transpose)
+        .collect()
+}
+
+/// Reads entries one by one from the possibly truncated or damaged data.
+///
+/// Returns salvaged entries and the number of dropped ones
+fn salvage_entries(data: &str) -> (Vec<(CompactAddress, CompactAddress)>, usize) {
+    let mut entries = Vec::new();
+    let mut dropped = 0;
+
+    // Skip the opening bracket of the outer array
+    let mut rest = match data.find('[') {
+        Some(start) => &data[start + 1..],
+        None => return (entries, 0),
+    };
+
+    // NOTE: addresses never contain brackets, so each entry is between the nearest pair
+    while let Some(start) = rest.find('[') {
+        let end = match rest[start..].find(']') {
+            Some(end) => start + end,
+            None => {
+                // Truncated last entry
+                dropped += 1;
+                break;
+            }
+        };
+
+        let entry = serde_json::from_str::<(String, String)>(&rest[start..=end])
+            .ok()
+            .and_then(|(token_wallet, owner_wallet)| {
+                let token_wallet = repack_address(&token_wallet).ok()?;
+                let owner_wallet = repack_address(&owner_wallet).ok()?;
+                Some(compact_entry(&token_wallet, &owner_wallet))
+            });
+
+        match entry {
+            Some(Some(entry)) => entries.push(entry),
+            Some(None) => {}
+            None => dropped += 1,
+        }
+
+        rest = &rest[end + 1..];
     }
 
-    Ok(match transport.get_contract_state(&token_wallet).await? {
-        RawContractState::NotExists { .. } => RecipientWallet::NotExists,
-        RawContractState::Exists(_) => RecipientWallet::Exists(token_wallet),
-    })
+    (entries, dropped)
+}
+
+fn expand_entry(
+    (token_wallet, owner_wallet): (&CompactAddress, &CompactAddress),
+) -> (String, String) {
+    (
+        token_wallet.expand().to_string(),
+        owner_wallet.expand().to_string(),
+    )
 }
 
 fn make_key(network_name: &str) -> String {
     format!("{OWNERS_CACHE_STORAGE_KEY}{network_name}")
 }
 
+fn make_states_key(network_name: &str) -> String {
+    format!("{TOKEN_CONTRACT_STATES_STORAGE_KEY}{network_name}")
+}
+
+fn make_journal_key(network_name: &str) -> String {
+    format!("{OWNERS_JOURNAL_STORAGE_KEY}{network_name}")
+}
+
+fn make_backup_key(key: &str) -> String {
+    format!("{key}__corrupted")
+}
+
 #[derive(Debug)]
 pub enum RecipientWallet {
     NotExists,
-    Exists(MsgAddressInt),
+    Exists {
+        address: MsgAddressInt,
+        version: TokenWalletVersion,
+        /// Current token balance of the recipient
+        balance: BigUint,
+    },
+}
+
+pub trait OwnersCacheHandler: Send + Sync {
+    /// Called when a new or changed entry was added to the cache.
+    ///
+    /// NOTE: cache is locked during this call, so it must not be accessed here
+    fn on_entry_inserted(&self, token_wallet: &MsgAddressInt, owner_wallet: &MsgAddressInt);
+}
+
+#[derive(Debug, Copy, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnersCacheStats {
+    /// Number of cached token wallets
+    pub entries: usize,
+    /// Lookups which were resolved from the cache
+    #[serde(with = "serde_u64")]
+    pub hits: u64,
+    /// Lookups of the addresses which were not cached
+    #[serde(with = "serde_u64")]
+    pub misses: u64,
+    /// Token wallet states which are being requested right now
+    pub in_flight_resolutions: usize,
+    /// Size of the last persisted cache in bytes
+    pub persisted_bytes: usize,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnersCacheRecovery {
+    /// Number of loaded entries
+    pub recovered: usize,
+    /// Number of entries which could not be read
+    pub dropped: usize,
+    /// Storage key of the corrupted data backup, if the data was corrupted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup_key: Option<String>,
+}
+
+/// Position in the changes of the owners cache
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnersCacheCursor {
+    /// Identifies the session in which the changes were made
+    #[serde(with = "serde_u64")]
+    pub epoch: u64,
+    /// Number of changes made during the session
+    #[serde(with = "serde_u64")]
+    pub version: u64,
+}
+
+/// Owners cache entries to exchange between devices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnersCacheDelta {
+    /// Cursor to use for the next export
+    pub cursor: OwnersCacheCursor,
+    /// Whether the delta contains all entries instead of the recent changes
+    pub full: bool,
+    /// List of `(token wallet, owner wallet)` pairs
+    pub entries: Vec<(String, String)>,
+}
+
+/// Recent changes of the owners map
+struct OwnersJournal {
+    epoch: u64,
+    /// Version of the oldest change in the journal
+    base_version: u64,
+    changes: std::collections::VecDeque<(CompactAddress, CompactAddress)>,
+}
+
+impl OwnersJournal {
+    fn cursor(&self) -> OwnersCacheCursor {
+        OwnersCacheCursor {
+            epoch: self.epoch,
+            version: self.base_version + self.changes.len() as u64,
+        }
+    }
+
+    fn push(&mut self, token_wallet: CompactAddress, owner_wallet: CompactAddress) {
+        if self.changes.len() >= MAX_JOURNAL_LEN {
+            self.changes.pop_front();
+            self.base_version += 1;
+        }
+        self.changes.push_back((token_wallet, owner_wallet));
+    }
+
+    /// Returns `None` if the changes since the cursor are not available
+    fn changes_since(
+        &self,
+        since: OwnersCacheCursor,
+    ) -> Option<HashMap<CompactAddress, CompactAddress>> {
+        if since.epoch != self.epoch || since.version < self.base_version {
+            return None;
+        }
+        let skip = usize::try_from(since.version - self.base_version).ok()?;
+        if skip > self.changes.len() {
+            return None;
+        }
+
+        // NOTE: only the latest owner is exported for the repeated token wallets
+        Some(self.changes.iter().skip(skip).copied().collect())
+    }
+}
+
+#[derive(Default)]
+struct OwnersCacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    in_flight_resolutions: AtomicUsize,
+    persisted_bytes: AtomicUsize,
+}
+
+struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl<'a> InFlightGuard<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 #[derive(thiserror::Error, Debug, Copy, Clone)]
@@ -278,4 +1163,198 @@ pub enum OwnersCacheError {
     InvalidRootTokenContract,
 }
 
-type OwnersMap = HashMap<MsgAddressInt, MsgAddressInt>;
+type OwnersMap = HashMap<CompactAddress, CompactAddress>;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedRootState {
+    state: ExistingContract,
+    version: TokenWalletVersion,
+    /// Unix timestamp in seconds
+    #[serde(with = "serde_u64")]
+    fetched_at: u64,
+    /// Whether the state was checked for updates in this session
+    #[serde(skip)]
+    verified: bool,
+}
+
+/// Standard address without anycast, stored as `(workchain, account id)`.
+///
+/// Takes much less memory than `MsgAddressInt` and is faster to hash
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+struct CompactAddress(i8, [u8; 32]);
+
+impl CompactAddress {
+    fn new(address: &MsgAddressInt) -> Option<Self> {
+        match address {
+            MsgAddressInt::AddrStd(ton_block::MsgAddrStd {
+                anycast: None,
+                workchain_id,
+                address,
+            }) if address.remaining_bits() == 256 => {
+                let mut account_id = [0; 32];
+                account_id.copy_from_slice(&address.get_bytestring(0));
+                Some(Self(*workchain_id, account_id))
+            }
+            _ => None,
+        }
+    }
+
+    fn expand(&self) -> MsgAddressInt {
+        MsgAddressInt::AddrStd(ton_block::MsgAddrStd::with_address(
+            None,
+            self.0,
+            UInt256::from(self.1).into(),
+        ))
+    }
+}
+
+fn compact_entry(
+    token_wallet: &MsgAddressInt,
+    owner_wallet: &MsgAddressInt,
+) -> Option<(CompactAddress, CompactAddress)> {
+    Some((
+        CompactAddress::new(token_wallet)?,
+        CompactAddress::new(owner_wallet)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::testing::{ChainSimulator, MemoryStorage};
+
+    #[test]
+    fn compact_address_round_trip() {
+        for address in [
+            "0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb",
+            "-1:3333333333333333333333333333333333333333333333333333333333333333",
+        ] {
+            let address = MsgAddressInt::from_str(address).unwrap();
+            let compact = CompactAddress::new(&address).unwrap();
+            assert_eq!(compact.expand(), address);
+        }
+    }
+
+    #[test]
+    fn journal_changes() {
+        let address = |byte: u8| CompactAddress(0, [byte; 32]);
+
+        let mut journal = OwnersJournal {
+            epoch: 1,
+            base_version: 0,
+            changes: Default::default(),
+        };
+        let start = journal.cursor();
+
+        journal.push(address(1), address(2));
+        let middle = journal.cursor();
+        journal.push(address(3), address(4));
+        journal.push(address(1), address(5));
+
+        let changes = journal.changes_since(middle).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[&address(1)], address(5));
+        assert_eq!(journal.changes_since(start).unwrap().len(), 2);
+
+        assert!(journal
+            .changes_since(OwnersCacheCursor { epoch: 2, ..start })
+            .is_none());
+        assert!(journal
+            .changes_since(OwnersCacheCursor {
+                version: 10,
+                ..start
+            })
+            .is_none());
+
+        for _ in 0..MAX_JOURNAL_LEN {
+            journal.push(address(6), address(7));
+        }
+        assert!(journal.changes_since(start).is_none());
+    }
+
+    #[tokio::test]
+    async fn journal_survives_restart() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let storage = Arc::new(MemoryStorage::default());
+        let load = || {
+            OwnersCache::load(
+                "test",
+                sim.clock().clone(),
+                storage.clone(),
+                sim.transport().clone(),
+                1,
+            )
+        };
+        let address = |byte: u8| CompactAddress(0, [byte; 32]).expand();
+
+        let cache = load().await.unwrap();
+        let start = cache.export_delta(None).await.cursor;
+        assert!(cache.add_entry(address(1), address(2)).await);
+        let middle = cache.export_delta(None).await.cursor;
+        drop(cache);
+
+        sim.clock().advance(std::time::Duration::from_secs(10));
+        let cache = load().await.unwrap();
+        assert_eq!(cache.export_delta(None).await.cursor, middle);
+
+        let delta = cache.export_delta(Some(start)).await;
+        assert!(!delta.full);
+        assert_eq!(delta.entries.len(), 1);
+
+        // Entries which don't belong to the known roots are skipped
+        let foreign = OwnersCacheDelta {
+            cursor: start,
+            full: false,
+            entries: vec![(address(3).to_string(), address(4).to_string())],
+        };
+        assert_eq!(cache.import_delta(foreign, &[]).await.unwrap(), 0);
+        assert_eq!(cache.get_owner(&address(3)).await, None);
+        assert_eq!(cache.export_delta(None).await.cursor, middle);
+    }
+
+    #[test]
+    fn parse_snapshot() {
+        let snapshot = r#"[
+            ["0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb", "EQAC4_IoTmioEGuCOrnyQE8zzEP8ytjh3oNb3ZZ4klRobFz0"]
+        ]"#;
+
+        let entries = parse_entries(serde_json::from_str(snapshot).unwrap()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].1.expand(),
+            MsgAddressInt::from_str(
+                "0:02e3f2284e68a8106b823ab9f2404f33cc43fccad8e1de835bdd96789254686c"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn salvage_corrupted_snapshot() {
+        let snapshot = r#"[["0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb","EQAC4_IoTmioEGuCOrnyQE8zzEP8ytjh3oNb3ZZ4klRobFz0"],["0:invalid","0:invalid"],["0:a921453472366b"#;
+
+        let (entries, dropped) = salvage_entries(snapshot);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(dropped, 2);
+    }
+
+    #[test]
+    fn bloom_filter_has_no_false_negatives() {
+        let filter = AddressBloomFilter::new(1000);
+
+        let addresses = (0..1000)
+            .map(|i: u32| {
+                use sha2::Digest;
+                CompactAddress(0, sha2::Sha256::digest(i.to_le_bytes()).into())
+            })
+            .collect::<Vec<_>>();
+
+        for address in &addresses {
+            filter.insert(address);
+        }
+        assert!(addresses.iter().all(|address| filter.contains(address)));
+    }
+}
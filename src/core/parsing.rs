@@ -12,6 +12,7 @@ use nekoton_contracts::{old_tip3, tip3_1};
 
 use crate::core::models::*;
 use crate::core::ton_wallet::{MultisigType, WalletType};
+use crate::external::AddressLabeler;
 
 pub struct InputMessage(pub Vec<ton_abi::Token>);
 
@@ -26,6 +27,31 @@ pub fn parse_payload(payload: ton_types::SliceData) -> Option<KnownPayload> {
         return parse_comment_payload(payload).map(KnownPayload::Comment);
     }
 
+    let depool = DePoolStakeFunctions::instance();
+    if function_id == depool.add_ordinary_stake.input_id {
+        let stake = depool
+            .add_ordinary_stake
+            .decode_input(payload, true)
+            .ok()?
+            .unpack_first()
+            .ok()?;
+        return Some(KnownPayload::DePoolStake(
+            DePoolStakeOperation::AddOrdinaryStake(stake),
+        ));
+    } else if function_id == depool.withdraw_part.input_id {
+        let withdraw_value = depool
+            .withdraw_part
+            .decode_input(payload, true)
+            .ok()?
+            .unpack_first()
+            .ok()?;
+        return Some(KnownPayload::DePoolStake(
+            DePoolStakeOperation::WithdrawPart(withdraw_value),
+        ));
+    } else if function_id == depool.withdraw_all.input_id {
+        return Some(KnownPayload::DePoolStake(DePoolStakeOperation::WithdrawAll));
+    }
+
     for version in [TokenWalletVersion::OldTip3v4, TokenWalletVersion::Tip3] {
         let functions = TokenWalletFunctions::for_version(version);
 
@@ -64,6 +90,67 @@ pub fn parse_payload(payload: ton_types::SliceData) -> Option<KnownPayload> {
     None
 }
 
+/// Collects labels of the transaction counterparties (message source and destinations)
+pub fn label_counterparties(
+    transaction: &Transaction,
+    labeler: &dyn AddressLabeler,
+) -> Vec<LabeledAddress> {
+    let mut result = Vec::<LabeledAddress>::new();
+
+    let counterparties = transaction.in_msg.src.iter().chain(
+        transaction
+            .out_msgs
+            .iter()
+            .filter_map(|msg| msg.dst.as_ref()),
+    );
+    for address in counterparties {
+        if result.iter().any(|item| &item.address == address) {
+            continue;
+        }
+        if let Some(label) = labeler.label(address) {
+            result.push(LabeledAddress {
+                address: address.clone(),
+                label,
+            });
+        }
+    }
+
+    result
+}
+
+/// Contract type which defines the set of known function calls
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ParsedContractType {
+    Wallet(WalletType),
+    TokenWallet(TokenWalletVersion),
+    Nft,
+}
+
+/// Decodes known function calls and events of the transaction into a typed event.
+///
+/// Returns `None` for unknown or aborted (for token wallets and NFTs) transactions
+pub fn parse_transaction(
+    tx: &ton_block::Transaction,
+    contract_type: ParsedContractType,
+) -> Option<ParsedTransaction> {
+    let read_description = || match tx.description.read_struct().ok()? {
+        ton_block::TransactionDescr::Ordinary(description) => Some(description),
+        _ => None,
+    };
+
+    match contract_type {
+        ParsedContractType::Wallet(wallet_type) => {
+            parse_transaction_additional_info(tx, wallet_type).map(ParsedTransaction::Wallet)
+        }
+        ParsedContractType::TokenWallet(version) => {
+            parse_token_transaction(tx, &read_description()?, version).map(ParsedTransaction::Token)
+        }
+        ParsedContractType::Nft => {
+            parse_nft_transaction(tx, &read_description()?).map(ParsedTransaction::Nft)
+        }
+    }
+}
+
 pub fn parse_transaction_additional_info(
     tx: &ton_block::Transaction,
     wallet_type: WalletType,
@@ -139,10 +226,24 @@ pub fn parse_transaction_additional_info(
     let token_notifications = WalletNotificationFunctions::instance();
 
     if int_header.bounced {
-        return None;
+        let function_id = in_msg.body().and_then(|mut body| {
+            // Skip bounced message prefix
+            body.move_by(32).ok()?;
+            read_function_id(&body).ok()
+        });
+
+        return Some(TransactionAdditionalInfo::Refund(RefundInfo {
+            source: message_source(int_header)?,
+            kind: RefundKind::Bounced(function_id),
+        }));
     }
 
-    let body = in_msg.body()?;
+    let body = match in_msg.body() {
+        Some(body) if !body.is_empty() => body,
+        // NOTE: empty transfers are indistinguishable from remaining gas refunds
+        // without the wallet history, see `parse_remaining_gas_refund`
+        _ => return None,
+    };
     let function_id = read_function_id(&body).ok()?;
 
     if function_id == 0 {
@@ -179,6 +280,76 @@ pub fn parse_transaction_additional_info(
     }
 }
 
+/// Internal message with body (i.e. contract call) sent by the wallet.
+///
+/// Called contract may return the remaining gas with an empty message
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RefundSource {
+    pub address: MsgAddressInt,
+    /// Logical time of the transaction which sent the message
+    pub lt: u64,
+}
+
+/// Collects contracts which were called by the transaction
+pub fn parse_refund_sources(tx: &ton_block::Transaction) -> Vec<RefundSource> {
+    let mut sources = Vec::new();
+    tx.out_msgs
+        .iterate(|item| {
+            let header = match item.0.header() {
+                ton_block::CommonMsgInfo::IntMsgInfo(header) => header,
+                _ => return Ok(true),
+            };
+            if matches!(item.0.body(), Some(body) if !body.is_empty()) {
+                sources.push(RefundSource {
+                    address: header.dst.clone(),
+                    lt: tx.lt,
+                });
+            }
+            Ok(true)
+        })
+        .ok();
+    sources
+}
+
+/// Parses the remaining gas refund.
+///
+/// Refund is an empty non-bounceable message from the contract which
+/// was called by the wallet earlier (see [`parse_refund_sources`]).
+/// Plain transfers from other contracts are not treated as refunds
+pub fn parse_remaining_gas_refund(
+    tx: &ton_block::Transaction,
+    sources: &[RefundSource],
+) -> Option<TransactionAdditionalInfo> {
+    let in_msg = tx.in_msg.as_ref()?.read_struct().ok()?;
+    let int_header = match in_msg.header() {
+        ton_block::CommonMsgInfo::IntMsgInfo(header) => header,
+        _ => return None,
+    };
+
+    if int_header.bounce || int_header.bounced {
+        return None;
+    }
+    if matches!(in_msg.body(), Some(body) if !body.is_empty()) {
+        return None;
+    }
+
+    let source = message_source(int_header)?;
+    sources
+        .iter()
+        .any(|item| item.address == source && item.lt < tx.lt)
+        .then_some(TransactionAdditionalInfo::Refund(RefundInfo {
+            source,
+            kind: RefundKind::RemainingGas,
+        }))
+}
+
+fn message_source(int_header: &ton_block::InternalMessageHeader) -> Option<MsgAddressInt> {
+    match &int_header.src {
+        ton_block::MsgAddressIntOrNone::Some(addr) => Some(addr.clone()),
+        ton_block::MsgAddressIntOrNone::None => None,
+    }
+}
+
 struct DePoolParticipantFunctions {
     on_round_complete: &'static ton_abi::Function,
     receive_answer: &'static ton_abi::Function,
@@ -198,6 +369,27 @@ impl DePoolParticipantFunctions {
     }
 }
 
+struct DePoolStakeFunctions {
+    add_ordinary_stake: &'static ton_abi::Function,
+    withdraw_part: &'static ton_abi::Function,
+    withdraw_all: &'static ton_abi::Function,
+}
+
+impl DePoolStakeFunctions {
+    fn instance() -> &'static Self {
+        use nekoton_contracts::depool;
+
+        static IDS: OnceBox<DePoolStakeFunctions> = OnceBox::new();
+        IDS.get_or_init(|| {
+            Box::new(DePoolStakeFunctions {
+                add_ordinary_stake: depool::add_ordinary_stake(),
+                withdraw_part: depool::withdraw_part(),
+                withdraw_all: depool::withdraw_all(),
+            })
+        })
+    }
+}
+
 struct WalletNotificationFunctions {
     notify_wallet_deployed: &'static ton_abi::Function,
 }
@@ -1015,4 +1207,204 @@ mod tests {
             TokenWalletTransaction::TransferBounced(_)
         ));
     }
+
+    fn make_internal_message(
+        src: &MsgAddressInt,
+        dst: &MsgAddressInt,
+        bounce: bool,
+        bounced: bool,
+        body: Option<ton_types::SliceData>,
+    ) -> ton_block::Message {
+        let mut header = ton_block::InternalMessageHeader::with_addresses_and_bounce(
+            src.clone(),
+            dst.clone(),
+            ton_block::CurrencyCollection::with_grams(100_000_000),
+            bounce,
+        );
+        header.bounced = bounced;
+        let mut message = ton_block::Message::with_int_header(header);
+        if let Some(body) = body {
+            message.set_body(body);
+        }
+        message
+    }
+
+    fn make_transaction(
+        address: &MsgAddressInt,
+        lt: u64,
+        in_msg: ton_block::Message,
+        out_msgs: &[ton_block::Message],
+    ) -> Transaction {
+        let mut tx = Transaction::with_address_and_status(
+            address.address(),
+            ton_block::AccountStatus::AccStateActive,
+        );
+        tx.set_logical_time(lt);
+        tx.write_in_msg(Some(&in_msg)).unwrap();
+        for msg in out_msgs {
+            tx.add_out_message(msg).unwrap();
+        }
+        tx
+    }
+
+    #[test]
+    fn parse_remaining_gas_refunds() {
+        let wallet = MsgAddressInt::from_str(
+            "0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb",
+        )
+        .unwrap();
+        let token_wallet = MsgAddressInt::from_str(
+            "0:1111111111111111111111111111111111111111111111111111111111111111",
+        )
+        .unwrap();
+        let other = MsgAddressInt::from_str(
+            "0:2222222222222222222222222222222222222222222222222222222222222222",
+        )
+        .unwrap();
+
+        let mut call_body = ton_types::BuilderData::new();
+        call_body.append_u32(0x73e22143).unwrap();
+        let call_body = ton_types::SliceData::load_builder(call_body).unwrap();
+
+        // Wallet calls the token wallet
+        let ext_in =
+            ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+                dst: wallet.clone(),
+                ..Default::default()
+            });
+        let call = make_internal_message(&wallet, &token_wallet, true, false, Some(call_body));
+        let call_tx = make_transaction(&wallet, 10, ext_in, &[call]);
+
+        let sources = parse_refund_sources(&call_tx);
+        assert_eq!(
+            sources,
+            [RefundSource {
+                address: token_wallet.clone(),
+                lt: 10
+            }]
+        );
+
+        // Remaining gas from the called contract
+        let refund = make_internal_message(&token_wallet, &wallet, false, false, None);
+        let refund_tx = make_transaction(&wallet, 20, refund, &[]);
+        assert!(parse_transaction_additional_info(&refund_tx, WalletType::EverWallet).is_none());
+        assert!(matches!(
+            parse_remaining_gas_refund(&refund_tx, &sources).unwrap(),
+            TransactionAdditionalInfo::Refund(RefundInfo {
+                source,
+                kind: RefundKind::RemainingGas,
+            }) if source == token_wallet
+        ));
+
+        // Refund can't precede the call
+        let early_refund_tx = make_transaction(
+            &wallet,
+            5,
+            make_internal_message(&token_wallet, &wallet, false, false, None),
+            &[],
+        );
+        assert!(parse_remaining_gas_refund(&early_refund_tx, &sources).is_none());
+
+        // Plain small transfer from an unrelated wallet
+        let transfer = make_internal_message(&other, &wallet, false, false, None);
+        let transfer_tx = make_transaction(&wallet, 30, transfer, &[]);
+        assert!(parse_transaction_additional_info(&transfer_tx, WalletType::EverWallet).is_none());
+        assert!(parse_remaining_gas_refund(&transfer_tx, &sources).is_none());
+
+        // Bounceable transfer from the called contract is not a refund
+        let transfer = make_internal_message(&token_wallet, &wallet, true, false, None);
+        let transfer_tx = make_transaction(&wallet, 40, transfer, &[]);
+        assert!(parse_remaining_gas_refund(&transfer_tx, &sources).is_none());
+
+        // Bounced call
+        let mut bounced_body = ton_types::BuilderData::new();
+        bounced_body.append_u32(u32::MAX).unwrap();
+        bounced_body.append_u32(0x73e22143).unwrap();
+        let bounced_body = ton_types::SliceData::load_builder(bounced_body).unwrap();
+        let bounced =
+            make_internal_message(&token_wallet, &wallet, false, true, Some(bounced_body));
+        let bounced_tx = make_transaction(&wallet, 50, bounced, &[]);
+
+        assert!(parse_remaining_gas_refund(&bounced_tx, &sources).is_none());
+        assert!(matches!(
+            parse_transaction_additional_info(&bounced_tx, WalletType::EverWallet).unwrap(),
+            TransactionAdditionalInfo::Refund(RefundInfo {
+                source,
+                kind: RefundKind::Bounced(Some(0x73e22143)),
+            }) if source == token_wallet
+        ));
+    }
+
+    #[test]
+    fn label_known_counterparties() {
+        use crate::external::{AddressCategory, StaticAddressLabeler};
+
+        let wallet = MsgAddressInt::from_str(
+            "0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb",
+        )
+        .unwrap();
+        let dex = MsgAddressInt::from_str(
+            "0:1111111111111111111111111111111111111111111111111111111111111111",
+        )
+        .unwrap();
+        let bridge = MsgAddressInt::from_str(
+            "0:2222222222222222222222222222222222222222222222222222222222222222",
+        )
+        .unwrap();
+        let other = MsgAddressInt::from_str(
+            "0:3333333333333333333333333333333333333333333333333333333333333333",
+        )
+        .unwrap();
+
+        let labeler = StaticAddressLabeler::default()
+            .with_label(dex.clone(), "DEX", AddressCategory::Dex)
+            .with_label(bridge.clone(), "Bridge", AddressCategory::Bridge);
+
+        let mut tx = make_transaction(
+            &wallet,
+            10,
+            make_internal_message(&dex, &wallet, false, false, None),
+            &[
+                make_internal_message(&wallet, &bridge, true, false, None),
+                make_internal_message(&wallet, &other, true, false, None),
+                make_internal_message(&wallet, &dex, true, false, None),
+            ],
+        );
+        tx.description
+            .write_struct(&ton_block::TransactionDescr::Ordinary(Default::default()))
+            .unwrap();
+        let tx = crate::core::models::Transaction::try_from((UInt256::default(), tx)).unwrap();
+
+        let labels = label_counterparties(&tx, &labeler);
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels[0].address, dex);
+        assert_eq!(labels[0].label.category, AddressCategory::Dex);
+        assert_eq!(labels[1].address, bridge);
+        assert_eq!(labels[1].label.name, "Bridge");
+    }
+
+    #[test]
+    fn parse_depool_stake_payloads() {
+        use nekoton_contracts::depool;
+
+        let payload = depool::withdraw_part()
+            .encode_internal_input(&[100u64.token_value().named("withdrawValue")])
+            .and_then(ton_types::SliceData::load_builder)
+            .unwrap();
+        assert!(matches!(
+            parse_payload(payload),
+            Some(KnownPayload::DePoolStake(
+                DePoolStakeOperation::WithdrawPart(100)
+            ))
+        ));
+
+        let payload = depool::withdraw_all()
+            .encode_internal_input(&[])
+            .and_then(ton_types::SliceData::load_builder)
+            .unwrap();
+        assert!(matches!(
+            parse_payload(payload),
+            Some(KnownPayload::DePoolStake(DePoolStakeOperation::WithdrawAll))
+        ));
+    }
 }
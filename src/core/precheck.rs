@@ -0,0 +1,273 @@
+use serde::Serialize;
+use ton_block::AccountState;
+
+use nekoton_abi::GenTimings;
+use nekoton_contracts::tip3_any::TokenWalletContractState;
+use nekoton_utils::*;
+
+use crate::transport::models::RawContractState;
+
+/// States with the generation time too far from the local clock
+/// are considered as a sign of the skewed clock
+const MAX_CLOCK_SKEW: u32 = 60;
+
+/// Outgoing transfer, checked before signing
+#[derive(Debug, Copy, Clone)]
+pub struct TransferPrecheck<'a> {
+    /// Current state of the sender wallet
+    pub sender_state: &'a RawContractState,
+    /// Current state of the recipient
+    pub recipient_state: &'a RawContractState,
+    /// Attached amount in nano TON
+    pub amount: u64,
+    /// Estimated fees in nano TON
+    pub fees: u64,
+    /// Bounce flag of the outgoing message
+    pub bounce: bool,
+    /// Expiration timestamp of the external message
+    pub expire_at: u32,
+}
+
+/// Collects everything that may go wrong with the transfer into a single list.
+///
+/// Returns an empty list when nothing suspicious was found
+pub fn precheck_transfer(
+    clock: &dyn Clock,
+    transfer: TransferPrecheck<'_>,
+) -> Vec<TransferWarning> {
+    let mut warnings = Vec::new();
+
+    let now = clock.now_sec_u64() as u32;
+    if transfer.expire_at <= now {
+        warnings.push(TransferWarning::AlreadyExpired {
+            expire_at: transfer.expire_at,
+            now,
+        });
+    }
+
+    // NOTE: states are fetched right before the check, so their generation
+    // time must be close to the local time
+    if let Some(gen_utime) = gen_utime(transfer.sender_state) {
+        let skew = now.abs_diff(gen_utime);
+        if skew > MAX_CLOCK_SKEW {
+            warnings.push(TransferWarning::ClockSkewed { gen_utime, now });
+        }
+    }
+
+    match transfer.sender_state {
+        RawContractState::Exists(contract) => {
+            if matches!(
+                contract.account.storage.state,
+                AccountState::AccountFrozen { .. }
+            ) {
+                warnings.push(TransferWarning::SenderFrozen);
+            }
+
+            let balance = contract.account.storage.balance.grams.as_u128();
+            let required = transfer.amount.saturating_add(transfer.fees);
+            if balance < required as u128 {
+                warnings.push(TransferWarning::InsufficientBalance {
+                    balance: u64::try_from(balance).unwrap_or(u64::MAX),
+                    required,
+                });
+            }
+        }
+        RawContractState::NotExists { .. } => {
+            warnings.push(TransferWarning::InsufficientBalance {
+                balance: 0,
+                required: transfer.amount.saturating_add(transfer.fees),
+            });
+        }
+    }
+
+    match transfer.recipient_state {
+        RawContractState::Exists(contract) => match &contract.account.storage.state {
+            AccountState::AccountActive { .. } => {
+                if !transfer.bounce {
+                    warnings.push(TransferWarning::NonBounceableToActive);
+                }
+
+                let state = TokenWalletContractState(contract.as_context(clock));
+                if state.get_version().is_ok() {
+                    warnings.push(TransferWarning::RecipientIsTokenWallet);
+                }
+            }
+            AccountState::AccountFrozen { .. } => {
+                warnings.push(TransferWarning::RecipientFrozen);
+            }
+            AccountState::AccountUninit => {
+                if transfer.bounce {
+                    warnings.push(TransferWarning::BounceToUninit);
+                }
+            }
+        },
+        RawContractState::NotExists { .. } => {
+            warnings.push(TransferWarning::RecipientNotExists);
+            if transfer.bounce {
+                warnings.push(TransferWarning::BounceToUninit);
+            }
+        }
+    }
+
+    warnings
+}
+
+fn gen_utime(state: &RawContractState) -> Option<u32> {
+    let timings = match state {
+        RawContractState::Exists(contract) => &contract.timings,
+        RawContractState::NotExists { timings } => timings,
+    };
+    match timings {
+        GenTimings::Known { gen_utime, .. } => Some(*gen_utime),
+        GenTimings::Unknown => None,
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "type", content = "data")]
+pub enum TransferWarning {
+    /// Message is already expired according to the local clock
+    #[serde(rename_all = "camelCase")]
+    AlreadyExpired { expire_at: u32, now: u32 },
+    /// Local clock differs too much from the blockchain time
+    #[serde(rename_all = "camelCase")]
+    ClockSkewed { gen_utime: u32, now: u32 },
+    /// Sender wallet is frozen and can't send messages
+    SenderFrozen,
+    /// Balance doesn't cover the attached amount and fees
+    #[serde(rename_all = "camelCase")]
+    InsufficientBalance {
+        #[serde(with = "serde_string")]
+        balance: u64,
+        #[serde(with = "serde_string")]
+        required: u64,
+    },
+    /// Recipient account doesn't exist yet
+    RecipientNotExists,
+    /// Recipient account is frozen, funds will only cover its debt
+    RecipientFrozen,
+    /// Bounceable message to the not deployed account will be returned back
+    BounceToUninit,
+    /// Non-bounceable message to the contract will not be returned on failure
+    NonBounceableToActive,
+    /// Recipient looks like a token wallet. Usually means that the token
+    /// owner address was expected instead
+    RecipientIsTokenWallet,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use nekoton_abi::LastTransactionId;
+    use ton_block::MsgAddressInt;
+
+    use super::*;
+    use crate::transport::models::ExistingContract;
+
+    fn existing_account(balance: u64) -> RawContractState {
+        let address = MsgAddressInt::from_str(
+            "0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb",
+        )
+        .unwrap();
+        let account = match ton_block::Account::with_address_and_ballance(
+            &address,
+            &ton_block::CurrencyCollection::with_grams(balance),
+        ) {
+            ton_block::Account::Account(account) => account,
+            ton_block::Account::AccountNone => unreachable!(),
+        };
+
+        RawContractState::Exists(ExistingContract {
+            account,
+            timings: GenTimings::Known {
+                gen_lt: 0,
+                gen_utime: 1650000000,
+            },
+            last_transaction_id: LastTransactionId::Inexact { latest_lt: 0 },
+        })
+    }
+
+    fn balance_warnings(
+        sender_state: &RawContractState,
+        amount: u64,
+        fees: u64,
+    ) -> Vec<TransferWarning> {
+        let clock = ConstClock::from_secs(1650000000);
+        precheck_transfer(
+            &clock,
+            TransferPrecheck {
+                sender_state,
+                recipient_state: sender_state,
+                amount,
+                fees,
+                bounce: false,
+                expire_at: 1650000060,
+            },
+        )
+        .into_iter()
+        .filter(|warning| matches!(warning, TransferWarning::InsufficientBalance { .. }))
+        .collect()
+    }
+
+    #[test]
+    fn insufficient_balance() {
+        let state = existing_account(1_000_000_000);
+
+        // Fees are also taken into account
+        assert_eq!(
+            balance_warnings(&state, 1_000_000_000, 10_000_000),
+            [TransferWarning::InsufficientBalance {
+                balance: 1_000_000_000,
+                required: 1_010_000_000,
+            }]
+        );
+        assert!(balance_warnings(&state, 990_000_000, 10_000_000).is_empty());
+    }
+
+    #[test]
+    fn required_amount_saturates() {
+        let state = existing_account(1_000_000_000);
+        assert_eq!(
+            balance_warnings(&state, u64::MAX, 10_000_000),
+            [TransferWarning::InsufficientBalance {
+                balance: 1_000_000_000,
+                required: u64::MAX,
+            }]
+        );
+    }
+
+    #[test]
+    fn transfer_to_not_existing_account() {
+        let clock = ConstClock::from_secs(1650000000);
+        let state = RawContractState::NotExists {
+            timings: GenTimings::Known {
+                gen_lt: 0,
+                gen_utime: 1650000000,
+            },
+        };
+
+        let warnings = precheck_transfer(
+            &clock,
+            TransferPrecheck {
+                sender_state: &state,
+                recipient_state: &state,
+                amount: 1_000_000_000,
+                fees: 10_000_000,
+                bounce: true,
+                expire_at: 1650000060,
+            },
+        );
+        assert_eq!(
+            warnings,
+            [
+                TransferWarning::InsufficientBalance {
+                    balance: 0,
+                    required: 1_010_000_000
+                },
+                TransferWarning::RecipientNotExists,
+                TransferWarning::BounceToUninit,
+            ]
+        );
+    }
+}
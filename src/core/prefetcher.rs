@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use ton_block::MsgAddressInt;
+
+use nekoton_utils::*;
+
+use super::models::Transaction;
+use super::token_wallet::RootTokenDetailsCache;
+use crate::transport::models::RawContractState;
+use crate::transport::Transport;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PrefetcherConfig {
+    /// Max number of speculative requests during the budget window
+    pub max_requests: u32,
+    /// Budget window duration in seconds
+    pub budget_window_sec: u64,
+    /// How long prefetched states are considered fresh, in seconds
+    pub state_ttl_sec: u64,
+}
+
+impl Default for PrefetcherConfig {
+    fn default() -> Self {
+        Self {
+            max_requests: 20,
+            budget_window_sec: 60,
+            state_ttl_sec: 30,
+        }
+    }
+}
+
+/// Speculatively fetches data which is likely to be needed by the next user
+/// navigation (e.g. counterparties of the new transactions).
+///
+/// All requests are limited by the budget, so the prefetcher never competes
+/// with the regular requests for the rate limits of the endpoint
+pub struct Prefetcher {
+    clock: Arc<dyn Clock>,
+    transport: Arc<dyn Transport>,
+    root_details_cache: Option<Arc<RootTokenDetailsCache>>,
+    config: PrefetcherConfig,
+    state: parking_lot::Mutex<PrefetcherState>,
+}
+
+impl Prefetcher {
+    pub fn new(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        config: PrefetcherConfig,
+    ) -> Self {
+        Self {
+            clock,
+            transport,
+            root_details_cache: None,
+            config,
+            state: Default::default(),
+        }
+    }
+
+    /// Enables prefetching of the root token contract details into the specified cache
+    pub fn with_root_details_cache(mut self, cache: Arc<RootTokenDetailsCache>) -> Self {
+        self.root_details_cache = Some(cache);
+        self
+    }
+
+    /// Returns the prefetched state if it is still fresh
+    pub fn get_cached_state(&self, address: &MsgAddressInt) -> Option<RawContractState> {
+        let now = self.clock.now_sec_u64();
+        let state = self.state.lock();
+        match state.states.get(address) {
+            Some((fetched_at, contract_state))
+                if fetched_at.saturating_add(self.config.state_ttl_sec) > now =>
+            {
+                Some(contract_state.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the prefetched state if it is still fresh, or requests it otherwise
+    pub async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        match self.get_cached_state(address) {
+            Some(contract_state) => Ok(contract_state),
+            None => self.transport.get_contract_state(address).await,
+        }
+    }
+
+    /// Number of requests which can be made during the current budget window
+    pub fn remaining_budget(&self) -> u32 {
+        let now = self.clock.now_sec_u64();
+        let mut state = self.state.lock();
+        state.refresh_window(now, &self.config);
+        self.config.max_requests.saturating_sub(state.used)
+    }
+
+    /// Fetches states of the counterparties of all specified transactions.
+    ///
+    /// Returns the number of fetched states
+    pub async fn prefetch_counterparties(
+        &self,
+        own_address: &MsgAddressInt,
+        transactions: &[Transaction],
+    ) -> usize {
+        let counterparties = transactions.iter().flat_map(|transaction| {
+            transaction.in_msg.src.iter().chain(
+                transaction
+                    .out_msgs
+                    .iter()
+                    .filter_map(|msg| msg.dst.as_ref()),
+            )
+        });
+        self.prefetch_states(counterparties.filter(|address| *address != own_address))
+            .await
+    }
+
+    /// Fetches states of the specified contracts which are not cached yet.
+    ///
+    /// Returns the number of fetched states
+    pub async fn prefetch_states<'a, I>(&self, addresses: I) -> usize
+    where
+        I: IntoIterator<Item = &'a MsgAddressInt>,
+    {
+        let mut requested = Vec::new();
+        for address in addresses {
+            if requested.contains(&address) || self.get_cached_state(address).is_some() {
+                continue;
+            }
+            if !self.try_acquire() {
+                break;
+            }
+            requested.push(address);
+        }
+
+        let transport = self.transport.as_ref();
+        stream::iter(requested)
+            .map(|address| async move {
+                // NOTE: errors are ignored because the data is not required right now
+                let contract_state = transport.get_contract_state(address).await.ok()?;
+
+                let now = self.clock.now_sec_u64();
+                let mut state = self.state.lock();
+                state.remove_expired(now, &self.config);
+                state.states.insert(address.clone(), (now, contract_state));
+                Some(())
+            })
+            .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+            .filter_map(futures_util::future::ready)
+            .count()
+            .await
+    }
+
+    /// Fetches details of the root token contracts which are not cached yet.
+    ///
+    /// Does nothing if the details cache was not specified.
+    /// Returns the number of fetched details
+    pub async fn prefetch_token_roots(&self, root_token_contracts: &[MsgAddressInt]) -> usize {
+        let cache = match &self.root_details_cache {
+            Some(cache) => cache,
+            None => return 0,
+        };
+
+        let mut requested = Vec::new();
+        for root_token_contract in root_token_contracts {
+            if cache.get_cached(root_token_contract).await.is_some() {
+                continue;
+            }
+            if !self.try_acquire() {
+                break;
+            }
+            requested.push(root_token_contract);
+        }
+
+        stream::iter(requested)
+            .map(|root_token_contract| cache.refresh(root_token_contract))
+            .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+            .filter(|result| futures_util::future::ready(result.is_ok()))
+            .count()
+            .await
+    }
+
+    fn try_acquire(&self) -> bool {
+        let now = self.clock.now_sec_u64();
+        self.state.lock().try_acquire(now, &self.config)
+    }
+}
+
+const MAX_CONCURRENT_REQUESTS: usize = 4;
+
+#[derive(Default)]
+struct PrefetcherState {
+    window_start: u64,
+    used: u32,
+    states: HashMap<MsgAddressInt, (u64, RawContractState)>,
+}
+
+impl PrefetcherState {
+    fn refresh_window(&mut self, now: u64, config: &PrefetcherConfig) {
+        if self.window_start.saturating_add(config.budget_window_sec) <= now {
+            self.window_start = now;
+            self.used = 0;
+        }
+    }
+
+    fn try_acquire(&mut self, now: u64, config: &PrefetcherConfig) -> bool {
+        self.refresh_window(now, config);
+        if self.used < config.max_requests {
+            self.used += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn remove_expired(&mut self, now: u64, config: &PrefetcherConfig) {
+        self.states
+            .retain(|_, (fetched_at, _)| fetched_at.saturating_add(config.state_ttl_sec) > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::testing::ChainSimulator;
+
+    #[tokio::test]
+    async fn prefetched_states_are_reused() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let addresses = (1..=3)
+            .map(|i| MsgAddressInt::from_str(&format!("0:{}", hex::encode([i; 32]))).unwrap())
+            .collect::<Vec<_>>();
+        for address in &addresses {
+            sim.transport().set_account(
+                address.clone(),
+                ton_block::Account::with_address_and_ballance(
+                    address,
+                    &ton_block::CurrencyCollection::with_grams(1_000_000_000),
+                ),
+            );
+        }
+
+        let prefetcher = Prefetcher::new(
+            sim.clock().clone(),
+            sim.transport().clone(),
+            PrefetcherConfig {
+                max_requests: 2,
+                budget_window_sec: 60,
+                state_ttl_sec: 30,
+            },
+        );
+
+        // Duplicates are requested once, requests above the budget are skipped
+        let fetched = prefetcher
+            .prefetch_states([&addresses[0], &addresses[0], &addresses[1], &addresses[2]])
+            .await;
+        assert_eq!(fetched, 2);
+        assert_eq!(prefetcher.remaining_budget(), 0);
+        assert!(prefetcher.get_cached_state(&addresses[0]).is_some());
+        assert!(prefetcher.get_cached_state(&addresses[1]).is_some());
+        assert!(prefetcher.get_cached_state(&addresses[2]).is_none());
+
+        // Fresh states are returned without new requests
+        sim.transport().set_account(
+            addresses[0].clone(),
+            ton_block::Account::with_address_and_ballance(
+                &addresses[0],
+                &ton_block::CurrencyCollection::with_grams(1),
+            ),
+        );
+        let cached = prefetcher.get_contract_state(&addresses[0]).await.unwrap();
+        assert_eq!(cached.brief().balance, 1_000_000_000);
+
+        // Expired states are requested again
+        sim.clock().advance(std::time::Duration::from_secs(30));
+        assert!(prefetcher.get_cached_state(&addresses[0]).is_none());
+        let fresh = prefetcher.get_contract_state(&addresses[0]).await.unwrap();
+        assert_eq!(fresh.brief().balance, 1);
+    }
+
+    #[test]
+    fn request_budget() {
+        let config = PrefetcherConfig {
+            max_requests: 2,
+            budget_window_sec: 60,
+            state_ttl_sec: 30,
+        };
+
+        let mut state = PrefetcherState::default();
+        assert!(state.try_acquire(1000, &config));
+        assert!(state.try_acquire(1010, &config));
+        assert!(!state.try_acquire(1059, &config));
+        assert!(state.try_acquire(1060, &config));
+    }
+}
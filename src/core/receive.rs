@@ -0,0 +1,110 @@
+use std::fmt::Write;
+
+use anyhow::Result;
+use serde::Serialize;
+use ton_block::MsgAddressInt;
+
+use nekoton_utils::*;
+
+use super::accounts_storage::AssetsList;
+
+/// Everything needed to render the receive screen of the account
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiveInfo {
+    #[serde(with = "serde_address")]
+    pub address: MsgAddressInt,
+    /// Packed url-safe bounceable address (`EQ...`)
+    pub bounceable: String,
+    /// Packed url-safe non-bounceable address (`UQ...`)
+    pub non_bounceable: String,
+    /// Packed address which should be shown to the sender
+    pub recommended: String,
+    /// `ton://transfer/...` link to the recommended address
+    pub payment_uri: String,
+    /// Payload of the QR code (UTF-8 encoded payment uri)
+    #[serde(with = "serde_bytes_base64")]
+    pub qr_payload: Vec<u8>,
+}
+
+impl ReceiveInfo {
+    /// Builds a payment link with the requested amount and comment
+    pub fn payment_uri_with(&self, amount: Option<u64>, comment: Option<&str>) -> String {
+        let mut uri = self.payment_uri.clone();
+        let mut separator = '?';
+        if let Some(amount) = amount {
+            let _ = write!(uri, "{separator}amount={amount}");
+            separator = '&';
+        }
+        if let Some(comment) = comment {
+            let _ = write!(uri, "{separator}text={}", percent_encode(comment));
+        }
+        uri
+    }
+}
+
+/// Collects all representations of the account address at once,
+/// so that all platforms render identical receive screens
+pub fn build_receive_info(account: &AssetsList) -> Result<ReceiveInfo> {
+    let address = account.ton_wallet.address.clone();
+    let bounceable = pack_std_smc_addr(true, &address, true)?;
+    let non_bounceable = pack_std_smc_addr(true, &address, false)?;
+
+    // NOTE: wallets can be not deployed yet, so the incoming transfers
+    // to the bounceable address would be returned back
+    let recommended = non_bounceable.clone();
+
+    let payment_uri = format!("ton://transfer/{recommended}");
+    let qr_payload = payment_uri.as_bytes().to_vec();
+
+    Ok(ReceiveInfo {
+        address,
+        bounceable,
+        non_bounceable,
+        recommended,
+        payment_uri,
+        qr_payload,
+    })
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                result.push(byte as char)
+            }
+            _ => {
+                let _ = write!(result, "%{byte:02X}");
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payment_uri_with_params() {
+        assert_eq!(percent_encode("hello world!"), "hello%20world%21");
+
+        let info = ReceiveInfo {
+            address: Default::default(),
+            bounceable: String::new(),
+            non_bounceable: String::new(),
+            recommended: String::new(),
+            payment_uri: "ton://transfer/UQ".to_owned(),
+            qr_payload: Vec::new(),
+        };
+        assert_eq!(
+            info.payment_uri_with(Some(100), Some("for coffee")),
+            "ton://transfer/UQ?amount=100&text=for%20coffee"
+        );
+        assert_eq!(
+            info.payment_uri_with(None, Some("x")),
+            "ton://transfer/UQ?text=x"
+        );
+    }
+}
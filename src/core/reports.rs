@@ -0,0 +1,217 @@
+use std::ops::Range;
+
+use serde::Serialize;
+
+use nekoton_utils::*;
+
+use super::models::{AccountStatus, KnownPayload, TransactionAdditionalInfo, TransactionWithData};
+
+/// Computes fees paid by the account during the specified period
+/// (range of transaction timestamps), grouped by operation type
+pub fn fee_summary(
+    transactions: &[TransactionWithData<TransactionAdditionalInfo>],
+    period: Range<u32>,
+) -> FeeSummary {
+    let mut summary = FeeSummary::default();
+
+    for item in transactions {
+        let transaction = &item.transaction;
+        if !period.contains(&transaction.created_at) {
+            continue;
+        }
+
+        let group = match FeeOperation::classify(item) {
+            FeeOperation::PlainTransfer => &mut summary.plain_transfer,
+            FeeOperation::TokenTransfer => &mut summary.token_transfer,
+            FeeOperation::Stake => &mut summary.stake,
+            FeeOperation::Deploy => &mut summary.deploy,
+            FeeOperation::Other => &mut summary.other,
+        };
+        group.add(transaction.total_fees);
+        summary.total.add(transaction.total_fees);
+    }
+
+    summary
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeOperation {
+    /// Outgoing native currency transfer
+    PlainTransfer,
+    /// Outgoing token transfer or swap back
+    TokenTransfer,
+    /// DePool stake or withdrawal request
+    Stake,
+    /// Transaction which deployed the contract
+    Deploy,
+    /// Incoming messages and other operations
+    Other,
+}
+
+impl FeeOperation {
+    pub fn classify(item: &TransactionWithData<TransactionAdditionalInfo>) -> Self {
+        let transaction = &item.transaction;
+        if transaction.orig_status != AccountStatus::Active
+            && transaction.end_status == AccountStatus::Active
+        {
+            return Self::Deploy;
+        }
+
+        match &item.data {
+            Some(TransactionAdditionalInfo::WalletInteraction(info)) => match &info.known_payload {
+                None | Some(KnownPayload::Comment(_)) => Self::PlainTransfer,
+                Some(KnownPayload::TokenOutgoingTransfer(_) | KnownPayload::TokenSwapBack(_)) => {
+                    Self::TokenTransfer
+                }
+                Some(KnownPayload::DePoolStake(_)) => Self::Stake,
+            },
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeSummary {
+    pub total: FeeGroup,
+    pub plain_transfer: FeeGroup,
+    pub token_transfer: FeeGroup,
+    pub stake: FeeGroup,
+    pub deploy: FeeGroup,
+    pub other: FeeGroup,
+}
+
+#[derive(Debug, Default, Copy, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeGroup {
+    /// Number of transactions in the group
+    pub transaction_count: usize,
+    /// Sum of total fees of the transactions
+    #[serde(with = "serde_string")]
+    pub fees: u64,
+}
+
+impl FeeGroup {
+    fn add(&mut self, fees: u64) {
+        self.transaction_count += 1;
+        self.fees = self.fees.saturating_add(fees);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigUint;
+    use ton_block::MsgAddressInt;
+
+    use super::*;
+    use crate::core::models::{
+        DePoolStakeOperation, TokenOutgoingTransfer, TokenSwapBack, Transaction, TransactionId,
+        TransferRecipient, WalletInteractionInfo, WalletInteractionMethod,
+    };
+
+    fn make_transaction(
+        orig_status: AccountStatus,
+        data: Option<TransactionAdditionalInfo>,
+    ) -> TransactionWithData<TransactionAdditionalInfo> {
+        TransactionWithData {
+            transaction: Transaction {
+                id: TransactionId {
+                    lt: 1,
+                    hash: Default::default(),
+                },
+                prev_trans_id: None,
+                created_at: 0,
+                aborted: false,
+                exit_code: None,
+                result_code: None,
+                orig_status,
+                end_status: AccountStatus::Active,
+                total_fees: 0,
+                in_msg: Default::default(),
+                out_msgs: Vec::new(),
+                #[cfg(feature = "extended_models")]
+                raw: Default::default(),
+            },
+            data,
+        }
+    }
+
+    fn wallet_interaction(known_payload: Option<KnownPayload>) -> TransactionAdditionalInfo {
+        TransactionAdditionalInfo::WalletInteraction(WalletInteractionInfo {
+            recipient: None,
+            known_payload,
+            method: WalletInteractionMethod::WalletV3Transfer,
+        })
+    }
+
+    #[test]
+    fn classify_fee_operations() {
+        let cases = [
+            (
+                AccountStatus::Uninit,
+                Some(wallet_interaction(None)),
+                FeeOperation::Deploy,
+            ),
+            (
+                AccountStatus::Active,
+                Some(wallet_interaction(None)),
+                FeeOperation::PlainTransfer,
+            ),
+            (
+                AccountStatus::Active,
+                Some(wallet_interaction(Some(KnownPayload::Comment(
+                    "hello".to_owned(),
+                )))),
+                FeeOperation::PlainTransfer,
+            ),
+            (
+                AccountStatus::Active,
+                Some(wallet_interaction(Some(
+                    KnownPayload::TokenOutgoingTransfer(TokenOutgoingTransfer {
+                        to: TransferRecipient::OwnerWallet(MsgAddressInt::default()),
+                        tokens: BigUint::from(1u32),
+                        payload: Default::default(),
+                    }),
+                ))),
+                FeeOperation::TokenTransfer,
+            ),
+            (
+                AccountStatus::Active,
+                Some(wallet_interaction(Some(KnownPayload::TokenSwapBack(
+                    TokenSwapBack {
+                        tokens: BigUint::from(1u32),
+                        callback_address: MsgAddressInt::default(),
+                        callback_payload: Default::default(),
+                    },
+                )))),
+                FeeOperation::TokenTransfer,
+            ),
+            (
+                AccountStatus::Active,
+                Some(wallet_interaction(Some(KnownPayload::DePoolStake(
+                    DePoolStakeOperation::AddOrdinaryStake(10),
+                )))),
+                FeeOperation::Stake,
+            ),
+            (
+                AccountStatus::Active,
+                Some(wallet_interaction(Some(KnownPayload::DePoolStake(
+                    DePoolStakeOperation::WithdrawAll,
+                )))),
+                FeeOperation::Stake,
+            ),
+            (
+                AccountStatus::Active,
+                Some(TransactionAdditionalInfo::Comment("incoming".to_owned())),
+                FeeOperation::Other,
+            ),
+            (AccountStatus::Active, None, FeeOperation::Other),
+        ];
+
+        for (i, (orig_status, data, expected)) in cases.into_iter().enumerate() {
+            let item = make_transaction(orig_status, data);
+            assert_eq!(FeeOperation::classify(&item), expected, "case {i}");
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use ton_block::{GetRepresentationHash, MsgAddressInt};
+use ton_types::UInt256;
+
+use nekoton_utils::*;
+
+use super::ton_wallet::WalletType;
+use crate::crypto::SignedMessage;
+use crate::external::Storage;
+
+pub const SCHEDULED_MESSAGES_STORAGE_KEY: &str = "__core__scheduled_messages";
+
+/// Stores messages which were signed in advance (e.g. while the hardware key
+/// is connected) and must be broadcast later, within the specified window.
+///
+/// Messages must be prepared with [`Expiration::Timestamp`] which is after the
+/// broadcast window start.
+///
+/// Ready messages are sent with [`ContractSubscription::send_scheduled`], so that
+/// the broadcast window is tracked along with the other pending transactions.
+///
+/// NOTE: only wallets which store hashes of the processed messages (see
+/// [`supports_scheduled_messages`]) accept pre-signed messages in any order.
+/// Seqno-based wallets (e.g. WalletV3) invalidate all pre-signed messages
+/// after any other outgoing transfer, and wallets with timestamp-based
+/// replay protection reject messages signed before the last processed one.
+///
+/// [`Expiration::Timestamp`]: crate::core::models::Expiration::Timestamp
+/// [`ContractSubscription::send_scheduled`]: crate::core::ContractSubscription::send_scheduled
+pub struct ScheduledMessages {
+    key: String,
+    storage: Arc<dyn Storage>,
+    messages: RwLock<Vec<ScheduledMessage>>,
+}
+
+impl ScheduledMessages {
+    pub async fn load(network_group: &str, storage: Arc<dyn Storage>) -> Result<Self> {
+        let key = make_key(network_group);
+
+        let messages = match storage.get(&key).await? {
+            Some(data) => serde_json::from_str::<Vec<ScheduledMessage>>(&data)?,
+            None => Default::default(),
+        };
+
+        Ok(Self {
+            key,
+            storage,
+            messages: RwLock::new(messages),
+        })
+    }
+
+    pub async fn load_unchecked(network_group: &str, storage: Arc<dyn Storage>) -> Self {
+        Self::load(network_group, storage.clone())
+            .await
+            .unwrap_or_else(|_| Self {
+                key: make_key(network_group),
+                storage,
+                messages: Default::default(),
+            })
+    }
+
+    /// Schedules the signed message. Returns message hash
+    pub async fn schedule(
+        &self,
+        clock: &dyn Clock,
+        wallet_type: WalletType,
+        address: MsgAddressInt,
+        message: SignedMessage,
+        broadcast_not_before: u32,
+    ) -> Result<UInt256> {
+        if !supports_scheduled_messages(wallet_type) {
+            return Err(ScheduledMessagesError::UnsupportedWalletType.into());
+        }
+        if message.expire_at <= broadcast_not_before {
+            return Err(ScheduledMessagesError::EmptyBroadcastWindow.into());
+        }
+        if message.expire_at <= clock.now_sec_u64() as u32 {
+            return Err(ScheduledMessagesError::MessageExpired.into());
+        }
+
+        let message_hash = message.message.hash()?;
+
+        let mut messages = self.messages.write().await;
+        messages.retain(|item| item.message_hash != message_hash);
+        messages.push(ScheduledMessage {
+            message_hash,
+            address,
+            message: message.message,
+            broadcast_not_before,
+            expire_at: message.expire_at,
+            created_at: clock.now_sec_u64() as u32,
+        });
+        messages.sort_by_key(|item| item.broadcast_not_before);
+        self.save(&messages);
+
+        Ok(message_hash)
+    }
+
+    /// Returns all scheduled messages, ordered by the broadcast window start
+    pub async fn messages(&self) -> Vec<ScheduledMessage> {
+        self.messages.read().await.clone()
+    }
+
+    pub async fn get(&self, message_hash: &UInt256) -> Option<ScheduledMessage> {
+        self.messages
+            .read()
+            .await
+            .iter()
+            .find(|item| &item.message_hash == message_hash)
+            .cloned()
+    }
+
+    /// Returns the closest broadcast window start, if there are any messages
+    pub async fn next_broadcast_at(&self) -> Option<u32> {
+        self.messages
+            .read()
+            .await
+            .first()
+            .map(|item| item.broadcast_not_before)
+    }
+
+    /// Removes and returns all messages which can be broadcast now.
+    ///
+    /// Expired messages are also removed, they are returned separately
+    pub async fn take_ready(&self, clock: &dyn Clock) -> ReadyMessages {
+        let now = clock.now_sec_u64() as u32;
+
+        let mut messages = self.messages.write().await;
+        let mut ready = ReadyMessages::default();
+        messages.retain(|item| {
+            if item.expire_at <= now {
+                ready.expired.push(item.clone());
+                false
+            } else if item.broadcast_not_before <= now {
+                ready.messages.push(item.clone());
+                false
+            } else {
+                true
+            }
+        });
+
+        if !ready.messages.is_empty() || !ready.expired.is_empty() {
+            self.save(&messages);
+        }
+        ready
+    }
+
+    /// Cancels the scheduled message
+    pub async fn remove(&self, message_hash: &UInt256) -> Option<ScheduledMessage> {
+        let mut messages = self.messages.write().await;
+        let index = messages
+            .iter()
+            .position(|item| &item.message_hash == message_hash)?;
+        let message = messages.remove(index);
+        self.save(&messages);
+        Some(message)
+    }
+
+    pub async fn clear(&self) {
+        let mut messages = self.messages.write().await;
+        messages.clear();
+        self.storage.remove_unchecked(&self.key);
+    }
+
+    fn save(&self, messages: &[ScheduledMessage]) {
+        let data = serde_json::to_string(messages).trust_me();
+        self.storage.set_unchecked(&self.key, &data);
+    }
+}
+
+/// Whether pre-signed messages of the wallet stay valid after other transfers
+pub fn supports_scheduled_messages(wallet_type: WalletType) -> bool {
+    match wallet_type {
+        WalletType::EverWallet => true,
+        WalletType::Multisig(multisig_type) => multisig_type.is_multisig2(),
+        WalletType::WalletV3 | WalletType::HighloadWalletV2 => false,
+    }
+}
+
+fn make_key(network_group: &str) -> String {
+    format!("{SCHEDULED_MESSAGES_STORAGE_KEY}{network_group}")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledMessage {
+    #[serde(with = "serde_uint256")]
+    pub message_hash: UInt256,
+    /// Sender address
+    #[serde(with = "serde_address")]
+    pub address: MsgAddressInt,
+    #[serde(with = "serde_ton_block")]
+    pub message: ton_block::Message,
+    /// Message must not be sent before this moment
+    pub broadcast_not_before: u32,
+    pub expire_at: u32,
+    pub created_at: u32,
+}
+
+impl ScheduledMessage {
+    /// Converts back into the signed message which can be sent
+    pub fn to_signed_message(&self) -> SignedMessage {
+        SignedMessage {
+            message: self.message.clone(),
+            expire_at: self.expire_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReadyMessages {
+    /// Messages which must be sent now
+    pub messages: Vec<ScheduledMessage>,
+    /// Messages which were not sent in time
+    pub expired: Vec<ScheduledMessage>,
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone)]
+pub enum ScheduledMessagesError {
+    #[error("Pre-signed messages are not supported by this wallet type")]
+    UnsupportedWalletType,
+    #[error("Message expires before the broadcast window starts")]
+    EmptyBroadcastWindow,
+    #[error("Message expired")]
+    MessageExpired,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::core::ton_wallet::MultisigType;
+    use crate::testing::{ManualClock, MemoryStorage};
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn test_address() -> MsgAddressInt {
+        MsgAddressInt::from_str(
+            "0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb",
+        )
+        .unwrap()
+    }
+
+    fn make_message(id: u32, expire_at: u32) -> SignedMessage {
+        let mut message =
+            ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+                dst: test_address(),
+                ..Default::default()
+            });
+        let mut body = ton_types::BuilderData::new();
+        body.append_u32(id).unwrap();
+        message.set_body(ton_types::SliceData::load_builder(body).unwrap());
+        SignedMessage { message, expire_at }
+    }
+
+    #[tokio::test]
+    async fn rejects_unsupported_wallets() {
+        let clock = ManualClock::from_secs(NOW);
+        let storage = Arc::new(MemoryStorage::default());
+        let messages = ScheduledMessages::load("test", storage).await.unwrap();
+
+        let now = NOW as u32;
+        for wallet_type in [
+            WalletType::WalletV3,
+            WalletType::HighloadWalletV2,
+            WalletType::Multisig(MultisigType::SafeMultisigWallet),
+        ] {
+            let result = messages
+                .schedule(
+                    &clock,
+                    wallet_type,
+                    test_address(),
+                    make_message(1, now + 3600),
+                    now + 60,
+                )
+                .await;
+            assert!(result.is_err());
+        }
+
+        for wallet_type in [
+            WalletType::EverWallet,
+            WalletType::Multisig(MultisigType::Multisig2_1),
+        ] {
+            messages
+                .schedule(
+                    &clock,
+                    wallet_type,
+                    test_address(),
+                    make_message(1, now + 3600),
+                    now + 60,
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(messages.messages().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn take_ready_and_expired() {
+        let clock = ManualClock::from_secs(NOW);
+        let storage = Arc::new(MemoryStorage::default());
+        let messages = ScheduledMessages::load("test", storage.clone())
+            .await
+            .unwrap();
+
+        let now = NOW as u32;
+        let schedule = |id, expire_at, broadcast_not_before| {
+            messages.schedule(
+                &clock,
+                WalletType::EverWallet,
+                test_address(),
+                make_message(id, expire_at),
+                broadcast_not_before,
+            )
+        };
+
+        let early = schedule(1, now + 120, now + 60).await.unwrap();
+        let late = schedule(2, now + 7200, now + 3600).await.unwrap();
+        assert!(schedule(3, now + 60, now + 60).await.is_err());
+        assert_eq!(messages.next_broadcast_at().await, Some(now + 60));
+
+        // Nothing is ready before the window starts
+        let ready = messages.take_ready(&clock).await;
+        assert!(ready.messages.is_empty() && ready.expired.is_empty());
+
+        clock.advance(Duration::from_secs(60));
+        let ready = messages.take_ready(&clock).await;
+        assert_eq!(ready.messages.len(), 1);
+        assert_eq!(ready.messages[0].message_hash, early);
+        assert!(ready.expired.is_empty());
+        assert_eq!(messages.next_broadcast_at().await, Some(now + 3600));
+
+        // State is persisted
+        let reloaded = ScheduledMessages::load("test", storage).await.unwrap();
+        assert_eq!(reloaded.messages().await.len(), 1);
+
+        // Message which was not taken in time expires
+        clock.advance(Duration::from_secs(7200));
+        let ready = messages.take_ready(&clock).await;
+        assert!(ready.messages.is_empty());
+        assert_eq!(ready.expired.len(), 1);
+        assert_eq!(ready.expired[0].message_hash, late);
+        assert!(messages.messages().await.is_empty());
+    }
+}
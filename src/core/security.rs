@@ -0,0 +1,223 @@
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use serde::Serialize;
+use ton_types::SliceData;
+
+use nekoton_abi::{unpack_headers, ExpireHeader, PubkeyHeader, TimeHeader};
+use nekoton_utils::*;
+
+use super::ton_wallet::{wallet_v3, WalletType};
+use crate::crypto::UnsignedMessage;
+use crate::transport::models::RawContractState;
+
+/// Messages with `time` header too far in the future are rejected by the
+/// default replay protection of ABI contracts
+const MAX_TIME_HEADER_SKEW_MS: u64 = 30 * 60 * 1000;
+
+/// Signed messages which live longer than this can be broadcast by anyone
+/// who intercepted them long after the user has forgotten about them
+const MAX_SAFE_LIFETIME: u32 = 3600;
+
+/// Checks whether the replay protection of the target wallet actually
+/// covers the constructed message.
+///
+/// Returns a list of warnings, empty list means that nothing suspicious was found
+pub fn check_replay_safety(
+    clock: &dyn Clock,
+    unsigned_message: &dyn UnsignedMessage,
+    wallet_type: WalletType,
+    state: &RawContractState,
+) -> Result<Vec<ReplaySafetyWarning>> {
+    let mut warnings = Vec::new();
+
+    let now_ms = clock.now_ms_u64();
+    let now = (now_ms / 1000) as u32;
+
+    let expire_at = unsigned_message.expire_at();
+    if expire_at == u32::MAX {
+        warnings.push(ReplaySafetyWarning::NeverExpires);
+    } else if expire_at <= now {
+        warnings.push(ReplaySafetyWarning::AlreadyExpired { expire_at, now });
+    } else if expire_at - now > MAX_SAFE_LIFETIME {
+        warnings.push(ReplaySafetyWarning::LongLifetime {
+            lifetime: expire_at - now,
+        });
+    }
+
+    // NOTE: signature doesn't affect the layout of the body, so
+    // a zero signature is enough to inspect the resulting message
+    let message = unsigned_message
+        .sign(&[0; ed25519_dalek::SIGNATURE_LENGTH])?
+        .message;
+    let body = message.body().ok_or(SecurityError::MessageBodyNotFound)?;
+
+    let data = match state {
+        RawContractState::Exists(contract) => match &contract.account.storage.state {
+            ton_block::AccountState::AccountActive { state_init, .. } => state_init.data.as_ref(),
+            ton_block::AccountState::AccountFrozen { .. } => {
+                warnings.push(ReplaySafetyWarning::AccountFrozen);
+                None
+            }
+            ton_block::AccountState::AccountUninit => None,
+        },
+        RawContractState::NotExists { .. } => None,
+    };
+
+    if data.is_none() && message.state_init().is_none() {
+        warnings.push(ReplaySafetyWarning::MissingStateInit);
+    }
+
+    match wallet_type {
+        WalletType::Multisig(_) | WalletType::EverWallet => {
+            let ((pubkey, time, expire), _) =
+                unpack_headers::<(PubkeyHeader, TimeHeader, ExpireHeader)>(&body)?;
+
+            if pubkey.is_none() {
+                warnings.push(ReplaySafetyWarning::MissingPubkeyHeader);
+            }
+            if expire != expire_at {
+                warnings.push(ReplaySafetyWarning::ExpirationMismatch {
+                    in_body: expire,
+                    expire_at,
+                });
+            }
+            if time > now_ms + MAX_TIME_HEADER_SKEW_MS {
+                warnings.push(ReplaySafetyWarning::TimeHeaderInFuture { time, now_ms });
+            }
+        }
+        WalletType::WalletV3 => {
+            let mut body = skip_signature(body)?;
+            let _wallet_id = body.get_next_u32()?;
+            let expire = body.get_next_u32()?;
+            let seqno = body.get_next_u32()?;
+
+            if expire != expire_at {
+                warnings.push(ReplaySafetyWarning::ExpirationMismatch {
+                    in_body: expire,
+                    expire_at,
+                });
+            }
+
+            let current_seqno = match data {
+                Some(data) => wallet_v3::InitData::try_from(data)?.seqno,
+                None => 0,
+            };
+            if seqno != current_seqno {
+                warnings.push(ReplaySafetyWarning::SeqnoMismatch {
+                    in_body: seqno,
+                    current: current_seqno,
+                });
+            }
+        }
+        WalletType::HighloadWalletV2 => {
+            let mut body = skip_signature(body)?;
+            let _wallet_id = body.get_next_u32()?;
+            let expire = body.get_next_u32()?;
+
+            // NOTE: query id consists of the expiration timestamp and messages hash,
+            // so the contract will reject the same query until it is cleaned up
+            if expire != expire_at {
+                warnings.push(ReplaySafetyWarning::ExpirationMismatch {
+                    in_body: expire,
+                    expire_at,
+                });
+            }
+        }
+    }
+
+    Ok(warnings)
+}
+
+fn skip_signature(mut body: SliceData) -> Result<SliceData> {
+    body.move_by(ed25519_dalek::SIGNATURE_LENGTH * 8)?;
+    Ok(body)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "type", content = "data")]
+pub enum ReplaySafetyWarning {
+    /// Message has no expiration, so it can be broadcast at any moment
+    /// while the replay protection state of the contract allows it
+    NeverExpires,
+    /// Message is already expired according to the local clock.
+    /// Usually means that the local clock is skewed
+    #[serde(rename_all = "camelCase")]
+    AlreadyExpired { expire_at: u32, now: u32 },
+    /// Message stays valid for too long
+    LongLifetime { lifetime: u32 },
+    /// Wallet account is frozen
+    AccountFrozen,
+    /// Wallet is not deployed and message has no state init
+    MissingStateInit,
+    /// ABI message body was built without the `pubkey` header
+    MissingPubkeyHeader,
+    /// Expiration timestamp in body differs from the message expiration
+    #[serde(rename_all = "camelCase")]
+    ExpirationMismatch { in_body: u32, expire_at: u32 },
+    /// `time` header is too far in the future and will be rejected by the contract.
+    /// Usually means that the local clock is skewed
+    #[serde(rename_all = "camelCase")]
+    TimeHeaderInFuture { time: u64, now_ms: u64 },
+    /// Seqno in body differs from the current wallet seqno.
+    /// Can be expected when there are some unprocessed pending transactions
+    #[serde(rename_all = "camelCase")]
+    SeqnoMismatch { in_body: u32, current: u32 },
+}
+
+#[derive(thiserror::Error, Debug)]
+enum SecurityError {
+    #[error("Message body not found")]
+    MessageBodyNotFound,
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::PublicKey;
+    use nekoton_abi::GenTimings;
+
+    use super::*;
+    use crate::core::models::Expiration;
+
+    fn public_key() -> PublicKey {
+        PublicKey::from_bytes(
+            &hex::decode("5ace46d93d8f3932499df9f2bc7ef787385e16965e7797258948febd186de7f6")
+                .unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn wallet_v3_deploy_is_safe() {
+        let clock = ConstClock::from_secs(1650000000);
+        let state = RawContractState::NotExists {
+            timings: GenTimings::Unknown,
+        };
+
+        let message =
+            wallet_v3::prepare_deploy(&clock, &public_key(), 0, Expiration::Timeout(60)).unwrap();
+        let warnings =
+            check_replay_safety(&clock, message.as_ref(), WalletType::WalletV3, &state).unwrap();
+        assert!(warnings.is_empty());
+
+        let message =
+            wallet_v3::prepare_deploy(&clock, &public_key(), 0, Expiration::Never).unwrap();
+        let warnings =
+            check_replay_safety(&clock, message.as_ref(), WalletType::WalletV3, &state).unwrap();
+        assert_eq!(warnings, [ReplaySafetyWarning::NeverExpires]);
+
+        let late_clock = ConstClock::from_secs(1650000100);
+        let message =
+            wallet_v3::prepare_deploy(&clock, &public_key(), 0, Expiration::Timeout(60)).unwrap();
+        let warnings =
+            check_replay_safety(&late_clock, message.as_ref(), WalletType::WalletV3, &state)
+                .unwrap();
+        assert_eq!(
+            warnings,
+            [ReplaySafetyWarning::AlreadyExpired {
+                expire_at: 1650000060,
+                now: 1650000100
+            }]
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+use ed25519_dalek::PublicKey;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use ton_block::MsgAddressInt;
+
+use nekoton_utils::*;
+
+use super::accounts_storage::{AccountsStorage, SpendingPolicyViolation};
+use super::keystore::KeyStore;
+use crate::crypto::{SignatureId, SignedMessage, Signer, UnsignedMessage};
+
+/// Queue of messages waiting for the user approval.
+///
+/// Modules enqueue prepared messages, host application approves or rejects
+/// them via [`SignQueueHandler`], and approved messages are signed with the keystore.
+///
+/// If accounts storage is specified, spending policies of the accounts
+/// are checked before asking the user and again right before signing.
+pub struct SignQueue<T: Signer> {
+    clock: Arc<dyn Clock>,
+    keystore: Arc<KeyStore>,
+    accounts_storage: Option<Arc<AccountsStorage>>,
+    handler: Arc<dyn SignQueueHandler<T>>,
+    next_id: AtomicU32,
+    requests: Mutex<VecDeque<SignRequest>>,
+}
+
+impl<T: Signer> SignQueue<T> {
+    pub fn new(
+        clock: Arc<dyn Clock>,
+        keystore: Arc<KeyStore>,
+        handler: Arc<dyn SignQueueHandler<T>>,
+    ) -> Self {
+        Self {
+            clock,
+            keystore,
+            accounts_storage: None,
+            handler,
+            next_id: AtomicU32::new(0),
+            requests: Default::default(),
+        }
+    }
+
+    /// Enables spending policies enforcement
+    pub fn with_accounts_storage(mut self, accounts_storage: Arc<AccountsStorage>) -> Self {
+        self.accounts_storage = Some(accounts_storage);
+        self
+    }
+
+    /// Adds new message to the end of the queue. Returns request id
+    pub fn enqueue(
+        &self,
+        context: SignRequestContext,
+        public_key: PublicKey,
+        signature_id: Option<SignatureId>,
+        message: Box<dyn UnsignedMessage>,
+    ) -> u32 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.requests.lock().push_back(SignRequest {
+            id,
+            context,
+            public_key,
+            signature_id,
+            message,
+        });
+        id
+    }
+
+    /// Removes request from the queue without asking the user.
+    /// Returns `false` if there was no such request
+    pub fn cancel(&self, id: u32) -> bool {
+        let mut requests = self.requests.lock();
+        match requests.iter().position(|request| request.id == id) {
+            Some(index) => {
+                requests.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.lock().is_empty()
+    }
+
+    /// Asks the user about the oldest request in the queue and signs it if it was approved.
+    ///
+    /// Returns `false` if the queue is empty. If signing fails, the request
+    /// is returned to the queue along with the error
+    pub async fn process_next(&self) -> Result<bool> {
+        let request = match self.requests.lock().pop_front() {
+            Some(request) => request,
+            None => return Ok(false),
+        };
+
+        let account = request
+            .context
+            .account
+            .clone()
+            .or_else(|| message_account(request.message.as_ref()));
+        let account_id = match &account {
+            Some(account) => self.find_account_id(account, &request.public_key).await,
+            None => None,
+        };
+
+        if let Err(violation) = self
+            .check_spending_policy(account_id.as_deref(), &request.context)
+            .await
+        {
+            self.handler.on_policy_violated(request, violation);
+            return Ok(true);
+        }
+
+        let input = match self.handler.approve(&request).await {
+            Some(input) => input,
+            None => {
+                self.handler.on_request_rejected(request);
+                return Ok(true);
+            }
+        };
+
+        // NOTE: other requests could have been signed while waiting for the approval,
+        // so the policy is checked again and the amount is reserved before signing
+        let day = self.today();
+        if let Some(account_id) = &account_id {
+            if let Err(violation) = self
+                .reserve_spending(account_id, &request.context, day)
+                .await
+            {
+                self.handler.on_policy_violated(request, violation);
+                return Ok(true);
+            }
+        }
+
+        let signed_message = match self.sign(&request, input).await {
+            Ok(signed_message) => signed_message,
+            Err(e) => {
+                if let (Some(accounts_storage), Some(account_id)) =
+                    (&self.accounts_storage, &account_id)
+                {
+                    accounts_storage
+                        .release_spending(account_id, request.context.amount, day)
+                        .await;
+                }
+                // NOTE: request stays at the front of the queue, so it can be retried
+                // (e.g. after the hardware key is reconnected) or cancelled
+                self.requests.lock().push_front(request);
+                return Err(e);
+            }
+        };
+
+        self.handler.on_message_signed(request, signed_message);
+        Ok(true)
+    }
+
+    /// Processes requests until the queue is empty or signing fails
+    pub async fn process_all(&self) -> Result<()> {
+        while self.process_next().await? {}
+        Ok(())
+    }
+
+    async fn sign(&self, request: &SignRequest, input: T::SignInput) -> Result<SignedMessage> {
+        let mut message = request.message.clone();
+        message.refresh_timeout(self.clock.as_ref());
+
+        self.keystore
+            .sign_message::<T>(message.as_ref(), request.signature_id, input)
+            .await
+    }
+
+    /// Returns id of the account which is controlled by the key of the request.
+    ///
+    /// If the address is stored only with other keys, the first of them is used
+    async fn find_account_id(
+        &self,
+        address: &MsgAddressInt,
+        public_key: &PublicKey,
+    ) -> Option<String> {
+        let accounts = self
+            .accounts_storage
+            .as_ref()?
+            .get_accounts_by_address(address)
+            .await;
+        accounts
+            .iter()
+            .find(|account| &account.ton_wallet.public_key == public_key)
+            .or_else(|| accounts.first())
+            .map(|account| account.id.clone())
+    }
+
+    async fn check_spending_policy(
+        &self,
+        account: Option<&str>,
+        context: &SignRequestContext,
+    ) -> Result<(), SpendingPolicyViolation> {
+        match (&self.accounts_storage, account) {
+            (Some(accounts_storage), Some(account)) => {
+                accounts_storage
+                    .check_spending(
+                        account,
+                        context.recipient.as_ref(),
+                        context.amount,
+                        self.today(),
+                    )
+                    .await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn reserve_spending(
+        &self,
+        account: &str,
+        context: &SignRequestContext,
+        day: u32,
+    ) -> Result<(), SpendingPolicyViolation> {
+        match &self.accounts_storage {
+            Some(accounts_storage) => {
+                accounts_storage
+                    .reserve_spending(account, context.recipient.as_ref(), context.amount, day)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn today(&self) -> u32 {
+        (self.clock.now_sec_u64() / 86400) as u32
+    }
+}
+
+/// Returns the wallet which will receive the external message.
+///
+/// NOTE: signature doesn't affect the destination, so the message is built with an empty one
+fn message_account(message: &dyn UnsignedMessage) -> Option<MsgAddressInt> {
+    message.sign(&[0; 64]).ok()?.message.dst()
+}
+
+#[derive(Clone)]
+pub struct SignRequest {
+    pub id: u32,
+    pub context: SignRequestContext,
+    pub public_key: PublicKey,
+    pub signature_id: Option<SignatureId>,
+    pub message: Box<dyn UnsignedMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignRequestContext {
+    /// Module or dApp which created the request
+    pub origin: String,
+    /// Human readable description of the action
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Wallet which sends the message.
+    /// Destination of the message is used if not specified
+    #[serde(
+        with = "serde_optional_address",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub account: Option<MsgAddressInt>,
+    /// Amount of the outgoing transfer
+    #[serde(
+        with = "serde_optional_u64",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub amount: Option<u64>,
+    /// Contract which will receive the message
+    #[serde(
+        with = "serde_optional_address",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub recipient: Option<MsgAddressInt>,
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+pub trait SignQueueHandler<T: Signer>: Send + Sync {
+    /// Called when the request must be shown to the user.
+    /// Returns signer input if the request was approved, `None` otherwise
+    async fn approve(&self, request: &SignRequest) -> Option<T::SignInput>;
+
+    /// Called when the request was approved and signed.
+    /// Signed message should be sent to the message queue of the subscription
+    fn on_message_signed(&self, request: SignRequest, message: SignedMessage);
+
+    /// Called when the user rejected the request
+    fn on_request_rejected(&self, request: SignRequest);
+
+    /// Called when the request was rejected by the spending policy of the account.
+    ///
+    /// Default implementation handles it as rejected by the user
+    fn on_policy_violated(&self, request: SignRequest, violation: SpendingPolicyViolation) {
+        let _ = violation;
+        self.on_request_rejected(request);
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Result;
+use num_bigint::BigUint;
+use sha3::{Digest, Keccak256};
+use ton_abi::{Param, ParamType, Token, TokenValue};
+
+use nekoton_abi::{pack_into_cell, unpack_from_cell};
+
+use crate::core::models::TokenSwapBack;
+
+const PAYLOAD_ABI_VERSION: ton_abi::contract::AbiVersion = ton_abi::contract::ABI_VERSION_2_0;
+
+/// Address in the EVM compatible network
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct EvmAddress(pub [u8; 20]);
+
+impl EvmAddress {
+    /// Mixed-case address representation (EIP-55)
+    pub fn to_checksummed(&self) -> String {
+        let address = hex::encode(self.0);
+        let hash = Keccak256::digest(address.as_bytes());
+
+        let mut result = String::with_capacity(42);
+        result.push_str("0x");
+        for (i, c) in address.chars().enumerate() {
+            let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+            if nibble >= 8 {
+                result.push(c.to_ascii_uppercase());
+            } else {
+                result.push(c);
+            }
+        }
+        result
+    }
+}
+
+impl FromStr for EvmAddress {
+    type Err = EvmAddressError;
+
+    /// Parses hex address. Checksum is verified only for mixed-case addresses
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex_part = s
+            .strip_prefix("0x")
+            .or_else(|| s.strip_prefix("0X"))
+            .ok_or(EvmAddressError::InvalidPrefix)?;
+
+        if hex_part.len() != 40 {
+            return Err(EvmAddressError::InvalidLength);
+        }
+
+        let mut address = [0; 20];
+        hex::decode_to_slice(hex_part, &mut address).map_err(|_| EvmAddressError::InvalidHex)?;
+        let address = Self(address);
+
+        let is_mixed_case = hex_part.chars().any(|c| c.is_ascii_lowercase())
+            && hex_part.chars().any(|c| c.is_ascii_uppercase());
+        if is_mixed_case && address.to_checksummed()[2..] != *hex_part {
+            return Err(EvmAddressError::InvalidChecksum);
+        }
+
+        Ok(address)
+    }
+}
+
+impl fmt::Display for EvmAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_checksummed())
+    }
+}
+
+/// Callback payload of the swap back to the EVM compatible network
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct EvmSwapBackPayload {
+    pub address: EvmAddress,
+    pub chain_id: u32,
+}
+
+impl EvmSwapBackPayload {
+    pub fn encode(&self) -> Result<ton_types::Cell> {
+        let tokens = [
+            Token::new(
+                "ethereumAddress",
+                TokenValue::Uint(ton_abi::Uint {
+                    number: BigUint::from_bytes_be(&self.address.0),
+                    size: 160,
+                }),
+            ),
+            Token::new(
+                "chainId",
+                TokenValue::Uint(ton_abi::Uint::new(self.chain_id as u128, 32)),
+            ),
+        ];
+        pack_into_cell(&tokens, PAYLOAD_ABI_VERSION)
+    }
+
+    pub fn decode(payload: ton_types::Cell) -> Result<Self> {
+        let params = [
+            Param::new("ethereumAddress", ParamType::Uint(160)),
+            Param::new("chainId", ParamType::Uint(32)),
+        ];
+        let tokens = unpack_from_cell(
+            &params,
+            ton_types::SliceData::load_cell(payload)?,
+            false,
+            PAYLOAD_ABI_VERSION,
+        )?;
+
+        match tokens.as_slice() {
+            [Token {
+                value: TokenValue::Uint(address),
+                ..
+            }, Token {
+                value: TokenValue::Uint(chain_id),
+                ..
+            }] => {
+                let bytes = address.number.to_bytes_be();
+                let chain_id =
+                    u32::try_from(&chain_id.number).map_err(|_| EvmAddressError::InvalidPayload)?;
+                if bytes.len() > 20 {
+                    return Err(EvmAddressError::InvalidPayload.into());
+                }
+
+                let mut address = [0; 20];
+                address[20 - bytes.len()..].copy_from_slice(&bytes);
+
+                Ok(Self {
+                    address: EvmAddress(address),
+                    chain_id,
+                })
+            }
+            _ => Err(EvmAddressError::InvalidPayload.into()),
+        }
+    }
+}
+
+impl TokenSwapBack {
+    /// Tries to decode the callback payload as an EVM destination
+    pub fn evm_payload(&self) -> Option<EvmSwapBackPayload> {
+        EvmSwapBackPayload::decode(self.callback_payload.clone()).ok()
+    }
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EvmAddressError {
+    #[error("Address must start with 0x")]
+    InvalidPrefix,
+    #[error("Invalid address length")]
+    InvalidLength,
+    #[error("Address contains non-hex characters")]
+    InvalidHex,
+    #[error("Invalid address checksum")]
+    InvalidChecksum,
+    #[error("Invalid swap back payload")]
+    InvalidPayload,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evm_address_checksum() {
+        let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+        let address = EvmAddress::from_str(checksummed).unwrap();
+        assert_eq!(address.to_string(), checksummed);
+        assert_eq!(
+            EvmAddress::from_str(&checksummed.to_lowercase()).unwrap(),
+            address
+        );
+        assert_eq!(
+            EvmAddress::from_str("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD").unwrap_err(),
+            EvmAddressError::InvalidChecksum
+        );
+
+        let payload = EvmSwapBackPayload {
+            address,
+            chain_id: 1,
+        };
+        let decoded = EvmSwapBackPayload::decode(payload.encode().unwrap()).unwrap();
+        assert_eq!(decoded, payload);
+    }
+}
@@ -13,11 +13,19 @@ use nekoton_utils::*;
 use crate::core::models::*;
 use crate::core::parsing::*;
 use crate::core::transactions_tree::*;
+use crate::external::Storage;
 use crate::transport::models::{RawContractState, RawTransaction};
 use crate::transport::Transport;
 
 use super::{ContractSubscription, InternalMessage};
 
+pub use self::root_details_cache::{RootTokenDetailsCache, ROOT_TOKEN_DETAILS_STORAGE_KEY};
+
+pub mod bridge;
+mod root_details_cache;
+
+pub const TOKEN_WALLET_CURSOR_STORAGE_KEY: &str = "__core__token_wallet_cursor";
+
 pub struct TokenWallet {
     clock: Arc<dyn Clock>,
     contract_subscription: ContractSubscription,
@@ -36,41 +44,87 @@ impl TokenWallet {
         root_token_contract: MsgAddressInt,
         handler: Arc<dyn TokenWalletSubscriptionHandler>,
     ) -> Result<TokenWallet> {
-        let state = match transport.get_contract_state(&root_token_contract).await? {
-            RawContractState::Exists(state) => state,
-            RawContractState::NotExists { .. } => {
-                return Err(TokenWalletError::InvalidRootTokenContract.into())
+        let (address, symbol, version) = get_token_wallet_details(
+            clock.as_ref(),
+            transport.as_ref(),
+            &owner,
+            root_token_contract,
+        )
+        .await?;
+
+        let mut balance = Default::default();
+        let on_contract_state =
+            &mut make_contract_state_handler(clock.clone(), version, &mut balance);
+        let on_transactions_found = &mut make_transactions_handler(handler.as_ref(), version);
+        let contract_subscription = match handler.transactions_cursor() {
+            // Only transactions after the restart are requested
+            Some(cursor) => {
+                ContractSubscription::subscribe_from(
+                    clock.clone(),
+                    transport,
+                    address,
+                    &cursor,
+                    on_contract_state,
+                    on_transactions_found,
+                )
+                .await?
+            }
+            None => {
+                ContractSubscription::subscribe(
+                    clock.clone(),
+                    transport,
+                    address,
+                    on_contract_state,
+                    Some(on_transactions_found),
+                )
+                .await?
             }
         };
-        let state = RootTokenContractState(state.as_context(clock.as_ref()));
-        let RootTokenContractDetails {
-            symbol: name,
-            decimals,
-            version,
-            name: full_name,
-            ..
-        } = state.guess_details()?;
 
-        let address = state.get_wallet_address(version, &owner)?;
+        handler.on_balance_changed(balance.clone());
 
-        let mut balance = Default::default();
-        let contract_subscription = ContractSubscription::subscribe(
-            clock.clone(),
-            transport,
-            address,
-            &mut make_contract_state_handler(clock.clone(), version, &mut balance),
-            Some(&mut make_transactions_handler(handler.as_ref(), version)),
+        Ok(Self {
+            clock,
+            contract_subscription,
+            handler,
+            owner,
+            symbol,
+            version,
+            balance,
+        })
+    }
+
+    /// Restores the token wallet from the snapshot, created by [`TokenWallet::serialize_state`].
+    ///
+    /// Transactions are not requested again, only the root token contract state and
+    /// the token wallet state are requested to get token details and balance
+    pub async fn restore(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        owner: MsgAddressInt,
+        root_token_contract: MsgAddressInt,
+        data: &str,
+        handler: Arc<dyn TokenWalletSubscriptionHandler>,
+    ) -> Result<TokenWallet> {
+        let (address, symbol, version) = get_token_wallet_details(
+            clock.as_ref(),
+            transport.as_ref(),
+            &owner,
+            root_token_contract,
         )
         .await?;
 
-        handler.on_balance_changed(balance.clone());
+        let contract_subscription =
+            ContractSubscription::restore_state(clock.clone(), transport.clone(), data)?;
+        if contract_subscription.address() != &address {
+            return Err(TokenWalletError::WalletAddressMismatch.into());
+        }
 
-        let symbol = Symbol {
-            name,
-            full_name,
-            decimals,
-            root_token_contract,
-        };
+        let mut balance = Default::default();
+        make_contract_state_handler(clock.clone(), version, &mut balance)(
+            &transport.get_contract_state(&address).await?,
+        );
+        handler.on_balance_changed(balance.clone());
 
         Ok(Self {
             clock,
@@ -83,6 +137,11 @@ impl TokenWallet {
         })
     }
 
+    /// See [`ContractSubscription::serialize_state`]
+    pub fn serialize_state(&self) -> String {
+        self.contract_subscription.serialize_state()
+    }
+
     pub fn contract_subscription(&self) -> &ContractSubscription {
         &self.contract_subscription
     }
@@ -205,54 +264,47 @@ impl TokenWallet {
         tokens: BigUint,
         notify_receiver: bool,
         payload: ton_types::Cell,
-        mut attached_amount: u64,
+        attached_amount: u64,
     ) -> Result<InternalMessage> {
-        if matches!(&destination, TransferRecipient::OwnerWallet(_)) {
-            attached_amount += INITIAL_BALANCE;
-        }
+        prepare_token_transfer(
+            self.version,
+            &self.owner,
+            self.address(),
+            destination,
+            tokens,
+            notify_receiver,
+            payload,
+            attached_amount,
+        )
+    }
 
+    /// Builds an internal message which burns tokens to move them back across the bridge.
+    ///
+    /// `callback_payload` usually contains the destination address in the other network
+    pub fn prepare_swap_back(
+        &self,
+        callback_address: MsgAddressInt,
+        tokens: BigUint,
+        callback_payload: ton_types::Cell,
+        attached_amount: u64,
+    ) -> Result<InternalMessage> {
         let (function, input) = match self.version {
             TokenWalletVersion::OldTip3v4 => {
-                use old_tip3::token_wallet_contract;
-                match destination {
-                    TransferRecipient::TokenWallet(token_wallet) => {
-                        MessageBuilder::new(token_wallet_contract::transfer())
-                            .arg(token_wallet) // to
-                            .arg(BigUint128(tokens)) // tokens
-                    }
-                    TransferRecipient::OwnerWallet(owner_wallet) => {
-                        MessageBuilder::new(token_wallet_contract::transfer_to_recipient())
-                            .arg(BigUint256(Default::default())) // recipient_public_key
-                            .arg(owner_wallet) // recipient_address
-                            .arg(BigUint128(tokens)) // tokens
-                            .arg(BigUint128(INITIAL_BALANCE.into())) // deploy_grams
-                    }
-                }
-                .arg(BigUint128(Default::default())) // grams / transfer_grams
-                .arg(&self.owner) // send_gas_to
-                .arg(notify_receiver) // notify_receiver
-                .arg(payload) // payload
-                .build()
+                MessageBuilder::new(old_tip3::token_wallet_contract::burn_by_owner())
+                    .arg(BigUint128(tokens)) // tokens
+                    .arg(BigUint128(Default::default())) // grams
+                    .arg(&self.owner) // send_gas_to
+                    .arg(callback_address) // callback_address
+                    .arg(callback_payload) // callback_payload
+                    .build()
             }
             TokenWalletVersion::Tip3 => {
-                use tip3_1::token_wallet_contract;
-                match destination {
-                    TransferRecipient::TokenWallet(token_wallet) => {
-                        MessageBuilder::new(token_wallet_contract::transfer_to_wallet())
-                            .arg(BigUint128(tokens)) // amount
-                            .arg(token_wallet) // recipient token wallet
-                    }
-                    TransferRecipient::OwnerWallet(owner_wallet) => {
-                        MessageBuilder::new(token_wallet_contract::transfer())
-                            .arg(BigUint128(tokens)) // amount
-                            .arg(owner_wallet) // recipient
-                            .arg(BigUint128(INITIAL_BALANCE.into())) // deployWalletValue
-                    }
-                }
-                .arg(&self.owner) // remainingGasTo
-                .arg(notify_receiver) // notify
-                .arg(payload) // payload
-                .build()
+                MessageBuilder::new(tip3_1::token_wallet_contract::burnable::burn())
+                    .arg(BigUint128(tokens)) // amount
+                    .arg(&self.owner) // remainingGasTo
+                    .arg(callback_address) // callbackTo
+                    .arg(callback_payload) // payload
+                    .build()
             }
         };
 
@@ -293,6 +345,7 @@ impl TokenWallet {
     pub async fn handle_block(&mut self, block: &ton_block::Block) -> Result<()> {
         let version = self.version;
         let mut balance: BigInt = self.balance.clone().into();
+        let mut has_unknown_transactions = false;
 
         let handler = self.handler.as_ref();
         self.contract_subscription.handle_block(
@@ -309,6 +362,10 @@ impl TokenWallet {
                         let data =
                             parse_token_transaction(&transaction.data, &description, version);
 
+                        if data.is_none() && !description.aborted {
+                            has_unknown_transactions = true;
+                        }
+
                         if let Some(data) = &data {
                             match data {
                                 TokenWalletTransaction::IncomingTransfer(
@@ -333,7 +390,11 @@ impl TokenWallet {
                         let transaction =
                             Transaction::try_from((transaction.hash, transaction.data)).ok()?;
 
-                        Some(TransactionWithData { transaction, data })
+                        Some(TransactionWithData {
+                            transaction,
+                            data,
+                            labels: Vec::new(),
+                        })
                     })
                     .collect();
 
@@ -343,7 +404,25 @@ impl TokenWallet {
             &mut |_| {},
         )?;
 
-        let balance = balance.to_biguint().unwrap_or_default();
+        let mut balance = balance.to_biguint().unwrap_or_default();
+
+        // NOTE: balance can also be changed by the calls which are not parsed
+        // (e.g. airdrops or mints via custom methods), so in that case
+        // the new balance is read from the contract state
+        if has_unknown_transactions {
+            if let Err(e) = self
+                .contract_subscription
+                .refresh_contract_state(&mut make_contract_state_handler(
+                    self.clock.clone(),
+                    version,
+                    &mut balance,
+                ))
+                .await
+            {
+                log::warn!("Failed to refresh token wallet state: {e:?}");
+            }
+        }
+
         if balance != self.balance {
             self.balance = balance;
             handler.on_balance_changed(self.balance.clone());
@@ -352,11 +431,15 @@ impl TokenWallet {
         Ok(())
     }
 
-    pub async fn preload_transactions(&mut self, from_lt: u64) -> Result<()> {
+    /// See [`ContractSubscription::preload_transactions`]
+    pub async fn preload_transactions(
+        &mut self,
+        cursor: &TransactionsCursor,
+    ) -> Result<Option<TransactionsCursor>> {
         let handler = self.handler.as_ref();
         self.contract_subscription
             .preload_transactions(
-                from_lt,
+                cursor,
                 &mut make_transactions_handler(handler, self.version),
             )
             .await
@@ -375,6 +458,80 @@ pub trait TokenWalletSubscriptionHandler: Send + Sync {
         transactions: Vec<TransactionWithData<TokenWalletTransaction>>,
         batch_info: TransactionsBatchInfo,
     );
+
+    /// Latest transaction which was already processed (e.g. before the restart).
+    ///
+    /// If specified, only newer transactions are requested on subscription
+    fn transactions_cursor(&self) -> Option<TransactionsCursor> {
+        None
+    }
+}
+
+/// Handler wrapper which persists the latest new transaction,
+/// so that only newer transactions are requested after restart
+pub struct TokenWalletCursorHandler {
+    key: String,
+    storage: Arc<dyn Storage>,
+    cursor: parking_lot::Mutex<Option<TransactionsCursor>>,
+    inner: Arc<dyn TokenWalletSubscriptionHandler>,
+}
+
+impl TokenWalletCursorHandler {
+    /// Loads the stored cursor of the token wallet
+    pub async fn load(
+        token_wallet: &MsgAddressInt,
+        storage: Arc<dyn Storage>,
+        inner: Arc<dyn TokenWalletSubscriptionHandler>,
+    ) -> Self {
+        let key = format!("{TOKEN_WALLET_CURSOR_STORAGE_KEY}{token_wallet}");
+        let cursor = match storage.get(&key).await {
+            Ok(Some(data)) => serde_json::from_str(&data).ok(),
+            _ => None,
+        };
+
+        Self {
+            key,
+            storage,
+            cursor: parking_lot::Mutex::new(cursor),
+            inner,
+        }
+    }
+
+    pub fn clear_cursor(&self) {
+        *self.cursor.lock() = None;
+        self.storage.remove_unchecked(&self.key);
+    }
+}
+
+impl TokenWalletSubscriptionHandler for TokenWalletCursorHandler {
+    fn on_balance_changed(&self, balance: BigUint) {
+        self.inner.on_balance_changed(balance);
+    }
+
+    fn on_transactions_found(
+        &self,
+        transactions: Vec<TransactionWithData<TokenWalletTransaction>>,
+        batch_info: TransactionsBatchInfo,
+    ) {
+        let latest = transactions
+            .iter()
+            .map(|item| item.transaction.id)
+            .max_by_key(|id| id.lt);
+        if let (TransactionsBatchType::New, Some(latest)) = (batch_info.batch_type, latest) {
+            let cursor = TransactionsCursor::from(latest);
+            let mut current = self.cursor.lock();
+            if !matches!(&*current, Some(current) if current.lt >= cursor.lt) {
+                *current = Some(cursor);
+                self.storage
+                    .set_unchecked(&self.key, &serde_json::to_string(&cursor).trust_me());
+            }
+        }
+        self.inner.on_transactions_found(transactions, batch_info);
+    }
+
+    fn transactions_cursor(&self) -> Option<TransactionsCursor> {
+        *self.cursor.lock()
+    }
 }
 
 pub async fn get_token_root_details(
@@ -449,8 +606,133 @@ pub async fn get_token_root_details_from_token_wallet(
     Ok((root_token_contract, details))
 }
 
+/// Checks that the token wallet belongs to the specified owner by recomputing
+/// its address from the root token contract state.
+///
+/// Allows rejecting notifications with spoofed owner addresses
+pub fn verify_ownership(
+    root_state: &RootTokenContractState<'_>,
+    version: TokenWalletVersion,
+    owner: &MsgAddressInt,
+    token_wallet: &MsgAddressInt,
+) -> Result<bool> {
+    let expected = root_state.get_wallet_address(version, owner)?;
+    Ok(&expected == token_wallet)
+}
+
+/// Builds an internal message to the token wallet which transfers tokens.
+///
+/// The message must be sent from the owner wallet (e.g. via multisig `sendTransaction`).
+/// Attached amount is increased to deploy the recipient token wallet
+/// when tokens are sent to the owner wallet
+#[allow(clippy::too_many_arguments)]
+pub fn prepare_token_transfer(
+    version: TokenWalletVersion,
+    owner: &MsgAddressInt,
+    token_wallet: &MsgAddressInt,
+    destination: TransferRecipient,
+    tokens: BigUint,
+    notify_receiver: bool,
+    payload: ton_types::Cell,
+    mut attached_amount: u64,
+) -> Result<InternalMessage> {
+    if matches!(&destination, TransferRecipient::OwnerWallet(_)) {
+        attached_amount += INITIAL_BALANCE;
+    }
+
+    let (function, input) = match version {
+        TokenWalletVersion::OldTip3v4 => {
+            use old_tip3::token_wallet_contract;
+            match destination {
+                TransferRecipient::TokenWallet(recipient_wallet) => {
+                    MessageBuilder::new(token_wallet_contract::transfer())
+                        .arg(recipient_wallet) // to
+                        .arg(BigUint128(tokens)) // tokens
+                }
+                TransferRecipient::OwnerWallet(owner_wallet) => {
+                    MessageBuilder::new(token_wallet_contract::transfer_to_recipient())
+                        .arg(BigUint256(Default::default())) // recipient_public_key
+                        .arg(owner_wallet) // recipient_address
+                        .arg(BigUint128(tokens)) // tokens
+                        .arg(BigUint128(INITIAL_BALANCE.into())) // deploy_grams
+                }
+            }
+            .arg(BigUint128(Default::default())) // grams / transfer_grams
+            .arg(owner) // send_gas_to
+            .arg(notify_receiver) // notify_receiver
+            .arg(payload) // payload
+            .build()
+        }
+        TokenWalletVersion::Tip3 => {
+            use tip3_1::token_wallet_contract;
+            match destination {
+                TransferRecipient::TokenWallet(recipient_wallet) => {
+                    MessageBuilder::new(token_wallet_contract::transfer_to_wallet())
+                        .arg(BigUint128(tokens)) // amount
+                        .arg(recipient_wallet) // recipient token wallet
+                }
+                TransferRecipient::OwnerWallet(owner_wallet) => {
+                    MessageBuilder::new(token_wallet_contract::transfer())
+                        .arg(BigUint128(tokens)) // amount
+                        .arg(owner_wallet) // recipient
+                        .arg(BigUint128(INITIAL_BALANCE.into())) // deployWalletValue
+                }
+            }
+            .arg(owner) // remainingGasTo
+            .arg(notify_receiver) // notify
+            .arg(payload) // payload
+            .build()
+        }
+    };
+
+    let body = function
+        .encode_internal_input(&input)
+        .and_then(ton_types::SliceData::load_builder)?;
+
+    Ok(InternalMessage {
+        source: Some(owner.clone()),
+        destination: token_wallet.clone(),
+        amount: attached_amount,
+        bounce: true,
+        body,
+    })
+}
+
 const INITIAL_BALANCE: u64 = 100_000_000; // 0.1 TON
 
+/// Returns the token wallet address of the owner, token symbol and version
+async fn get_token_wallet_details(
+    clock: &dyn Clock,
+    transport: &dyn Transport,
+    owner: &MsgAddressInt,
+    root_token_contract: MsgAddressInt,
+) -> Result<(MsgAddressInt, Symbol, TokenWalletVersion)> {
+    let state = match transport.get_contract_state(&root_token_contract).await? {
+        RawContractState::Exists(state) => state,
+        RawContractState::NotExists { .. } => {
+            return Err(TokenWalletError::InvalidRootTokenContract.into())
+        }
+    };
+    let state = RootTokenContractState(state.as_context(clock));
+    let RootTokenContractDetails {
+        symbol: name,
+        decimals,
+        version,
+        name: full_name,
+        ..
+    } = state.guess_details()?;
+
+    let address = state.get_wallet_address(version, owner)?;
+    let symbol = Symbol {
+        name,
+        full_name,
+        decimals,
+        root_token_contract,
+    };
+
+    Ok((address, symbol, version))
+}
+
 fn make_contract_state_handler(
     clock: Arc<dyn Clock>,
     version: TokenWalletVersion,
@@ -483,7 +765,11 @@ fn make_transactions_handler(
                         let transaction =
                             Transaction::try_from((transaction.hash, transaction.data)).ok()?;
 
-                        Some(TransactionWithData { transaction, data })
+                        Some(TransactionWithData {
+                            transaction,
+                            data,
+                            labels: Vec::new(),
+                        })
                     }
                     _ => None,
                 },
@@ -502,6 +788,8 @@ enum TokenWalletError {
     InvalidTokenWalletContract,
     #[error("Wallet not deployed")]
     WalletNotDeployed,
+    #[error("Wallet address mismatch")]
+    WalletAddressMismatch,
     #[error("No source transaction produced")]
     NoSourceTx,
     #[error("No destination transaction produced")]
@@ -520,6 +808,7 @@ mod tests {
     use nekoton_abi::LastTransactionId;
 
     use super::*;
+    use crate::testing::MemoryStorage;
     use crate::transport::models::ExistingContract;
 
     fn convert_address(addr: &str) -> MsgAddressInt {
@@ -560,6 +849,86 @@ mod tests {
     const TOKEN_WALLET_STATE_OLD_TIP3_V4: &str = "te6ccgECVQEAFvMAAm/ADk6181Tl8DNfOKc7bcmAjGfWEacXte0YTWIQ+z5zBn7iqqsdAwUjerAAADO+UykFDQDUxEATQAMBAvMAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAwAZ8FYfpuapKqDoI+vbJFnqj3MVTpNYdWW5D5x/ZK5FEMAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWWgvABgIDAMmAFSQopo5GzW/93YKmR1LWu54uMvkRuBqK8b9KUgFxRnlwAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAQAIQ9KQgiu1T9KAGBAEK9KQg9KEFAAACASAKBwEC/wgC/n+NCGAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAT4aSHbPNMAAY4dgQIA1xgg+QEB0wABlNP/AwGTAvhC4iD4ZfkQ8qiV0wAB8nri0z8Bjh34QyG5IJ8wIPgjgQPoqIIIG3dAoLnekyD4Y+DyNNgw0x8B+CO88rkUCQIW0x8B2zz4R26OgN4NCwNu33Ai0NMD+kAw+GmpOAD4RH9vcYIImJaAb3Jtb3Nwb3T4ZI6A4CHHANwh0x8h3QHbPPhHbo6A3ksNCwEGW9s8DAIO+EFu4wDbPFRMBFggghAML/INu46A4CCCECnEiX67joDgIIIQS/Fg4ruOgOAgghB5sl7hu46A4D8rFw4EUCCCEGi1Xz+64wIgghBx7uh1uuMCIIIQdWzN97rjAiCCEHmyXuG64wITEhEPAuow+EFu4wDTH/hEWG91+GTR+ERwb3Jwb3GAQG90+GT4SvhM+E34TvhQ+FH4Um8HIcD/jkIj0NMB+kAwMcjPhyDOgGDPQM+Bz4PIz5PmyXuGIm8nVQYnzxYmzwv/Jc8WJM8Lf8gkzxYjzxYizwoAbHLNzclw+wBUEAG+jlb4RCBvEyFvEvhJVQJvEchyz0DKAHPPQM4B+gL0AIBoz0DPgc+DyPhEbxXPCx8ibydVBifPFibPC/8lzxYkzwt/yCTPFiPPFiLPCgBscs3NyfhEbxT7AOIw4wB/+GdMA+Iw+EFu4wDR+E36Qm8T1wv/wwAglzD4TfhJxwXeII4UMPhMwwAgnDD4TPhFIG6SMHDeut7f8uBk+E36Qm8T1wv/wwCOgJL4AOJt+G/4TfpCbxPXC/+OFfhJyM+FiM6Abc9Az4HPgcmBAID7AN7bPH/4Z1RITAKwMPhBbuMA+kGV1NHQ+kDf1wwAldTR0NIA39H4TfpCbxPXC//DACCXMPhN+EnHBd4gjhQw+EzDACCcMPhM+EUgbpIwcN663t/y4GT4ACH4cCD4clvbPH/4Z1RMAuIw+EFu4wD4RvJzcfhm0fhM+EK6II4UMPhN+kJvE9cL/8AAIJUw+EzAAN/e8uBk+AB/+HL4TfpCbxPXC/+OLfhNyM+FiM6NA8icQAAAAAAAAAAAAAAAAAHPFs+Bz4HPkSFO7N74Ss8WyXH7AN7bPH/4ZxRMAZLtRNAg10nCAY480//TP9MA1fpA+kD4cfhw+G36QNTT/9N/9AQBIG6V0NN/bwLf+G/XCgD4cvhu+Gz4a/hqf/hh+Gb4Y/hijoDiFQH+9AVxIYBA9A6OJI0IYAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABN/4anIhgED0D5LIyd/4a3MhgED0DpPXC/+RcOL4bHQhgED0Do4kjQhgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAE3/htcPhubRYAzvhvjQhgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAE+HCNCGAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAT4cXD4cnABgED0DvK91wv/+GJw+GNw+GZ/+GEDQCCCED8Q0au7joDgIIIQSWlYf7uOgOAgghBL8WDiuuMCIxwYAv4w+EFu4wD6QZXU0dD6QN/XDX+V1NHQ03/f1w1/ldTR0NN/3/pBldTR0PpA39cMAJXU0dDSAN/U0fhN+kJvE9cL/8MAIJcw+E34SccF3iCOFDD4TMMAIJww+Ez4RSBukjBw3rre3/LgZCTCAPLgZCT4Trvy4GUl+kJvE9cL/8MAVBkCMvLgbyX4KMcFs/Lgb/hN+kJvE9cL/8MAjoAbGgHkjmj4J28QJLzy4G4jggr68IC88uBu+AAk+E4BobV/+G4jJn/Iz4WAygBzz0DOAfoCgGnPQM+Bz4PIz5BjSFwKJs8Lf/hMzwv/+E3PFiT6Qm8T1wv/wwCRJJL4KOLPFiPPCgAizxTNyXH7AOJfBts8f/hnTAHuggr68ID4J28Q2zyhtX+2CfgnbxAhggr68ICgtX+88uBuIHL7AiX4TgGhtX/4biZ/yM+FgMoAc89AzoBtz0DPgc+DyM+QY0hcCifPC3/4TM8L//hNzxYl+kJvE9cL/8MAkSWS+E3izxYkzwoAI88UzcmBAIH7ADBTAiggghA/VnlRuuMCIIIQSWlYf7rjAh8dApAw+EFu4wDTH/hEWG91+GTR+ERwb3Jwb3GAQG90+GT4TiHA/44jI9DTAfpAMDHIz4cgzoBgz0DPgc+Bz5MlpWH+Ic8Lf8lw+wBUHgGAjjf4RCBvEyFvEvhJVQJvEchyz0DKAHPPQM4B+gL0AIBoz0DPgc+B+ERvFc8LHyHPC3/J+ERvFPsA4jDjAH/4Z0wE/DD4QW7jAPpBldTR0PpA39cNf5XU0dDTf9/6QZXU0dD6QN/XDACV1NHQ0gDf1NH4T26z8uBr+En4TyBu8n9vEccF8uBsI/hPIG7yf28Qu/LgbSP4Trvy4GUjwgDy4GQk+CjHBbPy4G/4TfpCbxPXC//DAI6AjoDiI/hOAaG1f1QiISABtPhu+E8gbvJ/bxAkobV/+E8gbvJ/bxFvAvhvJH/Iz4WAygBzz0DOgG3PQM+Bz4PIz5BjSFwKJc8Lf/hMzwv/+E3PFiTPFiPPCgAizxTNyYEAgfsAXwXbPH/4Z0wCLts8ggr68IC88uBu+CdvENs8obV/cvsCU1MCcoIK+vCA+CdvENs8obV/tgn4J28QIYIK+vCAoLV/vPLgbiBy+wKCCvrwgPgnbxDbPKG1f7YJcvsCMFNTAiggghAtqU0vuuMCIIIQPxDRq7rjAiokAv4w+EFu4wDXDf+V1NHQ0//f+kGV1NHQ+kDf1w1/ldTR0NN/39cNf5XU0dDTf9/XDX+V1NHQ03/f+kGV1NHQ+kDf1wwAldTR0NIA39TR+E36Qm8T1wv/wwAglzD4TfhJxwXeII4UMPhMwwAgnDD4TPhFIG6SMHDeut7f8uBkJcIAVCUC/PLgZCX4Trvy4GUm+kJvE9cL/8AAIJQwJ8AA3/Lgb/hN+kJvE9cL/8MAjoCOIPgnbxAlJaC1f7zy4G4jggr68IC88uBuJ/hMvfLgZPgA4m0oyMv/cFiAQPRD+EpxWIBA9Bb4S3JYgED0FyjIy/9zWIBA9EMndFiAQPQWyPQAySkmAfz4S8jPhID0APQAz4HJjQhgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAEJsIAjjchIPkA+Cj6Qm8SyM+GQMoHy//J0CghyM+FiM4B+gKAac9Az4PPgyLPFM+Bz5Gi1Xz+yXH7ADExnSH5AMjPigBAy//J0DHi+E0nAbj6Qm8T1wv/wwCOUSf4TgGhtX/4biB/yM+FgMoAc89AzoBtz0DPgc+DyM+QY0hcCinPC3/4TM8L//hNzxYm+kJvE9cL/8MAkSaS+E3izxYlzwoAJM8UzcmBAIH7ACgBvI5TJ/hOAaG1f/huJSF/yM+FgMoAc89AzgH6AoBpz0DPgc+DyM+QY0hcCinPC3/4TM8L//hNzxYm+kJvE9cL/8MAkSaS+CjizxYlzwoAJM8Uzclx+wDiW18I2zx/+GdMAWaCCvrwgPgnbxDbPKG1f7YJ+CdvECGCCvrwgKC1fyegtX+88uBuJ/hNxwWz8uBvIHL7AjBTAegw0x/4RFhvdfhk0XQhwP+OIyPQ0wH6QDAxyM+HIM6AYM9Az4HPgc+StqU0viHPCx/JcPsAjjf4RCBvEyFvEvhJVQJvEchyz0DKAHPPQM4B+gL0AIBoz0DPgc+B+ERvFc8LHyHPCx/J+ERvFPsA4jDjAH/4Z0wDQCCCEBBHyQS7joDgIIIQGNIXAruOgOAgghApxIl+uuMCNy8sAv4w+EFu4wD6QZXU0dD6QN/6QZXU0dD6QN/XDX+V1NHQ03/f1w1/ldTR0NN/3/pBldTR0PpA39cMAJXU0dDSAN/U0fhN+kJvE9cL/8MAIJcw+E34SccF3iCOFDD4TMMAIJww+Ez4RSBukjBw3rre3/LgZCX6Qm8T1wv/wwDy4G8kVC0C9sIA8uBkJibHBbPy4G/4TfpCbxPXC//DAI6Ajlf4J28QJLzy4G4jggr68IByqLV/vPLgbvgAIyfIz4WIzgH6AoBpz0DPgc+DyM+Q/VnlRifPFibPC38k+kJvE9cL/8MAkSSS+CjizxYjzwoAIs8Uzclx+wDiXwfbPH/4Zy5MAcyCCvrwgPgnbxDbPKG1f7YJ+CdvECGCCvrwgHKotX+gtX+88uBuIHL7AifIz4WIzoBtz0DPgc+DyM+Q/VnlRijPFifPC38l+kJvE9cL/8MAkSWS+E3izxYkzwoAI88UzcmBAIH7ADBTAiggghAYbXO8uuMCIIIQGNIXArrjAjUwAv4w+EFu4wDXDX+V1NHQ03/f1w3/ldTR0NP/3/pBldTR0PpA3/pBldTR0PpA39cMAJXU0dDSAN/U0SH4UrEgnDD4UPpCbxPXC//AAN/y4HAkJG0iyMv/cFiAQPRD+EpxWIBA9Bb4S3JYgED0FyLIy/9zWIBA9EMhdFiAQPQWyPQAVDEDvsn4S8jPhID0APQAz4HJIPkAyM+KAEDL/8nQMWwh+EkhxwXy4Gck+E3HBbMglTAl+Ey93/Lgb/hN+kJvE9cL/8MAjoCOgOIm+E4BoLV/+G4iIJww+FD6Qm8T1wv/wwDeNDMyAciOQ/hQyM+FiM6Abc9Az4HPg8jPkWUEfub4KM8W+ErPFijPC38nzwv/yCfPFvhJzxYmzxbI+E7PC38lzxTNzc3JgQCA+wCOFCPIz4WIzoBtz0DPgc+ByYEAgPsA4jBfBts8f/hnTAEY+CdvENs8obV/cvsCUwE8ggr68ID4J28Q2zyhtX+2CfgnbxAhvPLgbiBy+wIwUwKsMPhBbuMA0x/4RFhvdfhk0fhEcG9ycG9xgEBvdPhk+E9us5b4TyBu8n+OJ3CNCGAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAARvAuIhwP9UNgHujiwj0NMB+kAwMcjPhyDOgGDPQM+Bz4HPkmG1zvIhbyJYIs8LfyHPFmwhyXD7AI5A+EQgbxMhbxL4SVUCbxHIcs9AygBzz0DOAfoC9ACAaM9Az4HPgfhEbxXPCx8hbyJYIs8LfyHPFmwhyfhEbxT7AOIw4wB/+GdMAiggghAPAliquuMCIIIQEEfJBLrjAj04A/Yw+EFu4wDXDX+V1NHQ03/f1w1/ldTR0NN/3/pBldTR0PpA3/pBldTR0PpA39TR+E36Qm8T1wv/wwAglzD4TfhJxwXeII4UMPhMwwAgnDD4TPhFIG6SMHDeut7f8uBkJMIA8uBkJPhOu/LgZfhN+kJvE9cL/8MAII6A3iBUPDkCYI4dMPhN+kJvE9cL/8AAIJ4wI/gnbxC7IJQwI8IA3t7f8uBu+E36Qm8T1wv/wwCOgDs6AcKOV/gAJPhOAaG1f/huI/hKf8jPhYDKAHPPQM4B+gKAac9Az4HPg8jPkLiiIqomzwt/+EzPC//4Tc8WJPpCbxPXC//DAJEkkvgo4s8WyCTPFiPPFM3NyXD7AOJfBds8f/hnTAHMggr68ID4J28Q2zyhtX+2CXL7AiT4TgGhtX/4bvhKf8jPhYDKAHPPQM6Abc9Az4HPg8jPkLiiIqomzwt/+EzPC//4Tc8WJPpCbxPXC//DAJEkkvhN4s8WyCTPFiPPFM3NyYEAgPsAUwEKMNs8wgBTAy4w+EFu4wD6QZXU0dD6QN/R2zzbPH/4Z1Q+TAC8+E36Qm8T1wv/wwAglzD4TfhJxwXeII4UMPhMwwAgnDD4TPhFIG6SMHDeut7f8uBk+E7AAPLgZPgAIMjPhQjOjQPID6AAAAAAAAAAAAAAAAABzxbPgc+ByYEAoPsAMAM+IIILIdFzu46A4CCCEAs/z1e7joDgIIIQDC/yDbrjAkVCQAP+MPhBbuMA1w1/ldTR0NN/3/pBldTR0PpA3/pBldTR0PpA39TR+Er4SccF8uBmI8IA8uBkI/hOu/LgZfgnbxDbPKG1f3L7AiP4TgGhtX/4bvhKf8jPhYDKAHPPQM6Abc9Az4HPg8jPkLiiIqolzwt/+EzPC//4Tc8WJM8WyCTPFlRTQQEkI88Uzc3JgQCA+wBfBNs8f/hnTAIoIIIQBcUAD7rjAiCCEAs/z1e64wJEQwJWMPhBbuMA1w1/ldTR0NN/39H4SvhJxwXy4Gb4ACD4TgGgtX/4bjDbPH/4Z1RMApYw+EFu4wD6QZXU0dD6QN/R+E36Qm8T1wv/wwAglzD4TfhJxwXeII4UMPhMwwAgnDD4TPhFIG6SMHDeut7f8uBk+AAg+HEw2zx/+GdUTAIkIIIJfDNZuuMCIIILIdFzuuMCSUYD8DD4QW7jAPpBldTR0PpA39cNf5XU0dDTf9/XDX+V1NHQ03/f0fhN+kJvE9cL/8MAIJcw+E34SccF3iCOFDD4TMMAIJww+Ez4RSBukjBw3rre3/LgZCHAACCWMPhPbrOz3/LgavhN+kJvE9cL/8MAjoCS+ADi+E9us1RIRwGIjhL4TyBu8n9vECK6liAjbwL4b96WICNvAvhv4vhN+kJvE9cL/44V+EnIz4WIzoBtz0DPgc+ByYEAgPsA3l8D2zx/+GdMASaCCvrwgPgnbxDbPKG1f7YJcvsCUwL+MPhBbuMA0x/4RFhvdfhk0fhEcG9ycG9xgEBvdPhk+EshwP+OIiPQ0wH6QDAxyM+HIM6AYM9Az4HPgc+SBfDNZiHPFMlw+wCONvhEIG8TIW8S+ElVAm8RyHLPQMoAc89AzgH6AvQAgGjPQM+Bz4H4RG8VzwsfIc8UyfhEbxT7AFRKAQ7iMOMAf/hnTARAIdYfMfhBbuMA+AAg0x8yIIIQGNIXArqOgI6A4jAw2zxUT01MAKz4QsjL//hDzws/+EbPCwDI+E34UPhRXiDOzs74SvhL+Ez4TvhP+FJeYM8RzszL/8t/ASBus44VyAFvIsgizwt/Ic8WbCHPFwHPg88RkzDPgeLKAMntVAEWIIIQLiiIqrqOgN5OATAh038z+E4BoLV/+G74TfpCbxPXC/+OgN5RAjwh038zIPhOAaC1f/hu+FH6Qm8T1wv/wwCOgI6A4jBSUAEY+E36Qm8T1wv/joDeUQFQggr68ID4J28Q2zyhtX+2CXL7AvhNyM+FiM6Abc9Az4HPgcmBAID7AFMBgPgnbxDbPKG1f3L7AvhRyM+FiM6Abc9Az4HPg8jPkOoV2UL4KM8W+ErPFiLPC3/I+EnPFvhOzwt/zc3JgQCA+wBTABhwaKb7YJVopv5gMd8Afu1E0NP/0z/TANX6QPpA+HH4cPht+kDU0//Tf/QEASBuldDTf28C3/hv1woA+HL4bvhs+Gv4an/4Yfhm+GP4Yg==";
     const TOKEN_WALLET_STATE_TIP3: &str = "te6ccgECUQEAEhwAAm/ACj1pJvwlyayak7Hwr6udq+Y3mCkMzcMYwZQLmbHB2t5ioqF6gw+/ZggAAAVs4d2NGRscVo/TQBMBAZMAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAwAZ1qB6Xzov/L4USs4vJ5dV/aZeSdezFQnE9z2sdXWpg2AIBa4AVJCimjkbNb/3dgqZHUta7ni4y+RG4Gorxv0pSAXFGeWAAAAAAAAAAAPbD/6sSR0AAAAAAEAMCBorbNVAEBCSK7VMg4wMgwP/jAiDA/uMC8gsQBgVPAtbtRNDXScMB+GaNCGAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAT4aSHbPNMAAZ+BAgDXGCD5AVj4QvkQ8qje0z8B+EMhufK0IPgjgQPoqIIIG3dAoLnytPhj0x8B2zz4R27yfA4HA1jtRNDXScMB+GYi0NMD+kAw+GmpOADcIccA4wIh1w0f8rwh4wMB2zz4R27yfEREBwEUIIIQFaA4+7rjAggElDD4Qm7jAPhG8nPU0x/6QZXU0dD6QN/6QZXU0dD6QN/R+En4SscFII6A346AjhQgyM+FCM6Ab89AyYEAgKYgtQf7AOJfBNs8f/hnDgsJEgEIXSLbPAoCfPhKyM74SwHOcAHLf3AByx8Syx/O+EGIyM+OK2zWzM7JAcwh+wQB0CCLOK2zWMcFk9dN0N7XTNDtHu1Tyds8UEEBHjAh+kJvE9cL/8MAII6A3gwBEDAh2zz4SccFDQGAbXDIy/9wWIBA9EP4SnFYgED0FgFyWIBA9BbI9ADJ+EGIyM+OK2zWzM7JyM+EgPQA9ADPgcn5AMjPigBAy//J0FAB+u1E0NdJwgGKjnJw7UTQ9AVxIYBA9A6OJI0IYAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABN/4anIhgED0Do4kjQhgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAE3/hrgED0DvK91wv/+GJw+GPiDwA07UTQ0//TP9MAMfpA1NHQ+kDR+Gv4avhj+GICCvSkIPShEUoBGKAAAAACMNs8+A/yABIALvhL+Er4Q/hCyMv/yz/Pg84ByM7Nye1UAgaK2zVQFAQkiu1TIOMDIMD/4wIgwP7jAvILSRgVTwEAFgL87UTQ10nDAfhmjQhgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAE+Gkh2zzTAAGOGoECANcYIPkBAdMAAZTT/wMBkwL4QuL5EPKoldMAAfJ64tM/AfhDIbnytCD4I4ED6KiCCBt3QKC58rT4Y9MfAfgjvPK50x8BIxcBDts8+Edu8nwZBILtRNDXScMB+GYi0NMD+kAw+GmpOAD4RH9vcYIImJaAb3Jtb3Nwb3T4ZOMCIccA4wIh1w0f8rwh4wMB2zz4R27yfEVERBkCKCCCEGi1Xz+74wIgghB9b/JUu+MCIBoCKCCCEHPiIUO64wIgghB9b/JUuuMCHBsCnDD4RvLgTPhCbuMA+kGV1NHQ+kDf0fhL+EnHBfLj6PhL+E34SnDIz4WAygBzz0DOcc8LblUgyM+QU/a2gssfzgHIzs3NyYBA+wDbPH/4Z0hOBOYw+Eby4Ez4Qm7jANcNf5XU0dDTf9/6QZXU0dD6QN/XDX+V1NHQ03/f+kGV1NHQ+kDf1wwAldTR0NIA39TR+Ev4SccF8uPoJcIA8uQaJfhMu/LkJCT6Qm8T1wv/wwAglzAk+EvHBbPe8uQG2zxw+wJVA9s8SEc4HQL2jQhgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAEJcIAjoCdIfkAyM+KAEDL/8nQMeL4TCehtX/4bFhVAlUD+EtVBlUEf8jPhYDKAHPPQM5xzwtuVUDIz5GeguV+y3/OVSDIzsoAzM3NyYEAgPsAW9s8f/hnHk4BDFRxVNs8MR8BuPhL+E34QYjIz44rbNbMzslVBCD5APgo+kJvEsjPhkDKB8v/ydBVQFUFJsjPhYjOAfoCi9AAAAAAAAAAAAAAAAAHzxbMz4NVMMjPkFaA4+7Myx/OAcjOzc3JcfsAUARQIIIQDwJYqrvjAiCCEDIE7Cm74wIgghBJaVh/u+MCIIIQaLVfP7vjAjsxKSEEUCCCEFYlSK264wIgghBmXc6fuuMCIIIQZ6C5X7rjAiCCEGi1Xz+64wIoJyUiARww+EJu4wD4RvJz0fLAZCMCFu1E0NdJwgGKjoDiSCQB9nDtRND0BXEhgED0Do4kjQhgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAE3/hqciGAQPQOjiSNCGAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAATf+Gtw+Gxw+G2I+G6AQPQO8r3XC//4YnD4Y08ErDD4RvLgTPhCbuMA1w1/ldTR0NN/3/pBldTR0PpA3/pBldTR0PpA39cMAJXU0dDSAN/U0fhJJNs8+QDIz4oAQMv/ydDHBfLkTNs8cvsC+EwloLV/+GwBSDhHJgG+jj9TAfhJU1b4SvhLcMjPhYDKAHPPQM5xzwtuVVDIz5HDYn8mzst/VTDIzlUgyM5ZyM7Mzc3NzcmBAICmArUH+wCOFCHIz4UIzoBvz0DJgQCApgK1B/sA4l8E2zx/+GdOA7Iw+Eby4Ez4Qm7jANMf+ERYb3X4ZNH4RHBvcoBAb3Rwb3H4ZPhBiMjPjits1szOySGOJyPQ0wH6QDAxyM+HIM6NBAAAAAAAAAAAAAAAAA5l3On4zxbMyXD7AEhQQwNyMPhG8uBM+EJu4wDXDX+V1NHQ03/f+kGV1NHQ+kDf+kGV1NHQ+kDf1NH4S/hJxwXy4+jbPNs8f/hnSD5OBFAgghBDhPKYuuMCIIIQRFdChLrjAiCCEEap1+y64wIgghBJaVh/uuMCLy4sKgKgMPhG8uBM+EJu4wDTH/hEWG91+GTR+ERwb3KAQG90cG9x+GT4TCGOKCPQ0wH6QDAxyM+HIM6NBAAAAAAAAAAAAAAAAAyWlYf4zxbLf8lw+wBIKwFyjjH4RCBvEyFvEvhJVQJvEchyz0DKAHPPQM4B+gL0AIBqz0D4RG8Vzwsfy3/J+ERvFPsA4uMAf/hnTgP8MPhG8uBM+EJu4wDXDX+V1NHQ03/f+kGV1NHQ+kDf+kGV1NHQ+kDf1wwAldTR0NIA39TR+Ev4SccF8uPoJMIA8uQaJPhMu/LkJCP6Qm8T1wv/wwAglzAj+CjHBbPe8uQG2zxw+wL4TCWhtX/4bAL4S1UTf8jPhYDKAHPPQM5xSEctAUbPC25VQMjPkZ6C5X7Lf85VIMjOygDMzc3JgQCA+wDbPH/4Z04D/jD4RvLgTPhCbuMA0x/4RFhvdfhk0fhEcG9ygEBvdHBvcfhk+Eohjh8j0NMB+kAwMcjPhyDOcc8LYQHIz5MRXQoSzs3JcPsAjjP4RCBvEyFvEvhJVQJvEchyz0DKAHPPQM4B+gL0AHHPC2kByPhEbxXPCx/Ozcn4RG8U+wDi4wBITjUD6jD4RvLgTPhCbuMA1w1/ldTR0NN/3/pBldTR0PpA39cMAJXU0dDSAN/U0fhK+EnHBfLj8ts8cvsC+EwkoLV/+GwBjjJUcBL4SvhLcMjPhYDKAHPPQM5xzwtuVTDIz5Hqe3iuzst/WcjOzM3NyYEAgKYCtQf7AEhHMAFqjish+kJvE9cL/8MAIJcwIfgoxwWz3o4UIcjPhQjOgG/PQMmBAICmArUH+wDe4l8D2zx/+GdOBFAgghATMqkxuuMCIIIQFaA4+7rjAiCCEB8BMpG64wIgghAyBOwpuuMCOTY0MgL4MPhG8uBM+EJu4wDTH/hEWG91+GTTH9H4RHBvcoBAb3Rwb3H4ZCCCEDIE7Cm6II5JMCCCEE9Hn6O6II48MCCCECpKxD66II4vMCCCEFYlSK26II4iMCCCEAwv8g26II4VMCCCEH7cHTe6IJkwIIIQDwJYqrrf39/f398xIUgzAcaOKCPQ0wH6QDAxyM+HIM6NBAAAAAAAAAAAAAAAAAsgTsKYzxbKAMlw+wCOMfhEIG8TIW8S+ElVAm8RyHLPQMoAc89AzgH6AvQAgGrPQPhEbxXPCx/KAMn4RG8U+wDi4wB/+GdOA/4w+Eby4Ez4Qm7jANMf+ERYb3X4ZNH4RHBvcoBAb3Rwb3H4ZPhLIY4fI9DTAfpAMDHIz4cgznHPC2EByM+SfATKRs7NyXD7AI4z+EQgbxMhbxL4SVUCbxHIcs9AygBzz0DOAfoC9ABxzwtpAcj4RG8Vzwsfzs3J+ERvFPsA4uMASE41AAZ/+GcEyjD4RvLgTPhCbuMA1NMf+kGV1NHQ+kDf+kGV1NHQ+kDf0fhJ+ErHBSCOgN/y4GTbPHD7AiD6Qm8T1wv/wwAglzAg+CjHBbPejhQgyM+FCM6Ab89AyYEAgKYCtQf7AN5fBOMAf/hnSDdHTgEmMCHbPPkAyM+KAEDL/8nQ+EnHBTgAVm1wyMv/cFiAQPRD+EpxWIBA9BYBcliAQPQWyPQAyfhOyM+EgPQA9ADPgckCoDD4RvLgTPhCbuMA0x/4RFhvdfhk0fhEcG9ygEBvdHBvcfhk+E0hjigj0NMB+kAwMcjPhyDOjQQAAAAAAAAAAAAAAAAJMyqTGM8Wyx/JcPsASDoBco4x+EQgbxMhbxL4SVUCbxHIcs9AygBzz0DOAfoC9ACAas9A+ERvFc8LH8sfyfhEbxT7AOLjAH/4Z04ETCCCCIV++rrjAiCCCzaRmbrjAiCCEAwv8g264wIgghAPAliquuMCQj89PAJ2MPhG8uBM+EJu4wD6QZXU0dD6QN/R+Ev4SccF8uPo+Ezy1C7Iz4UIzoBvz0DJgQCApiC1B/sA2zx/+GdITgNyMPhG8uBM+EJu4wDXDX+V1NHQ03/f+kGV1NHQ+kDf+kGV1NHQ+kDf1NH4SvhJxwXy4/LbPNs8f/hnSD5OAZojwgDy5Boj+Ey78uQk2zxw+wL4TCShtX/4bAL4S1UD+Ep/yM+FgMoAc89AznHPC25VQMjPkGStRsbLf85VIMjOWcjOzM3NzcmBAID7AEcElDD4RvLgTPhCbuMA1NMf+kGV1NHQ+kDf0fhK+EnHBfLj8ts8cPsC+E0iuo4UIMjPhQjOgG/PQMmBAICmArUH+wCOgOJfA9s8f/hnSEdATgFy+ErIzvhLAc74TAHLf/hNAcsfIgHLHyEBzvhOAcwj+wQj0CCLOK2zWMcFk9dN0N7XTNDtHu1Tyds8QQAE8AICnjD4RvLgTPhCbuMA0x/4RFhvdfhk0fhEcG9ygEBvdHBvcfhk+E4hjicj0NMB+kAwMcjPhyDOjQQAAAAAAAAAAAAAAAAICFfvqM8WzMlw+wBIQwFwjjD4RCBvEyFvEvhJVQJvEchyz0DKAHPPQM4B+gL0AIBqz0D4RG8VzwsfzMn4RG8U+wDi4wB/+GdOAAr4RvLgTAO8IdYfMfhG8uBM+EJu4wDbPHL7AiDTHzIgghBnoLlfuo49IdN/M/hMIaC1f/hs+EkB+Er4S3DIz4WAygBzz0DOcc8LblUgyM+Qn0I3ps7LfwHIzs3NyYEAgKYCtQf7AEhHRgGMjkAgghAZK1Gxuo41IdN/M/hMIaC1f/hs+Er4S3DIz4WAygBzz0DOcc8LblnIz5BwyoK2zst/zcmBAICmArUH+wDe4lvbPE4AJvgnbxBopv5gobV/ghAF9eEAtgkASu1E0NP/0z/TADH6QNTR0PpA03/TH9TR+G74bfhs+Gv4avhj+GICCvSkIPShS0oAFHNvbCAwLjQ5LjABCqAAAAACTAL+jQhgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAE+GqNCGAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAT4a3D4bHD4bYj4bnCNCGAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAARY0CD6QE9NAZr6QNN/0x/TH/pAN15A+Gr4a/hsMPhtMtQw+G4g+kJvE9cL/8MAIJcwIPgoxwWz3o4UIMjPhQjOgG/PQMmBAICmArUH+wDeW9s8+A/yAE4ARvhO+E34TPhL+Er4Q/hCyMv/yz/Pg85VMMjOy3/LH8zNye1UAAAADCD4Ye0e2Q==";
 
+    struct NoopHandler;
+
+    impl TokenWalletSubscriptionHandler for NoopHandler {
+        fn on_balance_changed(&self, _: BigUint) {}
+
+        fn on_transactions_found(
+            &self,
+            _: Vec<TransactionWithData<TokenWalletTransaction>>,
+            _: TransactionsBatchInfo,
+        ) {
+        }
+    }
+
+    fn make_transaction(lt: u64) -> TransactionWithData<TokenWalletTransaction> {
+        TransactionWithData {
+            transaction: Transaction {
+                id: TransactionId {
+                    lt,
+                    hash: ton_types::UInt256::from([lt as u8; 32]),
+                },
+                prev_trans_id: None,
+                created_at: 0,
+                aborted: false,
+                exit_code: None,
+                result_code: None,
+                orig_status: AccountStatus::Active,
+                end_status: AccountStatus::Active,
+                total_fees: 0,
+                in_msg: Default::default(),
+                out_msgs: Vec::new(),
+                #[cfg(feature = "extended_models")]
+                raw: Default::default(),
+            },
+            data: None,
+            labels: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn cursor_survives_restart() {
+        let storage = Arc::new(MemoryStorage::default());
+        let token_wallet =
+            convert_address("0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb");
+
+        let handler =
+            TokenWalletCursorHandler::load(&token_wallet, storage.clone(), Arc::new(NoopHandler))
+                .await;
+        assert!(handler.transactions_cursor().is_none());
+
+        let batch_info = |batch_type| TransactionsBatchInfo {
+            min_lt: 10,
+            max_lt: 30,
+            batch_type,
+        };
+
+        // Only new transactions move the cursor
+        handler.on_transactions_found(
+            vec![make_transaction(30), make_transaction(20)],
+            batch_info(TransactionsBatchType::New),
+        );
+        handler.on_transactions_found(
+            vec![make_transaction(10)],
+            batch_info(TransactionsBatchType::Old),
+        );
+
+        let restarted =
+            TokenWalletCursorHandler::load(&token_wallet, storage.clone(), Arc::new(NoopHandler))
+                .await;
+        let cursor = restarted.transactions_cursor().unwrap();
+        assert_eq!(
+            cursor,
+            TransactionsCursor::from(make_transaction(30).transaction.id)
+        );
+
+        restarted.clear_cursor();
+        let restarted =
+            TokenWalletCursorHandler::load(&token_wallet, storage, Arc::new(NoopHandler)).await;
+        assert!(restarted.transactions_cursor().is_none());
+    }
+
     #[test]
     fn get_token_wallet_balance() {
         let versions = [TokenWalletVersion::OldTip3v4, TokenWalletVersion::Tip3];
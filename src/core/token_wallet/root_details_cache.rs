@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::RwLock;
+use ton_block::MsgAddressInt;
+
+use nekoton_utils::*;
+
+use super::get_token_root_details;
+use crate::core::models::RootTokenContractDetails;
+use crate::external::Storage;
+use crate::transport::Transport;
+
+pub const ROOT_TOKEN_DETAILS_STORAGE_KEY: &str = "__core__root_token_details";
+
+/// Stores details of the root token contracts to avoid querying static metadata
+/// on every refresh.
+///
+/// NOTE: total supply is not updated automatically, use
+/// [`RootTokenDetailsCache::refresh`] to get the actual value
+pub struct RootTokenDetailsCache {
+    key: String,
+    clock: Arc<dyn Clock>,
+    storage: Arc<dyn Storage>,
+    transport: Arc<dyn Transport>,
+    details: RwLock<HashMap<MsgAddressInt, RootTokenContractDetails>>,
+}
+
+impl RootTokenDetailsCache {
+    pub async fn load(
+        network_name: &str,
+        clock: Arc<dyn Clock>,
+        storage: Arc<dyn Storage>,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Self> {
+        let key = make_key(network_name);
+
+        let details = match storage.get(&key).await? {
+            Some(data) => serde_json::from_str::<HashMap<String, RootTokenContractDetails>>(&data)?
+                .into_iter()
+                .map(|(address, details)| Ok((MsgAddressInt::from_str(&address)?, details)))
+                .collect::<Result<_>>()?,
+            None => Default::default(),
+        };
+
+        Ok(Self {
+            key,
+            clock,
+            storage,
+            transport,
+            details: RwLock::new(details),
+        })
+    }
+
+    pub async fn load_unchecked(
+        network_name: &str,
+        clock: Arc<dyn Clock>,
+        storage: Arc<dyn Storage>,
+        transport: Arc<dyn Transport>,
+    ) -> Self {
+        Self::load(
+            network_name,
+            clock.clone(),
+            storage.clone(),
+            transport.clone(),
+        )
+        .await
+        .unwrap_or_else(|_| Self {
+            key: make_key(network_name),
+            clock,
+            storage,
+            transport,
+            details: Default::default(),
+        })
+    }
+
+    /// Returns cached details or fetches them from the network
+    pub async fn get(
+        &self,
+        root_token_contract: &MsgAddressInt,
+    ) -> Result<RootTokenContractDetails> {
+        if let Some(details) = self.get_cached(root_token_contract).await {
+            return Ok(details);
+        }
+        self.refresh(root_token_contract).await
+    }
+
+    pub async fn get_cached(
+        &self,
+        root_token_contract: &MsgAddressInt,
+    ) -> Option<RootTokenContractDetails> {
+        self.details.read().await.get(root_token_contract).cloned()
+    }
+
+    /// Fetches details from the network and updates the cache
+    pub async fn refresh(
+        &self,
+        root_token_contract: &MsgAddressInt,
+    ) -> Result<RootTokenContractDetails> {
+        let details = get_token_root_details(
+            self.clock.as_ref(),
+            self.transport.as_ref(),
+            root_token_contract,
+        )
+        .await?;
+
+        let mut cache = self.details.write().await;
+        cache.insert(root_token_contract.clone(), details.clone());
+        self.save(&cache);
+
+        Ok(details)
+    }
+
+    pub async fn remove(&self, root_token_contract: &MsgAddressInt) {
+        let mut cache = self.details.write().await;
+        if cache.remove(root_token_contract).is_some() {
+            self.save(&cache);
+        }
+    }
+
+    fn save(&self, details: &HashMap<MsgAddressInt, RootTokenContractDetails>) {
+        let data = details
+            .iter()
+            .map(|(address, details)| (address.to_string(), details))
+            .collect::<HashMap<_, _>>();
+
+        self.storage
+            .set_unchecked(&self.key, &serde_json::to_string(&data).trust_me());
+    }
+}
+
+fn make_key(network_name: &str) -> String {
+    format!("{ROOT_TOKEN_DETAILS_STORAGE_KEY}{network_name}")
+}
@@ -17,6 +17,18 @@ pub fn prepare_deploy(
     public_key: &PublicKey,
     workchain: i8,
     expiration: Expiration,
+) -> Result<Box<dyn UnsignedMessage>> {
+    prepare_deploy_with_transfer(clock, public_key, workchain, Vec::new(), expiration)
+}
+
+/// Prepares deploy message which also sends the specified gifts,
+/// so that a fresh wallet can be deployed and used in one step
+pub fn prepare_deploy_with_transfer(
+    clock: &dyn Clock,
+    public_key: &PublicKey,
+    workchain: i8,
+    gifts: Vec<Gift>,
+    expiration: Expiration,
 ) -> Result<Box<dyn UnsignedMessage>> {
     let state_init = make_state_init(public_key)?;
     let hash = state_init.hash()?;
@@ -34,18 +46,7 @@ pub fn prepare_deploy(
         });
     message.set_state_init(state_init);
 
-    let (function, input) =
-        MessageBuilder::new(nekoton_contracts::wallets::ever_wallet::send_transaction_raw_0())
-            .build();
-
-    make_labs_unsigned_message(
-        clock,
-        message,
-        expiration,
-        public_key,
-        Cow::Borrowed(function),
-        input,
-    )
+    make_transfer_message(clock, public_key, message, gifts, expiration)
 }
 
 pub fn prepare_transfer(
@@ -56,12 +57,6 @@ pub fn prepare_transfer(
     gifts: Vec<Gift>,
     expiration: Expiration,
 ) -> Result<TransferAction> {
-    use nekoton_contracts::wallets::ever_wallet;
-
-    if gifts.len() > MAX_MESSAGES {
-        return Err(EverWalletError::TooManyGifts.into());
-    }
-
     let mut message =
         ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
             dst: address,
@@ -78,6 +73,22 @@ pub fn prepare_transfer(
         }
     };
 
+    make_transfer_message(clock, public_key, message, gifts, expiration).map(TransferAction::Sign)
+}
+
+fn make_transfer_message(
+    clock: &dyn Clock,
+    public_key: &PublicKey,
+    message: ton_block::Message,
+    gifts: Vec<Gift>,
+    expiration: Expiration,
+) -> Result<Box<dyn UnsignedMessage>> {
+    use nekoton_contracts::wallets::ever_wallet;
+
+    if gifts.len() > MAX_MESSAGES {
+        return Err(EverWalletError::TooManyGifts.into());
+    }
+
     let mut gifts = gifts.into_iter();
     let (function, input) = match (gifts.len(), gifts.next()) {
         (1, Some(gift)) if gift.state_init.is_none() => {
@@ -128,14 +139,14 @@ pub fn prepare_transfer(
         }
     };
 
-    Ok(TransferAction::Sign(make_labs_unsigned_message(
+    make_labs_unsigned_message(
         clock,
         message,
         expiration,
         public_key,
         Cow::Borrowed(function),
         input,
-    )?))
+    )
 }
 
 pub static CODE_HASH: &[u8; 32] = &[
@@ -0,0 +1,264 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use ed25519_dalek::PublicKey;
+use serde::Serialize;
+use ton_types::UInt256;
+
+use nekoton_utils::*;
+
+use super::{extract_wallet_init_data, highload_wallet_v2, multisig, wallet_v3, WalletType};
+use crate::transport::models::ExistingContract;
+
+/// Decoded internal state of the wallet contract
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WalletInspection {
+    pub wallet_type: WalletType,
+    #[serde(with = "serde_public_key")]
+    pub public_key: PublicKey,
+    #[serde(with = "serde_uint256")]
+    pub code_hash: UInt256,
+    #[serde(with = "serde_uint256")]
+    pub data_hash: UInt256,
+    pub data: InspectedWalletData,
+}
+
+/// Wallet specific data fields
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+pub enum InspectedWalletData {
+    #[serde(rename_all = "camelCase")]
+    WalletV3 {
+        seqno: u32,
+        wallet_id: u32,
+    },
+    #[serde(rename_all = "camelCase")]
+    HighloadWalletV2 {
+        wallet_id: u32,
+        #[serde(with = "serde_u64")]
+        last_cleaned: u64,
+        /// Number of stored queries, which are not yet cleaned up
+        stored_queries: usize,
+    },
+    #[serde(rename_all = "camelCase")]
+    Multisig {
+        #[serde(with = "serde_vec_uint256")]
+        custodians: Vec<UInt256>,
+        required_confirms: u8,
+        #[serde(with = "serde_u64")]
+        expiration_time: u64,
+        pending_transactions: usize,
+    },
+    EverWallet,
+}
+
+/// Decodes wallet type specific data of the deployed wallet contract.
+///
+/// Intended for diagnostics, regular wallet logic must not depend on it
+pub fn inspect_wallet(clock: &dyn Clock, contract: &ExistingContract) -> Result<WalletInspection> {
+    let (public_key, wallet_type) = extract_wallet_init_data(contract)?;
+
+    let (code_hash, data) = match &contract.account.storage.state {
+        ton_block::AccountState::AccountActive {
+            state_init:
+                ton_block::StateInit {
+                    code: Some(code),
+                    data: Some(data),
+                    ..
+                },
+            ..
+        } => (code.repr_hash(), data),
+        // NOTE: checked in `extract_wallet_init_data`
+        _ => return Err(WalletInspectionError::AccountNotActive.into()),
+    };
+
+    let inspected = match wallet_type {
+        WalletType::WalletV3 => {
+            let data = wallet_v3::InitData::try_from(data)?;
+            InspectedWalletData::WalletV3 {
+                seqno: data.seqno,
+                wallet_id: data.wallet_id,
+            }
+        }
+        WalletType::HighloadWalletV2 => {
+            let data = highload_wallet_v2::InitData::try_from(data)?;
+            InspectedWalletData::HighloadWalletV2 {
+                wallet_id: data.wallet_id,
+                last_cleaned: data.last_cleaned,
+                stored_queries: data.data.len()?,
+            }
+        }
+        WalletType::Multisig(multisig_type) => {
+            let account = Cow::Borrowed(&contract.account);
+            let params = multisig::get_params(clock, multisig_type, account.clone())?;
+            let custodians = multisig::get_custodians(clock, multisig_type, account.clone())?;
+            let pending_transactions =
+                multisig::get_pending_transactions(clock, multisig_type, account, &custodians)?
+                    .len();
+
+            InspectedWalletData::Multisig {
+                custodians,
+                required_confirms: params.required_confirms,
+                expiration_time: params.expiration_time,
+                pending_transactions,
+            }
+        }
+        WalletType::EverWallet => InspectedWalletData::EverWallet,
+    };
+
+    Ok(WalletInspection {
+        wallet_type,
+        public_key,
+        code_hash,
+        data_hash: data.repr_hash(),
+        data: inspected,
+    })
+}
+
+#[derive(thiserror::Error, Debug)]
+enum WalletInspectionError {
+    #[error("Account is not active")]
+    AccountNotActive,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+    use ton_block::MsgAddressInt;
+
+    use super::*;
+    use crate::core::models::Expiration;
+    use crate::crypto::UnsignedMessage;
+    use crate::testing::ChainSimulator;
+    use crate::transport::models::RawContractState;
+    use crate::transport::Transport;
+
+    fn make_keypair(byte: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[byte; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    async fn deploy(
+        sim: &ChainSimulator,
+        keypair: &Keypair,
+        address: &MsgAddressInt,
+        message: Box<dyn UnsignedMessage>,
+    ) -> ExistingContract {
+        sim.transport().set_account(
+            address.clone(),
+            ton_block::Account::with_address_and_ballance(
+                address,
+                &ton_block::CurrencyCollection::with_grams(10_000_000_000),
+            ),
+        );
+
+        let signature = keypair.sign(message.hash()).to_bytes();
+        let message = message.sign(&signature).unwrap().message;
+        sim.transport().send_message(&message).await.unwrap();
+        sim.advance(Duration::from_secs(1)).unwrap();
+
+        match sim.transport().get_contract_state(address).await.unwrap() {
+            RawContractState::Exists(contract) => contract,
+            RawContractState::NotExists { .. } => panic!("wallet must exist"),
+        }
+    }
+
+    #[tokio::test]
+    async fn inspect_wallet_v3() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let keypair = make_keypair(1);
+
+        let address = wallet_v3::compute_contract_address(&keypair.public, 0);
+        let message = wallet_v3::prepare_deploy(
+            sim.clock().as_ref(),
+            &keypair.public,
+            0,
+            Expiration::Timeout(60),
+        )
+        .unwrap();
+        let contract = deploy(&sim, &keypair, &address, message).await;
+
+        let inspection = inspect_wallet(sim.clock().as_ref(), &contract).unwrap();
+        assert_eq!(inspection.wallet_type, WalletType::WalletV3);
+        assert_eq!(inspection.public_key, keypair.public);
+        assert_eq!(
+            inspection.code_hash,
+            nekoton_contracts::wallets::code::wallet_v3().repr_hash()
+        );
+        assert!(matches!(
+            inspection.data,
+            InspectedWalletData::WalletV3 {
+                seqno: 1,
+                wallet_id: 0x4BA92D8A,
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn inspect_multisig() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let keypair = make_keypair(1);
+        let custodian = make_keypair(2).public;
+
+        let multisig_type = multisig::MultisigType::SafeMultisigWallet;
+        let address = multisig::compute_contract_address(&keypair.public, multisig_type, 0);
+        let message = multisig::prepare_deploy(
+            sim.clock().as_ref(),
+            &keypair.public,
+            multisig_type,
+            0,
+            Expiration::Timeout(60),
+            multisig::DeployParams {
+                owners: &[keypair.public, custodian],
+                req_confirms: 2,
+                expiration_time: None,
+            },
+        )
+        .unwrap();
+        let contract = deploy(&sim, &keypair, &address, message).await;
+
+        let inspection = inspect_wallet(sim.clock().as_ref(), &contract).unwrap();
+        assert_eq!(inspection.wallet_type, WalletType::Multisig(multisig_type));
+        assert_eq!(inspection.public_key, keypair.public);
+        match inspection.data {
+            InspectedWalletData::Multisig {
+                custodians,
+                required_confirms,
+                pending_transactions,
+                ..
+            } => {
+                assert_eq!(custodians.len(), 2);
+                assert!(custodians.contains(&UInt256::from(custodian.to_bytes())));
+                assert_eq!(required_confirms, 2);
+                assert_eq!(pending_transactions, 0);
+            }
+            data => panic!("unexpected data: {data:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn inspect_not_deployed_wallet() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let keypair = make_keypair(1);
+
+        let address = wallet_v3::compute_contract_address(&keypair.public, 0);
+        sim.transport().set_account(
+            address.clone(),
+            ton_block::Account::with_address_and_ballance(
+                &address,
+                &ton_block::CurrencyCollection::with_grams(10_000_000_000),
+            ),
+        );
+        let contract = match sim.transport().get_contract_state(&address).await.unwrap() {
+            RawContractState::Exists(contract) => contract,
+            RawContractState::NotExists { .. } => panic!("account must exist"),
+        };
+
+        assert!(inspect_wallet(sim.clock().as_ref(), &contract).is_err());
+    }
+}
@@ -13,21 +13,27 @@ use ton_types::{SliceData, UInt256};
 use nekoton_abi::*;
 use nekoton_utils::*;
 
-pub use self::multisig::MultisigType;
+pub use self::inspect::{inspect_wallet, InspectedWalletData, WalletInspection};
+pub use self::multisig::{CustomMultisigCodes, MultisigType};
+use super::dead_letters::DeadLetters;
 use super::models::{
     ContractState, Expiration, MessageFlags, MultisigPendingTransaction, MultisigPendingUpdate,
     PendingTransaction, Transaction, TransactionAdditionalInfo, TransactionWithData,
-    TransactionsBatchInfo,
+    TransactionsBatchInfo, TransactionsBatchType, TransactionsCursor,
 };
+use super::prefetcher::Prefetcher;
+use super::scheduled_messages::ScheduledMessage;
 use super::{ContractSubscription, PollingMethod};
 use crate::core::parsing::*;
 use crate::core::InternalMessage;
 use crate::crypto::UnsignedMessage;
+use crate::external::{AddressLabeler, EventSink, SubscriptionEvent, SubscriptionEventKind};
 use crate::transport::models::{ExistingContract, RawContractState, RawTransaction};
 use crate::transport::Transport;
 
 pub mod ever_wallet;
 pub mod highload_wallet_v2;
+mod inspect;
 pub mod multisig;
 pub mod wallet_v3;
 
@@ -40,6 +46,9 @@ pub struct TonWallet {
     contract_subscription: ContractSubscription,
     handler: Arc<dyn TonWalletSubscriptionHandler>,
     wallet_data: WalletData,
+    address_labeler: Option<Arc<dyn AddressLabeler>>,
+    prefetcher: Option<Arc<Prefetcher>>,
+    custom_multisig_codes: Option<Arc<CustomMultisigCodes>>,
 }
 
 impl TonWallet {
@@ -69,6 +78,8 @@ impl TonWallet {
             Some(&mut make_transactions_handler(
                 handler.as_ref(),
                 wallet_type,
+                None,
+                None,
             )),
         )
         .await?;
@@ -80,6 +91,9 @@ impl TonWallet {
             contract_subscription,
             handler,
             wallet_data,
+            address_labeler: None,
+            prefetcher: None,
+            custom_multisig_codes: None,
         })
     }
 
@@ -88,9 +102,41 @@ impl TonWallet {
         transport: Arc<dyn Transport>,
         address: MsgAddressInt,
         handler: Arc<dyn TonWalletSubscriptionHandler>,
+    ) -> Result<Self> {
+        Self::subscribe_by_address_impl(clock, transport, address, None, handler).await
+    }
+
+    /// Same as [`TonWallet::subscribe_by_address`], but also recognizes the registered
+    /// multisig forks. The registry is then used for the wallet deploy
+    pub async fn subscribe_custom_by_address(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        address: MsgAddressInt,
+        custom_multisig_codes: Arc<CustomMultisigCodes>,
+        handler: Arc<dyn TonWalletSubscriptionHandler>,
+    ) -> Result<Self> {
+        Self::subscribe_by_address_impl(
+            clock,
+            transport,
+            address,
+            Some(custom_multisig_codes),
+            handler,
+        )
+        .await
+    }
+
+    async fn subscribe_by_address_impl(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        address: MsgAddressInt,
+        custom_multisig_codes: Option<Arc<CustomMultisigCodes>>,
+        handler: Arc<dyn TonWalletSubscriptionHandler>,
     ) -> Result<Self> {
         let (public_key, wallet_type) = match transport.get_contract_state(&address).await? {
-            RawContractState::Exists(contract) => extract_wallet_init_data(&contract)?,
+            RawContractState::Exists(contract) => match &custom_multisig_codes {
+                Some(codes) => extract_custom_wallet_init_data(&contract, codes)?,
+                None => extract_wallet_init_data(&contract)?,
+            },
             RawContractState::NotExists { .. } => {
                 return Err(TonWalletError::AccountNotExists.into())
             }
@@ -112,6 +158,8 @@ impl TonWallet {
             Some(&mut make_transactions_handler(
                 handler.as_ref(),
                 wallet_type,
+                None,
+                None,
             )),
         )
         .await?;
@@ -123,6 +171,9 @@ impl TonWallet {
             contract_subscription,
             handler,
             wallet_data,
+            address_labeler: None,
+            prefetcher: None,
+            custom_multisig_codes,
         })
     }
 
@@ -148,6 +199,8 @@ impl TonWallet {
             Some(&mut make_transactions_handler(
                 handler.as_ref(),
                 existing_wallet.wallet_type,
+                None,
+                None,
             )),
         )
         .await?;
@@ -159,13 +212,96 @@ impl TonWallet {
             contract_subscription,
             handler,
             wallet_data,
+            address_labeler: None,
+            prefetcher: None,
+            custom_multisig_codes: None,
+        })
+    }
+
+    /// Restores the wallet from the snapshot, created by [`TonWallet::serialize_state`].
+    ///
+    /// Transactions are not requested again, only the account state
+    /// is requested to extract the wallet data (e.g. multisig custodians)
+    pub async fn restore(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        public_key: PublicKey,
+        wallet_type: WalletType,
+        data: &str,
+        handler: Arc<dyn TonWalletSubscriptionHandler>,
+    ) -> Result<Self> {
+        let contract_subscription =
+            ContractSubscription::restore_state(clock.clone(), transport.clone(), data)?;
+
+        let mut wallet_data = WalletData::default();
+        if let RawContractState::Exists(contract) = transport
+            .get_contract_state(contract_subscription.address())
+            .await?
+        {
+            wallet_data.update(
+                clock.as_ref(),
+                &public_key,
+                wallet_type,
+                &contract.account,
+                handler.as_ref(),
+            )?;
+        }
+
+        Ok(Self {
+            clock,
+            public_key,
+            wallet_type,
+            contract_subscription,
+            handler,
+            wallet_data,
+            address_labeler: None,
+            prefetcher: None,
+            custom_multisig_codes: None,
         })
     }
 
+    /// See [`ContractSubscription::serialize_state`]
+    pub fn serialize_state(&self) -> String {
+        self.contract_subscription.serialize_state()
+    }
+
     pub fn contract_subscription(&self) -> &ContractSubscription {
         &self.contract_subscription
     }
 
+    /// See [`ContractSubscription::set_send_safety_margin`]
+    pub fn set_send_safety_margin(&mut self, seconds: u32) {
+        self.contract_subscription.set_send_safety_margin(seconds);
+    }
+
+    /// See [`ContractSubscription::set_dead_letters`]
+    pub fn set_dead_letters(&mut self, dead_letters: Option<Arc<DeadLetters>>) {
+        self.contract_subscription.set_dead_letters(dead_letters);
+    }
+
+    /// Attaches labels of the well-known counterparties to the found transactions
+    pub fn set_address_labeler(&mut self, address_labeler: Option<Arc<dyn AddressLabeler>>) {
+        self.address_labeler = address_labeler;
+    }
+
+    /// Prefetches states of the counterparties of the new transactions
+    pub fn set_prefetcher(&mut self, prefetcher: Option<Arc<Prefetcher>>) {
+        self.prefetcher = prefetcher;
+    }
+
+    /// Registered multisig forks, which can be used to deploy the wallet
+    pub fn set_custom_multisig_codes(
+        &mut self,
+        custom_multisig_codes: Option<Arc<CustomMultisigCodes>>,
+    ) {
+        self.custom_multisig_codes = custom_multisig_codes;
+    }
+
+    /// See [`ContractSubscription::subscribe_updates`]
+    pub async fn subscribe_updates(&mut self) -> Result<bool> {
+        self.contract_subscription.subscribe_updates().await
+    }
+
     pub fn workchain(&self) -> i8 {
         self.contract_subscription.address().workchain_id() as i8
     }
@@ -214,11 +350,8 @@ impl TonWallet {
 
     pub fn prepare_deploy(&self, expiration: Expiration) -> Result<Box<dyn UnsignedMessage>> {
         match self.wallet_type {
-            WalletType::Multisig(multisig_type) => multisig::prepare_deploy(
-                self.clock.as_ref(),
-                &self.public_key,
+            WalletType::Multisig(multisig_type) => self.prepare_multisig_deploy(
                 multisig_type,
-                self.workchain(),
                 expiration,
                 multisig::DeployParams::single_custodian(&self.public_key),
             ),
@@ -243,6 +376,33 @@ impl TonWallet {
         }
     }
 
+    /// Prepares deploy message which also sends the specified gifts.
+    ///
+    /// Only supported by wallets which can be deployed with any message
+    pub fn prepare_deploy_with_transfer(
+        &self,
+        gifts: Vec<Gift>,
+        expiration: Expiration,
+    ) -> Result<Box<dyn UnsignedMessage>> {
+        match self.wallet_type {
+            WalletType::WalletV3 => wallet_v3::prepare_deploy_with_transfer(
+                self.clock.as_ref(),
+                &self.public_key,
+                self.workchain(),
+                gifts,
+                expiration,
+            ),
+            WalletType::EverWallet => ever_wallet::prepare_deploy_with_transfer(
+                self.clock.as_ref(),
+                &self.public_key,
+                self.workchain(),
+                gifts,
+                expiration,
+            ),
+            _ => Err(TonWalletError::InvalidContractType.into()),
+        }
+    }
+
     pub fn prepare_deploy_with_multiple_owners(
         &self,
         expiration: Expiration,
@@ -251,11 +411,8 @@ impl TonWallet {
         expiration_time: Option<u32>,
     ) -> Result<Box<dyn UnsignedMessage>> {
         match self.wallet_type {
-            WalletType::Multisig(multisig_type) => multisig::prepare_deploy(
-                self.clock.as_ref(),
-                &self.public_key,
+            WalletType::Multisig(multisig_type) => self.prepare_multisig_deploy(
                 multisig_type,
-                self.workchain(),
                 expiration,
                 multisig::DeployParams {
                     owners: custodians,
@@ -268,6 +425,44 @@ impl TonWallet {
         }
     }
 
+    /// Deploys either the known multisig code or the registered fork with the wallet address
+    fn prepare_multisig_deploy(
+        &self,
+        multisig_type: MultisigType,
+        expiration: Expiration,
+        params: multisig::DeployParams<'_>,
+    ) -> Result<Box<dyn UnsignedMessage>> {
+        let workchain = self.workchain();
+        if &multisig::compute_contract_address(&self.public_key, multisig_type, workchain)
+            == self.address()
+        {
+            return multisig::prepare_deploy(
+                self.clock.as_ref(),
+                &self.public_key,
+                multisig_type,
+                workchain,
+                expiration,
+                params,
+            );
+        }
+
+        let custom = self
+            .custom_multisig_codes
+            .as_ref()
+            .and_then(|codes| codes.find(&self.public_key, multisig_type, self.address()));
+        match custom {
+            Some(custom) => multisig::prepare_custom_deploy(
+                self.clock.as_ref(),
+                &self.public_key,
+                &custom,
+                workchain,
+                expiration,
+                params,
+            ),
+            None => Err(TonWalletError::UnknownContractCode.into()),
+        }
+    }
+
     pub fn prepare_transfer(
         &mut self,
         current_state: &ton_block::AccountStuff,
@@ -275,6 +470,8 @@ impl TonWallet {
         gift: Gift,
         expiration: Expiration,
     ) -> Result<TransferAction> {
+        check_std_address(&gift.destination)?;
+
         match self.wallet_type {
             WalletType::Multisig(multisig_type) => {
                 match &current_state.storage.state {
@@ -336,6 +533,86 @@ impl TonWallet {
         }
     }
 
+    /// Prepares one external message with several outgoing transfers.
+    ///
+    /// Number of gifts is limited by [`TonWalletDetails::max_messages`]
+    /// (e.g. highload wallet allows sending hundreds of transfers at once)
+    pub fn prepare_transfer_batch(
+        &mut self,
+        current_state: &ton_block::AccountStuff,
+        public_key: &PublicKey,
+        mut gifts: Vec<Gift>,
+        expiration: Expiration,
+    ) -> Result<TransferAction> {
+        for gift in &gifts {
+            check_std_address(&gift.destination)?;
+        }
+
+        match self.wallet_type {
+            WalletType::Multisig(_) => {
+                if gifts.len() != 1 {
+                    return Err(TonWalletError::TooManyGifts.into());
+                }
+                let gift = gifts.remove(0);
+                self.prepare_transfer(current_state, public_key, gift, expiration)
+            }
+            WalletType::WalletV3 => wallet_v3::prepare_transfer(
+                self.clock.as_ref(),
+                public_key,
+                current_state,
+                0,
+                gifts,
+                expiration,
+            ),
+            WalletType::EverWallet => ever_wallet::prepare_transfer(
+                self.clock.as_ref(),
+                public_key,
+                current_state,
+                self.address().clone(),
+                gifts,
+                expiration,
+            ),
+            WalletType::HighloadWalletV2 => highload_wallet_v2::prepare_transfer(
+                self.clock.as_ref(),
+                public_key,
+                current_state,
+                gifts,
+                expiration,
+            ),
+        }
+    }
+
+    /// Creates a new multisig pending transaction even if the wallet has only one custodian
+    pub fn prepare_submit_transaction(
+        &self,
+        current_state: &ton_block::AccountStuff,
+        public_key: &PublicKey,
+        gift: Gift,
+        expiration: Expiration,
+    ) -> Result<Box<dyn UnsignedMessage>> {
+        match self.wallet_type {
+            WalletType::Multisig(multisig_type) => {
+                if !matches!(
+                    current_state.storage.state,
+                    ton_block::AccountState::AccountActive { .. }
+                ) {
+                    return Err(TonWalletError::AccountNotExists.into());
+                }
+
+                multisig::prepare_submit_transaction(
+                    self.clock.as_ref(),
+                    multisig_type,
+                    public_key,
+                    self.address().clone(),
+                    gift,
+                    expiration,
+                )
+            }
+            // Non-multisig wallets doesn't support pending transactions
+            _ => Err(TonWalletError::InvalidContractType.into()),
+        }
+    }
+
     pub fn prepare_confirm_transaction(
         &self,
         current_state: &ton_block::AccountStuff,
@@ -468,7 +745,17 @@ impl TonWallet {
         self.contract_subscription.send(message, expire_at).await
     }
 
+    /// See [`ContractSubscription::send_scheduled`]
+    pub async fn send_scheduled(
+        &mut self,
+        message: &ScheduledMessage,
+    ) -> Result<PendingTransaction> {
+        self.contract_subscription.send_scheduled(message).await
+    }
+
     pub async fn refresh(&mut self) -> Result<()> {
+        let mut new_transactions = Vec::new();
+
         let handler = self.handler.as_ref();
         self.contract_subscription
             .refresh(
@@ -479,20 +766,35 @@ impl TonWallet {
                     self.wallet_type,
                     &mut self.wallet_data,
                 ),
-                &mut make_transactions_handler(handler, self.wallet_type),
+                &mut make_transactions_handler(
+                    handler,
+                    self.wallet_type,
+                    self.address_labeler.as_deref(),
+                    self.prefetcher.as_ref().map(|_| &mut new_transactions),
+                ),
                 &mut make_message_sent_handler(handler),
                 &mut make_message_expired_handler(handler),
             )
-            .await
+            .await?;
+
+        self.prefetch_counterparties(&new_transactions).await;
+        Ok(())
     }
 
     pub async fn handle_block(&mut self, block: &ton_block::Block) -> Result<()> {
         // TODO: update wallet data here
 
+        let mut new_transactions = Vec::new();
+
         let handler = self.handler.as_ref();
         let new_account_state = self.contract_subscription.handle_block(
             block,
-            &mut make_transactions_handler(handler, self.wallet_type),
+            &mut make_transactions_handler(
+                handler,
+                self.wallet_type,
+                self.address_labeler.as_deref(),
+                self.prefetcher.as_ref().map(|_| &mut new_transactions),
+            ),
             &mut make_message_sent_handler(handler),
             &mut make_message_expired_handler(handler),
         )?;
@@ -501,15 +803,25 @@ impl TonWallet {
             handler.on_state_changed(account_state);
         }
 
+        self.prefetch_counterparties(&new_transactions).await;
         Ok(())
     }
 
-    pub async fn preload_transactions(&mut self, from_lt: u64) -> Result<()> {
+    /// See [`ContractSubscription::preload_transactions`]
+    pub async fn preload_transactions(
+        &mut self,
+        cursor: &TransactionsCursor,
+    ) -> Result<Option<TransactionsCursor>> {
         let handler = self.handler.as_ref();
         self.contract_subscription
             .preload_transactions(
-                from_lt,
-                &mut make_transactions_handler(handler, self.wallet_type),
+                cursor,
+                &mut make_transactions_handler(
+                    handler,
+                    self.wallet_type,
+                    self.address_labeler.as_deref(),
+                    None,
+                ),
             )
             .await
     }
@@ -517,6 +829,14 @@ impl TonWallet {
     pub async fn estimate_fees(&mut self, message: &ton_block::Message) -> Result<u128> {
         self.contract_subscription.estimate_fees(message).await
     }
+
+    async fn prefetch_counterparties(&self, transactions: &[Transaction]) {
+        if let Some(prefetcher) = &self.prefetcher {
+            prefetcher
+                .prefetch_counterparties(self.contract_subscription.address(), transactions)
+                .await;
+        }
+    }
 }
 
 #[derive(Default)]
@@ -615,6 +935,26 @@ impl WalletData {
 }
 
 pub fn extract_wallet_init_data(contract: &ExistingContract) -> Result<(PublicKey, WalletType)> {
+    extract_wallet_init_data_impl(contract, multisig::guess_multisig_type)
+}
+
+/// Same as [`extract_wallet_init_data`], but also recognizes the registered multisig forks
+pub fn extract_custom_wallet_init_data(
+    contract: &ExistingContract,
+    custom_multisig_codes: &CustomMultisigCodes,
+) -> Result<(PublicKey, WalletType)> {
+    extract_wallet_init_data_impl(contract, |code_hash| {
+        custom_multisig_codes.guess_multisig_type(code_hash)
+    })
+}
+
+fn extract_wallet_init_data_impl<F>(
+    contract: &ExistingContract,
+    guess_multisig_type: F,
+) -> Result<(PublicKey, WalletType)>
+where
+    F: FnOnce(&UInt256) -> Option<MultisigType>,
+{
     let (code, data) = match &contract.account.storage.state {
         ton_block::AccountState::AccountActive {
             state_init:
@@ -629,7 +969,7 @@ pub fn extract_wallet_init_data(contract: &ExistingContract) -> Result<(PublicKe
     };
 
     let code_hash = code.repr_hash();
-    if let Some(multisig_type) = multisig::guess_multisig_type(&code_hash) {
+    if let Some(multisig_type) = guess_multisig_type(&code_hash) {
         let public_key = extract_public_key(&contract.account)?;
         Ok((public_key, WalletType::Multisig(multisig_type)))
     } else if wallet_v3::is_wallet_v3(&code_hash) {
@@ -774,6 +1114,10 @@ enum TonWalletError {
     PendingUpdateNotFound,
     #[error("Updated data mismatch")]
     UpdatedDataMismatch,
+    #[error("Too many outgoing messages")]
+    TooManyGifts,
+    #[error("Wallet address doesn't match any known contract code")]
+    UnknownContractCode,
 }
 
 fn make_contract_state_handler<'a>(
@@ -799,25 +1143,55 @@ fn make_contract_state_handler<'a>(
     }
 }
 
-fn make_transactions_handler(
-    handler: &'_ dyn TonWalletSubscriptionHandler,
+fn make_transactions_handler<'a>(
+    handler: &'a dyn TonWalletSubscriptionHandler,
     wallet_type: WalletType,
-) -> impl FnMut(Vec<RawTransaction>, TransactionsBatchInfo) + '_ {
+    address_labeler: Option<&'a dyn AddressLabeler>,
+    mut new_transactions: Option<&'a mut Vec<Transaction>>,
+) -> impl FnMut(Vec<RawTransaction>, TransactionsBatchInfo) + 'a {
+    // Contracts which were recently called by the wallet
+    let mut refund_sources = Vec::<RefundSource>::new();
+
     move |transactions, batch_info| {
+        refund_sources.extend(
+            transactions
+                .iter()
+                .flat_map(|transaction| parse_refund_sources(&transaction.data)),
+        );
+
         let transactions = transactions
             .into_iter()
-            .filter_map(move |transaction| {
-                let data = parse_transaction_additional_info(&transaction.data, wallet_type);
+            .filter_map(|transaction| {
+                let data = parse_transaction_additional_info(&transaction.data, wallet_type)
+                    .or_else(|| parse_remaining_gas_refund(&transaction.data, &refund_sources));
                 let transaction =
                     Transaction::try_from((transaction.hash, transaction.data)).ok()?;
-                Some(TransactionWithData { transaction, data })
+                let labels = address_labeler
+                    .map(|labeler| label_counterparties(&transaction, labeler))
+                    .unwrap_or_default();
+                Some(TransactionWithData {
+                    transaction,
+                    data,
+                    labels,
+                })
             })
-            .collect();
+            .collect::<Vec<_>>();
+
+        if let Some(new_transactions) = new_transactions.as_deref_mut() {
+            if batch_info.batch_type == TransactionsBatchType::New {
+                new_transactions.extend(transactions.iter().map(|item| item.transaction.clone()));
+            }
+        }
+
+        refund_sources.sort_unstable_by(|a, b| b.lt.cmp(&a.lt));
+        refund_sources.truncate(MAX_REFUND_SOURCES);
 
         handler.on_transactions_found(transactions, batch_info)
     }
 }
 
+const MAX_REFUND_SOURCES: usize = 32;
+
 fn make_message_sent_handler(
     handler: &'_ dyn TonWalletSubscriptionHandler,
 ) -> impl FnMut(PendingTransaction, RawTransaction) + '_ {
@@ -833,17 +1207,25 @@ fn make_message_expired_handler(
     move |pending_transaction| handler.on_message_expired(pending_transaction)
 }
 
+/// Capabilities of the wallet contract
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TonWalletDetails {
+    /// Wallet must be deployed with a separate message before the first transfer
     pub requires_separate_deploy: bool,
+    /// Minimal amount of the outgoing transfer
     #[serde(with = "serde_string")]
     pub min_amount: u64,
+    /// Max number of outgoing messages in one transfer
     pub max_messages: usize,
+    /// Outgoing messages can have arbitrary body
     pub supports_payload: bool,
+    /// Outgoing messages can have state init
     pub supports_state_init: bool,
     pub supports_multiple_owners: bool,
     pub supports_code_update: bool,
+    /// Lifetime of the multisig transaction in seconds, zero for simple wallets
     pub expiration_time: u32,
+    /// Number of custodians confirmations required for transfer, if known
     pub required_confirmations: Option<NonZeroU8>,
 }
 
@@ -1025,3 +1407,136 @@ pub trait TonWalletSubscriptionHandler: Send + Sync {
         let _ = unconfirmed_updates;
     }
 }
+
+/// Forwards wallet events into the [`EventSink`]
+pub struct EventSinkHandler {
+    address: MsgAddressInt,
+    sink: Arc<dyn EventSink>,
+    last_balance: parking_lot::Mutex<Option<u64>>,
+}
+
+impl EventSinkHandler {
+    pub fn new(address: MsgAddressInt, sink: Arc<dyn EventSink>) -> Self {
+        Self {
+            address,
+            sink,
+            last_balance: Default::default(),
+        }
+    }
+
+    fn emit(&self, kind: SubscriptionEventKind) {
+        self.sink.on_event(SubscriptionEvent {
+            address: self.address.clone(),
+            kind,
+        });
+    }
+}
+
+impl TonWalletSubscriptionHandler for EventSinkHandler {
+    fn on_message_sent(
+        &self,
+        pending_transaction: PendingTransaction,
+        transaction: Option<Transaction>,
+    ) {
+        self.emit(SubscriptionEventKind::MessageSent {
+            pending_transaction,
+            transaction,
+        });
+    }
+
+    fn on_message_expired(&self, pending_transaction: PendingTransaction) {
+        self.emit(SubscriptionEventKind::MessageExpired(pending_transaction));
+    }
+
+    fn on_state_changed(&self, new_state: ContractState) {
+        let changed =
+            self.last_balance.lock().replace(new_state.balance) != Some(new_state.balance);
+        if changed {
+            self.emit(SubscriptionEventKind::BalanceChanged(new_state.balance));
+        }
+    }
+
+    fn on_transactions_found(
+        &self,
+        transactions: Vec<TransactionWithData<TransactionAdditionalInfo>>,
+        batch_info: TransactionsBatchInfo,
+    ) {
+        // NOTE: preloaded transactions are not forwarded
+        if batch_info.batch_type != TransactionsBatchType::New || transactions.is_empty() {
+            return;
+        }
+
+        self.emit(SubscriptionEventKind::NewTransactions(
+            transactions
+                .into_iter()
+                .map(|item| item.transaction)
+                .collect(),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SecretKey;
+
+    use super::*;
+    use crate::testing::ChainSimulator;
+
+    #[derive(Default)]
+    struct CustodiansHandler(parking_lot::Mutex<Option<Vec<UInt256>>>);
+
+    impl TonWalletSubscriptionHandler for CustodiansHandler {
+        fn on_message_sent(&self, _: PendingTransaction, _: Option<Transaction>) {}
+
+        fn on_message_expired(&self, _: PendingTransaction) {}
+
+        fn on_custodians_changed(&self, custodians: &[UInt256]) {
+            *self.0.lock() = Some(custodians.to_vec());
+        }
+    }
+
+    #[tokio::test]
+    async fn restore_from_snapshot() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let public_key = PublicKey::from(&SecretKey::from_bytes(&[1; 32]).unwrap());
+
+        let address = compute_address(&public_key, WalletType::WalletV3, 0);
+        sim.transport().set_account(
+            address.clone(),
+            ton_block::Account::with_address_and_ballance(
+                &address,
+                &ton_block::CurrencyCollection::with_grams(1_000_000_000),
+            ),
+        );
+
+        let wallet = TonWallet::subscribe(
+            sim.clock().clone(),
+            sim.transport().clone(),
+            0,
+            public_key,
+            WalletType::WalletV3,
+            Arc::new(CustodiansHandler::default()),
+        )
+        .await
+        .unwrap();
+
+        let handler = Arc::new(CustodiansHandler::default());
+        let restored = TonWallet::restore(
+            sim.clock().clone(),
+            sim.transport().clone(),
+            public_key,
+            WalletType::WalletV3,
+            &wallet.serialize_state(),
+            handler.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(restored.address(), wallet.address());
+        assert_eq!(restored.contract_state(), wallet.contract_state());
+        assert_eq!(
+            *handler.0.lock(),
+            Some(vec![UInt256::from(public_key.to_bytes())])
+        );
+    }
+}
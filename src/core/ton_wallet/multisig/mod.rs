@@ -1,8 +1,10 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 
 use anyhow::Result;
 use ed25519_dalek::PublicKey;
+use parking_lot::RwLock;
 use ton_block::{Deserializable, GetRepresentationHash, MsgAddressInt, Serializable};
 use ton_types::UInt256;
 
@@ -16,6 +18,9 @@ use crate::core::models::{
 use crate::core::utils::*;
 use crate::crypto::UnsignedMessage;
 
+pub mod offline;
+pub mod qr;
+
 #[derive(Copy, Clone, Debug)]
 pub struct DeployParams<'a> {
     pub owners: &'a [PublicKey],
@@ -31,6 +36,26 @@ impl<'a> DeployParams<'a> {
             expiration_time: None,
         }
     }
+
+    /// Checks custodians and required confirmations against the contract limits
+    pub fn validate(&self) -> Result<()> {
+        let custodian_count = self.owners.len();
+        if custodian_count == 0 || custodian_count > MultisigType::MAX_CUSTODIAN_COUNT {
+            return Err(MultisigError::InvalidCustodianCount(custodian_count).into());
+        }
+
+        for (i, owner) in self.owners.iter().enumerate() {
+            if self.owners[..i].contains(owner) {
+                return Err(MultisigError::DuplicateCustodian.into());
+            }
+        }
+
+        if self.req_confirms == 0 || self.req_confirms as usize > custodian_count {
+            return Err(MultisigError::InvalidReqConfirms(self.req_confirms).into());
+        }
+
+        Ok(())
+    }
 }
 
 pub fn prepare_deploy(
@@ -42,13 +67,49 @@ pub fn prepare_deploy(
     params: DeployParams<'_>,
 ) -> Result<Box<dyn UnsignedMessage>> {
     let state_init = prepare_state_init(public_key, multisig_type);
-    let hash = state_init.hash().trust_me();
+    prepare_deploy_with_state_init(
+        clock,
+        public_key,
+        multisig_type,
+        state_init,
+        workchain,
+        expiration,
+        params,
+    )
+}
 
-    let dst = MsgAddressInt::AddrStd(ton_block::MsgAddrStd {
-        anycast: None,
-        workchain_id: workchain,
-        address: hash.into(),
-    });
+/// Prepares deploy of the multisig fork, registered in [`CustomMultisigCodes`]
+pub fn prepare_custom_deploy(
+    clock: &dyn Clock,
+    public_key: &PublicKey,
+    custom: &CustomMultisigCode,
+    workchain: i8,
+    expiration: Expiration,
+    params: DeployParams<'_>,
+) -> Result<Box<dyn UnsignedMessage>> {
+    prepare_deploy_with_state_init(
+        clock,
+        public_key,
+        custom.compatible_with,
+        custom.state_init(public_key),
+        workchain,
+        expiration,
+        params,
+    )
+}
+
+fn prepare_deploy_with_state_init(
+    clock: &dyn Clock,
+    public_key: &PublicKey,
+    multisig_type: MultisigType,
+    state_init: ton_block::StateInit,
+    workchain: i8,
+    expiration: Expiration,
+    params: DeployParams<'_>,
+) -> Result<Box<dyn UnsignedMessage>> {
+    params.validate()?;
+
+    let dst = address_from_state_init(&state_init, workchain);
 
     let mut message =
         ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
@@ -122,54 +183,79 @@ pub fn prepare_transfer(
 ) -> Result<TransferAction> {
     let is_new_multisig = multisig_type.is_multisig2();
 
-    let (function, input) = if has_multiple_owners || is_new_multisig && gift.state_init.is_some() {
-        let all_balance = match MessageFlags::try_from(gift.flags) {
-            Ok(MessageFlags::Normal) => false,
-            Ok(MessageFlags::AllBalance) => true,
-            _ => return Err(MultisigError::UnsupportedFlagsSet.into()),
-        };
+    if has_multiple_owners || is_new_multisig && gift.state_init.is_some() {
+        return prepare_submit_transaction(
+            clock,
+            multisig_type,
+            public_key,
+            address,
+            gift,
+            expiration,
+        )
+        .map(TransferAction::Sign);
+    }
 
-        let function = if is_new_multisig {
-            nekoton_contracts::wallets::multisig2::submit_transaction()
-        } else {
-            nekoton_contracts::wallets::multisig::submit_transaction()
-        };
+    let function = if is_new_multisig {
+        nekoton_contracts::wallets::multisig2::send_transaction()
+    } else {
+        nekoton_contracts::wallets::multisig::send_transaction()
+    };
+    let (function, input) = MessageBuilder::new(function)
+        .arg(gift.destination)
+        .arg(BigUint128(gift.amount.into()))
+        .arg(gift.bounce)
+        .arg(gift.flags)
+        .arg(gift.body.unwrap_or_default().into_cell())
+        .build();
+
+    make_ext_message(clock, public_key, address, expiration, function, input)
+        .map(TransferAction::Sign)
+}
+
+/// Creates a new pending transaction, which must be confirmed by other custodians
+/// (see [`prepare_confirm_transaction`] and [`get_pending_transactions`])
+pub fn prepare_submit_transaction(
+    clock: &dyn Clock,
+    multisig_type: MultisigType,
+    public_key: &PublicKey,
+    address: MsgAddressInt,
+    gift: Gift,
+    expiration: Expiration,
+) -> Result<Box<dyn UnsignedMessage>> {
+    let is_new_multisig = multisig_type.is_multisig2();
 
-        let message = MessageBuilder::new(function)
-            .arg(gift.destination)
-            .arg(BigUint128(gift.amount.into()))
-            .arg(gift.bounce)
-            .arg(all_balance)
-            .arg(gift.body.unwrap_or_default().into_cell());
+    let all_balance = match MessageFlags::try_from(gift.flags) {
+        Ok(MessageFlags::Normal) => false,
+        Ok(MessageFlags::AllBalance) => true,
+        _ => return Err(MultisigError::UnsupportedFlagsSet.into()),
+    };
 
-        if is_new_multisig {
-            message
-                .arg(
-                    gift.state_init
-                        .map(|state_init| state_init.serialize())
-                        .transpose()?,
-                )
-                .build()
-        } else {
-            message.build()
-        }
+    let function = if is_new_multisig {
+        nekoton_contracts::wallets::multisig2::submit_transaction()
     } else {
-        let function = if is_new_multisig {
-            nekoton_contracts::wallets::multisig2::send_transaction()
-        } else {
-            nekoton_contracts::wallets::multisig::send_transaction()
-        };
-        MessageBuilder::new(function)
-            .arg(gift.destination)
-            .arg(BigUint128(gift.amount.into()))
-            .arg(gift.bounce)
-            .arg(gift.flags)
-            .arg(gift.body.unwrap_or_default().into_cell())
+        nekoton_contracts::wallets::multisig::submit_transaction()
+    };
+
+    let message = MessageBuilder::new(function)
+        .arg(gift.destination)
+        .arg(BigUint128(gift.amount.into()))
+        .arg(gift.bounce)
+        .arg(all_balance)
+        .arg(gift.body.unwrap_or_default().into_cell());
+
+    let (function, input) = if is_new_multisig {
+        message
+            .arg(
+                gift.state_init
+                    .map(|state_init| state_init.serialize())
+                    .transpose()?,
+            )
             .build()
+    } else {
+        message.build()
     };
 
     make_ext_message(clock, public_key, address, expiration, function, input)
-        .map(TransferAction::Sign)
 }
 
 pub fn prepare_code_update(
@@ -270,6 +356,11 @@ impl MultisigType {
         matches!(self, Self::Multisig2 | Self::Multisig2_1)
     }
 
+    /// Max number of custodians accepted by the constructor.
+    ///
+    /// NOTE: all known multisig contracts use the same `MAX_CUSTODIAN_COUNT`
+    pub const MAX_CUSTODIAN_COUNT: usize = 32;
+
     pub fn is_updatable(&self) -> bool {
         matches!(
             self,
@@ -364,19 +455,102 @@ pub fn guess_multisig_type(code_hash: &UInt256) -> Option<MultisigType> {
     }
 }
 
+/// Multisig fork, which has the same ABI as one of the known types
+#[derive(Clone, Debug)]
+pub struct CustomMultisigCode {
+    /// Known type with the same ABI. Its functions are used for all contract calls
+    pub compatible_with: MultisigType,
+    pub code: ton_types::Cell,
+    /// Initial data into which the public key is inserted to derive the address.
+    /// Initial data of the compatible type is used if not specified
+    pub init_data: Option<ton_types::Cell>,
+}
+
+impl CustomMultisigCode {
+    pub fn code_hash(&self) -> UInt256 {
+        self.code.repr_hash()
+    }
+
+    pub fn state_init(&self, public_key: &PublicKey) -> ton_block::StateInit {
+        let mut state_init = self.compatible_with.state_init();
+        state_init.set_code(self.code.clone());
+        if let Some(init_data) = &self.init_data {
+            state_init.set_data(init_data.clone());
+        }
+        insert_public_key(&mut state_init, public_key);
+        state_init
+    }
+
+    pub fn compute_address(&self, public_key: &PublicKey, workchain_id: i8) -> MsgAddressInt {
+        address_from_state_init(&self.state_init(public_key), workchain_id)
+    }
+}
+
+/// Registered multisig forks by code hash.
+///
+/// Forks are network specific, so each network owns its registry
+/// (see [`crate::core::network_manager::Network::custom_multisig_codes`])
+#[derive(Default)]
+pub struct CustomMultisigCodes {
+    codes: RwLock<HashMap<UInt256, CustomMultisigCode>>,
+}
+
+impl CustomMultisigCodes {
+    /// Registers the multisig fork. Returns its code hash.
+    ///
+    /// Wallets with the registered code hash are then recognized as `compatible_with`,
+    /// so the whole subscription and transfer pipeline can be used for them.
+    /// Deploy uses the registered code and initial data
+    pub fn register(&self, custom: CustomMultisigCode) -> UInt256 {
+        let code_hash = custom.code_hash();
+        self.codes.write().insert(code_hash, custom);
+        code_hash
+    }
+
+    pub fn unregister(&self, code_hash: &UInt256) -> Option<CustomMultisigCode> {
+        self.codes.write().remove(code_hash)
+    }
+
+    pub fn get(&self, code_hash: &UInt256) -> Option<CustomMultisigCode> {
+        self.codes.read().get(code_hash).cloned()
+    }
+
+    /// Same as [`guess_multisig_type`], but also recognizes the registered forks
+    pub fn guess_multisig_type(&self, code_hash: &UInt256) -> Option<MultisigType> {
+        guess_multisig_type(code_hash).or_else(|| {
+            self.codes
+                .read()
+                .get(code_hash)
+                .map(|custom| custom.compatible_with)
+        })
+    }
+
+    /// Finds the registered fork which is deployed at the specified address
+    pub fn find(
+        &self,
+        public_key: &PublicKey,
+        multisig_type: MultisigType,
+        address: &MsgAddressInt,
+    ) -> Option<CustomMultisigCode> {
+        let workchain_id = address.workchain_id() as i8;
+        self.codes
+            .read()
+            .values()
+            .find(|custom| {
+                custom.compatible_with == multisig_type
+                    && &custom.compute_address(public_key, workchain_id) == address
+            })
+            .cloned()
+    }
+}
+
 pub fn compute_contract_address(
     public_key: &PublicKey,
     multisig_type: MultisigType,
     workchain_id: i8,
 ) -> MsgAddressInt {
     let state_init = prepare_state_init(public_key, multisig_type);
-    let hash = state_init.hash().trust_me();
-
-    MsgAddressInt::AddrStd(ton_block::MsgAddrStd {
-        anycast: None,
-        workchain_id,
-        address: hash.into(),
-    })
+    address_from_state_init(&state_init, workchain_id)
 }
 
 pub fn ton_wallet_details(multisig_type: MultisigType) -> TonWalletDetails {
@@ -408,15 +582,26 @@ pub fn ton_wallet_details(multisig_type: MultisigType) -> TonWalletDetails {
 
 fn prepare_state_init(public_key: &PublicKey, multisig_type: MultisigType) -> ton_block::StateInit {
     let mut state_init = multisig_type.state_init();
+    insert_public_key(&mut state_init, public_key);
+    state_init
+}
 
+fn insert_public_key(state_init: &mut ton_block::StateInit, public_key: &PublicKey) {
     let new_data = ton_abi::Contract::insert_pubkey(
         ton_types::SliceData::load_cell(state_init.data.clone().unwrap_or_default()).trust_me(),
         public_key.as_bytes(),
     )
     .trust_me();
     state_init.set_data(new_data.into_cell());
+}
 
-    state_init
+fn address_from_state_init(state_init: &ton_block::StateInit, workchain_id: i8) -> MsgAddressInt {
+    let hash = state_init.hash().trust_me();
+    MsgAddressInt::AddrStd(ton_block::MsgAddrStd {
+        anycast: None,
+        workchain_id,
+        address: hash.into(),
+    })
 }
 
 fn run_local(
@@ -719,6 +904,12 @@ enum MultisigError {
     CustomExpirationTimeNotSupported,
     #[error("Update is not supported or not implemented for this contract type")]
     UnsupportedUpdate,
+    #[error("Invalid custodian count: {0}")]
+    InvalidCustodianCount(usize),
+    #[error("Duplicate custodian")]
+    DuplicateCustodian,
+    #[error("Invalid required confirmations count: {0}")]
+    InvalidReqConfirms(u8),
 }
 
 #[cfg(test)]
@@ -738,4 +929,77 @@ mod tests {
             "0:3de70f9212154344a3158768b3fed731fc865ca15948b0d6d0d34daf4c6a7a0a"
         );
     }
+
+    #[test]
+    fn validate_deploy_params() {
+        let first = PublicKey::from(&ed25519_dalek::SecretKey::from_bytes(&[1; 32]).unwrap());
+        let second = PublicKey::from(&ed25519_dalek::SecretKey::from_bytes(&[2; 32]).unwrap());
+        fn params(owners: &[PublicKey], req_confirms: u8) -> DeployParams<'_> {
+            DeployParams {
+                owners,
+                req_confirms,
+                expiration_time: None,
+            }
+        }
+
+        assert!(params(&[first, second], 2).validate().is_ok());
+        assert!(params(&[first, second], 3).validate().is_err());
+        assert!(params(&[first, second], 0).validate().is_err());
+        assert!(params(&[first, first], 1).validate().is_err());
+        assert!(params(&[], 1).validate().is_err());
+        assert!(params(&[first; 33], 1).validate().is_err());
+    }
+
+    #[test]
+    fn custom_code_deploy() {
+        let clock = ConstClock::from_secs(1650000000);
+        let key = PublicKey::from(&ed25519_dalek::SecretKey::from_bytes(&[1; 32]).unwrap());
+
+        let mut code = ton_types::BuilderData::new();
+        code.append_u32(0xdeadbeef).unwrap();
+        code.checked_append_reference(MultisigType::Multisig2.code())
+            .unwrap();
+        let custom = CustomMultisigCode {
+            compatible_with: MultisigType::Multisig2,
+            code: code.into_cell().unwrap(),
+            init_data: None,
+        };
+
+        let custom_codes = CustomMultisigCodes::default();
+        let code_hash = custom_codes.register(custom.clone());
+        assert_eq!(guess_multisig_type(&code_hash), None);
+        assert_eq!(
+            custom_codes.guess_multisig_type(&code_hash),
+            Some(MultisigType::Multisig2)
+        );
+
+        let address = custom.compute_address(&key, 0);
+        assert_ne!(
+            address,
+            compute_contract_address(&key, MultisigType::Multisig2, 0)
+        );
+        let found = custom_codes
+            .find(&key, MultisigType::Multisig2, &address)
+            .unwrap();
+        assert_eq!(found.code_hash(), code_hash);
+
+        let message = prepare_custom_deploy(
+            &clock,
+            &key,
+            &found,
+            0,
+            Expiration::Timeout(60),
+            DeployParams::single_custodian(&key),
+        )
+        .unwrap()
+        .sign(&[0; 64])
+        .unwrap()
+        .message;
+        assert_eq!(message.dst(), Some(address));
+        let state_init = message.state_init().unwrap();
+        assert_eq!(state_init.code.as_ref().unwrap().repr_hash(), code_hash);
+
+        assert!(custom_codes.unregister(&code_hash).is_some());
+        assert_eq!(custom_codes.guess_multisig_type(&code_hash), None);
+    }
 }
@@ -0,0 +1,436 @@
+use anyhow::Result;
+use ed25519_dalek::PublicKey;
+use serde::{Deserialize, Serialize};
+use ton_block::MsgAddressInt;
+use ton_types::{BuilderData, Cell, SliceData, UInt256};
+
+use nekoton_abi::*;
+use nekoton_utils::*;
+
+use super::MultisigType;
+use crate::core::models::{Expiration, ExpireAt};
+use crate::core::utils::default_headers;
+use crate::crypto::{verify_signature, Signature, SignatureId, SignedMessage};
+
+/// Confirmation of the multisig transaction, prepared for the custodians
+/// which sign messages offline (e.g. on a separate device).
+///
+/// Bundle is serializable, so it can be passed between custodians as is.
+/// Each custodian adds its signature, and the resulting bundle is assembled
+/// into the final `confirmTransaction` messages.
+///
+/// Payloads of the deserialized bundle are rebuilt and compared with the
+/// received ones, so the bundle can't be used to sign anything else.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", try_from = "UncheckedSignatureBundle")]
+pub struct SignatureBundle {
+    pub multisig_type: MultisigType,
+    #[serde(with = "serde_address")]
+    pub address: MsgAddressInt,
+    #[serde(with = "serde_u64")]
+    pub transaction_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature_id: Option<SignatureId>,
+    pub entries: Vec<SignatureBundleEntry>,
+}
+
+impl SignatureBundle {
+    /// Prepares unsigned confirmation payloads for the specified custodians
+    pub fn new(
+        clock: &dyn Clock,
+        multisig_type: MultisigType,
+        address: MsgAddressInt,
+        transaction_id: u64,
+        custodians: &[PublicKey],
+        expiration: Expiration,
+        signature_id: Option<SignatureId>,
+    ) -> Result<Self> {
+        let time = clock.now_ms_u64();
+        let expire_at = ExpireAt::new_from_millis(expiration, time).timestamp;
+
+        let entries = custodians
+            .iter()
+            .map(|public_key| {
+                let (payload, hash) = build_confirmation(
+                    multisig_type,
+                    &address,
+                    transaction_id,
+                    public_key,
+                    time,
+                    expire_at,
+                )?;
+
+                Ok(SignatureBundleEntry {
+                    public_key: *public_key,
+                    time,
+                    expire_at,
+                    hash,
+                    payload,
+                    signature: None,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            multisig_type,
+            address,
+            transaction_id,
+            signature_id,
+            entries,
+        })
+    }
+
+    pub fn entry(&self, public_key: &PublicKey) -> Option<&SignatureBundleEntry> {
+        self.entries
+            .iter()
+            .find(|entry| &entry.public_key == public_key)
+    }
+
+    /// Verifies and stores the signature of the custodian
+    pub fn add_signature(&mut self, public_key: &PublicKey, signature: Signature) -> Result<()> {
+        let signature_id = self.signature_id;
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| &entry.public_key == public_key)
+            .ok_or(SignatureBundleError::CustodianNotFound)?;
+
+        entry.check_payload(self.multisig_type, &self.address, self.transaction_id)?;
+        if !verify_signature(public_key, entry.hash.as_slice(), &signature, signature_id) {
+            return Err(SignatureBundleError::InvalidSignature.into());
+        }
+
+        entry.signature = Some(signature);
+        Ok(())
+    }
+
+    /// Copies signatures from the bundle, received from another custodian.
+    ///
+    /// Each signature is verified, and the other bundle must not contain unknown custodians
+    pub fn merge(&mut self, other: SignatureBundle) -> Result<()> {
+        if self.address != other.address
+            || self.transaction_id != other.transaction_id
+            || self.multisig_type != other.multisig_type
+            || self.signature_id != other.signature_id
+        {
+            return Err(SignatureBundleError::BundleMismatch.into());
+        }
+
+        // NOTE: all entries are verified first, so the bundle is not changed on error
+        let mut signatures = Vec::with_capacity(other.entries.len());
+        for other in other.entries {
+            let (index, entry) = self
+                .entries
+                .iter()
+                .enumerate()
+                .find(|(_, entry)| entry.public_key == other.public_key)
+                .ok_or(SignatureBundleError::CustodianNotFound)?;
+
+            if entry.hash != other.hash || entry.payload.repr_hash() != other.payload.repr_hash() {
+                return Err(SignatureBundleError::BundleMismatch.into());
+            }
+            entry.check_payload(self.multisig_type, &self.address, self.transaction_id)?;
+
+            let signature = match other.signature {
+                Some(signature) if entry.signature.is_none() => signature,
+                _ => continue,
+            };
+            if !verify_signature(
+                &entry.public_key,
+                entry.hash.as_slice(),
+                &signature,
+                self.signature_id,
+            ) {
+                return Err(SignatureBundleError::InvalidSignature.into());
+            }
+
+            signatures.push((index, signature));
+        }
+
+        for (index, signature) in signatures {
+            self.entries[index].signature = Some(signature);
+        }
+
+        Ok(())
+    }
+
+    /// Checks that all payloads confirm the transaction of the bundle
+    pub fn check_payloads(&self) -> Result<()> {
+        self.entries.iter().try_for_each(|entry| {
+            entry.check_payload(self.multisig_type, &self.address, self.transaction_id)
+        })
+    }
+
+    pub fn signature_count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.signature.is_some())
+            .count()
+    }
+
+    /// Builds confirmation messages for all signed entries
+    pub fn assemble(&self) -> Result<Vec<SignedMessage>> {
+        let abi_version = confirm_transaction(self.multisig_type).abi_version;
+
+        self.entries
+            .iter()
+            .filter_map(|entry| {
+                let signature = entry.signature.as_ref()?;
+                Some(entry.assemble(&self.address, abi_version, signature))
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureBundleEntry {
+    #[serde(with = "serde_public_key")]
+    pub public_key: PublicKey,
+    /// Payload creation time in milliseconds (`time` header)
+    #[serde(with = "serde_u64")]
+    pub time: u64,
+    pub expire_at: u32,
+    /// Hash of the unsigned payload which must be signed
+    #[serde(with = "serde_uint256")]
+    pub hash: UInt256,
+    /// Unsigned message body
+    #[serde(with = "serde_cell")]
+    pub payload: Cell,
+    #[serde(
+        with = "serde_optional_hex_array",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub signature: Option<Signature>,
+}
+
+impl SignatureBundleEntry {
+    /// Rebuilds the payload from the entry fields and compares it with the stored one
+    fn check_payload(
+        &self,
+        multisig_type: MultisigType,
+        address: &MsgAddressInt,
+        transaction_id: u64,
+    ) -> Result<()> {
+        let (payload, hash) = build_confirmation(
+            multisig_type,
+            address,
+            transaction_id,
+            &self.public_key,
+            self.time,
+            self.expire_at,
+        )?;
+        if hash != self.hash || payload.repr_hash() != self.payload.repr_hash() {
+            return Err(SignatureBundleError::PayloadMismatch.into());
+        }
+        Ok(())
+    }
+
+    fn assemble(
+        &self,
+        address: &MsgAddressInt,
+        abi_version: ton_abi::contract::AbiVersion,
+        signature: &Signature,
+    ) -> Result<SignedMessage> {
+        let payload = ton_abi::Function::fill_sign(
+            &abi_version,
+            Some(signature),
+            None,
+            BuilderData::from(self.payload.clone()),
+        )
+        .and_then(SliceData::load_builder)?;
+
+        let mut message =
+            ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+                dst: address.clone(),
+                ..Default::default()
+            });
+        message.set_body(payload);
+
+        Ok(SignedMessage {
+            message,
+            expire_at: self.expire_at,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UncheckedSignatureBundle {
+    multisig_type: MultisigType,
+    #[serde(with = "serde_address")]
+    address: MsgAddressInt,
+    #[serde(with = "serde_u64")]
+    transaction_id: u64,
+    #[serde(default)]
+    signature_id: Option<SignatureId>,
+    entries: Vec<SignatureBundleEntry>,
+}
+
+impl TryFrom<UncheckedSignatureBundle> for SignatureBundle {
+    type Error = anyhow::Error;
+
+    fn try_from(bundle: UncheckedSignatureBundle) -> Result<Self, Self::Error> {
+        let bundle = Self {
+            multisig_type: bundle.multisig_type,
+            address: bundle.address,
+            transaction_id: bundle.transaction_id,
+            signature_id: bundle.signature_id,
+            entries: bundle.entries,
+        };
+        bundle.check_payloads()?;
+        Ok(bundle)
+    }
+}
+
+/// Builds the unsigned `confirmTransaction` payload and its hash
+pub(super) fn build_confirmation(
+    multisig_type: MultisigType,
+    address: &MsgAddressInt,
+    transaction_id: u64,
+    public_key: &PublicKey,
+    time: u64,
+    expire_at: u32,
+) -> Result<(Cell, UInt256)> {
+    let (function, input) = MessageBuilder::new(confirm_transaction(multisig_type))
+        .arg(transaction_id)
+        .build();
+
+    let (_, header) = default_headers(time, Expiration::Timestamp(expire_at), public_key);
+    let (payload, hash) =
+        function.create_unsigned_call(&header, &input, false, true, Some(address.clone()))?;
+    Ok((payload.into_cell()?, hash))
+}
+
+fn confirm_transaction(multisig_type: MultisigType) -> &'static ton_abi::Function {
+    if multisig_type.is_multisig2() {
+        nekoton_contracts::wallets::multisig2::confirm_transaction()
+    } else {
+        nekoton_contracts::wallets::multisig::confirm_transaction()
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+enum SignatureBundleError {
+    #[error("Custodian not found in bundle")]
+    CustodianNotFound,
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("Bundles are for different transactions")]
+    BundleMismatch,
+    #[error("Payload doesn't match the transaction")]
+    PayloadMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+
+    use super::*;
+
+    fn keypair(byte: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[byte; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    #[test]
+    fn co_sign_and_assemble() {
+        let clock = ConstClock::from_secs(1650000000);
+        let first = keypair(1);
+        let second = keypair(2);
+
+        let address = MsgAddressInt::default();
+        let mut bundle = SignatureBundle::new(
+            &clock,
+            MultisigType::Multisig2,
+            address,
+            123,
+            &[first.public, second.public],
+            Expiration::Timeout(60),
+            None,
+        )
+        .unwrap();
+
+        let data = serde_json::to_string(&bundle).unwrap();
+        let mut other = serde_json::from_str::<SignatureBundle>(&data).unwrap();
+
+        let hash = bundle.entry(&first.public).unwrap().hash;
+        let signature = first.sign(hash.as_slice()).to_bytes();
+        bundle.add_signature(&first.public, signature).unwrap();
+        assert!(bundle.add_signature(&second.public, signature).is_err());
+
+        let hash = other.entry(&second.public).unwrap().hash;
+        let signature = second.sign(hash.as_slice()).to_bytes();
+        other.add_signature(&second.public, signature).unwrap();
+
+        // Forged signature is rejected
+        let mut forged = other.clone();
+        forged.entries[1].signature = Some(first.sign(hash.as_slice()).to_bytes());
+        assert!(bundle.clone().merge(forged).is_err());
+
+        // Unknown custodian is rejected
+        let mut unknown = other.clone();
+        unknown.entries[1].public_key = keypair(3).public;
+        assert!(bundle.clone().merge(unknown).is_err());
+
+        bundle.merge(other).unwrap();
+        assert_eq!(bundle.signature_count(), 2);
+
+        let messages = bundle.assemble().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages
+            .iter()
+            .all(|message| message.expire_at == 1650000060));
+    }
+
+    #[test]
+    fn tampered_payload_is_rejected() {
+        let clock = ConstClock::from_secs(1650000000);
+        let custodian = keypair(1);
+
+        let bundle = SignatureBundle::new(
+            &clock,
+            MultisigType::Multisig2,
+            MsgAddressInt::default(),
+            123,
+            &[custodian.public],
+            Expiration::Timeout(60),
+            None,
+        )
+        .unwrap();
+
+        // Payload and hash of the confirmation of another transaction
+        let other = SignatureBundle::new(
+            &clock,
+            MultisigType::Multisig2,
+            MsgAddressInt::default(),
+            456,
+            &[custodian.public],
+            Expiration::Timeout(60),
+            None,
+        )
+        .unwrap();
+
+        let mut tampered = bundle.clone();
+        tampered.entries[0].payload = other.entries[0].payload.clone();
+        tampered.entries[0].hash = other.entries[0].hash;
+
+        let data = serde_json::to_string(&tampered).unwrap();
+        assert!(serde_json::from_str::<SignatureBundle>(&data).is_err());
+        let data = serde_json::to_string(&bundle).unwrap();
+        serde_json::from_str::<SignatureBundle>(&data).unwrap();
+
+        let signature = custodian.sign(other.entries[0].hash.as_slice()).to_bytes();
+        assert!(tampered
+            .add_signature(&custodian.public, signature)
+            .is_err());
+
+        tampered.entries[0].signature = Some(signature);
+        let mut target = tampered.clone();
+        target.entries[0].signature = None;
+        assert!(target.merge(tampered).is_err());
+        assert_eq!(target.signature_count(), 0);
+    }
+}
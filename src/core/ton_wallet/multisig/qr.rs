@@ -0,0 +1,327 @@
+//! Compact binary codec for the offline confirmation of multisig transactions.
+//!
+//! The online device encodes a [`SigningRequest`] for each custodian, the co-signer
+//! device decodes it, signs the hash and encodes a [`SigningResponse`] back.
+//! Both blobs are small enough to be shown as a single QR code.
+
+use anyhow::Result;
+use ed25519_dalek::PublicKey;
+use ton_block::{MsgAddrStd, MsgAddressInt};
+use ton_types::{Cell, SliceData, UInt256};
+
+use nekoton_utils::*;
+
+use super::offline::{build_confirmation, SignatureBundle, SignatureBundleEntry};
+use super::MultisigType;
+use crate::crypto::{Signature, SignatureId};
+
+const REQUEST_TAG: u8 = 0x01;
+const RESPONSE_TAG: u8 = 0x02;
+
+/// Unsigned `confirmTransaction` payload of a single custodian
+#[derive(Clone, Debug)]
+pub struct SigningRequest {
+    pub multisig_type: MultisigType,
+    pub address: MsgAddressInt,
+    pub transaction_id: u64,
+    pub signature_id: Option<SignatureId>,
+    pub public_key: PublicKey,
+    /// Payload creation time in milliseconds (`time` header)
+    pub time: u64,
+    pub expire_at: u32,
+    /// Hash of the unsigned payload which must be signed
+    pub hash: UInt256,
+    /// Unsigned message body
+    pub payload: Cell,
+}
+
+impl SigningRequest {
+    /// Extracts the request for the specified custodian from the bundle
+    pub fn from_bundle(bundle: &SignatureBundle, public_key: &PublicKey) -> Option<Self> {
+        let entry = bundle.entry(public_key)?;
+        Some(Self {
+            multisig_type: bundle.multisig_type,
+            address: bundle.address.clone(),
+            transaction_id: bundle.transaction_id,
+            signature_id: bundle.signature_id,
+            public_key: entry.public_key,
+            time: entry.time,
+            expire_at: entry.expire_at,
+            hash: entry.hash,
+            payload: entry.payload.clone(),
+        })
+    }
+
+    /// Creates a bundle with a single entry, which can be merged into the original bundle
+    pub fn into_bundle(self) -> SignatureBundle {
+        SignatureBundle {
+            multisig_type: self.multisig_type,
+            address: self.address,
+            transaction_id: self.transaction_id,
+            signature_id: self.signature_id,
+            entries: vec![SignatureBundleEntry {
+                public_key: self.public_key,
+                time: self.time,
+                expire_at: self.expire_at,
+                hash: self.hash,
+                payload: self.payload,
+                signature: None,
+            }],
+        }
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        check_std_address(&self.address)?;
+        let payload = ton_types::serialize_toc(&self.payload)?;
+
+        let mut result = Vec::with_capacity(128 + payload.len());
+        result.push(REQUEST_TAG);
+        result.push(encode_multisig_type(self.multisig_type));
+        result.push(self.address.workchain_id() as u8);
+        result.extend_from_slice(&self.address.address().get_bytestring(0));
+        result.extend_from_slice(&self.transaction_id.to_be_bytes());
+        match self.signature_id {
+            Some(signature_id) => {
+                result.push(1);
+                result.extend_from_slice(&signature_id.to_be_bytes());
+            }
+            None => result.push(0),
+        }
+        result.extend_from_slice(self.public_key.as_bytes());
+        result.extend_from_slice(&self.time.to_be_bytes());
+        result.extend_from_slice(&self.expire_at.to_be_bytes());
+        result.extend_from_slice(self.hash.as_slice());
+        result.extend_from_slice(&payload);
+        Ok(result)
+    }
+
+    /// Decodes the request and checks that the payload confirms
+    /// the specified transaction of the specified wallet
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader(data);
+        if reader.read_u8()? != REQUEST_TAG {
+            return Err(QrCodecError::UnexpectedTag.into());
+        }
+
+        let multisig_type = decode_multisig_type(reader.read_u8()?)?;
+        let workchain = reader.read_u8()? as i8;
+        let account_id = SliceData::from_raw(reader.read_array::<32>()?.to_vec(), 256);
+        let address = MsgAddressInt::AddrStd(MsgAddrStd::with_address(None, workchain, account_id));
+        let transaction_id = u64::from_be_bytes(reader.read_array()?);
+        let signature_id = match reader.read_u8()? {
+            0 => None,
+            1 => Some(i32::from_be_bytes(reader.read_array()?)),
+            _ => return Err(QrCodecError::InvalidData.into()),
+        };
+        let public_key = PublicKey::from_bytes(&reader.read_array::<32>()?)
+            .map_err(|_| QrCodecError::InvalidData)?;
+        let time = u64::from_be_bytes(reader.read_array()?);
+        let expire_at = u32::from_be_bytes(reader.read_array()?);
+        let hash = UInt256::from(reader.read_array::<32>()?);
+        let payload = ton_types::deserialize_tree_of_cells(&mut reader.0)?;
+
+        // NOTE: the hash is signed blindly on the co-signer device,
+        // so it must not be trusted without rebuilding the payload
+        let (expected_payload, expected_hash) = build_confirmation(
+            multisig_type,
+            &address,
+            transaction_id,
+            &public_key,
+            time,
+            expire_at,
+        )?;
+        if expected_hash != hash || expected_payload.repr_hash() != payload.repr_hash() {
+            return Err(QrCodecError::HashMismatch.into());
+        }
+
+        Ok(Self {
+            multisig_type,
+            address,
+            transaction_id,
+            signature_id,
+            public_key,
+            time,
+            expire_at,
+            hash,
+            payload,
+        })
+    }
+}
+
+/// Signature of the custodian for the [`SigningRequest`]
+#[derive(Clone, Debug)]
+pub struct SigningResponse {
+    pub transaction_id: u64,
+    pub public_key: PublicKey,
+    pub signature: Signature,
+}
+
+impl SigningResponse {
+    /// Verifies the signature and stores it into the bundle
+    pub fn apply(&self, bundle: &mut SignatureBundle) -> Result<()> {
+        if bundle.transaction_id != self.transaction_id {
+            return Err(QrCodecError::TransactionMismatch.into());
+        }
+        bundle.add_signature(&self.public_key, self.signature)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(1 + 8 + 32 + 64);
+        result.push(RESPONSE_TAG);
+        result.extend_from_slice(&self.transaction_id.to_be_bytes());
+        result.extend_from_slice(self.public_key.as_bytes());
+        result.extend_from_slice(&self.signature);
+        result
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader(data);
+        if reader.read_u8()? != RESPONSE_TAG {
+            return Err(QrCodecError::UnexpectedTag.into());
+        }
+
+        let transaction_id = u64::from_be_bytes(reader.read_array()?);
+        let public_key = PublicKey::from_bytes(&reader.read_array::<32>()?)
+            .map_err(|_| QrCodecError::InvalidData)?;
+        let signature = reader.read_array()?;
+        if !reader.0.is_empty() {
+            return Err(QrCodecError::InvalidData.into());
+        }
+
+        Ok(Self {
+            transaction_id,
+            public_key,
+            signature,
+        })
+    }
+}
+
+fn encode_multisig_type(multisig_type: MultisigType) -> u8 {
+    match multisig_type {
+        MultisigType::SafeMultisigWallet => 0,
+        MultisigType::SafeMultisigWallet24h => 1,
+        MultisigType::SetcodeMultisigWallet => 2,
+        MultisigType::SetcodeMultisigWallet24h => 3,
+        MultisigType::BridgeMultisigWallet => 4,
+        MultisigType::SurfWallet => 5,
+        MultisigType::Multisig2 => 6,
+        MultisigType::Multisig2_1 => 7,
+    }
+}
+
+fn decode_multisig_type(value: u8) -> Result<MultisigType, QrCodecError> {
+    Ok(match value {
+        0 => MultisigType::SafeMultisigWallet,
+        1 => MultisigType::SafeMultisigWallet24h,
+        2 => MultisigType::SetcodeMultisigWallet,
+        3 => MultisigType::SetcodeMultisigWallet24h,
+        4 => MultisigType::BridgeMultisigWallet,
+        5 => MultisigType::SurfWallet,
+        6 => MultisigType::Multisig2,
+        7 => MultisigType::Multisig2_1,
+        _ => return Err(QrCodecError::InvalidData),
+    })
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn read_u8(&mut self) -> Result<u8, QrCodecError> {
+        let [value] = self.read_array::<1>()?;
+        Ok(value)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], QrCodecError> {
+        if self.0.len() < N {
+            return Err(QrCodecError::UnexpectedEof);
+        }
+        let (head, tail) = self.0.split_at(N);
+        self.0 = tail;
+
+        let mut result = [0; N];
+        result.copy_from_slice(head);
+        Ok(result)
+    }
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone)]
+pub enum QrCodecError {
+    #[error("Unexpected tag")]
+    UnexpectedTag,
+    #[error("Unexpected end of data")]
+    UnexpectedEof,
+    #[error("Invalid data")]
+    InvalidData,
+    #[error("Signature is for another transaction")]
+    TransactionMismatch,
+    #[error("Payload doesn't match the transaction")]
+    HashMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+
+    use super::*;
+    use crate::core::models::Expiration;
+    use crate::crypto::extend_with_signature_id;
+
+    #[test]
+    fn request_response_roundtrip() {
+        let clock = ConstClock::from_secs(1650000000);
+        let secret = SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        let keypair = Keypair { secret, public };
+
+        let address = MsgAddressInt::from_str(
+            "0:2e8f6c9b5f2ee5b1e0a0d9b2b8a8a3b1a5e7d6f5c4b3a2918070605040302010",
+        )
+        .unwrap();
+        let mut bundle = SignatureBundle::new(
+            &clock,
+            MultisigType::Multisig2,
+            address,
+            123,
+            &[public],
+            Expiration::Timeout(60),
+            Some(42),
+        )
+        .unwrap();
+
+        let request = SigningRequest::from_bundle(&bundle, &public).unwrap();
+        let data = request.encode().unwrap();
+
+        // Request for another transaction or with a substituted hash is rejected
+        let mut tampered = request.clone();
+        tampered.transaction_id = 321;
+        assert!(SigningRequest::decode(&tampered.encode().unwrap()).is_err());
+
+        let mut tampered = request.clone();
+        tampered.hash = UInt256::default();
+        assert!(SigningRequest::decode(&tampered.encode().unwrap()).is_err());
+
+        let decoded = SigningRequest::decode(&data).unwrap();
+        assert_eq!(decoded.address, bundle.address);
+        assert_eq!(decoded.transaction_id, 123);
+        assert_eq!(decoded.signature_id, Some(42));
+        assert_eq!(decoded.hash, request.hash);
+        assert_eq!(decoded.payload, request.payload);
+
+        let data_to_sign = extend_with_signature_id(decoded.hash.as_slice(), decoded.signature_id);
+        let response = SigningResponse {
+            transaction_id: decoded.transaction_id,
+            public_key: decoded.public_key,
+            signature: keypair.sign(&data_to_sign).to_bytes(),
+        };
+        let data = response.encode();
+        assert!(SigningRequest::decode(&data).is_err());
+
+        SigningResponse::decode(&data)
+            .unwrap()
+            .apply(&mut bundle)
+            .unwrap();
+        assert_eq!(bundle.signature_count(), 1);
+    }
+}
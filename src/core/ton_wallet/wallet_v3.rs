@@ -17,8 +17,37 @@ pub fn prepare_deploy(
     workchain: i8,
     expiration: Expiration,
 ) -> Result<Box<dyn UnsignedMessage>> {
-    let init_data = InitData::from_key(public_key).with_wallet_id(WALLET_ID);
-    let dst = compute_contract_address(public_key, workchain);
+    prepare_deploy_with_transfer(clock, public_key, workchain, Vec::new(), expiration)
+}
+
+/// Prepares deploy message which also sends the specified gifts,
+/// so that a fresh wallet can be deployed and used in one step
+pub fn prepare_deploy_with_transfer(
+    clock: &dyn Clock,
+    public_key: &PublicKey,
+    workchain: i8,
+    gifts: Vec<Gift>,
+    expiration: Expiration,
+) -> Result<Box<dyn UnsignedMessage>> {
+    prepare_deploy_with_wallet_id(clock, public_key, WALLET_ID, workchain, gifts, expiration)
+}
+
+/// Same as [`prepare_deploy_with_transfer`], but for the wallet with non-default wallet id
+/// (see [`compute_contract_address_with_wallet_id`])
+pub fn prepare_deploy_with_wallet_id(
+    clock: &dyn Clock,
+    public_key: &PublicKey,
+    wallet_id: u32,
+    workchain: i8,
+    gifts: Vec<Gift>,
+    expiration: Expiration,
+) -> Result<Box<dyn UnsignedMessage>> {
+    if gifts.len() > MAX_MESSAGES {
+        return Err(WalletV3Error::TooManyGifts.into());
+    }
+
+    let init_data = InitData::from_key(public_key).with_wallet_id(wallet_id);
+    let dst = init_data.compute_addr(workchain)?;
     let mut message =
         ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
             dst,
@@ -28,11 +57,11 @@ pub fn prepare_deploy(
     message.set_state_init(init_data.make_state_init()?);
 
     let expire_at = ExpireAt::new(clock, expiration);
-    let (hash, payload) = init_data.make_transfer_payload(None, expire_at.timestamp)?;
+    let (hash, payload) = init_data.make_transfer_payload(gifts.clone(), expire_at.timestamp)?;
 
     Ok(Box::new(UnsignedWalletV3Message {
         init_data,
-        gifts: Vec::new(),
+        gifts,
         payload,
         message,
         expire_at,
@@ -91,6 +120,29 @@ pub fn prepare_transfer(
     seqno_offset: u32,
     gifts: Vec<Gift>,
     expiration: Expiration,
+) -> Result<TransferAction> {
+    prepare_transfer_with_wallet_id(
+        clock,
+        public_key,
+        WALLET_ID,
+        current_state,
+        seqno_offset,
+        gifts,
+        expiration,
+    )
+}
+
+/// Same as [`prepare_transfer`], but for the wallet with non-default wallet id.
+///
+/// Wallet id is only used to deploy the wallet, deployed wallets use the id from their data
+pub fn prepare_transfer_with_wallet_id(
+    clock: &dyn Clock,
+    public_key: &PublicKey,
+    wallet_id: u32,
+    current_state: &ton_block::AccountStuff,
+    seqno_offset: u32,
+    gifts: Vec<Gift>,
+    expiration: Expiration,
 ) -> Result<TransferAction> {
     if gifts.len() > MAX_MESSAGES {
         return Err(WalletV3Error::TooManyGifts.into());
@@ -104,10 +156,16 @@ pub fn prepare_transfer(
         ton_block::AccountState::AccountFrozen { .. } => {
             return Err(WalletV3Error::AccountIsFrozen.into())
         }
-        ton_block::AccountState::AccountUninit => (
-            InitData::from_key(public_key).with_wallet_id(WALLET_ID),
-            true,
-        ),
+        ton_block::AccountState::AccountUninit => {
+            let init_data = InitData::from_key(public_key).with_wallet_id(wallet_id);
+            // NOTE: wallet would be deployed at the different address
+            if init_data.compute_addr(current_state.addr.workchain_id() as i8)?
+                != current_state.addr
+            {
+                return Err(WalletV3Error::AddressMismatch.into());
+            }
+            (init_data, true)
+        }
     };
 
     init_data.seqno += seqno_offset;
@@ -215,6 +273,31 @@ pub fn compute_contract_address(public_key: &PublicKey, workchain_id: i8) -> Msg
         .trust_me()
 }
 
+/// Computes address of the wallet with non-default wallet id,
+/// e.g. created by other applications
+pub fn compute_contract_address_with_wallet_id(
+    public_key: &PublicKey,
+    wallet_id: u32,
+    workchain_id: i8,
+) -> MsgAddressInt {
+    InitData::from_key(public_key)
+        .with_wallet_id(wallet_id)
+        .compute_addr(workchain_id)
+        .trust_me()
+}
+
+/// Returns seqno of the next transfer. Zero for not deployed wallets
+pub fn get_seqno(current_state: &ton_block::AccountStuff) -> Result<u32> {
+    match &current_state.storage.state {
+        ton_block::AccountState::AccountActive { state_init, .. } => match &state_init.data {
+            Some(data) => Ok(InitData::try_from(data)?.seqno),
+            None => Err(WalletV3Error::InvalidInitData.into()),
+        },
+        ton_block::AccountState::AccountFrozen { .. } => Err(WalletV3Error::AccountIsFrozen.into()),
+        ton_block::AccountState::AccountUninit => Ok(0),
+    }
+}
+
 pub static DETAILS: TonWalletDetails = TonWalletDetails {
     requires_separate_deploy: false,
     min_amount: 1, // 0.000000001 TON
@@ -348,4 +431,77 @@ enum WalletV3Error {
     AccountIsFrozen,
     #[error("Too many outgoing messages")]
     TooManyGifts,
+    #[error("Wallet address doesn't match the public key and wallet id")]
+    AddressMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uninit_account(address: &MsgAddressInt) -> ton_block::AccountStuff {
+        match ton_block::Account::with_address_and_ballance(
+            address,
+            &ton_block::CurrencyCollection::with_grams(1_000_000_000),
+        ) {
+            ton_block::Account::Account(account) => account,
+            ton_block::Account::AccountNone => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn custom_wallet_id() {
+        const CUSTOM_WALLET_ID: u32 = 698983191;
+
+        let clock = ConstClock::from_secs(1650000000);
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public_key = PublicKey::from(&secret);
+
+        let address = compute_contract_address_with_wallet_id(&public_key, CUSTOM_WALLET_ID, 0);
+        assert_ne!(address, compute_contract_address(&public_key, 0));
+
+        // Deploy message is sent to the wallet with the custom id
+        let message = prepare_deploy_with_wallet_id(
+            &clock,
+            &public_key,
+            CUSTOM_WALLET_ID,
+            0,
+            Vec::new(),
+            Expiration::Timeout(60),
+        )
+        .unwrap();
+        let message = message.sign(&[0; 64]).unwrap().message;
+        assert_eq!(message.dst().as_ref(), Some(&address));
+
+        // Transfer from the not deployed wallet requires the matching wallet id
+        let current_state = uninit_account(&address);
+        let result = prepare_transfer(
+            &clock,
+            &public_key,
+            &current_state,
+            0,
+            Vec::new(),
+            Expiration::Timeout(60),
+        );
+        assert!(result.is_err());
+
+        let action = prepare_transfer_with_wallet_id(
+            &clock,
+            &public_key,
+            CUSTOM_WALLET_ID,
+            &current_state,
+            0,
+            Vec::new(),
+            Expiration::Timeout(60),
+        )
+        .unwrap();
+        let message = match action {
+            TransferAction::Sign(message) => message.sign(&[0; 64]).unwrap().message,
+            TransferAction::DeployFirst => panic!("wallet v3 is deployed with the transfer"),
+        };
+        assert_eq!(message.dst().as_ref(), Some(&address));
+        let state_init = message.state_init().unwrap();
+        let init_data = InitData::try_from(state_init.data.as_ref().unwrap()).unwrap();
+        assert_eq!(init_data.wallet_id, CUSTOM_WALLET_ID);
+    }
 }
@@ -0,0 +1,281 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures_util::stream::{self, Stream};
+use ton_block::MsgAddressInt;
+
+use crate::models::TransactionsCursor;
+use crate::transport::models::RawTransaction;
+use crate::transport::Transport;
+
+/// Walks the account history backwards, page by page (e.g. for the infinite scroll).
+///
+/// The position can be saved with [`TransactionsHistory::position`] and
+/// restored with [`TransactionsHistory::from_cursor`]
+pub struct TransactionsHistory {
+    transport: Arc<dyn Transport>,
+    address: MsgAddressInt,
+    page_size: u8,
+    position: HistoryPosition,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum HistoryPosition {
+    /// Next page starts from the latest transaction
+    Latest,
+    /// Next page starts from the transaction at the cursor (inclusive)
+    At(TransactionsCursor),
+    /// There are no older transactions
+    Finished,
+}
+
+impl TransactionsHistory {
+    /// Starts from the latest transaction
+    pub fn new(transport: Arc<dyn Transport>, address: MsgAddressInt, page_size: u8) -> Self {
+        Self::with_position(transport, address, page_size, HistoryPosition::Latest)
+    }
+
+    /// Continues from the previously saved cursor
+    pub fn from_cursor(
+        transport: Arc<dyn Transport>,
+        address: MsgAddressInt,
+        page_size: u8,
+        cursor: TransactionsCursor,
+    ) -> Self {
+        Self::with_position(transport, address, page_size, HistoryPosition::At(cursor))
+    }
+
+    fn with_position(
+        transport: Arc<dyn Transport>,
+        address: MsgAddressInt,
+        page_size: u8,
+        position: HistoryPosition,
+    ) -> Self {
+        let page_size = page_size.clamp(1, transport.info().max_transactions_per_fetch.max(1));
+
+        Self {
+            transport,
+            address,
+            page_size,
+            position,
+        }
+    }
+
+    pub fn address(&self) -> &MsgAddressInt {
+        &self.address
+    }
+
+    pub fn position(&self) -> HistoryPosition {
+        self.position
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position == HistoryPosition::Finished
+    }
+
+    /// Fetches the next page of older transactions, newest first.
+    ///
+    /// Returns `None` when the beginning of the history is reached.
+    /// The position is not changed if the request fails, so it can be retried
+    pub async fn next_page(&mut self) -> Result<Option<Vec<RawTransaction>>> {
+        let (transactions, next) = match self.position {
+            HistoryPosition::Latest => {
+                let transactions = self
+                    .transport
+                    .get_transactions(&self.address, u64::MAX, self.page_size)
+                    .await?;
+                let next = transactions
+                    .last()
+                    .and_then(|transaction| TransactionsCursor::next_after(&transaction.data));
+                (transactions, next)
+            }
+            HistoryPosition::At(cursor) => {
+                self.transport
+                    .get_transactions_page(&self.address, &cursor, self.page_size)
+                    .await?
+            }
+            HistoryPosition::Finished => return Ok(None),
+        };
+
+        self.position = match next {
+            Some(cursor) if !transactions.is_empty() => HistoryPosition::At(cursor),
+            _ => HistoryPosition::Finished,
+        };
+
+        Ok((!transactions.is_empty()).then_some(transactions))
+    }
+
+    /// Converts the history into a stream of pages
+    pub fn into_stream(self) -> impl Stream<Item = Result<Vec<RawTransaction>>> {
+        stream::try_unfold(self, |mut history| async move {
+            Ok(history.next_page().await?.map(|page| (page, history)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+    use futures_util::TryStreamExt;
+
+    use super::*;
+    use crate::core::models::Expiration;
+    use crate::core::ton_wallet::{wallet_v3, Gift, TransferAction};
+    use crate::crypto::UnsignedMessage;
+    use crate::testing::ChainSimulator;
+    use crate::transport::models::RawContractState;
+
+    /// Produces `blocks * 4` new transactions of the returned account,
+    /// the sender wallet is deployed on the first call
+    async fn make_history(sim: &ChainSimulator, blocks: usize) -> MsgAddressInt {
+        let secret = SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        let keypair = Keypair { secret, public };
+
+        let wallet = wallet_v3::compute_contract_address(&public, 0);
+        if let RawContractState::NotExists { .. } =
+            sim.transport().get_contract_state(&wallet).await.unwrap()
+        {
+            sim.transport().set_account(
+                wallet.clone(),
+                ton_block::Account::with_address_and_ballance(
+                    &wallet,
+                    &ton_block::CurrencyCollection::with_grams(100_000_000_000),
+                ),
+            );
+
+            let message = wallet_v3::prepare_deploy(
+                sim.clock().as_ref(),
+                &public,
+                0,
+                Expiration::Timeout(60),
+            )
+            .unwrap();
+            let signature = keypair.sign(message.hash()).to_bytes();
+            let message = message.sign(&signature).unwrap().message;
+            sim.transport().send_message(&message).await.unwrap();
+            sim.advance(Duration::from_secs(1)).unwrap();
+        }
+
+        let target = MsgAddressInt::from_str(
+            "0:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb",
+        )
+        .unwrap();
+        for _ in 0..blocks {
+            let account = match sim.transport().get_contract_state(&wallet).await.unwrap() {
+                RawContractState::Exists(contract) => contract.account,
+                RawContractState::NotExists { .. } => panic!("wallet must exist"),
+            };
+            let gifts = (0..4)
+                .map(|_| Gift {
+                    flags: 3,
+                    bounce: false,
+                    destination: target.clone(),
+                    amount: 1_000_000_000,
+                    body: None,
+                    state_init: None,
+                })
+                .collect();
+
+            let message = match wallet_v3::prepare_transfer(
+                sim.clock().as_ref(),
+                &public,
+                &account,
+                0,
+                gifts,
+                Expiration::Timeout(60),
+            )
+            .unwrap()
+            {
+                TransferAction::Sign(message) => message,
+                TransferAction::DeployFirst => panic!("wallet must be deployed"),
+            };
+            let signature = keypair.sign(message.hash()).to_bytes();
+            let message = message.sign(&signature).unwrap().message;
+            sim.transport().send_message(&message).await.unwrap();
+            sim.advance(Duration::from_secs(1)).unwrap();
+        }
+
+        target
+    }
+
+    fn hashes(transactions: &[RawTransaction]) -> Vec<ton_types::UInt256> {
+        transactions.iter().map(|item| item.hash).collect()
+    }
+
+    #[tokio::test]
+    async fn pages_cover_history_without_duplicates() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let address = make_history(&sim, 2).await;
+
+        let all = sim
+            .transport()
+            .get_transactions(&address, u64::MAX, 50)
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 8);
+
+        let mut history = TransactionsHistory::new(sim.transport().clone(), address.clone(), 3);
+        let mut pages = Vec::new();
+        while let Some(page) = history.next_page().await.unwrap() {
+            pages.push(page);
+        }
+        assert!(history.is_finished());
+        assert!(history.next_page().await.unwrap().is_none());
+
+        // Pages are consecutive, newest first, and each transaction is returned once
+        assert_eq!(pages.iter().map(Vec::len).collect::<Vec<_>>(), [3, 3, 2]);
+        assert_eq!(hashes(&pages.concat()), hashes(&all));
+
+        // Stream yields the same pages
+        let streamed = TransactionsHistory::new(sim.transport().clone(), address.clone(), 3)
+            .into_stream()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(hashes(&streamed.concat()), hashes(&all));
+
+        // Page size is never zero
+        let mut history = TransactionsHistory::new(sim.transport().clone(), address, 0);
+        assert_eq!(history.next_page().await.unwrap().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn new_transactions_do_not_shift_saved_position() {
+        let sim = ChainSimulator::new(1_700_000_000);
+        let address = make_history(&sim, 2).await;
+
+        let mut history = TransactionsHistory::new(sim.transport().clone(), address.clone(), 3);
+        let first = history.next_page().await.unwrap().unwrap();
+        let cursor = match history.position() {
+            HistoryPosition::At(cursor) => cursor,
+            position => panic!("unexpected position: {position:?}"),
+        };
+
+        // New transactions are inserted at the top of the history
+        let target = make_history(&sim, 1).await;
+        assert_eq!(target, address);
+
+        let mut restored =
+            TransactionsHistory::from_cursor(sim.transport().clone(), address.clone(), 3, cursor);
+        let mut rest = Vec::new();
+        while let Some(page) = restored.next_page().await.unwrap() {
+            rest.extend(page);
+        }
+
+        let all = sim
+            .transport()
+            .get_transactions(&address, u64::MAX, 50)
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 12);
+
+        // Only the older transactions are returned after the saved position
+        let mut seen = first;
+        seen.extend(rest);
+        assert_eq!(hashes(&seen), hashes(&all[4..]));
+    }
+}
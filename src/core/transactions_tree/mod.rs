@@ -5,6 +5,7 @@ use nekoton_abi::Executor;
 use nekoton_utils::Clock;
 use ton_block::{Account, Message, MsgAddressInt, Transaction};
 
+use super::prefetcher::Prefetcher;
 use crate::transport::Transport;
 
 pub struct TransactionsTreeStream {
@@ -15,6 +16,7 @@ pub struct TransactionsTreeStream {
     unlimited_message_balance: bool,
     unlimited_account_balance: bool,
     transport: Arc<dyn Transport>,
+    prefetcher: Option<Arc<Prefetcher>>,
     clock: Arc<dyn Clock>,
 }
 
@@ -33,10 +35,17 @@ impl TransactionsTreeStream {
             unlimited_message_balance: false,
             unlimited_account_balance: false,
             transport,
+            prefetcher: None,
             clock,
         }
     }
 
+    /// Reuses the states which were already prefetched
+    pub fn with_prefetcher(&mut self, prefetcher: Arc<Prefetcher>) -> &mut Self {
+        self.prefetcher = Some(prefetcher);
+        self
+    }
+
     pub fn disable_signature_check(&mut self) -> &mut Self {
         self.disable_signature_check = true;
         self
@@ -144,10 +153,11 @@ impl TransactionsTreeStream {
     async fn get_state(&self, address: &MsgAddressInt) -> TransactionTreeResult<StoredAccount> {
         match self.states.get(address) {
             None => {
-                let account = self
-                    .transport
-                    .get_contract_state(address)
-                    .await
+                let contract_state = match &self.prefetcher {
+                    Some(prefetcher) => prefetcher.get_contract_state(address).await,
+                    None => self.transport.get_contract_state(address).await,
+                };
+                let account = contract_state
                     .map_err(TransactionTreeError::TransportError)?
                     .into_account();
 
@@ -15,7 +15,7 @@ use nekoton_utils::*;
 use crate::core::models::*;
 #[cfg(feature = "wallet_core")]
 use crate::crypto::{SignedMessage, UnsignedMessage};
-use crate::transport::models::RawTransaction;
+use crate::transport::models::{ExistingContract, RawTransaction};
 use crate::transport::Transport;
 
 pub fn convert_transactions(
@@ -171,6 +171,50 @@ pub fn parse_block(
     ))
 }
 
+/// Executes the getter of an arbitrary contract against its state.
+///
+/// Responsible getters are detected by the `answerId` input, which
+/// is filled automatically when omitted
+pub fn run_local(
+    clock: &dyn Clock,
+    contract_state: &ExistingContract,
+    abi: &ton_abi::Contract,
+    method: &str,
+    inputs: &[ton_abi::Token],
+) -> Result<Vec<ton_abi::Token>> {
+    let function = abi.function(method)?;
+    let context = contract_state.as_context(clock);
+
+    let responsible = matches!(
+        function.inputs.first(),
+        Some(param) if param.name == "answerId" && param.kind == ton_abi::ParamType::Uint(32)
+    );
+
+    let nekoton_abi::ExecutionOutput {
+        tokens,
+        result_code,
+    } = if responsible {
+        if inputs.len() + 1 == function.inputs.len() {
+            let mut inputs_with_answer_id = Vec::with_capacity(function.inputs.len());
+            inputs_with_answer_id.push(nekoton_abi::answer_id());
+            inputs_with_answer_id.extend_from_slice(inputs);
+            context.run_local_responsible(function, &inputs_with_answer_id)?
+        } else {
+            context.run_local_responsible(function, inputs)?
+        }
+    } else {
+        context.run_local(function, inputs)?
+    };
+
+    tokens.ok_or_else(|| RunLocalError::NonZeroResultCode(result_code).into())
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone)]
+pub enum RunLocalError {
+    #[error("Non-zero execution result code: {}", .0)]
+    NonZeroResultCode(i32),
+}
+
 #[derive(thiserror::Error, Debug, Copy, Clone)]
 pub enum BlockParsingError {
     #[error("Invalid block structure")]
@@ -314,6 +358,7 @@ pub struct MessageContext {
     pub latest_lt: u64,
     pub created_at: u32,
     pub expire_at: u32,
+    pub broadcast_not_before: Option<u32>,
 }
 
 pub trait PendingTransactionsExt {
@@ -351,6 +396,7 @@ impl PendingTransactionsExt for Vec<PendingTransaction> {
             latest_lt: ctx.latest_lt,
             created_at: ctx.created_at,
             expire_at: ctx.expire_at,
+            broadcast_not_before: ctx.broadcast_not_before,
         };
 
         self.push(pending_transaction.clone());
@@ -404,9 +450,9 @@ impl UnsignedMessage for LabsUnsignedMessage {
     fn refresh_timeout(&mut self, clock: &dyn Clock) {
         let time = clock.now_ms_u64();
 
-        if !self.expire_at.refresh_from_millis(time) {
-            return;
-        }
+        // NOTE: `time` header is used for the replay protection, so the message
+        // is rebuilt even if the expiration timestamp stays the same
+        self.expire_at.refresh_from_millis(time);
 
         *self.header.get_mut("time").trust_me() = ton_abi::TokenValue::Time(time);
         *self.header.get_mut("expire").trust_me() = ton_abi::TokenValue::Expire(self.expire_at());
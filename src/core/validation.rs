@@ -0,0 +1,203 @@
+use std::str::FromStr;
+
+use serde::Serialize;
+use ton_block::MsgAddressInt;
+use ton_types::UInt256;
+
+use nekoton_utils::*;
+
+/// Address, parsed from the user input
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidatedAddress {
+    #[serde(with = "serde_address")]
+    pub address: MsgAddressInt,
+    pub workchain: i8,
+    pub kind: AddressKind,
+    /// Bounce flag of the user-friendly address. `None` for raw addresses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounceable: Option<bool>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressKind {
+    /// `workchain:hex`
+    Raw,
+    /// User-friendly base64 address
+    Base64,
+    /// User-friendly base64 address with url safe charset
+    Base64Url,
+}
+
+/// Checks the address from the input field and describes why it is invalid
+pub fn validate_address(address: &str) -> Result<ValidatedAddress, AddressValidationError> {
+    let address = address.trim();
+    if address.is_empty() {
+        return Err(AddressValidationError::Empty);
+    }
+
+    match address.split_once(':') {
+        Some((workchain, account_id)) => validate_raw_address(address, workchain, account_id),
+        None => validate_packed_address(address),
+    }
+}
+
+fn validate_raw_address(
+    address: &str,
+    workchain: &str,
+    account_id: &str,
+) -> Result<ValidatedAddress, AddressValidationError> {
+    let workchain =
+        i8::from_str(workchain).map_err(|_| AddressValidationError::InvalidWorkchain)?;
+
+    if !account_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(AddressValidationError::InvalidHex);
+    }
+    if account_id.len() != 64 {
+        return Err(AddressValidationError::InvalidLength);
+    }
+
+    let address =
+        MsgAddressInt::from_str(address).map_err(|_| AddressValidationError::InvalidFormat)?;
+
+    Ok(ValidatedAddress {
+        address,
+        workchain,
+        kind: AddressKind::Raw,
+        bounceable: None,
+    })
+}
+
+fn validate_packed_address(address: &str) -> Result<ValidatedAddress, AddressValidationError> {
+    const FLAG_NON_BOUNCEABLE: u8 = 0x51;
+    const FLAG_BOUNCEABLE: u8 = 0x11;
+    const FLAG_TESTNET: u8 = 0x80;
+
+    let url_safe = address.contains(['-', '_']);
+    let data = if url_safe {
+        base64::decode_config(address, base64::URL_SAFE)
+    } else {
+        base64::decode(address)
+    }
+    .map_err(|_| AddressValidationError::InvalidBase64)?;
+
+    if data.len() != 36 {
+        return Err(AddressValidationError::InvalidLength);
+    }
+
+    let bounceable = match data[0] & !FLAG_TESTNET {
+        FLAG_BOUNCEABLE => true,
+        FLAG_NON_BOUNCEABLE => false,
+        _ => return Err(AddressValidationError::InvalidFlags),
+    };
+
+    let address = unpack_std_smc_addr(address, url_safe)
+        .map_err(|_| AddressValidationError::InvalidChecksum)?;
+
+    Ok(ValidatedAddress {
+        workchain: data[1] as i8,
+        address,
+        kind: if url_safe {
+            AddressKind::Base64Url
+        } else {
+            AddressKind::Base64
+        },
+        bounceable: Some(bounceable),
+    })
+}
+
+/// BOC, parsed from the user input
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidatedBoc {
+    /// Representation hash of the root cell
+    #[serde(with = "serde_uint256")]
+    pub hash: UInt256,
+    /// Number of bits in the root cell
+    pub bit_len: usize,
+    /// Number of references in the root cell
+    pub reference_count: usize,
+}
+
+/// Checks the base64 encoded BOC from the input field
+pub fn validate_boc(boc: &str) -> Result<ValidatedBoc, BocValidationError> {
+    let boc = boc.trim();
+    if boc.is_empty() {
+        return Err(BocValidationError::Empty);
+    }
+
+    let data = base64::decode(boc).map_err(|_| BocValidationError::InvalidBase64)?;
+    let cell = ton_types::deserialize_tree_of_cells(&mut data.as_slice())
+        .map_err(|_| BocValidationError::InvalidBoc)?;
+
+    Ok(ValidatedBoc {
+        hash: cell.repr_hash(),
+        bit_len: cell.bit_length(),
+        reference_count: cell.references_count(),
+    })
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressValidationError {
+    #[error("Address is empty")]
+    Empty,
+    #[error("Invalid workchain")]
+    InvalidWorkchain,
+    #[error("Address contains non-hex characters")]
+    InvalidHex,
+    #[error("Invalid address length")]
+    InvalidLength,
+    #[error("Invalid address format")]
+    InvalidFormat,
+    #[error("Invalid base64")]
+    InvalidBase64,
+    #[error("Invalid address flags")]
+    InvalidFlags,
+    #[error("Invalid checksum")]
+    InvalidChecksum,
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BocValidationError {
+    #[error("BOC is empty")]
+    Empty,
+    #[error("Invalid base64")]
+    InvalidBase64,
+    #[error("Invalid BOC")]
+    InvalidBoc,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_addresses() {
+        let raw = "0:02e3f2284e68a8106b823ab9f2404f33cc43fccad8e1de835bdd96789254686c";
+        let validated = validate_address(raw).unwrap();
+        assert_eq!(validated.kind, AddressKind::Raw);
+        assert_eq!(validated.bounceable, None);
+
+        let validated =
+            validate_address("UQAC4_IoTmioEGuCOrnyQE8zzEP8ytjh3oNb3ZZ4klRobAEx").unwrap();
+        assert_eq!(validated.kind, AddressKind::Base64Url);
+        assert_eq!(validated.bounceable, Some(false));
+        assert_eq!(validated.address.to_string(), raw);
+
+        for (address, error) in [
+            ("", AddressValidationError::Empty),
+            ("a:00", AddressValidationError::InvalidWorkchain),
+            ("0:xyz", AddressValidationError::InvalidHex),
+            ("0:02e3f2", AddressValidationError::InvalidLength),
+            (
+                "UQAC4_IoTmioEGuCOrnyQE8zzEP8ytjh3oNb3ZZ4klRobAEy",
+                AddressValidationError::InvalidChecksum,
+            ),
+        ] {
+            assert_eq!(validate_address(address).unwrap_err(), error);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::hash_map::{self, HashMap};
+use std::collections::HashSet;
 
 use anyhow::Result;
 use chacha20poly1305::{ChaCha20Poly1305, KeyInit, Nonce};
@@ -25,6 +26,36 @@ impl DerivedKeySigner {
         Self::default()
     }
 
+    /// Returns all accounts derived from the master key, sorted by account id
+    pub fn get_accounts(&self, master_key: &PublicKey) -> Result<Vec<SignerEntry>> {
+        let key = self.get_master_key(master_key)?;
+        let mut accounts = key
+            .accounts_map
+            .iter()
+            .map(|(public_key, account)| SignerEntry {
+                name: account.name.clone(),
+                public_key: PublicKey::from_bytes(public_key).trust_me(),
+                master_key: key.public_key,
+                account_id: account.account_id,
+            })
+            .collect::<Vec<_>>();
+        accounts.sort_by_key(|entry| entry.account_id);
+        Ok(accounts)
+    }
+
+    /// Returns the lowest account id which is not yet derived from the master key
+    pub fn next_account_id(&self, master_key: &PublicKey) -> Result<u16> {
+        let key = self.get_master_key(master_key)?;
+        let used = key
+            .accounts_map
+            .values()
+            .map(|account| account.account_id)
+            .collect::<HashSet<_>>();
+        (0..=u16::MAX)
+            .find(|account_id| !used.contains(account_id))
+            .ok_or_else(|| MasterKeyError::NoFreeAccountId.into())
+    }
+
     fn get_master_key(&self, master_key: &PublicKey) -> Result<&MasterKey> {
         match self.master_keys.get(master_key.as_bytes()) {
             Some(key) => Ok(key),
@@ -769,6 +800,8 @@ enum MasterKeyError {
     DerivationError,
     #[error("Derived key already exists")]
     DerivedKeyExists,
+    #[error("All account ids are already used")]
+    NoFreeAccountId,
 }
 
 #[cfg(test)]
@@ -864,6 +897,54 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_next_account_id() -> Result<()> {
+        let mut signer = DerivedKeySigner::new();
+
+        let cache = PasswordCache::new();
+        let ctx = SignerContext {
+            password_cache: &cache,
+        };
+
+        let password = || Password::Explicit {
+            password: SecUtf8::from("123"),
+            cache_behavior: PasswordCacheBehavior::Remove,
+        };
+
+        let entry = signer
+            .add_key(
+                ctx,
+                DerivedKeyCreateInput::Import {
+                    key_name: None,
+                    phrase: SecUtf8::from(TEST_PHRASE),
+                    password: password(),
+                },
+            )
+            .await?;
+        assert_eq!(signer.next_account_id(&entry.master_key)?, 1);
+
+        signer
+            .add_key(
+                ctx,
+                DerivedKeyCreateInput::Derive {
+                    key_name: None,
+                    master_key: entry.master_key,
+                    account_id: 2,
+                    password: password(),
+                },
+            )
+            .await?;
+        assert_eq!(signer.next_account_id(&entry.master_key)?, 1);
+
+        let accounts = signer.get_accounts(&entry.master_key)?;
+        assert_eq!(
+            accounts.iter().map(|x| x.account_id).collect::<Vec<_>>(),
+            [0, 2]
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_change_password() -> Result<()> {
         let mut signer = DerivedKeySigner::new();
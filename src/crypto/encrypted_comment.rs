@@ -0,0 +1,107 @@
+use anyhow::Result;
+use ed25519_dalek::PublicKey;
+use ton_abi::{Param, ParamType, TokenValue};
+use ton_types::{SliceData, UInt256};
+
+use nekoton_abi::*;
+
+use super::{EncryptedData, EncryptionAlgorithm};
+
+/// Prefix of the encrypted comment payload (plain comments are prefixed with zero)
+pub const ENCRYPTED_COMMENT_PREFIX: u32 = 1;
+
+const PAYLOAD_ABI_VERSION: ton_abi::contract::AbiVersion = ton_abi::contract::ABI_VERSION_2_0;
+
+/// Encodes data, encrypted for a single recipient, as a message body
+pub fn create_encrypted_comment_payload(data: &EncryptedData) -> Result<SliceData> {
+    let algorithm: u8 = match data.algorithm {
+        EncryptionAlgorithm::ChaCha20Poly1305 => 0,
+    };
+
+    TokenValue::pack_values_into_chain(
+        &[
+            ENCRYPTED_COMMENT_PREFIX.token_value().unnamed(),
+            algorithm.token_value().unnamed(),
+            UInt256::from(data.source_public_key.to_bytes())
+                .token_value()
+                .unnamed(),
+            UInt256::from(data.recipient_public_key.to_bytes())
+                .token_value()
+                .unnamed(),
+            data.nonce.clone().token_value().unnamed(),
+            data.data.clone().token_value().unnamed(),
+        ],
+        Vec::new(),
+        &PAYLOAD_ABI_VERSION,
+    )
+    .and_then(SliceData::load_builder)
+}
+
+/// Decodes the payload, created by [`create_encrypted_comment_payload`].
+///
+/// Returns `None` if the payload is not an encrypted comment
+pub fn parse_encrypted_comment_payload(payload: SliceData) -> Option<EncryptedData> {
+    let params = [
+        Param::new("prefix", ParamType::Uint(32)),
+        Param::new("algorithm", ParamType::Uint(8)),
+        Param::new("sourcePublicKey", ParamType::Uint(256)),
+        Param::new("recipientPublicKey", ParamType::Uint(256)),
+        Param::new("nonce", ParamType::Bytes),
+        Param::new("data", ParamType::Bytes),
+    ];
+
+    let mut tokens = unpack_from_cell(&params, payload, true, PAYLOAD_ABI_VERSION)
+        .ok()?
+        .into_iter()
+        .map(|token| token.value);
+
+    let prefix: u32 = tokens.next()?.unpack().ok()?;
+    if prefix != ENCRYPTED_COMMENT_PREFIX {
+        return None;
+    }
+
+    let algorithm = match tokens.next()?.unpack().ok()? {
+        0u8 => EncryptionAlgorithm::ChaCha20Poly1305,
+        _ => return None,
+    };
+    let source_public_key: UInt256 = tokens.next()?.unpack().ok()?;
+    let recipient_public_key: UInt256 = tokens.next()?.unpack().ok()?;
+
+    Some(EncryptedData {
+        algorithm,
+        source_public_key: PublicKey::from_bytes(source_public_key.as_slice()).ok()?,
+        recipient_public_key: PublicKey::from_bytes(recipient_public_key.as_slice()).ok()?,
+        nonce: tokens.next()?.unpack().ok()?,
+        data: tokens.next()?.unpack().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypted_comment_payload() {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public_key = PublicKey::from(&secret);
+
+        let data = EncryptedData {
+            algorithm: EncryptionAlgorithm::ChaCha20Poly1305,
+            source_public_key: public_key,
+            recipient_public_key: public_key,
+            data: vec![0xaa; 100],
+            nonce: vec![0xbb; 12],
+        };
+
+        let payload = create_encrypted_comment_payload(&data).unwrap();
+        assert!(parse_comment_payload(payload.clone()).is_none());
+
+        let parsed = parse_encrypted_comment_payload(payload).unwrap();
+        assert_eq!(parsed.source_public_key, data.source_public_key);
+        assert_eq!(parsed.data, data.data);
+        assert_eq!(parsed.nonce, data.nonce);
+
+        let comment = create_comment_payload("test").unwrap();
+        assert!(parse_encrypted_comment_payload(comment).is_none());
+    }
+}
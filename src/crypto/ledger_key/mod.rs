@@ -154,6 +154,13 @@ impl StoreSigner for LedgerKeySigner {
         input: Self::SignInput,
     ) -> Result<[u8; ed25519_dalek::SIGNATURE_LENGTH]> {
         let key = self.get_key(&input.public_key)?;
+
+        // Make sure that the connected device holds the same key
+        let device_key = self.connection.get_public_key(key.account_id).await?;
+        if device_key != key.public_key.to_bytes() {
+            return Err(LedgerKeyError::DeviceMismatch.into());
+        }
+
         let signature = match input.context {
             None => {
                 self.connection
@@ -333,6 +340,132 @@ pub enum LedgerKeyError {
     InvalidPublicKey,
     #[error("Method not supported")]
     MethodNotSupported,
+    #[error("Connected device doesn't contain this key")]
+    DeviceMismatch,
 }
 
-mod test {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct DummyConnection;
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl LedgerConnection for DummyConnection {
+        async fn get_public_key(&self, _: u16) -> Result<[u8; ed25519_dalek::PUBLIC_KEY_LENGTH]> {
+            Err(LedgerKeyError::MethodNotSupported.into())
+        }
+
+        async fn sign(
+            &self,
+            _: u16,
+            _: Option<i32>,
+            _: &[u8],
+        ) -> Result<[u8; ed25519_dalek::SIGNATURE_LENGTH]> {
+            Err(LedgerKeyError::MethodNotSupported.into())
+        }
+
+        async fn sign_transaction(
+            &self,
+            _: u16,
+            _: u16,
+            _: Option<i32>,
+            _: &[u8],
+            _: &LedgerSignatureContext,
+        ) -> Result<[u8; ed25519_dalek::SIGNATURE_LENGTH]> {
+            Err(LedgerKeyError::MethodNotSupported.into())
+        }
+    }
+
+    /// Device which always returns the same key
+    struct StaticConnection([u8; ed25519_dalek::PUBLIC_KEY_LENGTH]);
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl LedgerConnection for StaticConnection {
+        async fn get_public_key(&self, _: u16) -> Result<[u8; ed25519_dalek::PUBLIC_KEY_LENGTH]> {
+            Ok(self.0)
+        }
+
+        async fn sign(
+            &self,
+            _: u16,
+            _: Option<i32>,
+            _: &[u8],
+        ) -> Result<[u8; ed25519_dalek::SIGNATURE_LENGTH]> {
+            Ok([0; ed25519_dalek::SIGNATURE_LENGTH])
+        }
+
+        async fn sign_transaction(
+            &self,
+            _: u16,
+            _: u16,
+            _: Option<i32>,
+            _: &[u8],
+            _: &LedgerSignatureContext,
+        ) -> Result<[u8; ed25519_dalek::SIGNATURE_LENGTH]> {
+            Ok([0; ed25519_dalek::SIGNATURE_LENGTH])
+        }
+    }
+
+    #[tokio::test]
+    async fn sign_with_another_device() {
+        let stored_key = PublicKey::from(&ed25519_dalek::SecretKey::from_bytes(&[1; 32]).unwrap());
+        let device_key = PublicKey::from(&ed25519_dalek::SecretKey::from_bytes(&[2; 32]).unwrap());
+
+        let make_signer = |connection: StaticConnection| {
+            let mut signer = LedgerKeySigner::new(Arc::new(connection));
+            signer.keys.insert(
+                stored_key.to_bytes(),
+                LedgerKey::new("Ledger key".to_owned(), 1, stored_key, stored_key).unwrap(),
+            );
+            signer
+        };
+
+        let cache = crate::crypto::PasswordCache::new();
+        let input = || LedgerSignInput {
+            wallet: WalletType::EverWallet,
+            public_key: stored_key,
+            context: None,
+        };
+
+        // Device returns a different key for the same account id
+        let signer = make_signer(StaticConnection(device_key.to_bytes()));
+        let ctx = SignerContext {
+            password_cache: &cache,
+        };
+        let err = signer.sign(ctx, &[0; 32], None, input()).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LedgerKeyError>(),
+            Some(LedgerKeyError::DeviceMismatch)
+        ));
+
+        // Same device
+        let signer = make_signer(StaticConnection(stored_key.to_bytes()));
+        let ctx = SignerContext {
+            password_cache: &cache,
+        };
+        signer.sign(ctx, &[0; 32], None, input()).await.unwrap();
+    }
+
+    #[test]
+    fn store_and_load_state() {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public_key = PublicKey::from(&secret);
+
+        let mut signer = LedgerKeySigner::new(Arc::new(DummyConnection));
+        signer.keys.insert(
+            public_key.to_bytes(),
+            LedgerKey::new("Ledger key".to_owned(), 1, public_key, public_key).unwrap(),
+        );
+
+        let mut restored = LedgerKeySigner::new(Arc::new(DummyConnection));
+        restored.load_state(&signer.store_state()).unwrap();
+
+        let entries = restored.get_entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].account_id, 1);
+        assert_eq!(entries[0].public_key, public_key);
+    }
+}
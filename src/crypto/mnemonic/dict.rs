@@ -1,6 +1,126 @@
+use serde::Serialize;
+
 use super::LANGUAGE;
 
+const MAX_SUGGESTIONS: usize = 5;
+const MAX_EDIT_DISTANCE: usize = 2;
+
 pub fn get_hints(prefix: &str) -> &[&'static str] {
     let wordlist = LANGUAGE.wordlist();
     wordlist.get_words_by_prefix(prefix)
 }
+
+/// Returns the closest words from the wordlist, best matches first.
+///
+/// Words which start with the specified one go first, then words
+/// with the smallest edit distance (including swapped letters)
+pub fn suggest_corrections(word: &str) -> Vec<&'static str> {
+    let word = word.trim().to_lowercase();
+    if word.is_empty() {
+        return Vec::new();
+    }
+
+    let mut candidates = get_hints("")
+        .iter()
+        .filter_map(|&item| {
+            let distance = if item.starts_with(word.as_str()) {
+                0
+            } else {
+                edit_distance(&word, item)
+            };
+            (distance <= MAX_EDIT_DISTANCE).then_some((distance, item))
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort_by_key(|(distance, _)| *distance);
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, item)| item)
+        .collect()
+}
+
+/// Checks each word of the phrase separately, so that the wrong
+/// words can be highlighted instead of rejecting the whole phrase.
+///
+/// NOTE: checksum is not verified here
+pub fn validate_words(phrase: &str) -> Vec<WordValidation> {
+    let wordmap = LANGUAGE.wordmap();
+
+    phrase
+        .split_whitespace()
+        .enumerate()
+        .map(|(index, word)| {
+            let valid = wordmap.get_bits(&word.to_lowercase()).is_ok();
+            WordValidation {
+                index,
+                word: word.to_owned(),
+                valid,
+                suggestions: if valid {
+                    Vec::new()
+                } else {
+                    suggest_corrections(word)
+                },
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WordValidation {
+    pub index: usize,
+    pub word: String,
+    pub valid: bool,
+    pub suggestions: Vec<&'static str>,
+}
+
+/// Optimal string alignment distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=b.len() {
+        rows[0][j] = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggestions_for_typos() {
+        assert_eq!(suggest_corrections("scam")[0], "scan");
+        assert_eq!(suggest_corrections("fevre")[0], "fever");
+        assert!(suggest_corrections("piono").contains(&"piano"));
+
+        let validation =
+            validate_words("pioneer fever hazard scam install wise reform corn bubble leisure");
+        let invalid = validation
+            .iter()
+            .filter(|item| !item.valid)
+            .map(|item| item.index)
+            .collect::<Vec<_>>();
+        assert_eq!(invalid, [3]);
+    }
+}
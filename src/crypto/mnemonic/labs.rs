@@ -3,9 +3,12 @@ use std::convert::TryInto;
 use anyhow::Result;
 use nekoton_utils::TrustMe;
 use tiny_hderive::bip32::ExtendedPrivKey;
+use unicode_normalization::UnicodeNormalization;
 
 use super::LANGUAGE;
 
+const PBKDF_ITERATIONS: u32 = 2048;
+
 pub fn derive_master_key(phrase: &str) -> Result<[u8; 64]> {
     let mnemonic = bip39::Mnemonic::from_phrase(phrase, LANGUAGE)?;
     let hd = bip39::Seed::new(&mnemonic, "");
@@ -15,11 +18,47 @@ pub fn derive_master_key(phrase: &str) -> Result<[u8; 64]> {
 pub fn derive_from_phrase(phrase: &str, account_id: u16) -> Result<ed25519_dalek::Keypair> {
     let mnemonic = bip39::Mnemonic::from_phrase(phrase, LANGUAGE)?;
     let hd = bip39::Seed::new(&mnemonic, "");
-    let seed_bytes = hd.as_bytes();
+    derive_from_seed(hd.as_bytes(), account_id)
+}
+
+/// Derives keypair from the phrase in any supported language.
+///
+/// Phrase must be in the canonical form (see [`super::normalize_phrase`])
+pub fn derive_from_normalized_phrase(
+    phrase: &str,
+    language: bip39::Language,
+    account_id: u16,
+) -> Result<ed25519_dalek::Keypair> {
+    // NOTE: only validates words and checksum
+    bip39::Mnemonic::from_phrase(phrase, language)?;
+
+    // Seed is computed from the NFKD form of the phrase as required by BIP39
+    let password = phrase.nfkd().collect::<String>();
+    let mut seed = [0; 64];
+    pbkdf2::pbkdf2::<hmac::Hmac<sha2::Sha512>>(
+        password.as_bytes(),
+        b"mnemonic",
+        PBKDF_ITERATIONS,
+        &mut seed,
+    );
+
+    derive_from_seed(&seed, account_id)
+}
+
+/// Derives keypair using the custom BIP32 derivation path (e.g. `m/44'/396'/0'/0/0`)
+pub fn derive_from_phrase_with_path(phrase: &str, path: &str) -> Result<ed25519_dalek::Keypair> {
+    let mnemonic = bip39::Mnemonic::from_phrase(phrase, LANGUAGE)?;
+    let hd = bip39::Seed::new(&mnemonic, "");
+    derive_from_seed_with_path(hd.as_bytes(), path)
+}
+
+fn derive_from_seed(seed_bytes: &[u8], account_id: u16) -> Result<ed25519_dalek::Keypair> {
+    derive_from_seed_with_path(seed_bytes, &format!("m/44'/396'/0'/0/{account_id}"))
+}
 
-    let derived =
-        ExtendedPrivKey::derive(seed_bytes, format!("m/44'/396'/0'/0/{account_id}").as_str())
-            .map_err(|_| anyhow::anyhow!("Invalid derivation path"))?;
+fn derive_from_seed_with_path(seed_bytes: &[u8], path: &str) -> Result<ed25519_dalek::Keypair> {
+    let derived = ExtendedPrivKey::derive(seed_bytes, path)
+        .map_err(|_| anyhow::anyhow!("Invalid derivation path"))?;
 
     let secret = ed25519_dalek::SecretKey::from_bytes(&derived.secret())?;
     let public = ed25519_dalek::PublicKey::from(&secret);
@@ -57,6 +96,25 @@ mod tests {
         assert_eq!(secret.as_bytes(), target_secret.as_bytes())
     }
 
+    #[test]
+    fn normalized_phrase_derive() {
+        let phrase =
+            "pioneer fever hazard scan install wise reform corn bubble leisure amazing note";
+        let expected = derive_from_phrase(phrase, 0).unwrap();
+        let key = derive_from_normalized_phrase(phrase, LANGUAGE, 0).unwrap();
+        assert_eq!(key.secret.as_bytes(), expected.secret.as_bytes());
+    }
+
+    #[test]
+    fn custom_path_derive() {
+        let phrase =
+            "pioneer fever hazard scan install wise reform corn bubble leisure amazing note";
+        let expected = derive_from_phrase(phrase, 3).unwrap();
+        let key = derive_from_phrase_with_path(phrase, "m/44'/396'/0'/0/3").unwrap();
+        assert_eq!(key.secret.as_bytes(), expected.secret.as_bytes());
+        assert!(derive_from_phrase_with_path(phrase, "44/396").is_err());
+    }
+
     #[test]
     fn master_key_derive() {
         let ph = "pioneer fever hazard scan install wise reform corn bubble leisure amazing note";
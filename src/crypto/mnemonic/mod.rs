@@ -3,6 +3,7 @@ use ed25519_dalek::Keypair;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use sha2::Digest;
+use unicode_normalization::UnicodeNormalization;
 
 pub mod dict;
 pub(super) mod labs;
@@ -28,6 +29,59 @@ impl MnemonicType {
     }
 }
 
+/// BIP39 wordlist of the phrase.
+///
+/// New phrases are always generated in English, other languages
+/// are only accepted for import (requires `mnemonic_languages` feature)
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MnemonicLanguage {
+    #[default]
+    English,
+    ChineseSimplified,
+    ChineseTraditional,
+    French,
+    Italian,
+    Japanese,
+    Korean,
+    Spanish,
+}
+
+impl MnemonicLanguage {
+    const ALL: [Self; 8] = [
+        Self::English,
+        Self::ChineseSimplified,
+        Self::ChineseTraditional,
+        Self::French,
+        Self::Italian,
+        Self::Japanese,
+        Self::Korean,
+        Self::Spanish,
+    ];
+
+    fn to_bip39(self) -> Option<bip39::Language> {
+        Some(match self {
+            Self::English => bip39::Language::English,
+            #[cfg(feature = "mnemonic_languages")]
+            Self::ChineseSimplified => bip39::Language::ChineseSimplified,
+            #[cfg(feature = "mnemonic_languages")]
+            Self::ChineseTraditional => bip39::Language::ChineseTraditional,
+            #[cfg(feature = "mnemonic_languages")]
+            Self::French => bip39::Language::French,
+            #[cfg(feature = "mnemonic_languages")]
+            Self::Italian => bip39::Language::Italian,
+            #[cfg(feature = "mnemonic_languages")]
+            Self::Japanese => bip39::Language::Japanese,
+            #[cfg(feature = "mnemonic_languages")]
+            Self::Korean => bip39::Language::Korean,
+            #[cfg(feature = "mnemonic_languages")]
+            Self::Spanish => bip39::Language::Spanish,
+            #[allow(unreachable_patterns)]
+            _ => return None,
+        })
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GeneratedKey {
@@ -42,6 +96,100 @@ pub fn derive_from_phrase(phrase: &str, mnemonic_type: MnemonicType) -> Result<K
     }
 }
 
+/// Derives keypair from the 12 words phrase using the custom BIP32 derivation path.
+///
+/// The default path is `m/44'/396'/0'/0/{account_id}`
+pub fn derive_from_phrase_with_path(phrase: &str, path: &str) -> Result<Keypair, Error> {
+    labs::derive_from_phrase_with_path(phrase, path)
+}
+
+/// Checks the number of words, the words themselves and the checksum (for 12 words phrases)
+/// without deriving the keypair
+pub fn validate_phrase(phrase: &str, mnemonic_type: MnemonicType) -> Result<(), Error> {
+    let expected_words = match mnemonic_type {
+        MnemonicType::Legacy => 24,
+        MnemonicType::Labs(_) => 12,
+    };
+    if phrase.split_whitespace().count() != expected_words {
+        return Err(MnemonicError::InvalidWordCount { expected_words }.into());
+    }
+
+    match mnemonic_type {
+        MnemonicType::Legacy => {
+            let wordmap = LANGUAGE.wordmap();
+            for word in phrase.split_whitespace() {
+                wordmap
+                    .get_bits(word)
+                    .map_err(|_| MnemonicError::UnknownWord)?;
+            }
+        }
+        MnemonicType::Labs(_) => {
+            bip39::Mnemonic::validate(phrase, LANGUAGE)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Derives keypair from the phrase, created with the specified wordlist.
+///
+/// Legacy phrases are only supported in English
+pub fn derive_from_phrase_with_language(
+    phrase: &str,
+    mnemonic_type: MnemonicType,
+    language: MnemonicLanguage,
+) -> Result<Keypair, Error> {
+    let phrase = normalize_phrase(phrase, language)?;
+    match (mnemonic_type, language) {
+        (_, MnemonicLanguage::English) => derive_from_phrase(&phrase, mnemonic_type),
+        (MnemonicType::Labs(account_id), language) => {
+            let language = language
+                .to_bip39()
+                .ok_or(MnemonicError::UnsupportedLanguage)?;
+            labs::derive_from_normalized_phrase(&phrase, language, account_id)
+        }
+        (MnemonicType::Legacy, _) => Err(MnemonicError::UnsupportedLanguage.into()),
+    }
+}
+
+/// Converts phrase into the canonical form: words from the wordlist,
+/// separated by a single space.
+///
+/// Words are compared in NFKD form, case insensitive
+pub fn normalize_phrase(phrase: &str, language: MnemonicLanguage) -> Result<String, Error> {
+    let language = language
+        .to_bip39()
+        .ok_or(MnemonicError::UnsupportedLanguage)?;
+    let wordlist = language.wordlist();
+    let wordmap = language.wordmap();
+
+    let mut result = String::with_capacity(phrase.len());
+    for word in phrase.nfkd().collect::<String>().split_whitespace() {
+        let word = word.to_lowercase();
+        // NOTE: some wordlists are stored in composed form
+        let bits = match wordmap.get_bits(&word) {
+            Ok(bits) => bits,
+            Err(_) => wordmap
+                .get_bits(&word.nfc().collect::<String>())
+                .map_err(|_| MnemonicError::UnknownWord)?,
+        };
+
+        if !result.is_empty() {
+            result.push(' ');
+        }
+        result.push_str(wordlist.get_word(bits));
+    }
+
+    Ok(result)
+}
+
+/// Finds the first supported wordlist which contains all words of the phrase
+pub fn detect_language(phrase: &str) -> Option<MnemonicLanguage> {
+    MnemonicLanguage::ALL
+        .into_iter()
+        .find(|&language| normalize_phrase(phrase, language).is_ok())
+}
+
 /// Generates mnemonic and keypair.
 pub fn generate_key(account_type: MnemonicType) -> GeneratedKey {
     use bip39::util::{Bits11, IterExt};
@@ -75,3 +223,46 @@ pub fn generate_key(account_type: MnemonicType) -> GeneratedKey {
         },
     }
 }
+
+#[derive(thiserror::Error, Debug)]
+enum MnemonicError {
+    #[error("Unsupported mnemonic language")]
+    UnsupportedLanguage,
+    #[error("Unknown word")]
+    UnknownWord,
+    #[error("Expected {expected_words} words")]
+    InvalidWordCount { expected_words: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_english_phrase() {
+        let phrase = normalize_phrase(
+            "  Pioneer fever hazard scan install\twise reform corn bubble leisure amazing NOTE ",
+            MnemonicLanguage::English,
+        )
+        .unwrap();
+        assert_eq!(
+            phrase,
+            "pioneer fever hazard scan install wise reform corn bubble leisure amazing note"
+        );
+        assert_eq!(detect_language(&phrase), Some(MnemonicLanguage::English));
+        assert!(normalize_phrase("pioneer fevre", MnemonicLanguage::English).is_err());
+    }
+
+    #[test]
+    fn validate_phrases() {
+        let labs = "pioneer fever hazard scan install wise reform corn bubble leisure amazing note";
+        validate_phrase(labs, MnemonicType::Labs(0)).unwrap();
+        assert!(validate_phrase(labs, MnemonicType::Legacy).is_err());
+        assert!(validate_phrase(&labs.replace("scan", "scam"), MnemonicType::Labs(0)).is_err());
+
+        for account_type in [MnemonicType::Legacy, MnemonicType::Labs(0)] {
+            let key = generate_key(account_type);
+            validate_phrase(&key.words.join(" "), account_type).unwrap();
+        }
+    }
+}
@@ -11,12 +11,14 @@ use zeroize::Zeroizing;
 use nekoton_utils::*;
 
 pub use derived_key::*;
+pub use encrypted_comment::*;
 pub use encrypted_key::*;
 pub use ledger_key::*;
 pub use mnemonic::*;
 pub use password_cache::*;
 
 mod derived_key;
+mod encrypted_comment;
 mod encrypted_key;
 mod ledger_key;
 mod mnemonic;
@@ -58,6 +60,13 @@ pub struct SignedMessage {
     pub expire_at: u32,
 }
 
+impl SignedMessage {
+    /// Returns the number of seconds until the message expires
+    pub fn expires_in(&self, clock: &dyn Clock) -> u32 {
+        self.expire_at.saturating_sub(clock.now_sec_u64() as u32)
+    }
+}
+
 impl Serialize for SignedMessage {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -252,6 +261,37 @@ pub fn extend_with_signature_id(data: &[u8], signature_id: Option<SignatureId>)
     }
 }
 
+/// Checks the signature produced by [`Signer::sign`] with the same `signature_id`
+pub fn verify_signature(
+    public_key: &PublicKey,
+    data: &[u8],
+    signature: &Signature,
+    signature_id: Option<SignatureId>,
+) -> bool {
+    use ed25519_dalek::Verifier;
+
+    let signature = match ed25519_dalek::Signature::try_from(&signature[..]) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    let data = extend_with_signature_id(data, signature_id);
+    public_key.verify(&data, &signature).is_ok()
+}
+
+/// Derives deterministic symmetric key from the master seed.
+///
+/// Keys with different `purpose` labels are independent, so the same seed
+/// can be used for several storages (e.g. `"backup"`, `"encrypted_storage"`)
+pub fn derive_storage_key(master_seed: &[u8], purpose: &str) -> Zeroizing<[u8; 32]> {
+    const SALT: &[u8] = b"nekoton storage key";
+
+    let hkdf = hkdf::Hkdf::<sha2::Sha256>::new(Some(SALT), master_seed);
+    let mut key = Zeroizing::new([0; 32]);
+    // NOTE: shouldn't fail because 32 bytes is a valid output length
+    hkdf.expand(purpose.as_bytes(), &mut *key).trust_me();
+    key
+}
+
 pub mod x25519 {
     use curve25519_dalek_ng::scalar::Scalar;
     use zeroize::Zeroizing;
@@ -274,3 +314,39 @@ pub mod x25519 {
         Zeroizing::new((Scalar::from_bits(k) * u).to_bytes())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Keypair, SecretKey};
+
+    use super::*;
+
+    #[test]
+    fn verify_signature_with_id() {
+        let secret = SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        let pair = Keypair { secret, public };
+
+        let data = [0xab; 32];
+        let signature = {
+            use ed25519_dalek::Signer;
+            pair.sign(&extend_with_signature_id(&data, Some(42)))
+                .to_bytes()
+        };
+
+        assert!(verify_signature(&public, &data, &signature, Some(42)));
+        assert!(!verify_signature(&public, &data, &signature, Some(43)));
+        assert!(!verify_signature(&public, &data, &signature, None));
+        assert!(!verify_signature(&public, &[0; 32], &signature, Some(42)));
+    }
+
+    #[test]
+    fn storage_keys_are_independent() {
+        let seed = [0x11; 64];
+
+        let backup = derive_storage_key(&seed, "backup");
+        assert_eq!(backup, derive_storage_key(&seed, "backup"));
+        assert_ne!(backup, derive_storage_key(&seed, "encrypted_storage"));
+        assert_ne!(backup, derive_storage_key(&[0x22; 64], "backup"));
+    }
+}
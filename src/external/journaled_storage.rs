@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+use nekoton_utils::{Clock, TrustMe};
+
+use super::Storage;
+
+const JOURNAL_SUFFIX: &str = "__journal";
+
+/// Storage wrapper which writes each value into the journal entry first.
+///
+/// If the application crashes during the write, the complete value
+/// is restored from the journal on the next read, so the truncated data
+/// is never returned (and the whole cache or accounts list is not reset).
+///
+/// Unchecked writes can optionally be throttled, see [`JournaledStorage::with_throttling`]
+pub struct JournaledStorage {
+    inner: Arc<dyn Storage>,
+    throttling: Option<Throttling>,
+}
+
+impl JournaledStorage {
+    pub fn new(inner: Arc<dyn Storage>) -> Self {
+        Self {
+            inner,
+            throttling: None,
+        }
+    }
+
+    /// Writes each key with [`Storage::set_unchecked`] at most once per `interval_ms`.
+    ///
+    /// Values written more often are kept in memory and only the latest one is stored:
+    /// either with the next write after the interval, on [`JournaledStorage::flush`]
+    /// or when the storage is dropped. Checked writes are never delayed
+    pub fn with_throttling(mut self, clock: Arc<dyn Clock>, interval_ms: u64) -> Self {
+        self.throttling = Some(Throttling {
+            clock,
+            interval_ms,
+            keys: Default::default(),
+        });
+        self
+    }
+
+    /// Writes all delayed values
+    pub async fn flush(&self) -> Result<()> {
+        for (key, value) in self.take_pending() {
+            self.set(&key, &value).await?;
+        }
+        Ok(())
+    }
+
+    fn take_pending(&self) -> Vec<(String, String)> {
+        let throttling = match &self.throttling {
+            Some(throttling) => throttling,
+            None => return Vec::new(),
+        };
+
+        throttling
+            .keys
+            .lock()
+            .iter_mut()
+            .filter_map(|(key, state)| Some((key.clone(), state.pending.take()?)))
+            .collect()
+    }
+
+    fn write_unchecked(&self, key: &str, value: &str) {
+        let journal_key = make_journal_key(key);
+        self.inner
+            .set_unchecked(&journal_key, &JournalEntry::make(value));
+        self.inner.set_unchecked(key, value);
+        self.inner.remove_unchecked(&journal_key);
+    }
+}
+
+impl Drop for JournaledStorage {
+    fn drop(&mut self) {
+        for (key, value) in self.take_pending() {
+            self.write_unchecked(&key, &value);
+        }
+    }
+}
+
+struct Throttling {
+    clock: Arc<dyn Clock>,
+    interval_ms: u64,
+    keys: parking_lot::Mutex<HashMap<String, ThrottledKey>>,
+}
+
+impl Throttling {
+    fn pending(&self, key: &str) -> Option<String> {
+        self.keys.lock().get(key)?.pending.clone()
+    }
+
+    /// Returns `true` if the value must be written now
+    fn update(&self, key: &str, value: &str) -> bool {
+        let now = self.clock.now_ms_u64();
+
+        let mut keys = self.keys.lock();
+        let state = keys.entry(key.to_owned()).or_default();
+        match state.last_write_ms {
+            Some(last_write_ms) if now < last_write_ms.saturating_add(self.interval_ms) => {
+                state.pending = Some(value.to_owned());
+                false
+            }
+            _ => {
+                state.last_write_ms = Some(now);
+                state.pending = None;
+                true
+            }
+        }
+    }
+
+    /// Marks the key as written now, discarding the delayed value
+    fn reset(&self, key: &str) {
+        let now = self.clock.now_ms_u64();
+        let mut keys = self.keys.lock();
+        let state = keys.entry(key.to_owned()).or_default();
+        state.last_write_ms = Some(now);
+        state.pending = None;
+    }
+
+    fn remove(&self, key: &str) {
+        self.keys.lock().remove(key);
+    }
+}
+
+#[derive(Default)]
+struct ThrottledKey {
+    last_write_ms: Option<u64>,
+    pending: Option<String>,
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl Storage for JournaledStorage {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        if let Some(pending) = self.throttling.as_ref().and_then(|t| t.pending(key)) {
+            return Ok(Some(pending));
+        }
+
+        let journal_key = make_journal_key(key);
+        if let Some(journal) = self.inner.get(&journal_key).await? {
+            if let Some(data) = JournalEntry::parse(&journal) {
+                // Previous write was interrupted, finish it
+                self.inner.set(key, &data).await?;
+                self.inner.remove(&journal_key).await?;
+                return Ok(Some(data));
+            }
+
+            // Journal entry itself is incomplete, so the value was not touched
+            self.inner.remove(&journal_key).await?;
+        }
+
+        self.inner.get(key).await
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        if let Some(throttling) = &self.throttling {
+            throttling.reset(key);
+        }
+
+        let journal_key = make_journal_key(key);
+        self.inner
+            .set(&journal_key, &JournalEntry::make(value))
+            .await?;
+        self.inner.set(key, value).await?;
+        self.inner.remove(&journal_key).await
+    }
+
+    fn set_unchecked(&self, key: &str, value: &str) {
+        match &self.throttling {
+            Some(throttling) if !throttling.update(key, value) => {}
+            _ => self.write_unchecked(key, value),
+        }
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        if let Some(throttling) = &self.throttling {
+            throttling.remove(key);
+        }
+        self.inner.remove(&make_journal_key(key)).await?;
+        self.inner.remove(key).await
+    }
+
+    fn remove_unchecked(&self, key: &str) {
+        if let Some(throttling) = &self.throttling {
+            throttling.remove(key);
+        }
+        self.inner.remove_unchecked(&make_journal_key(key));
+        self.inner.remove_unchecked(key);
+    }
+}
+
+fn make_journal_key(key: &str) -> String {
+    format!("{key}{JOURNAL_SUFFIX}")
+}
+
+#[derive(Serialize, Deserialize)]
+struct JournalEntry<'a> {
+    checksum: String,
+    data: std::borrow::Cow<'a, str>,
+}
+
+impl<'a> JournalEntry<'a> {
+    fn make(data: &'a str) -> String {
+        serde_json::to_string(&JournalEntry {
+            checksum: checksum(data),
+            data: data.into(),
+        })
+        .trust_me()
+    }
+
+    fn parse(entry: &str) -> Option<String> {
+        let entry = serde_json::from_str::<JournalEntry<'_>>(entry).ok()?;
+        (entry.checksum == checksum(&entry.data)).then(|| entry.data.into_owned())
+    }
+}
+
+fn checksum(data: &str) -> String {
+    hex::encode(sha2::Sha256::digest(data.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{ManualClock, MemoryStorage};
+
+    #[tokio::test]
+    async fn recover_interrupted_write() {
+        let inner = Arc::new(MemoryStorage::default());
+        let storage = JournaledStorage::new(inner.clone());
+
+        storage.set("key", r#"["old"]"#).await.unwrap();
+        assert!(inner.get("key__journal").await.unwrap().is_none());
+
+        // Crash after the journal was written, but before the value was complete
+        inner.set_unchecked("key__journal", &JournalEntry::make(r#"["new"]"#));
+        inner.set_unchecked("key", r#"["ne"#);
+        assert_eq!(storage.get("key").await.unwrap().unwrap(), r#"["new"]"#);
+        assert_eq!(inner.get("key").await.unwrap().unwrap(), r#"["new"]"#);
+
+        // Crash during the journal write
+        inner.set_unchecked("key__journal", r#"{"checksum":"ab"#);
+        assert_eq!(storage.get("key").await.unwrap().unwrap(), r#"["new"]"#);
+        assert!(inner.get("key__journal").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn throttled_writes() {
+        let inner = Arc::new(MemoryStorage::default());
+        let clock = Arc::new(ManualClock::from_secs(1_700_000_000));
+        let storage = JournaledStorage::new(inner.clone()).with_throttling(clock.clone(), 1000);
+
+        storage.set_unchecked("key", "1");
+        storage.set_unchecked("key", "2");
+        storage.set_unchecked("key", "3");
+        assert_eq!(inner.get("key").await.unwrap().unwrap(), "1");
+        assert_eq!(storage.get("key").await.unwrap().unwrap(), "3");
+
+        // Next write after the interval stores the latest value
+        clock.advance(Duration::from_secs(1));
+        storage.set_unchecked("key", "4");
+        assert_eq!(inner.get("key").await.unwrap().unwrap(), "4");
+
+        storage.set_unchecked("key", "5");
+        storage.flush().await.unwrap();
+        assert_eq!(inner.get("key").await.unwrap().unwrap(), "5");
+
+        // Checked writes are not delayed
+        storage.set("key", "6").await.unwrap();
+        assert_eq!(inner.get("key").await.unwrap().unwrap(), "6");
+
+        storage.set_unchecked("key", "7");
+        drop(storage);
+        assert_eq!(inner.get("key").await.unwrap().unwrap(), "7");
+        assert!(inner.get("key__journal").await.unwrap().is_none());
+    }
+}
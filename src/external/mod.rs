@@ -1,6 +1,19 @@
+use std::collections::HashMap;
+#[cfg(feature = "bundled_address_labels")]
+use std::str::FromStr;
+
 use anyhow::Result;
-use nekoton_utils::serde_optional_hex_array;
+#[cfg(feature = "bundled_address_labels")]
+use nekoton_utils::TrustMe;
+use nekoton_utils::{serde_address, serde_optional_hex_array, serde_string};
 use serde::{Deserialize, Serialize};
+use ton_block::MsgAddressInt;
+
+pub use self::journaled_storage::JournaledStorage;
+use crate::core::models::{PendingTransaction, Transaction};
+pub use crate::models::{AddressCategory, AddressLabel};
+
+mod journaled_storage;
 
 #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
 #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
@@ -21,11 +34,21 @@ pub trait Storage: Sync + Send {
     fn remove_unchecked(&self, key: &str);
 }
 
+#[cfg(feature = "adnl_transport")]
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+pub trait AdnlConnection: Send + Sync {
+    /// Sends TL-serialized `liteServer.query` and returns the TL-serialized answer
+    async fn query(&self, request: Vec<u8>) -> Result<Vec<u8>>;
+}
+
 #[cfg(feature = "gql_transport")]
 #[derive(Debug, Clone)]
 pub struct GqlRequest {
     pub data: String,
     pub long_query: bool,
+    /// Whether the request can be safely retried (e.g. it is not a message broadcast)
+    pub idempotent: bool,
 }
 
 #[cfg(feature = "gql_transport")]
@@ -35,8 +58,43 @@ pub trait GqlConnection: Send + Sync {
     fn is_local(&self) -> bool;
 
     async fn post(&self, req: GqlRequest) -> Result<String>;
+
+    /// Whether [`GqlConnection::subscribe`] is supported (e.g. websockets are available)
+    fn supports_subscriptions(&self) -> bool {
+        false
+    }
+
+    /// Starts the GraphQL subscription (e.g. using `graphql-ws` protocol).
+    ///
+    /// Subscription is active until the returned handle is dropped
+    async fn subscribe(
+        &self,
+        req: GqlRequest,
+        handler: std::sync::Arc<dyn GqlSubscriptionHandler>,
+    ) -> Result<Box<dyn GqlSubscription>> {
+        let _ = (req, handler);
+        Err(GqlSubscriptionsNotSupported.into())
+    }
+}
+
+#[cfg(feature = "gql_transport")]
+pub trait GqlSubscriptionHandler: Send + Sync {
+    /// Called with the JSON encoded `data` field of each subscription event
+    fn on_data(&self, data: String);
+
+    /// Called once when the subscription is closed by the server or due to the connection error
+    fn on_closed(&self, error: Option<anyhow::Error>);
 }
 
+/// Handle of the active GraphQL subscription. Unsubscribes on drop
+#[cfg(feature = "gql_transport")]
+pub trait GqlSubscription: Send + Sync {}
+
+#[cfg(feature = "gql_transport")]
+#[derive(thiserror::Error, Debug, Copy, Clone)]
+#[error("GraphQL subscriptions are not supported")]
+pub struct GqlSubscriptionsNotSupported;
+
 #[cfg(feature = "jrpc_transport")]
 #[derive(Debug, Clone)]
 pub struct JrpcRequest {
@@ -100,3 +158,104 @@ pub trait LedgerConnection: Send + Sync {
         context: &LedgerSignatureContext,
     ) -> Result<[u8; ed25519_dalek::SIGNATURE_LENGTH]>;
 }
+
+/// Provides human-readable names for the well-known addresses
+/// (DEX routers, bridges, exchanges, etc.).
+///
+/// See [`crate::core::parsing::label_counterparties`]
+pub trait AddressLabeler: Send + Sync {
+    fn label(&self, address: &MsgAddressInt) -> Option<AddressLabel>;
+}
+
+/// Labeler with the fixed set of addresses
+#[derive(Default, Clone)]
+pub struct StaticAddressLabeler {
+    labels: HashMap<MsgAddressInt, AddressLabel>,
+}
+
+impl StaticAddressLabeler {
+    /// Labeler with the system contracts of the masterchain
+    #[cfg(feature = "bundled_address_labels")]
+    pub fn bundled() -> Self {
+        const SYSTEM_CONTRACTS: [(&str, &str); 3] = [
+            (
+                "-1:0000000000000000000000000000000000000000000000000000000000000000",
+                "Minter",
+            ),
+            (
+                "-1:3333333333333333333333333333333333333333333333333333333333333333",
+                "Elector",
+            ),
+            (
+                "-1:5555555555555555555555555555555555555555555555555555555555555555",
+                "Config",
+            ),
+        ];
+
+        let mut labeler = Self::default();
+        for (address, name) in SYSTEM_CONTRACTS {
+            labeler = labeler.with_label(
+                MsgAddressInt::from_str(address).trust_me(),
+                name,
+                AddressCategory::System,
+            );
+        }
+        labeler
+    }
+
+    pub fn with_label(
+        mut self,
+        address: MsgAddressInt,
+        name: impl Into<String>,
+        category: AddressCategory,
+    ) -> Self {
+        self.labels.insert(
+            address,
+            AddressLabel {
+                name: name.into(),
+                category,
+            },
+        );
+        self
+    }
+}
+
+impl AddressLabeler for StaticAddressLabeler {
+    fn label(&self, address: &MsgAddressInt) -> Option<AddressLabel> {
+        self.labels.get(address).cloned()
+    }
+}
+
+/// Receives normalized events from the subscriptions, e.g. to forward
+/// them into the external message queue.
+///
+/// See [`crate::core::ton_wallet::EventSinkHandler`]
+pub trait EventSink: Send + Sync {
+    fn on_event(&self, event: SubscriptionEvent);
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionEvent {
+    /// Subscribed contract
+    #[serde(with = "serde_address")]
+    pub address: MsgAddressInt,
+    pub kind: SubscriptionEventKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+pub enum SubscriptionEventKind {
+    /// New transactions were found (only for the new batches)
+    NewTransactions(Vec<Transaction>),
+    /// Contract balance changed
+    BalanceChanged(#[serde(with = "serde_string")] u64),
+    /// Transaction for the sent message was found
+    #[serde(rename_all = "camelCase")]
+    MessageSent {
+        pending_transaction: PendingTransaction,
+        transaction: Option<Transaction>,
+    },
+    /// No transaction was produced for the message before its expiration
+    MessageExpired(PendingTransaction),
+}
@@ -57,6 +57,8 @@ pub mod crypto;
 #[cfg(feature = "wallet_core")]
 pub mod external;
 pub mod models;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod transport;
 
 pub use nekoton_abi as abi;
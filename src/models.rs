@@ -28,6 +28,29 @@ pub enum TransactionAdditionalInfo {
     TokenWalletDeployed(TokenWalletDeployedNotification),
     /// User interaction with wallet contract
     WalletInteraction(WalletInteractionInfo),
+    /// Unspent value of some previous operation returned back
+    Refund(RefundInfo),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefundInfo {
+    /// Contract which returned the value.
+    /// Can be used to group the refund with the originating operation
+    #[serde(with = "serde_address")]
+    pub source: MsgAddressInt,
+    pub kind: RefundKind,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+pub enum RefundKind {
+    /// Bounced message (e.g. value attached to the failed deploy or token operation).
+    /// Contains the function id of the original message if it had one
+    Bounced(Option<u32>),
+    /// Transfer without body from the contract which was called by the wallet
+    /// (e.g. remaining gas from the token wallet after transfer)
+    RemainingGas,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -53,6 +76,18 @@ pub enum KnownPayload {
     Comment(String),
     TokenOutgoingTransfer(TokenOutgoingTransfer),
     TokenSwapBack(TokenSwapBack),
+    DePoolStake(DePoolStakeOperation),
+}
+
+/// Participant request to the DePool
+#[derive(Clone, Debug, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+pub enum DePoolStakeOperation {
+    /// Ordinary stake amount in nano EVER
+    AddOrdinaryStake(#[serde(with = "serde_string")] u64),
+    /// Amount in nano EVER to withdraw after the round completion
+    WithdrawPart(#[serde(with = "serde_string")] u64),
+    WithdrawAll,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -282,6 +317,15 @@ pub enum NftTransaction {
     ChangeManager(IncomingChangeManager),
 }
 
+/// Typed event, decoded from the raw transaction
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+pub enum ParsedTransaction {
+    Wallet(TransactionAdditionalInfo),
+    Token(TokenWalletTransaction),
+    Nft(NftTransaction),
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TokenIncomingTransfer {
@@ -379,6 +423,7 @@ pub struct NetworkCapabilities {
     /// Network global id.
     pub global_id: i32,
     /// Raw software capabilities.
+    #[serde(with = "serde_u64")]
     pub raw: u64,
 }
 
@@ -476,8 +521,11 @@ pub struct Symbol {
 #[derive(Debug, Clone, Default, Serialize, Deserialize, Copy)]
 #[serde(rename_all = "camelCase")]
 pub struct ContractState {
-    /// Latest known lt
-    #[serde(skip)]
+    /// Latest known lt.
+    ///
+    /// NOTE: serialized as `lastLt` string since the subscription snapshots were added,
+    /// `0` is used when it is missing in the data from the older versions
+    #[serde(default, with = "serde_u64")]
     pub last_lt: u64,
 
     /// Full account balance in nano TON
@@ -516,16 +564,27 @@ pub struct PendingTransaction {
     )]
     pub src: Option<MsgAddressInt>,
     /// Last known lt at the time the message was sent
+    #[serde(with = "serde_u64")]
     pub latest_lt: u64,
     /// Message broadcast timestamp (adjusted)
     pub created_at: u32,
     /// Expiration timestamp (adjusted)
     pub expire_at: u32,
+    /// Start of the broadcast window of the pre-signed message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub broadcast_not_before: Option<u32>,
+}
+
+impl PendingTransaction {
+    /// Returns the number of seconds until the message expires
+    pub fn expires_in(&self, clock: &dyn Clock) -> u32 {
+        self.expire_at.saturating_sub(clock.now_sec_u64() as u32)
+    }
 }
 
 impl PartialEq<Transaction> for PendingTransaction {
     fn eq(&self, other: &Transaction) -> bool {
-        self.expire_at <= other.created_at
+        other.created_at < self.expire_at
             && self.message_hash == other.in_msg.hash
             && self.src == other.in_msg.src
     }
@@ -543,6 +602,78 @@ pub enum MessageType {
 pub struct TransactionWithData<T> {
     pub transaction: Transaction,
     pub data: Option<T>,
+    /// Well-known counterparties of the transaction
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<LabeledAddress>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabeledAddress {
+    #[serde(with = "serde_address")]
+    pub address: MsgAddressInt,
+    pub label: AddressLabel,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressLabel {
+    pub name: String,
+    pub category: AddressCategory,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressCategory {
+    /// Blockchain system contracts
+    System,
+    Dex,
+    Bridge,
+    Exchange,
+    Staking,
+    Other,
+}
+
+/// Position in the account transactions history.
+///
+/// Points to the first transaction of the page (inclusive), so that
+/// pages don't overlap and nothing is skipped at page boundaries
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionsCursor {
+    #[serde(with = "serde_string")]
+    pub lt: u64,
+    #[serde(with = "serde_uint256")]
+    pub hash: UInt256,
+}
+
+impl TransactionsCursor {
+    /// Cursor of the transaction preceding the specified one.
+    ///
+    /// Returns `None` for the first transaction of the account
+    pub fn next_after(transaction: &ton_block::Transaction) -> Option<Self> {
+        (transaction.prev_trans_lt != 0).then_some(Self {
+            lt: transaction.prev_trans_lt,
+            hash: transaction.prev_trans_hash,
+        })
+    }
+
+    /// Cursor of the latest transaction, if it is known exactly
+    pub fn latest(last_transaction_id: &LastTransactionId) -> Option<Self> {
+        match last_transaction_id {
+            LastTransactionId::Exact(id) => Some((*id).into()),
+            LastTransactionId::Inexact { .. } => None,
+        }
+    }
+}
+
+impl From<TransactionId> for TransactionsCursor {
+    fn from(id: TransactionId) -> Self {
+        Self {
+            lt: id.lt,
+            hash: id.hash,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -964,10 +1095,31 @@ pub enum MessageFlagsError {
 mod tests {
     use super::*;
 
+    fn test_transaction() -> Transaction {
+        let transaction =  ton_block::Transaction::construct_from_base64("te6ccgECCgEAAmIAA7VxDMDpxVKoQf1ESN4flYWnx79MwznjFCnHv2LMYnj5e/AAAMAPptS0HL7tNWkkUnpwkWevWy0v6QllFeZdkxpKd3jABu53GMiwAADABeYcjBYH/izgADRpb9DoBQQBAhEMgEHGGW16hEADAgBvyYehIEwUWEAAAAAAAAIAAAAAAAJdRbUJwB114ymQlNQVCfa9Moy2h4xlzAjFN0wo4BiqckBQGUwAnUF2QxOIAAAAAAAAAAAQgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAACAAgnIKnXiVk1PWmbnJrrJ8ZuP6tVF8xWwdT4FzwpwwbcybITXW+aJKu2Ai+6iWudx7E+cmmtyYoMFlMnA6RJvjslElAgHgCAYBAd8HAMtoACGYHTiqVQg/qIkbw/KwtPj36ZhnPGKFOPfsWYxPHy9/AC7y/frS28SA7otT/U3XeMKVAioEwv3n4cO+8/UnsFk6VAnHZSQABhRYYAAAGAH02paEwP/FnAVWDH6AAAABKgXyAEAB34gAIZgdOKpVCD+oiRvD8rC0+PfpmGc8YoU49+xZjE8fL34FEnWHwu7iFVw1r2O1eQN6i3g5Ib9nJIGpQqRtpYG36Pjrmo9/vgPWf5ev1vhedfPUgkaxeInhVroDrGaLYfhoEl1JbFYH/i5IAAADOBwJAIJiAF3l+/Wlt4kB3Ran+puu8YUqBFQJhfvPw4d95+pPYLJ0qBOOykgAAAAAAAAAAAAAAAAAAAqsGP0AAAACVAvkAA==").unwrap();
+        Transaction::try_from((Default::default(), transaction)).unwrap()
+    }
+
     #[test]
     fn test_parse_transaction() {
-        let transaction =  ton_block::Transaction::construct_from_base64("te6ccgECCgEAAmIAA7VxDMDpxVKoQf1ESN4flYWnx79MwznjFCnHv2LMYnj5e/AAAMAPptS0HL7tNWkkUnpwkWevWy0v6QllFeZdkxpKd3jABu53GMiwAADABeYcjBYH/izgADRpb9DoBQQBAhEMgEHGGW16hEADAgBvyYehIEwUWEAAAAAAAAIAAAAAAAJdRbUJwB114ymQlNQVCfa9Moy2h4xlzAjFN0wo4BiqckBQGUwAnUF2QxOIAAAAAAAAAAAQgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAACAAgnIKnXiVk1PWmbnJrrJ8ZuP6tVF8xWwdT4FzwpwwbcybITXW+aJKu2Ai+6iWudx7E+cmmtyYoMFlMnA6RJvjslElAgHgCAYBAd8HAMtoACGYHTiqVQg/qIkbw/KwtPj36ZhnPGKFOPfsWYxPHy9/AC7y/frS28SA7otT/U3XeMKVAioEwv3n4cO+8/UnsFk6VAnHZSQABhRYYAAAGAH02paEwP/FnAVWDH6AAAABKgXyAEAB34gAIZgdOKpVCD+oiRvD8rC0+PfpmGc8YoU49+xZjE8fL34FEnWHwu7iFVw1r2O1eQN6i3g5Ib9nJIGpQqRtpYG36Pjrmo9/vgPWf5ev1vhedfPUgkaxeInhVroDrGaLYfhoEl1JbFYH/i5IAAADOBwJAIJiAF3l+/Wlt4kB3Ran+puu8YUqBFQJhfvPw4d95+pPYLJ0qBOOykgAAAAAAAAAAAAAAAAAAAqsGP0AAAACVAvkAA==").unwrap();
-        let parsed = Transaction::try_from((Default::default(), transaction)).unwrap();
+        let parsed = test_transaction();
         assert!(parsed.in_msg.body.is_some())
     }
+
+    #[test]
+    fn pending_transaction_matches_before_expiration() {
+        let transaction = test_transaction();
+        let mut pending = PendingTransaction {
+            message_hash: transaction.in_msg.hash,
+            src: transaction.in_msg.src.clone(),
+            latest_lt: 0,
+            created_at: transaction.created_at - 10,
+            expire_at: transaction.created_at + 60,
+            broadcast_not_before: None,
+        };
+        assert!(pending == transaction);
+
+        pending.expire_at = transaction.created_at;
+        assert!(pending != transaction);
+    }
 }
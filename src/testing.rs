@@ -0,0 +1,585 @@
+//! Deterministic in-memory chain for testing subscriptions and wallets.
+//!
+//! [`ChainSimulator`] combines a [`ManualClock`] with a [`MockTransport`], which
+//! executes sent messages on the local accounts only when the next block is produced.
+//! This allows to test pending transactions expiration, polling and transactions
+//! fetching without the real network.
+//!
+//! [`MemoryStorage`] is a storage for the stores which are tested along with them.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use ton_block::{Account, MsgAddressInt, Serializable};
+use ton_executor::BlockchainConfig;
+use ton_types::UInt256;
+
+use nekoton_abi::{Executor, GenTimings, LastTransactionId, TransactionId};
+use nekoton_utils::Clock;
+
+use crate::models::{NetworkCapabilities, ReliableBehavior};
+use crate::transport::models::*;
+use crate::transport::{Transport, TransportCapabilities, TransportInfo};
+
+/// Max number of messages processed in one block (prevents infinite bounce loops)
+const MAX_MESSAGES_PER_BLOCK: usize = 1000;
+
+/// Clock which only changes when it is explicitly advanced
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now_ms: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn from_secs(secs: u64) -> Self {
+        Self {
+            now_ms: AtomicU64::new(secs * 1000),
+        }
+    }
+
+    pub fn set_ms(&self, now_ms: u64) {
+        self.now_ms.store(now_ms, Ordering::Release);
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now_ms
+            .fetch_add(duration.as_millis() as u64, Ordering::AcqRel);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_sec_u64(&self) -> u64 {
+        self.now_ms_u64() / 1000
+    }
+
+    fn now_ms_f64(&self) -> f64 {
+        self.now_ms_u64() as f64
+    }
+
+    fn now_ms_u64(&self) -> u64 {
+        self.now_ms.load(Ordering::Acquire)
+    }
+}
+
+/// Storage which keeps all values in memory
+#[cfg(feature = "wallet_core")]
+#[derive(Debug, Default)]
+pub struct MemoryStorage(Mutex<HashMap<String, String>>);
+
+#[cfg(feature = "wallet_core")]
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl crate::external::Storage for MemoryStorage {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.0.lock().get(key).cloned())
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.set_unchecked(key, value);
+        Ok(())
+    }
+
+    fn set_unchecked(&self, key: &str, value: &str) {
+        self.0.lock().insert(key.to_string(), value.to_string());
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        self.remove_unchecked(key);
+        Ok(())
+    }
+
+    fn remove_unchecked(&self, key: &str) {
+        self.0.lock().remove(key);
+    }
+}
+
+/// Transport over the in-memory accounts.
+///
+/// Sent messages are queued until [`MockTransport::produce_block`] is called
+pub struct MockTransport {
+    clock: Arc<dyn Clock>,
+    config: BlockchainConfig,
+    state: Mutex<ChainState>,
+}
+
+#[derive(Default)]
+struct ChainState {
+    lt: u64,
+    accounts: HashMap<MsgAddressInt, AccountEntry>,
+    pending_messages: Vec<ton_block::Message>,
+    reject_messages: bool,
+    transactions: HashMap<UInt256, (MsgAddressInt, usize)>,
+    dst_transactions: HashMap<UInt256, UInt256>,
+}
+
+struct AccountEntry {
+    account: Account,
+    gen_utime: u32,
+    /// Ordered by lt ascending
+    transactions: Vec<RawTransaction>,
+}
+
+impl AccountEntry {
+    fn to_state(&self) -> RawContractState {
+        let timings = GenTimings::Known {
+            gen_lt: self
+                .transactions
+                .last()
+                .map(|transaction| transaction.data.lt + 1)
+                .unwrap_or_default(),
+            gen_utime: self.gen_utime,
+        };
+
+        match &self.account {
+            Account::Account(account) => RawContractState::Exists(ExistingContract {
+                account: account.clone(),
+                timings,
+                last_transaction_id: match self.transactions.last() {
+                    Some(transaction) => LastTransactionId::Exact(TransactionId {
+                        lt: transaction.data.lt,
+                        hash: transaction.hash,
+                    }),
+                    None => LastTransactionId::Inexact {
+                        latest_lt: account.storage.last_trans_lt,
+                    },
+                },
+            }),
+            Account::AccountNone => RawContractState::NotExists { timings },
+        }
+    }
+}
+
+/// Result of the block production
+#[derive(Debug, Clone, Default)]
+pub struct ProducedBlock {
+    pub gen_utime: u32,
+    pub transactions: Vec<RawTransaction>,
+    /// Messages which were rejected by the executor (e.g. expired external messages)
+    pub rejected_messages: Vec<ton_block::Message>,
+}
+
+impl MockTransport {
+    pub fn new(clock: Arc<dyn Clock>, config: BlockchainConfig) -> Self {
+        Self {
+            clock,
+            config,
+            state: Mutex::new(ChainState {
+                lt: 1_000_000,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Replaces the account state (e.g. to create a funded account)
+    pub fn set_account(&self, address: MsgAddressInt, account: Account) {
+        let gen_utime = self.clock.now_sec_u64() as u32;
+        let mut state = self.state.lock();
+        match state.accounts.get_mut(&address) {
+            Some(entry) => {
+                entry.account = account;
+                entry.gen_utime = gen_utime;
+            }
+            None => {
+                state.accounts.insert(
+                    address,
+                    AccountEntry {
+                        account,
+                        gen_utime,
+                        transactions: Vec::new(),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Messages which will be executed in the next block
+    pub fn pending_messages(&self) -> Vec<ton_block::Message> {
+        self.state.lock().pending_messages.clone()
+    }
+
+    /// Makes all subsequent `send_message` calls fail (simulates broadcast errors)
+    pub fn set_reject_messages(&self, reject: bool) {
+        self.state.lock().reject_messages = reject;
+    }
+
+    /// Drops all messages which were not executed yet (simulates lost messages)
+    pub fn drop_pending_messages(&self) -> usize {
+        std::mem::take(&mut self.state.lock().pending_messages).len()
+    }
+
+    /// Executes all queued messages and internal messages produced by them
+    pub fn produce_block(&self) -> Result<ProducedBlock> {
+        let gen_utime = self.clock.now_sec_u64() as u32;
+
+        let mut state = self.state.lock();
+        let mut queue = std::mem::take(&mut state.pending_messages);
+        queue.reverse();
+
+        let mut block = ProducedBlock {
+            gen_utime,
+            ..Default::default()
+        };
+
+        let mut processed = 0;
+        while let Some(message) = queue.pop() {
+            processed += 1;
+            if processed > MAX_MESSAGES_PER_BLOCK {
+                return Err(MockTransportError::TooManyMessages.into());
+            }
+
+            let address = match message.dst() {
+                Some(address) => address,
+                None => continue,
+            };
+
+            let account = state
+                .accounts
+                .get(&address)
+                .map(|entry| entry.account.clone())
+                .unwrap_or(Account::AccountNone);
+
+            let mut executor =
+                Executor::with_params(self.config.clone(), account, state.lt, gen_utime, state.lt);
+            let transaction = match executor.run_mut(&message) {
+                Ok(transaction) => transaction,
+                Err(e) => {
+                    log::debug!("Message rejected: {e:?}");
+                    block.rejected_messages.push(message);
+                    continue;
+                }
+            };
+            state.lt = executor.last_transaction_lt() + 1;
+
+            let mut out_messages = Vec::new();
+            transaction.iterate_out_msgs(|message| {
+                if message.is_internal() {
+                    out_messages.push(message);
+                }
+                Ok(true)
+            })?;
+            // NOTE: internal messages are executed in the same block,
+            // right after the transaction which produced them
+            queue.extend(out_messages.into_iter().rev());
+
+            let hash = transaction.serialize()?.repr_hash();
+            let raw = RawTransaction {
+                hash,
+                data: transaction,
+            };
+
+            let entry = state
+                .accounts
+                .entry(address.clone())
+                .or_insert_with(|| AccountEntry {
+                    account: Account::AccountNone,
+                    gen_utime,
+                    transactions: Vec::new(),
+                });
+            entry.account = executor.into_account();
+            entry.gen_utime = gen_utime;
+            entry.transactions.push(raw.clone());
+            let index = entry.transactions.len() - 1;
+
+            state.transactions.insert(hash, (address, index));
+            if let Some(in_msg) = &raw.data.in_msg {
+                state
+                    .dst_transactions
+                    .insert(in_msg.cell().repr_hash(), hash);
+            }
+            block.transactions.push(raw);
+        }
+
+        Ok(block)
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl Transport for MockTransport {
+    fn info(&self) -> TransportInfo {
+        TransportInfo {
+            max_transactions_per_fetch: 50,
+            reliable_behavior: ReliableBehavior::IntensivePolling,
+            has_key_blocks: false,
+        }
+    }
+
+    fn capabilities(&self) -> TransportCapabilities {
+        TransportCapabilities {
+            blocks: false,
+            code_hash_search: true,
+            batched_states: false,
+            proofs: false,
+            time_sync: true,
+            subscriptions: false,
+            message_lookup: true,
+        }
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        if message.ext_in_header().is_none() {
+            return Err(MockTransportError::ExternalMessageExpected.into());
+        }
+
+        let mut state = self.state.lock();
+        if state.reject_messages {
+            return Err(MockTransportError::MessageRejected.into());
+        }
+        state.pending_messages.push(message.clone());
+        Ok(())
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        let state = self.state.lock();
+        Ok(match state.accounts.get(address) {
+            Some(entry) => entry.to_state(),
+            None => RawContractState::NotExists {
+                timings: GenTimings::Known {
+                    gen_lt: state.lt,
+                    gen_utime: self.clock.now_sec_u64() as u32,
+                },
+            },
+        })
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        let state = self.get_contract_state(address).await?;
+        Ok(match &state {
+            RawContractState::Exists(contract)
+                if contract.account.storage.last_trans_lt == last_trans_lt =>
+            {
+                PollContractState::Unchanged {
+                    timings: contract.timings,
+                }
+            }
+            _ => state.into(),
+        })
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        let state = self.state.lock();
+        let mut addresses = state
+            .accounts
+            .iter()
+            .filter_map(|(address, entry)| {
+                let code_hash_matches = match &entry.account {
+                    Account::Account(account) => matches!(
+                        &account.storage.state,
+                        ton_block::AccountState::AccountActive { state_init, .. }
+                            if matches!(&state_init.code, Some(code) if &code.repr_hash() == code_hash)
+                    ),
+                    Account::AccountNone => false,
+                };
+                code_hash_matches.then(|| address.clone())
+            })
+            .collect::<Vec<_>>();
+        addresses.sort_by_cached_key(ToString::to_string);
+
+        let skip = match continuation {
+            Some(continuation) => addresses
+                .iter()
+                .position(|address| address == continuation)
+                .map(|index| index + 1)
+                .unwrap_or_default(),
+            None => 0,
+        };
+
+        Ok(addresses
+            .into_iter()
+            .skip(skip)
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        let state = self.state.lock();
+        Ok(match state.accounts.get(address) {
+            Some(entry) => entry
+                .transactions
+                .iter()
+                .rev()
+                .filter(|transaction| transaction.data.lt <= from_lt)
+                .take(count as usize)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        })
+    }
+
+    async fn get_transaction(&self, id: &UInt256) -> Result<Option<RawTransaction>> {
+        let state = self.state.lock();
+        Ok(state.transactions.get(id).and_then(|(address, index)| {
+            state
+                .accounts
+                .get(address)
+                .and_then(|entry| entry.transactions.get(*index))
+                .cloned()
+        }))
+    }
+
+    async fn get_dst_transaction(&self, message_hash: &UInt256) -> Result<Option<RawTransaction>> {
+        let id = self
+            .state
+            .lock()
+            .dst_transactions
+            .get(message_hash)
+            .copied();
+        match id {
+            Some(id) => self.get_transaction(&id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        Err(MockTransportError::KeyBlocksNotSupported.into())
+    }
+
+    async fn get_chain_time(&self) -> Result<u32> {
+        Ok(self.clock.now_sec_u64() as u32)
+    }
+
+    async fn get_capabilities(&self, _: &dyn Clock) -> Result<NetworkCapabilities> {
+        Ok(NetworkCapabilities {
+            global_id: self.config.global_id(),
+            raw: self.config.capabilites(),
+        })
+    }
+
+    async fn get_blockchain_config(&self, _: &dyn Clock, _: bool) -> Result<BlockchainConfig> {
+        Ok(self.config.clone())
+    }
+}
+
+/// Manually controlled chain: time advances only when requested,
+/// and blocks are produced only when requested
+pub struct ChainSimulator {
+    clock: Arc<ManualClock>,
+    transport: Arc<MockTransport>,
+}
+
+impl ChainSimulator {
+    /// Creates an empty chain with the default blockchain config
+    pub fn new(now_sec: u64) -> Self {
+        Self::with_config(now_sec, nekoton_abi::default_blockchain_config().clone())
+    }
+
+    pub fn with_config(now_sec: u64, config: BlockchainConfig) -> Self {
+        let clock = Arc::new(ManualClock::from_secs(now_sec));
+        let transport = Arc::new(MockTransport::new(clock.clone(), config));
+        Self { clock, transport }
+    }
+
+    pub fn clock(&self) -> &Arc<ManualClock> {
+        &self.clock
+    }
+
+    pub fn transport(&self) -> &Arc<MockTransport> {
+        &self.transport
+    }
+
+    /// Advances the time and produces a new block
+    pub fn advance(&self, duration: Duration) -> Result<ProducedBlock> {
+        self.clock.advance(duration);
+        self.transport.produce_block()
+    }
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone)]
+enum MockTransportError {
+    #[error("External message expected")]
+    ExternalMessageExpected,
+    #[error("Message rejected")]
+    MessageRejected,
+    #[error("Too many messages in one block")]
+    TooManyMessages,
+    #[error("Key blocks are not supported")]
+    KeyBlocksNotSupported,
+}
+
+#[cfg(all(test, feature = "wallet_core"))]
+mod tests {
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+    use ton_block::CurrencyCollection;
+
+    use super::*;
+    use crate::core::models::Expiration;
+    use crate::core::ton_wallet::wallet_v3;
+    use crate::crypto::UnsignedMessage;
+
+    #[tokio::test]
+    async fn deploy_and_expire() {
+        let simulator = ChainSimulator::new(1650000000);
+        let transport = simulator.transport();
+
+        let secret = SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        let keypair = Keypair { secret, public };
+
+        let address = wallet_v3::compute_contract_address(&public, 0);
+        transport.set_account(
+            address.clone(),
+            Account::with_address_and_ballance(
+                &address,
+                &CurrencyCollection::with_grams(10_000_000_000),
+            ),
+        );
+
+        let deploy = |expiration| {
+            let message =
+                wallet_v3::prepare_deploy(simulator.clock().as_ref(), &public, 0, expiration)
+                    .unwrap();
+            let signature = keypair.sign(message.hash()).to_bytes();
+            message.sign(&signature).unwrap().message
+        };
+
+        // Expired message is rejected
+        transport
+            .send_message(&deploy(Expiration::Timeout(10)))
+            .await
+            .unwrap();
+        let block = simulator.advance(Duration::from_secs(20)).unwrap();
+        assert!(block.transactions.is_empty());
+        assert_eq!(block.rejected_messages.len(), 1);
+
+        // Fresh message is executed
+        let message = deploy(Expiration::Timeout(60));
+        transport.send_message(&message).await.unwrap();
+        let block = simulator.advance(Duration::from_secs(1)).unwrap();
+        assert_eq!(block.transactions.len(), 1);
+
+        let state = transport.get_contract_state(&address).await.unwrap();
+        assert!(state.brief().is_deployed);
+
+        let transaction = transport
+            .get_dst_transaction(&message.serialize().unwrap().repr_hash())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(transaction, block.transactions[0]);
+
+        let last_lt = state.brief().last_lt;
+        let polled = transport
+            .poll_contract_state(&address, last_lt)
+            .await
+            .unwrap();
+        assert!(matches!(polled, PollContractState::Unchanged { .. }));
+    }
+}
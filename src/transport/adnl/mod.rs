@@ -0,0 +1,542 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use quick_cache::sync::Cache as QuickCache;
+use ton_block::{Account, Deserializable, MsgAddressInt, Serializable};
+use ton_types::UInt256;
+
+use nekoton_abi::{GenTimings, LastTransactionId, TransactionId};
+use nekoton_utils::*;
+
+use crate::core::models::{NetworkCapabilities, ReliableBehavior};
+use crate::external::AdnlConnection;
+
+use self::tl::BlockIdExt;
+use super::models::{
+    ExistingContract, PollContractState, RawContractState, RawTransaction, StateAt,
+};
+use super::utils::*;
+use super::{Transport, TransportCapabilities, TransportInfo};
+
+mod tl;
+
+/// Lite server returns at most 16 transactions per request
+const MAX_TRANSACTIONS_PER_FETCH: u8 = 16;
+
+/// Default number of requests to reach older transactions (i.e. 320 transactions)
+const DEFAULT_MAX_WALK_REQUESTS: usize = 20;
+
+/// Transport which talks to the lite servers directly, without any indexer.
+///
+/// NOTE: lite servers don't index transactions by hash and accounts by code hash,
+/// so `get_transaction`, `get_dst_transaction` and `get_accounts_by_code_hash`
+/// are not supported
+pub struct AdnlTransport {
+    connection: Arc<dyn AdnlConnection>,
+    config_cache: ConfigCache,
+    /// Known transaction hashes by (account, lt) to continue pagination
+    transaction_hashes: QuickCache<(MsgAddressInt, u64), UInt256>,
+    max_walk_requests: usize,
+}
+
+impl AdnlTransport {
+    pub fn new(connection: Arc<dyn AdnlConnection>) -> Self {
+        const DEFAULT_TRANSACTION_HASHES_CAPACITY: usize = 1000;
+
+        Self {
+            connection,
+            config_cache: ConfigCache::new(false),
+            transaction_hashes: QuickCache::new(DEFAULT_TRANSACTION_HASHES_CAPACITY),
+            max_walk_requests: DEFAULT_MAX_WALK_REQUESTS,
+        }
+    }
+
+    /// Limits the number of requests in [`Transport::get_transactions`].
+    ///
+    /// Older transactions can only be reached by walking from the latest one,
+    /// so fetching them fails when the limit is exceeded. Default: `20`
+    pub fn with_max_walk_requests(mut self, max_walk_requests: usize) -> Self {
+        self.max_walk_requests = max_walk_requests.max(1);
+        self
+    }
+
+    async fn get_masterchain_info(&self) -> Result<BlockIdExt> {
+        let data = self.connection.query(tl::get_masterchain_info()).await?;
+        tl::parse_masterchain_info(&data)
+    }
+
+    async fn get_block(&self, id: &BlockIdExt) -> Result<ton_block::Block> {
+        let data = self.connection.query(tl::get_block(id)).await?;
+        let (_, data) = tl::parse_block_data(&data)?;
+
+        let cell = ton_types::deserialize_tree_of_cells(&mut data.as_slice())?;
+        if cell.repr_hash() != id.root_hash {
+            return Err(AdnlTransportError::InvalidBlock.into());
+        }
+        Ok(ton_block::Block::construct_from_cell(cell)?)
+    }
+
+    /// Returns the latest account state and the hash of its last transaction
+    async fn get_account_state(
+        &self,
+        address: &MsgAddressInt,
+    ) -> Result<(RawContractState, Option<UInt256>)> {
+        let block_id = self.get_masterchain_info().await?;
+        self.get_account_state_at(&block_id, address, GenTimings::Unknown)
+            .await
+    }
+
+    /// Returns the account state after the specified masterchain block
+    async fn get_account_state_at(
+        &self,
+        block_id: &BlockIdExt,
+        address: &MsgAddressInt,
+        timings: GenTimings,
+    ) -> Result<(RawContractState, Option<UInt256>)> {
+        let data = self
+            .connection
+            .query(tl::get_account_state(block_id, address))
+            .await?;
+        let response = tl::parse_account_state(&data)?;
+
+        if response.state.is_empty() {
+            return Ok((RawContractState::NotExists { timings }, None));
+        }
+
+        let account = match Account::construct_from_bytes(&response.state) {
+            Ok(Account::Account(account)) => account,
+            Ok(Account::AccountNone) => return Ok((RawContractState::NotExists { timings }, None)),
+            Err(_) => return Err(AdnlTransportError::InvalidAccountState.into()),
+        };
+
+        let last_trans_lt = account.storage.last_trans_lt;
+        let last_trans_hash = find_last_transaction_hash(&response.proof, address)?;
+
+        let last_transaction_id = match last_trans_hash {
+            Some(hash) => LastTransactionId::Exact(TransactionId {
+                lt: last_trans_lt,
+                hash,
+            }),
+            None => LastTransactionId::Inexact {
+                latest_lt: last_trans_lt,
+            },
+        };
+
+        Ok((
+            RawContractState::Exists(ExistingContract {
+                account,
+                timings,
+                last_transaction_id,
+            }),
+            last_trans_hash,
+        ))
+    }
+
+    /// Fetches transactions starting from the specified one (inclusive), newest first
+    async fn get_transactions_batch(
+        &self,
+        address: &MsgAddressInt,
+        lt: u64,
+        hash: &UInt256,
+    ) -> Result<Vec<RawTransaction>> {
+        let data = self
+            .connection
+            .query(tl::get_transactions(
+                MAX_TRANSACTIONS_PER_FETCH as u32,
+                address,
+                lt,
+                hash,
+            ))
+            .await?;
+        let data = tl::parse_transaction_list(&data)?;
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        ton_types::deserialize_cells_tree(&mut data.as_slice())?
+            .into_iter()
+            .map(|cell| {
+                let hash = cell.repr_hash();
+                let data = ton_block::Transaction::construct_from_cell(cell)?;
+                self.transaction_hashes
+                    .insert((address.clone(), data.prev_trans_lt), data.prev_trans_hash);
+                Ok(RawTransaction { hash, data })
+            })
+            .collect()
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl Transport for AdnlTransport {
+    fn info(&self) -> TransportInfo {
+        TransportInfo {
+            max_transactions_per_fetch: MAX_TRANSACTIONS_PER_FETCH,
+            reliable_behavior: ReliableBehavior::IntensivePolling,
+            has_key_blocks: true,
+        }
+    }
+
+    fn capabilities(&self) -> TransportCapabilities {
+        TransportCapabilities {
+            blocks: false,
+            code_hash_search: false,
+            batched_states: false,
+            // NOTE: proofs are only used to find the last transaction hash,
+            // received states are not verified against them
+            proofs: false,
+            time_sync: true,
+            subscriptions: false,
+            message_lookup: false,
+        }
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        let body = message.write_to_bytes()?;
+        let data = self.connection.query(tl::send_message(&body)).await?;
+        tl::parse_send_msg_status(&data)?;
+        Ok(())
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        let (state, _) = self.get_account_state(address).await?;
+        Ok(state)
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        let state = self.get_contract_state(address).await?;
+        Ok(match state {
+            RawContractState::Exists(contract)
+                if contract.account.storage.last_trans_lt == last_trans_lt =>
+            {
+                PollContractState::Unchanged {
+                    timings: contract.timings,
+                }
+            }
+            state => state.into(),
+        })
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        _code_hash: &UInt256,
+        _limit: u8,
+        _continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        Err(AdnlTransportError::MethodNotSupported.into())
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        let (mut lt, mut hash) = match self.transaction_hashes.get(&(address.clone(), from_lt)) {
+            Some(hash) => (from_lt, hash),
+            None => match self.get_account_state(address).await? {
+                (RawContractState::Exists(contract), Some(hash)) => {
+                    (contract.account.storage.last_trans_lt, hash)
+                }
+                _ => return Ok(Vec::new()),
+            },
+        };
+
+        // NOTE: lite server can only iterate transactions from the known one,
+        // so older transactions are reached by walking from the latest
+        let mut result = Vec::with_capacity(count as usize);
+        let mut requests = 0;
+        while result.len() < count as usize && lt != 0 {
+            if requests >= self.max_walk_requests {
+                return Err(AdnlTransportError::TooManyWalkRequests.into());
+            }
+            requests += 1;
+
+            let transactions = self.get_transactions_batch(address, lt, &hash).await?;
+            let last = match transactions.last() {
+                Some(last) => last,
+                None => break,
+            };
+            lt = last.data.prev_trans_lt;
+            hash = last.data.prev_trans_hash;
+
+            result.extend(
+                transactions
+                    .into_iter()
+                    .filter(|transaction| transaction.data.lt <= from_lt),
+            );
+        }
+        result.truncate(count as usize);
+
+        Ok(result)
+    }
+
+    async fn get_transaction(&self, _id: &UInt256) -> Result<Option<RawTransaction>> {
+        Err(AdnlTransportError::MethodNotSupported.into())
+    }
+
+    async fn get_dst_transaction(&self, _message_hash: &UInt256) -> Result<Option<RawTransaction>> {
+        Err(AdnlTransportError::MethodNotSupported.into())
+    }
+
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: StateAt,
+    ) -> Result<RawContractState> {
+        let query = match at {
+            StateAt::Block(seqno) => {
+                tl::lookup_block(ton_block::MASTERCHAIN_ID, tl::MASTERCHAIN_SHARD, seqno)
+            }
+            StateAt::Time(utime) => {
+                tl::lookup_block_by_utime(ton_block::MASTERCHAIN_ID, tl::MASTERCHAIN_SHARD, utime)
+            }
+        };
+
+        let data = self.connection.query(query).await?;
+        let block_id = tl::parse_block_header(&data)?;
+
+        let block = self.get_block(&block_id).await?;
+        let info = block.info.read_struct()?;
+        let timings = GenTimings::Known {
+            gen_lt: info.end_lt(),
+            gen_utime: info.gen_utime().as_u32(),
+        };
+
+        let (state, _) = self
+            .get_account_state_at(&block_id, address, timings)
+            .await?;
+        Ok(state)
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        let last_block_id = self.get_masterchain_info().await?;
+        let last_block = self.get_block(&last_block_id).await?;
+
+        let info = last_block.info.read_struct()?;
+        if info.key_block() {
+            return Ok(last_block);
+        }
+
+        let data = self
+            .connection
+            .query(tl::lookup_block(
+                last_block_id.workchain,
+                tl::MASTERCHAIN_SHARD,
+                info.prev_key_block_seqno(),
+            ))
+            .await?;
+        let key_block_id = tl::parse_block_header(&data)?;
+
+        self.get_block(&key_block_id).await
+    }
+
+    async fn get_chain_time(&self) -> Result<u32> {
+        let data = self
+            .connection
+            .query(tl::get_masterchain_info_ext())
+            .await?;
+        let (_, last_utime) = tl::parse_masterchain_info_ext(&data)?;
+        Ok(last_utime)
+    }
+
+    async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        let (capabilities, _) = self
+            .config_cache
+            .get_blockchain_config(self, clock, false)
+            .await?;
+        Ok(capabilities)
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        clock: &dyn Clock,
+        force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        let (_, config) = self
+            .config_cache
+            .get_blockchain_config(self, clock, force)
+            .await?;
+        Ok(config)
+    }
+}
+
+/// Reads the shard account from the shard state proof
+fn find_last_transaction_hash(proof: &[u8], address: &MsgAddressInt) -> Result<Option<UInt256>> {
+    let roots = ton_types::deserialize_cells_tree(&mut &*proof)?;
+    let state_proof = roots
+        .get(1)
+        .ok_or(AdnlTransportError::InvalidAccountState)?;
+
+    let state_proof = ton_block::MerkleProof::construct_from_cell(state_proof.clone())?;
+    let state = ton_block::ShardStateUnsplit::construct_from_cell(state_proof.proof.virtualize(1))?;
+
+    let shard_account = state.read_accounts()?.account(&address.address())?;
+    Ok(shard_account.map(|account| *account.last_trans_hash()))
+}
+
+#[derive(thiserror::Error, Debug)]
+enum AdnlTransportError {
+    #[error("Invalid account state")]
+    InvalidAccountState,
+    #[error("Invalid block")]
+    InvalidBlock,
+    #[error("Method is not supported by lite servers")]
+    MethodNotSupported,
+    #[error("Too many requests to reach the transactions")]
+    TooManyWalkRequests,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::tl::answers;
+    use super::*;
+
+    struct HistoricalStateConnection {
+        block_id: BlockIdExt,
+        block: Vec<u8>,
+    }
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl AdnlConnection for HistoricalStateConnection {
+        async fn query(&self, request: Vec<u8>) -> Result<Vec<u8>> {
+            Ok(match answers::query_id(&request) {
+                answers::LOOKUP_BLOCK_ID => answers::block_header(&self.block_id),
+                answers::GET_BLOCK_ID => answers::block_data(&self.block_id, &self.block),
+                answers::GET_MASTERCHAIN_INFO_EXT_ID => {
+                    answers::masterchain_info_ext(&self.block_id, 1000)
+                }
+                answers::GET_ACCOUNT_STATE_ID => {
+                    // State must be requested at the found block
+                    let block_id = answers::block_id_of_account_state_query(&request);
+                    assert_eq!(block_id, self.block_id);
+                    answers::account_state(&block_id, &[], &[])
+                }
+                id => anyhow::bail!("unexpected query {id:08x}"),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn contract_state_at_time() {
+        let block = ton_block::Block::default().serialize().unwrap();
+        let block_id = BlockIdExt {
+            workchain: ton_block::MASTERCHAIN_ID,
+            shard: tl::MASTERCHAIN_SHARD,
+            seqno: 123,
+            root_hash: block.repr_hash(),
+            file_hash: Default::default(),
+        };
+
+        let transport = AdnlTransport::new(Arc::new(HistoricalStateConnection {
+            block_id,
+            block: ton_types::serialize_toc(&block).unwrap(),
+        }));
+
+        let address = MsgAddressInt::from_str(
+            "0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb",
+        )
+        .unwrap();
+
+        let state = transport
+            .get_contract_state_at(&address, StateAt::Time(1000))
+            .await
+            .unwrap();
+        assert!(matches!(
+            state,
+            RawContractState::NotExists {
+                timings: GenTimings::Known { .. }
+            }
+        ));
+
+        let state = transport
+            .get_contract_state_at(&address, StateAt::Block(123))
+            .await
+            .unwrap();
+        assert!(matches!(
+            state,
+            RawContractState::NotExists {
+                timings: GenTimings::Known { .. }
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn chain_time_from_masterchain_info() {
+        let block_id = BlockIdExt {
+            workchain: ton_block::MASTERCHAIN_ID,
+            shard: tl::MASTERCHAIN_SHARD,
+            seqno: 123,
+            root_hash: Default::default(),
+            file_hash: Default::default(),
+        };
+
+        // NOTE: full block is not requested, so it is not provided
+        let transport = AdnlTransport::new(Arc::new(HistoricalStateConnection {
+            block_id,
+            block: Vec::new(),
+        }));
+        assert_eq!(transport.get_chain_time().await.unwrap(), 1000);
+    }
+
+    /// Returns the same transaction for all transactions queries
+    struct EndlessHistoryConnection {
+        transaction: Vec<u8>,
+        requests: std::sync::atomic::AtomicUsize,
+    }
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl AdnlConnection for EndlessHistoryConnection {
+        async fn query(&self, request: Vec<u8>) -> Result<Vec<u8>> {
+            Ok(match answers::query_id(&request) {
+                answers::GET_TRANSACTIONS_ID => {
+                    self.requests
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    answers::transaction_list(&self.transaction)
+                }
+                id => anyhow::bail!("unexpected query {id:08x}"),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn transactions_walk_is_bounded() {
+        // Transaction is always newer than the requested one,
+        // so the walk would never stop without the limit
+        let transaction = ton_block::Transaction {
+            lt: 2_000_000,
+            prev_trans_lt: 2_000_000,
+            ..Default::default()
+        };
+        let connection = Arc::new(EndlessHistoryConnection {
+            transaction: ton_types::serialize_toc(&transaction.serialize().unwrap()).unwrap(),
+            requests: Default::default(),
+        });
+        let transport = AdnlTransport::new(connection.clone()).with_max_walk_requests(3);
+
+        let address = MsgAddressInt::from_str(
+            "0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb",
+        )
+        .unwrap();
+        transport
+            .transaction_hashes
+            .insert((address.clone(), 1_000_000), UInt256::default());
+
+        let result = transport.get_transactions(&address, 1_000_000, 10).await;
+        assert!(result.is_err());
+        assert_eq!(
+            connection
+                .requests
+                .load(std::sync::atomic::Ordering::Relaxed),
+            3
+        );
+    }
+}
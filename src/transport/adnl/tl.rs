@@ -0,0 +1,399 @@
+//! Minimal TL codec for the lite server queries used by the transport
+
+use anyhow::Result;
+use ton_block::MsgAddressInt;
+use ton_types::UInt256;
+
+const LITE_SERVER_QUERY: u32 = 0x798c06df;
+const LITE_SERVER_ERROR: u32 = 0xbba9e148;
+
+const GET_MASTERCHAIN_INFO: u32 = 0x89b5e62e;
+const MASTERCHAIN_INFO: u32 = 0x85832881;
+const GET_MASTERCHAIN_INFO_EXT: u32 = 0x70a671df;
+const MASTERCHAIN_INFO_EXT: u32 = 0xa8cce0f5;
+const GET_ACCOUNT_STATE: u32 = 0x6b890e25;
+const ACCOUNT_STATE: u32 = 0x7079c751;
+const GET_TRANSACTIONS: u32 = 0x1c40e7a1;
+const TRANSACTION_LIST: u32 = 0x6f26c60b;
+const SEND_MESSAGE: u32 = 0x690ad482;
+const SEND_MSG_STATUS: u32 = 0x3950e597;
+const GET_BLOCK: u32 = 0x6377cf0d;
+const BLOCK_DATA: u32 = 0xa574ed6c;
+const LOOKUP_BLOCK: u32 = 0xfac8f71e;
+const BLOCK_HEADER: u32 = 0x752d8219;
+
+pub const MASTERCHAIN_SHARD: u64 = 0x8000000000000000;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BlockIdExt {
+    pub workchain: i32,
+    pub shard: u64,
+    pub seqno: u32,
+    pub root_hash: UInt256,
+    pub file_hash: UInt256,
+}
+
+pub struct AccountState {
+    pub proof: Vec<u8>,
+    pub state: Vec<u8>,
+}
+
+pub fn get_masterchain_info() -> Vec<u8> {
+    TlWriter::with_id(GET_MASTERCHAIN_INFO).into_query()
+}
+
+pub fn get_masterchain_info_ext() -> Vec<u8> {
+    let mut writer = TlWriter::with_id(GET_MASTERCHAIN_INFO_EXT);
+    writer.write_u32(0); // mode
+    writer.into_query()
+}
+
+pub fn get_account_state(block_id: &BlockIdExt, address: &MsgAddressInt) -> Vec<u8> {
+    let mut writer = TlWriter::with_id(GET_ACCOUNT_STATE);
+    writer.write_block_id_ext(block_id);
+    writer.write_account_id(address);
+    writer.into_query()
+}
+
+pub fn get_transactions(count: u32, address: &MsgAddressInt, lt: u64, hash: &UInt256) -> Vec<u8> {
+    let mut writer = TlWriter::with_id(GET_TRANSACTIONS);
+    writer.write_u32(count);
+    writer.write_account_id(address);
+    writer.write_u64(lt);
+    writer.write_u256(hash);
+    writer.into_query()
+}
+
+pub fn send_message(body: &[u8]) -> Vec<u8> {
+    let mut writer = TlWriter::with_id(SEND_MESSAGE);
+    writer.write_bytes(body);
+    writer.into_query()
+}
+
+pub fn get_block(block_id: &BlockIdExt) -> Vec<u8> {
+    let mut writer = TlWriter::with_id(GET_BLOCK);
+    writer.write_block_id_ext(block_id);
+    writer.into_query()
+}
+
+pub fn lookup_block(workchain: i32, shard: u64, seqno: u32) -> Vec<u8> {
+    let mut writer = TlWriter::with_id(LOOKUP_BLOCK);
+    writer.write_u32(1); // mode: lookup by seqno
+    writer.write_i32(workchain);
+    writer.write_u64(shard);
+    writer.write_u32(seqno);
+    writer.into_query()
+}
+
+/// Finds the first block generated after the specified time
+pub fn lookup_block_by_utime(workchain: i32, shard: u64, utime: u32) -> Vec<u8> {
+    let mut writer = TlWriter::with_id(LOOKUP_BLOCK);
+    writer.write_u32(4); // mode: lookup by utime
+    writer.write_i32(workchain);
+    writer.write_u64(shard);
+    writer.write_u32(0); // seqno
+    writer.write_u32(utime);
+    writer.into_query()
+}
+
+pub fn parse_masterchain_info(data: &[u8]) -> Result<BlockIdExt> {
+    let mut reader = TlReader::answer(data, MASTERCHAIN_INFO)?;
+    reader.read_block_id_ext()
+}
+
+/// Returns the latest masterchain block and its generation time
+pub fn parse_masterchain_info_ext(data: &[u8]) -> Result<(BlockIdExt, u32)> {
+    let mut reader = TlReader::answer(data, MASTERCHAIN_INFO_EXT)?;
+    reader.read_u32()?; // mode
+    reader.read_i32()?; // version
+    reader.read_u64()?; // capabilities
+    let last = reader.read_block_id_ext()?;
+    let last_utime = reader.read_u32()?;
+    Ok((last, last_utime))
+}
+
+pub fn parse_account_state(data: &[u8]) -> Result<AccountState> {
+    let mut reader = TlReader::answer(data, ACCOUNT_STATE)?;
+    reader.read_block_id_ext()?; // id
+    reader.read_block_id_ext()?; // shardblk
+    reader.read_bytes()?; // shard_proof
+    let proof = reader.read_bytes()?.to_vec();
+    let state = reader.read_bytes()?.to_vec();
+    Ok(AccountState { proof, state })
+}
+
+pub fn parse_transaction_list(data: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = TlReader::answer(data, TRANSACTION_LIST)?;
+    let count = reader.read_u32()?;
+    for _ in 0..count {
+        reader.read_block_id_ext()?;
+    }
+    Ok(reader.read_bytes()?.to_vec())
+}
+
+pub fn parse_send_msg_status(data: &[u8]) -> Result<i32> {
+    let mut reader = TlReader::answer(data, SEND_MSG_STATUS)?;
+    reader.read_i32()
+}
+
+pub fn parse_block_data(data: &[u8]) -> Result<(BlockIdExt, Vec<u8>)> {
+    let mut reader = TlReader::answer(data, BLOCK_DATA)?;
+    let id = reader.read_block_id_ext()?;
+    let data = reader.read_bytes()?.to_vec();
+    Ok((id, data))
+}
+
+pub fn parse_block_header(data: &[u8]) -> Result<BlockIdExt> {
+    let mut reader = TlReader::answer(data, BLOCK_HEADER)?;
+    reader.read_block_id_ext()
+}
+
+struct TlWriter(Vec<u8>);
+
+impl TlWriter {
+    fn with_id(id: u32) -> Self {
+        let mut writer = Self(Vec::with_capacity(128));
+        writer.write_u32(id);
+        writer
+    }
+
+    /// Wraps the function into `liteServer.query`
+    fn into_query(self) -> Vec<u8> {
+        let mut query = Self::with_id(LITE_SERVER_QUERY);
+        query.write_bytes(&self.0);
+        query.0
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_i32(&mut self, value: i32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_u256(&mut self, value: &UInt256) {
+        self.0.extend_from_slice(value.as_slice());
+    }
+
+    fn write_bytes(&mut self, data: &[u8]) {
+        let len = data.len();
+        let header_len = if len < 254 {
+            self.0.push(len as u8);
+            1
+        } else {
+            self.0.push(254);
+            self.0.extend_from_slice(&(len as u32).to_le_bytes()[..3]);
+            4
+        };
+        self.0.extend_from_slice(data);
+
+        let padding = (4 - (header_len + len) % 4) % 4;
+        self.0.extend(std::iter::repeat(0).take(padding));
+    }
+
+    fn write_block_id_ext(&mut self, id: &BlockIdExt) {
+        self.write_i32(id.workchain);
+        self.write_u64(id.shard);
+        self.write_u32(id.seqno);
+        self.write_u256(&id.root_hash);
+        self.write_u256(&id.file_hash);
+    }
+
+    fn write_account_id(&mut self, address: &MsgAddressInt) {
+        self.write_i32(address.workchain_id());
+        self.0
+            .extend_from_slice(&address.address().get_bytestring(0));
+    }
+}
+
+struct TlReader<'a>(&'a [u8]);
+
+impl<'a> TlReader<'a> {
+    /// Checks the answer constructor and converts lite server errors
+    fn answer(data: &'a [u8], id: u32) -> Result<Self> {
+        let mut reader = Self(data);
+        match reader.read_u32()? {
+            constructor if constructor == id => Ok(reader),
+            LITE_SERVER_ERROR => {
+                let code = reader.read_i32()?;
+                let message = String::from_utf8_lossy(reader.read_bytes()?).into_owned();
+                Err(TlError::LiteServerError { code, message }.into())
+            }
+            _ => Err(TlError::UnexpectedAnswer.into()),
+        }
+    }
+
+    fn read_raw(&mut self, len: usize) -> Result<&'a [u8], TlError> {
+        if self.0.len() < len {
+            return Err(TlError::UnexpectedEof);
+        }
+        let (data, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(data)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, TlError> {
+        let data = self.read_raw(4)?;
+        Ok(u32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        Ok(self.read_u32()? as i32)
+    }
+
+    fn read_u64(&mut self) -> Result<u64, TlError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_raw(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn read_u256(&mut self) -> Result<UInt256, TlError> {
+        Ok(UInt256::from_slice(self.read_raw(32)?))
+    }
+
+    fn read_bytes(&mut self) -> Result<&'a [u8], TlError> {
+        let first = self.read_raw(1)?[0];
+        let (header_len, len) = if first < 254 {
+            (1, first as usize)
+        } else {
+            let len = self.read_raw(3)?;
+            (4, u32::from_le_bytes([len[0], len[1], len[2], 0]) as usize)
+        };
+        let data = self.read_raw(len)?;
+
+        let padding = (4 - (header_len + len) % 4) % 4;
+        self.read_raw(padding)?;
+        Ok(data)
+    }
+
+    fn read_block_id_ext(&mut self) -> Result<BlockIdExt> {
+        Ok(BlockIdExt {
+            workchain: self.read_i32()?,
+            shard: self.read_u64()?,
+            seqno: self.read_u32()?,
+            root_hash: self.read_u256()?,
+            file_hash: self.read_u256()?,
+        })
+    }
+}
+
+/// Encoders of the lite server answers, used by the mock connections
+#[cfg(test)]
+pub mod answers {
+    use super::*;
+
+    /// Returns the function id of the query
+    pub fn query_id(query: &[u8]) -> u32 {
+        let mut reader = TlReader(query);
+        assert_eq!(reader.read_u32().unwrap(), LITE_SERVER_QUERY);
+        let mut reader = TlReader(reader.read_bytes().unwrap());
+        reader.read_u32().unwrap()
+    }
+
+    pub fn block_header(id: &BlockIdExt) -> Vec<u8> {
+        let mut writer = TlWriter::with_id(BLOCK_HEADER);
+        writer.write_block_id_ext(id);
+        writer.write_u32(0); // mode
+        writer.write_bytes(&[]); // header_proof
+        writer.0
+    }
+
+    pub fn masterchain_info_ext(id: &BlockIdExt, last_utime: u32) -> Vec<u8> {
+        let mut writer = TlWriter::with_id(MASTERCHAIN_INFO_EXT);
+        writer.write_u32(0); // mode
+        writer.write_i32(0); // version
+        writer.write_u64(0); // capabilities
+        writer.write_block_id_ext(id);
+        writer.write_u32(last_utime);
+        writer.write_u32(last_utime); // now
+        writer.write_u256(&UInt256::default()); // state_root_hash
+        writer.write_i32(ton_block::MASTERCHAIN_ID); // init.workchain
+        writer.write_u256(&UInt256::default()); // init.root_hash
+        writer.write_u256(&UInt256::default()); // init.file_hash
+        writer.0
+    }
+
+    pub fn block_data(id: &BlockIdExt, data: &[u8]) -> Vec<u8> {
+        let mut writer = TlWriter::with_id(BLOCK_DATA);
+        writer.write_block_id_ext(id);
+        writer.write_bytes(data);
+        writer.0
+    }
+
+    pub fn transaction_list(transactions: &[u8]) -> Vec<u8> {
+        let mut writer = TlWriter::with_id(TRANSACTION_LIST);
+        writer.write_u32(0); // ids
+        writer.write_bytes(transactions);
+        writer.0
+    }
+
+    pub fn account_state(id: &BlockIdExt, proof: &[u8], state: &[u8]) -> Vec<u8> {
+        let mut writer = TlWriter::with_id(ACCOUNT_STATE);
+        writer.write_block_id_ext(id); // id
+        writer.write_block_id_ext(id); // shardblk
+        writer.write_bytes(&[]); // shard_proof
+        writer.write_bytes(proof);
+        writer.write_bytes(state);
+        writer.0
+    }
+
+    pub fn block_id_of_account_state_query(query: &[u8]) -> BlockIdExt {
+        let mut reader = TlReader(query);
+        reader.read_u32().unwrap();
+        let mut reader = TlReader(reader.read_bytes().unwrap());
+        assert_eq!(reader.read_u32().unwrap(), GET_ACCOUNT_STATE);
+        reader.read_block_id_ext().unwrap()
+    }
+
+    pub const GET_ACCOUNT_STATE_ID: u32 = GET_ACCOUNT_STATE;
+    pub const GET_BLOCK_ID: u32 = GET_BLOCK;
+    pub const GET_TRANSACTIONS_ID: u32 = GET_TRANSACTIONS;
+    pub const GET_MASTERCHAIN_INFO_EXT_ID: u32 = GET_MASTERCHAIN_INFO_EXT;
+    pub const LOOKUP_BLOCK_ID: u32 = LOOKUP_BLOCK;
+}
+
+#[derive(thiserror::Error, Debug)]
+enum TlError {
+    #[error("Unexpected end of data")]
+    UnexpectedEof,
+    #[error("Unexpected answer")]
+    UnexpectedAnswer,
+    #[error("Lite server error {code}: {message}")]
+    LiteServerError { code: i32, message: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_round_trip() {
+        for len in [0, 1, 3, 253, 254, 1000] {
+            let data = vec![0xaa; len];
+
+            let mut writer = TlWriter(Vec::new());
+            writer.write_bytes(&data);
+            writer.write_u32(123);
+            assert_eq!(writer.0.len() % 4, 0);
+
+            let mut reader = TlReader(&writer.0);
+            assert_eq!(reader.read_bytes().unwrap(), data.as_slice());
+            assert_eq!(reader.read_u32().unwrap(), 123);
+        }
+    }
+
+    #[test]
+    fn parse_lite_server_error() {
+        let mut writer = TlWriter::with_id(LITE_SERVER_ERROR);
+        writer.write_i32(651);
+        writer.write_bytes(b"not found");
+
+        let error = parse_masterchain_info(&writer.0).unwrap_err();
+        assert_eq!(error.to_string(), "Lite server error 651: not found");
+    }
+}
@@ -0,0 +1,372 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use futures_util::future::{self, Either};
+use ton_block::MsgAddressInt;
+
+use nekoton_utils::Clock;
+
+use super::models::*;
+use super::{
+    AccountUpdatesHandler, AccountUpdatesSubscription, Transport, TransportCapabilities,
+    TransportInfo,
+};
+use crate::models::{ContractState, NetworkCapabilities, TransactionsCursor};
+
+/// Cooperative cancellation of the running operations.
+///
+/// All clones share the same state
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<CancellationTokenInner>,
+}
+
+#[derive(Default)]
+struct CancellationTokenInner {
+    cancelled: AtomicBool,
+    notify: tokio::sync::Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels all operations which use this token
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Release);
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Completes when the token is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            // NOTE: future is registered before the flag check, so the
+            // notification between the check and the await is not lost
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Limits of the operation: deadline and/or cancellation token.
+///
+/// Deadline is checked using the clock before each step of the operation,
+/// because there are no timers in the library. To interrupt requests which are
+/// already in progress, cancel the token from the host timer
+#[derive(Clone, Default)]
+pub struct OperationBounds {
+    /// Unix timestamp in milliseconds
+    pub deadline_ms: Option<u64>,
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl OperationBounds {
+    /// No limits (same as the behavior without bounds)
+    pub fn unbounded() -> Self {
+        Self::default()
+    }
+
+    pub fn with_timeout(clock: &dyn Clock, timeout: Duration) -> Self {
+        Self {
+            deadline_ms: Some(
+                clock
+                    .now_ms_u64()
+                    .saturating_add(timeout.as_millis() as u64),
+            ),
+            cancellation: None,
+        }
+    }
+
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Fails if the operation must not be continued
+    pub fn check(&self, clock: &dyn Clock) -> Result<(), OperationError> {
+        if matches!(&self.cancellation, Some(token) if token.is_cancelled()) {
+            return Err(OperationError::Cancelled);
+        }
+        if matches!(self.deadline_ms, Some(deadline_ms) if clock.now_ms_u64() >= deadline_ms) {
+            return Err(OperationError::DeadlineExceeded);
+        }
+        Ok(())
+    }
+
+    /// Runs the future until it completes or the token is cancelled
+    pub async fn run<F, T>(&self, clock: &dyn Clock, fut: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        self.check(clock)?;
+
+        let result = match &self.cancellation {
+            Some(token) => match future::select(Box::pin(fut), Box::pin(token.cancelled())).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => return Err(OperationError::Cancelled.into()),
+            },
+            None => fut.await,
+        };
+
+        // NOTE: result is discarded if it was received after the deadline,
+        // so that the caller doesn't act on the stale data
+        self.check(clock)?;
+        result
+    }
+
+    /// Starts the operation which must not be interrupted once started
+    /// (e.g. message broadcast, which can't be recalled).
+    ///
+    /// Bounds are only checked before the start, and the result is always returned
+    pub async fn dispatch<F, T>(&self, clock: &dyn Clock, fut: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        self.check(clock)?;
+        fut.await
+    }
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OperationError {
+    #[error("Operation cancelled")]
+    Cancelled,
+    #[error("Operation deadline exceeded")]
+    DeadlineExceeded,
+}
+
+/// Transport wrapper which applies the same bounds to all requests.
+///
+/// Can be passed anywhere instead of the original transport
+/// (e.g. to bound the refresh of the subscription or owners resolution)
+pub struct BoundedTransport {
+    transport: Arc<dyn Transport>,
+    clock: Arc<dyn Clock>,
+    bounds: OperationBounds,
+}
+
+impl BoundedTransport {
+    pub fn new(
+        transport: Arc<dyn Transport>,
+        clock: Arc<dyn Clock>,
+        bounds: OperationBounds,
+    ) -> Self {
+        Self {
+            transport,
+            clock,
+            bounds,
+        }
+    }
+
+    pub fn bounds(&self) -> &OperationBounds {
+        &self.bounds
+    }
+
+    async fn run<F, T>(&self, fut: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        self.bounds.run(self.clock.as_ref(), fut).await
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl Transport for BoundedTransport {
+    fn info(&self) -> TransportInfo {
+        self.transport.info()
+    }
+
+    fn capabilities(&self) -> TransportCapabilities {
+        self.transport.capabilities()
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        self.bounds
+            .dispatch(self.clock.as_ref(), self.transport.send_message(message))
+            .await
+    }
+
+    async fn send_messages(
+        &self,
+        messages: &[ton_block::Message],
+    ) -> Result<Vec<SendMessageStatus>> {
+        self.bounds
+            .dispatch(self.clock.as_ref(), self.transport.send_messages(messages))
+            .await
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        self.run(self.transport.get_contract_state(address)).await
+    }
+
+    async fn get_contract_states(
+        &self,
+        addresses: &[MsgAddressInt],
+    ) -> Result<Vec<Result<RawContractState>>> {
+        self.run(self.transport.get_contract_states(addresses))
+            .await
+    }
+
+    async fn subscribe_account(
+        &self,
+        address: &MsgAddressInt,
+        handler: Arc<dyn AccountUpdatesHandler>,
+    ) -> Result<Option<Box<dyn AccountUpdatesSubscription>>> {
+        self.run(self.transport.subscribe_account(address, handler))
+            .await
+    }
+
+    async fn get_brief_contract_state(&self, address: &MsgAddressInt) -> Result<ContractState> {
+        self.run(self.transport.get_brief_contract_state(address))
+            .await
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        self.run(self.transport.poll_contract_state(address, last_trans_lt))
+            .await
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &ton_types::UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        self.run(
+            self.transport
+                .get_accounts_by_code_hash(code_hash, limit, continuation),
+        )
+        .await
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        self.run(self.transport.get_transactions(address, from_lt, count))
+            .await
+    }
+
+    async fn get_transactions_page(
+        &self,
+        address: &MsgAddressInt,
+        cursor: &TransactionsCursor,
+        count: u8,
+    ) -> Result<(Vec<RawTransaction>, Option<TransactionsCursor>)> {
+        self.run(self.transport.get_transactions_page(address, cursor, count))
+            .await
+    }
+
+    async fn get_transaction(&self, id: &ton_types::UInt256) -> Result<Option<RawTransaction>> {
+        self.run(self.transport.get_transaction(id)).await
+    }
+
+    async fn get_dst_transaction(
+        &self,
+        message_hash: &ton_types::UInt256,
+    ) -> Result<Option<RawTransaction>> {
+        self.run(self.transport.get_dst_transaction(message_hash))
+            .await
+    }
+
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: StateAt,
+    ) -> Result<RawContractState> {
+        self.run(self.transport.get_contract_state_at(address, at))
+            .await
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        self.run(self.transport.get_latest_key_block()).await
+    }
+
+    async fn get_chain_time(&self) -> Result<u32> {
+        self.run(self.transport.get_chain_time()).await
+    }
+
+    async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        self.run(self.transport.get_capabilities(clock)).await
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        clock: &dyn Clock,
+        force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        self.run(self.transport.get_blockchain_config(clock, force))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nekoton_utils::ConstClock;
+
+    use super::*;
+    use crate::testing::ManualClock;
+
+    #[tokio::test]
+    async fn bounded_operations() {
+        let clock = ConstClock::from_secs(1000);
+
+        let bounds = OperationBounds::with_timeout(&clock, Duration::from_secs(10));
+        assert_eq!(bounds.run(&clock, async { Ok(1) }).await.unwrap(), 1);
+
+        let expired = OperationBounds::with_timeout(&clock, Duration::ZERO);
+        assert_eq!(expired.check(&clock), Err(OperationError::DeadlineExceeded));
+
+        let token = CancellationToken::new();
+        let bounds = OperationBounds::unbounded().with_cancellation(token.clone());
+
+        let pending = bounds.run(&clock, future::pending::<Result<()>>());
+        token.cancel();
+        let error = pending.await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<OperationError>(),
+            Some(&OperationError::Cancelled)
+        );
+    }
+
+    #[tokio::test]
+    async fn late_send_is_not_discarded() {
+        let clock = ManualClock::from_secs(1000);
+        let token = CancellationToken::new();
+        let bounds = OperationBounds::with_timeout(&clock, Duration::from_secs(10))
+            .with_cancellation(token.clone());
+
+        // Message was accepted after the deadline and the cancellation
+        let send = async {
+            clock.advance(Duration::from_secs(20));
+            token.cancel();
+            Ok(())
+        };
+        bounds.dispatch(&clock, send).await.unwrap();
+
+        // But it is not sent at all if the bounds are already exceeded
+        let error = bounds.dispatch(&clock, async { Ok(()) }).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<OperationError>(),
+            Some(&OperationError::Cancelled)
+        );
+    }
+}
@@ -1,4 +1,6 @@
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -9,16 +11,24 @@ use ton_block::{Account, Deserializable, Message, MsgAddressInt, Serializable};
 use nekoton_abi::{GenTimings, LastTransactionId};
 use nekoton_utils::*;
 
-use crate::core::models::{NetworkCapabilities, ReliableBehavior};
-use crate::external::{GqlConnection, GqlRequest};
+use crate::core::models::{ContractState, NetworkCapabilities, ReliableBehavior};
+use crate::external::{GqlConnection, GqlRequest, GqlSubscription, GqlSubscriptionHandler};
 
 use self::queries::*;
 use super::models::*;
 use super::utils::ConfigCache;
-use super::{Transport, TransportInfo};
+use super::{
+    AccountUpdatesHandler, AccountUpdatesSubscription, StateTooLarge, Transport,
+    TransportCapabilities, TransportInfo,
+};
 
 mod queries;
 
+const ACC_TYPE_ACTIVE: u8 = 1;
+
+/// Max number of accounts requested in one query
+const MAX_ACCOUNTS_PER_QUERY: usize = 50;
+
 pub struct GqlTransport {
     connection: Arc<dyn GqlConnection>,
     config_cache: ConfigCache,
@@ -34,6 +44,23 @@ impl GqlTransport {
         }
     }
 
+    /// Replaces size limit errors with [`StateTooLarge`] if the account exists.
+    /// Other errors are returned as is
+    async fn check_state_too_large(
+        &self,
+        address: &MsgAddressInt,
+        error: anyhow::Error,
+    ) -> anyhow::Error {
+        if !is_size_limit_error(&error) {
+            return error;
+        }
+
+        match self.get_brief_contract_state(address).await {
+            Ok(state) if state.last_lt > 0 => StateTooLarge(state).into(),
+            _ => error,
+        }
+    }
+
     async fn fetch<T>(&self, params: T::Variables) -> Result<T::ResponseData>
     where
         T: GqlQuery,
@@ -44,6 +71,7 @@ impl GqlTransport {
             .post(GqlRequest {
                 data: request_body,
                 long_query: T::LONG_QUERY,
+                idempotent: T::IDEMPOTENT,
             })
             .await
             .map_err(api_failure)?;
@@ -55,6 +83,7 @@ impl GqlTransport {
 
         match serde_json::from_str::<Response<T::ResponseData>>(&response) {
             Ok(response) => response.data.ok_or_else(|| invalid_response().into()),
+            Err(e) if e.is_eof() => Err(NodeClientError::TruncatedResponse.into()),
             Err(e) => Err(api_failure(format!(
                 "Failed parsing api response: {e}. Response data: {response}"
             ))
@@ -173,6 +202,95 @@ impl GqlTransport {
 
         Ok(block_id)
     }
+
+    async fn subscribe<T>(
+        &self,
+        params: T::Variables,
+        handler: AccountUpdatesAdapter,
+    ) -> Result<Box<dyn GqlSubscription>>
+    where
+        T: GqlQuery,
+    {
+        let request_body = serde_json::to_string(&T::build_query(&params)).trust_me();
+        self.connection
+            .subscribe(
+                GqlRequest {
+                    data: request_body,
+                    long_query: true,
+                    idempotent: true,
+                },
+                Arc::new(handler),
+            )
+            .await
+            .map_err(|e| api_failure(e).into())
+    }
+}
+
+/// Active account subscription. Unsubscribes on drop
+struct GqlAccountSubscription {
+    _state: Box<dyn GqlSubscription>,
+    _transactions: Box<dyn GqlSubscription>,
+}
+
+impl AccountUpdatesSubscription for GqlAccountSubscription {}
+
+#[derive(Copy, Clone)]
+enum AccountUpdateKind {
+    State,
+    Transactions,
+}
+
+struct AccountUpdatesAdapter {
+    kind: AccountUpdateKind,
+    handler: Arc<dyn AccountUpdatesHandler>,
+    closed: Arc<AtomicBool>,
+}
+
+impl AccountUpdatesAdapter {
+    fn handle_data(&self, data: &str) -> Result<()> {
+        match self.kind {
+            AccountUpdateKind::State => {
+                let data = serde_json::from_str::<subscription_account_state::ResponseData>(data)?;
+                // NOTE: state can be omitted if it is too large
+                let state = match data.accounts.boc {
+                    Some(boc) => Some(
+                        parse_contract_state(&boc).ok_or(NodeClientError::InvalidAccountState)?,
+                    ),
+                    None => None,
+                };
+                self.handler.on_state(state);
+            }
+            AccountUpdateKind::Transactions => {
+                let data =
+                    serde_json::from_str::<subscription_account_transactions::ResponseData>(data)?;
+                let bytes = base64::decode(data.transactions.boc)?;
+                let cell = ton_types::deserialize_tree_of_cells(&mut bytes.as_slice())
+                    .map_err(|_| NodeClientError::InvalidTransaction)?;
+                let hash = cell.repr_hash();
+                self.handler.on_transaction(RawTransaction {
+                    hash,
+                    data: ton_block::Transaction::construct_from_cell(cell)
+                        .map_err(|_| NodeClientError::InvalidTransaction)?,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl GqlSubscriptionHandler for AccountUpdatesAdapter {
+    fn on_data(&self, data: String) {
+        if let Err(e) = self.handle_data(&data) {
+            log::warn!("Invalid GQL subscription event: {e:?}");
+        }
+    }
+
+    fn on_closed(&self, error: Option<anyhow::Error>) {
+        // Both subscriptions share the handler
+        if !self.closed.swap(true, Ordering::AcqRel) {
+            self.handler.on_closed(error);
+        }
+    }
 }
 
 #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
@@ -186,16 +304,20 @@ impl Transport for GqlTransport {
         }
     }
 
-    async fn send_message(&self, message: &Message) -> Result<()> {
-        let cell = message
-            .write_to_new_cell()
-            .and_then(ton_types::BuilderData::into_cell)
-            .map_err(|_| NodeClientError::FailedToSerialize)?;
+    fn capabilities(&self) -> TransportCapabilities {
+        TransportCapabilities {
+            blocks: true,
+            code_hash_search: true,
+            batched_states: true,
+            proofs: false,
+            time_sync: true,
+            subscriptions: self.connection.supports_subscriptions(),
+            message_lookup: true,
+        }
+    }
 
-        let boc = base64::encode(
-            ton_types::serialize_toc(&cell).map_err(|_| NodeClientError::FailedToSerialize)?,
-        );
-        let id = base64::encode(cell.repr_hash());
+    async fn send_message(&self, message: &Message) -> Result<()> {
+        let (id, boc) = encode_message(message)?;
 
         let _ = self
             .fetch::<MutationSendMessage>(mutation_send_message::Variables { id, boc })
@@ -204,42 +326,159 @@ impl Transport for GqlTransport {
         Ok(())
     }
 
+    async fn send_messages(&self, messages: &[Message]) -> Result<Vec<SendMessageStatus>> {
+        let mut statuses = Vec::with_capacity(messages.len());
+        let mut ids = Vec::with_capacity(messages.len());
+        let mut requests = Vec::with_capacity(messages.len());
+        for message in messages {
+            match encode_message(message) {
+                Ok((id, body)) => {
+                    ids.push(Some(id.clone()));
+                    requests.push(mutation_send_messages::MessageRequest { id, body });
+                    statuses.push(SendMessageStatus::Accepted);
+                }
+                Err(e) => {
+                    ids.push(None);
+                    statuses.push(SendMessageStatus::Rejected {
+                        reason: e.to_string(),
+                    });
+                }
+            }
+        }
+
+        if requests.is_empty() {
+            return Ok(statuses);
+        }
+
+        // NOTE: response contains ids of the accepted messages,
+        // all messages are considered failed if it is lost
+        let accepted = match self
+            .fetch::<MutationSendMessages>(mutation_send_messages::Variables { requests })
+            .await
+        {
+            Ok(response) => response
+                .post_requests
+                .unwrap_or_default()
+                .into_iter()
+                .flatten()
+                .collect::<HashSet<_>>(),
+            Err(_) => Default::default(),
+        };
+
+        // Resend failed messages one by one to find out the reason
+        for ((message, id), status) in messages.iter().zip(&ids).zip(&mut statuses) {
+            match id {
+                Some(id) if !accepted.contains(id) => {
+                    *status = self.send_message(message).await.into();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(statuses)
+    }
+
     async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
-        let account_state = match self
+        let response = self
             .fetch::<QueryAccountState>(query_account_state::Variables {
                 address: address.to_string(),
             })
+            .await;
+
+        let account_state = match response {
+            Ok(response) => match response.accounts.into_iter().next().and_then(|s| s.boc) {
+                Some(boc) => boc,
+                None => {
+                    return Ok(RawContractState::NotExists {
+                        timings: GenTimings::Unknown,
+                    })
+                }
+            },
+            Err(e) => return Err(self.check_state_too_large(address, e).await),
+        };
+
+        parse_contract_state(&account_state)
+            .ok_or_else(|| NodeClientError::InvalidAccountState.into())
+    }
+
+    async fn get_contract_states(
+        &self,
+        addresses: &[MsgAddressInt],
+    ) -> Result<Vec<Result<RawContractState>>> {
+        let mut states = Vec::with_capacity(addresses.len());
+
+        for chunk in addresses.chunks(MAX_ACCOUNTS_PER_QUERY) {
+            let response = self
+                .fetch::<QueryAccountStates>(query_account_states::Variables {
+                    addresses: chunk.iter().map(ToString::to_string).collect(),
+                    limit: chunk.len(),
+                })
+                .await;
+
+            let accounts = match response {
+                Ok(response) => response
+                    .accounts
+                    .into_iter()
+                    .map(|account| (account.id, account.boc))
+                    .collect::<HashMap<_, _>>(),
+                // Some states can exceed the response limits, so fall back to
+                // separate queries which report such states properly
+                Err(e) if is_size_limit_error(&e) => {
+                    for address in chunk {
+                        states.push(self.get_contract_state(address).await);
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            for address in chunk {
+                let state = match accounts.get(&address.to_string()) {
+                    Some(Some(boc)) => match parse_contract_state(boc) {
+                        Some(state) => Ok(state),
+                        None => self.get_contract_state(address).await,
+                    },
+                    _ => Ok(RawContractState::NotExists {
+                        timings: GenTimings::Unknown,
+                    }),
+                };
+                states.push(state);
+            }
+        }
+
+        Ok(states)
+    }
+
+    async fn get_brief_contract_state(&self, address: &MsgAddressInt) -> Result<ContractState> {
+        let account = match self
+            .fetch::<QueryAccountBriefState>(query_account_brief_state::Variables {
+                address: address.to_string(),
+            })
             .await?
             .accounts
             .into_iter()
             .next()
-            .and_then(|state| state.boc)
         {
-            Some(boc) => boc,
-            None => {
-                return Ok(RawContractState::NotExists {
-                    timings: GenTimings::Unknown,
-                })
-            }
+            Some(account) => account,
+            None => return Ok(ContractState::default()),
         };
 
-        match Account::construct_from_base64(&account_state) {
-            Ok(Account::Account(account)) => {
-                let last_transaction_id = LastTransactionId::Inexact {
-                    latest_lt: account.storage.last_trans_lt,
-                };
-
-                Ok(RawContractState::Exists(ExistingContract {
-                    account,
-                    timings: GenTimings::Unknown,
-                    last_transaction_id,
-                }))
-            }
-            Ok(_) => Ok(RawContractState::NotExists {
-                timings: GenTimings::Unknown,
-            }),
-            Err(_) => Err(NodeClientError::InvalidAccountState.into()),
-        }
+        let parse_u64 =
+            |value: &str| u64::from_str(value).map_err(|_| NodeClientError::InvalidAccountState);
+
+        let last_lt = parse_u64(&account.last_trans_lt)?;
+        Ok(ContractState {
+            last_lt,
+            balance: parse_u64(&account.balance)?,
+            gen_timings: GenTimings::Unknown,
+            last_transaction_id: Some(LastTransactionId::Inexact { latest_lt: last_lt }),
+            is_deployed: account.acc_type == ACC_TYPE_ACTIVE,
+            code_hash: account
+                .code_hash
+                .map(|hash| ton_types::UInt256::from_str(&hash))
+                .transpose()
+                .map_err(|_| NodeClientError::InvalidAccountState)?,
+        })
     }
 
     async fn poll_contract_state(
@@ -345,6 +584,48 @@ impl Transport for GqlTransport {
         .transpose()
     }
 
+    async fn subscribe_account(
+        &self,
+        address: &MsgAddressInt,
+        handler: Arc<dyn AccountUpdatesHandler>,
+    ) -> Result<Option<Box<dyn AccountUpdatesSubscription>>> {
+        if !self.connection.supports_subscriptions() {
+            return Ok(None);
+        }
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let address = address.to_string();
+
+        let state = self
+            .subscribe::<SubscriptionAccountState>(
+                subscription_account_state::Variables {
+                    address: address.clone(),
+                },
+                AccountUpdatesAdapter {
+                    kind: AccountUpdateKind::State,
+                    handler: handler.clone(),
+                    closed: closed.clone(),
+                },
+            )
+            .await?;
+
+        let transactions = self
+            .subscribe::<SubscriptionAccountTransactions>(
+                subscription_account_transactions::Variables { address },
+                AccountUpdatesAdapter {
+                    kind: AccountUpdateKind::Transactions,
+                    handler,
+                    closed,
+                },
+            )
+            .await?;
+
+        Ok(Some(Box::new(GqlAccountSubscription {
+            _state: state,
+            _transactions: transactions,
+        })))
+    }
+
     async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
         let blocks = self.fetch::<QueryLatestKeyBlock>(()).await?.blocks;
         let boc = blocks.into_iter().next().ok_or_else(no_blocks_found)?.boc;
@@ -353,6 +634,12 @@ impl Transport for GqlTransport {
             .map_err(|_| NodeClientError::InvalidBlock.into())
     }
 
+    async fn get_chain_time(&self) -> Result<u32> {
+        let blocks = self.fetch::<QueryLatestMasterchainBlock>(()).await?.blocks;
+        let block = blocks.into_iter().next().ok_or_else(no_blocks_found)?;
+        Ok(block.gen_utime as u32)
+    }
+
     async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
         let (capabilities, _) = self
             .config_cache
@@ -381,6 +668,54 @@ pub struct LatestBlock {
     pub gen_utime: u32,
 }
 
+/// Returns message id and base64 encoded BOC
+fn encode_message(message: &Message) -> Result<(String, String)> {
+    let cell = message
+        .write_to_new_cell()
+        .and_then(ton_types::BuilderData::into_cell)
+        .map_err(|_| NodeClientError::FailedToSerialize)?;
+
+    let boc = base64::encode(
+        ton_types::serialize_toc(&cell).map_err(|_| NodeClientError::FailedToSerialize)?,
+    );
+    let id = base64::encode(cell.repr_hash());
+
+    Ok((id, boc))
+}
+
+/// Returns `None` if the state is invalid or truncated
+fn parse_contract_state(boc: &str) -> Option<RawContractState> {
+    match Account::construct_from_base64(boc).ok()? {
+        Account::Account(account) => {
+            let last_transaction_id = LastTransactionId::Inexact {
+                latest_lt: account.storage.last_trans_lt,
+            };
+
+            Some(RawContractState::Exists(ExistingContract {
+                account,
+                timings: GenTimings::Unknown,
+                last_transaction_id,
+            }))
+        }
+        Account::AccountNone => Some(RawContractState::NotExists {
+            timings: GenTimings::Unknown,
+        }),
+    }
+}
+
+/// Response size limits are reported either as a truncated response
+/// or as a failed request with the corresponding reason
+fn is_size_limit_error(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<NodeClientError>() {
+        Some(NodeClientError::TruncatedResponse) => true,
+        Some(NodeClientError::ApiFailure { reason }) => {
+            let reason = reason.to_lowercase();
+            reason.contains("too large") || reason.contains("size limit")
+        }
+        _ => false,
+    }
+}
+
 fn check_shard_match(workchain_id: i32, shard: &str, addr: &MsgAddressInt) -> Result<bool> {
     let shard = u64::from_str_radix(shard, 16)?;
 
@@ -421,6 +756,8 @@ pub enum NodeClientError {
     ApiFailure { reason: String },
     #[error("Invalid response")]
     InvalidResponse,
+    #[error("Response is truncated")]
+    TruncatedResponse,
     #[error("Invalid transaction data")]
     InvalidTransaction,
     #[error("Failed to serialize data")]
@@ -465,6 +802,185 @@ mod tests {
         }
     }
 
+    /// Returns prepared responses in order
+    #[derive(Default)]
+    struct ScriptedConnection(parking_lot::Mutex<std::collections::VecDeque<Result<String>>>);
+
+    impl ScriptedConnection {
+        fn push(&self, response: Result<&str>) {
+            self.0.lock().push_back(response.map(str::to_owned));
+        }
+
+        fn remaining(&self) -> usize {
+            self.0.lock().len()
+        }
+    }
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl GqlConnection for ScriptedConnection {
+        fn is_local(&self) -> bool {
+            false
+        }
+
+        async fn post(&self, _: GqlRequest) -> Result<String> {
+            self.0
+                .lock()
+                .pop_front()
+                .unwrap_or_else(|| Err(anyhow::anyhow!("Unexpected request")))
+        }
+    }
+
+    #[tokio::test]
+    async fn only_failed_messages_are_resent() {
+        let connection = Arc::new(ScriptedConnection::default());
+        let transport = GqlTransport::new(connection.clone());
+
+        let messages = (1..=3u8)
+            .map(|i| {
+                Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+                    dst: MsgAddressInt::from_str(&format!("0:{}", hex::encode([i; 32]))).unwrap(),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+        let ids = messages
+            .iter()
+            .map(|message| encode_message(message).unwrap().0)
+            .collect::<Vec<_>>();
+
+        // Second message was not accepted and is resent separately
+        let batch_response = format!(
+            r#"{{"data":{{"postRequests":["{}","{}"]}}}}"#,
+            ids[0], ids[2]
+        );
+        connection.push(Ok(&batch_response));
+        connection.push(Ok(r#"{"data":{"postRequests":null}}"#));
+
+        let statuses = transport.send_messages(&messages).await.unwrap();
+        assert_eq!(statuses, vec![SendMessageStatus::Accepted; 3]);
+        assert_eq!(connection.remaining(), 0);
+
+        // All messages are resent if the batch response is lost
+        connection.push(Err(anyhow::anyhow!("Connection reset")));
+        connection.push(Ok(r#"{"data":{"postRequests":null}}"#));
+        connection.push(Err(anyhow::anyhow!("Message rejected")));
+        connection.push(Ok(r#"{"data":{"postRequests":null}}"#));
+
+        let statuses = transport.send_messages(&messages).await.unwrap();
+        assert_eq!(statuses[0], SendMessageStatus::Accepted);
+        assert!(matches!(statuses[1], SendMessageStatus::Rejected { .. }));
+        assert_eq!(statuses[2], SendMessageStatus::Accepted);
+        assert_eq!(connection.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn contract_states_fallback() {
+        let connection = Arc::new(ScriptedConnection::default());
+        let transport = GqlTransport::new(connection.clone());
+
+        let addresses = [
+            MsgAddressInt::from_str(
+                "0:1111111111111111111111111111111111111111111111111111111111111111",
+            )
+            .unwrap(),
+            MsgAddressInt::from_str(
+                "0:2222222222222222222222222222222222222222222222222222222222222222",
+            )
+            .unwrap(),
+        ];
+
+        // Truncated batch response falls back to separate queries
+        connection.push(Ok(r#"{"data":{"accounts":["#));
+        connection.push(Ok(r#"{"data":{"accounts":[]}}"#));
+        connection.push(Err(anyhow::anyhow!("Connection refused")));
+
+        let states = transport.get_contract_states(&addresses).await.unwrap();
+        assert_eq!(states.len(), 2);
+        assert!(matches!(states[0], Ok(RawContractState::NotExists { .. })));
+        assert!(states[1].is_err());
+        assert_eq!(connection.remaining(), 0);
+
+        // Other errors are not retried
+        connection.push(Err(anyhow::anyhow!("Connection refused")));
+        connection.push(Ok(r#"{"data":{"accounts":[]}}"#));
+
+        assert!(transport.get_contract_states(&addresses).await.is_err());
+        assert_eq!(connection.remaining(), 1);
+    }
+
+    #[derive(Default)]
+    struct RecordingHandler {
+        states: parking_lot::Mutex<Vec<Option<RawContractState>>>,
+        transactions: parking_lot::Mutex<Vec<RawTransaction>>,
+    }
+
+    impl AccountUpdatesHandler for RecordingHandler {
+        fn on_state(&self, state: Option<RawContractState>) {
+            self.states.lock().push(state);
+        }
+
+        fn on_transaction(&self, transaction: RawTransaction) {
+            self.transactions.lock().push(transaction);
+        }
+
+        fn on_closed(&self, _: Option<anyhow::Error>) {}
+    }
+
+    #[test]
+    fn account_updates_adapter() {
+        let handler = Arc::new(RecordingHandler::default());
+        let make_adapter = |kind| AccountUpdatesAdapter {
+            kind,
+            handler: handler.clone(),
+            closed: Default::default(),
+        };
+
+        // Omitted state is still reported
+        let adapter = make_adapter(AccountUpdateKind::State);
+        adapter.handle_data(r#"{"accounts":{"boc":null}}"#).unwrap();
+        assert!(matches!(handler.states.lock().as_slice(), [None]));
+
+        // Invalid state is not reported
+        assert!(adapter
+            .handle_data(r#"{"accounts":{"boc":"invalid"}}"#)
+            .is_err());
+        assert_eq!(handler.states.lock().len(), 1);
+
+        const TX: &str = "te6ccgECBgEAATYAA7F6khRTRyNmt/7uwVMjqWtdzxcZfIjcDUV436UpALijPLAAAMhcEbrIEikkn05Ku83ZEENvShriMSDo3Wrh+PZVqEZZFR73UDNgAADIW3VTuCYJRG2wABQiKAMCAQALDERIQEkgAIJy0W3rZziZMBhdOhOSsj9f2V3MqUZWvD39kx9ersOjLDlIhSvIB0KaWZELIJ7zw+I+Sy9Ykv6tSJbSpB49hDcHiQEBoAQBq0gBd5fv1pbeJAd0Wp/qbrvGFKgRUCYX7z8OHfefqT2CydMAKkhRTRyNmt/7uwVMjqWtdzxcZfIjcDUV436UpALijPLEBAYduXAAABkLgdvLrMEojabABQBbRE1D9QAAAAAAAAB5AAAAAES7c48AAATFd7ZSmgAAAAAAAAAAAAAAAAAAAACCwA==";
+
+        let adapter = make_adapter(AccountUpdateKind::Transactions);
+        adapter
+            .handle_data(&format!(r#"{{"transactions":{{"boc":"{TX}"}}}}"#))
+            .unwrap();
+
+        let expected = ton_block::Transaction::construct_from_base64(TX).unwrap();
+        let transactions = handler.transactions.lock();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            transactions[0].hash,
+            expected.serialize().unwrap().repr_hash()
+        );
+        assert_eq!(transactions[0].data.lt, expected.lt);
+    }
+
+    #[test]
+    fn size_limit_errors() {
+        assert!(is_size_limit_error(
+            &NodeClientError::TruncatedResponse.into()
+        ));
+        assert!(is_size_limit_error(
+            &api_failure("413 Payload Too Large").into()
+        ));
+        assert!(!is_size_limit_error(
+            &api_failure("Connection refused").into()
+        ));
+        assert!(!is_size_limit_error(
+            &NodeClientError::InvalidAccountState.into()
+        ));
+        assert!(!is_size_limit_error(&anyhow::anyhow!("Timeout")));
+    }
+
     #[tokio::test]
     async fn test_connection() {
         let transport = GqlTransport::new(Arc::new(reqwest::Client::new()));
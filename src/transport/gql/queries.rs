@@ -5,6 +5,7 @@ pub trait GqlQuery {
     type ResponseData: for<'de> serde::Deserialize<'de>;
 
     const LONG_QUERY: bool = false;
+    const IDEMPOTENT: bool = true;
 
     fn build_query(variables: &'_ Self::Variables) -> QueryBody<'_>;
 }
@@ -16,14 +17,14 @@ pub struct QueryBody<'a> {
 }
 
 macro_rules! declare_queries {
-    ($($query:ident => $query_module:tt $((LONG_QUERY = $long_query:literal))?),*$(,)?) => {
+    ($($query:ident => $query_module:tt $(($($param:ident = $value:literal),+))?),*$(,)?) => {
         $(pub struct $query;
 
         impl GqlQuery for $query {
             type Variables = $query_module::Variables;
             type ResponseData = $query_module::ResponseData;
 
-            $(const LONG_QUERY: bool = $long_query;)?
+            $($(const $param: bool = $value;)+)?
 
             fn build_query(variables: &'_ Self::Variables) -> QueryBody<'_> {
                 QueryBody {
@@ -40,6 +41,8 @@ declare_queries! {
     QueryNextBlock => query_next_block (LONG_QUERY = true),
     QueryBlockAfterSplit => query_block_after_split (LONG_QUERY = true),
     QueryAccountState => query_account_state,
+    QueryAccountStates => query_account_states,
+    QueryAccountBriefState => query_account_brief_state,
     QueryAccountTransactions => query_account_transactions,
     QueryTransaction => query_transaction,
     QueryDstTransaction => query_dst_transaction,
@@ -48,7 +51,10 @@ declare_queries! {
     QueryLatestKeyBlock => query_latest_key_block,
     QueryNodeSeConditions => query_node_se_conditions,
     QueryNodeSeLatestBlock => query_node_se_latest_block,
-    MutationSendMessage => mutation_send_message,
+    MutationSendMessage => mutation_send_message (IDEMPOTENT = false),
+    MutationSendMessages => mutation_send_messages (IDEMPOTENT = false),
+    SubscriptionAccountState => subscription_account_state,
+    SubscriptionAccountTransactions => subscription_account_transactions,
 }
 
 pub mod query_block {
@@ -143,6 +149,57 @@ pub mod query_account_state {
     }
 }
 
+pub mod query_account_states {
+    use super::*;
+
+    pub const QUERY: &str =
+        "query($a:[String],$l:Int!){accounts(filter:{id:{in:$a}},limit:$l){id boc}}";
+
+    #[derive(Serialize)]
+    pub struct Variables {
+        #[serde(rename = "a")]
+        pub addresses: Vec<String>,
+        #[serde(rename = "l")]
+        pub limit: usize,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ResponseData {
+        pub accounts: Vec<QueryAccountStatesAccounts>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct QueryAccountStatesAccounts {
+        pub id: String,
+        pub boc: Option<String>,
+    }
+}
+
+pub mod query_account_brief_state {
+    use super::*;
+
+    pub const QUERY: &str = "query($a:String!){accounts(filter:{id:{eq:$a}},limit:1){acc_type balance(format:DEC) code_hash last_trans_lt(format:DEC)}}";
+
+    #[derive(Serialize)]
+    pub struct Variables {
+        #[serde(rename = "a")]
+        pub address: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ResponseData {
+        pub accounts: Vec<QueryAccountBriefStateAccounts>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct QueryAccountBriefStateAccounts {
+        pub acc_type: u8,
+        pub balance: String,
+        pub code_hash: Option<String>,
+        pub last_trans_lt: String,
+    }
+}
+
 pub mod query_account_transactions {
     use super::*;
 
@@ -359,3 +416,72 @@ pub mod mutation_send_message {
     #[derive(Deserialize)]
     pub struct ResponseData {}
 }
+
+pub mod mutation_send_messages {
+    use super::*;
+
+    pub const QUERY: &str = "mutation($requests:[Request]){postRequests(requests:$requests)}";
+
+    #[derive(Serialize)]
+    pub struct Variables {
+        pub requests: Vec<MessageRequest>,
+    }
+
+    #[derive(Serialize)]
+    pub struct MessageRequest {
+        pub id: String,
+        pub body: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ResponseData {
+        /// Ids of the accepted requests
+        #[serde(rename = "postRequests")]
+        pub post_requests: Option<Vec<Option<String>>>,
+    }
+}
+
+pub mod subscription_account_state {
+    use super::*;
+
+    pub const QUERY: &str = "subscription($a:String!){accounts(filter:{id:{eq:$a}}){boc}}";
+
+    #[derive(Serialize)]
+    pub struct Variables {
+        #[serde(rename = "a")]
+        pub address: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ResponseData {
+        pub accounts: SubscriptionAccountStateAccounts,
+    }
+
+    #[derive(Deserialize)]
+    pub struct SubscriptionAccountStateAccounts {
+        pub boc: Option<String>,
+    }
+}
+
+pub mod subscription_account_transactions {
+    use super::*;
+
+    pub const QUERY: &str =
+        "subscription($a:String!){transactions(filter:{account_addr:{eq:$a}}){boc}}";
+
+    #[derive(Serialize)]
+    pub struct Variables {
+        #[serde(rename = "a")]
+        pub address: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ResponseData {
+        pub transactions: SubscriptionAccountTransactionsTransactions,
+    }
+
+    #[derive(Deserialize)]
+    pub struct SubscriptionAccountTransactionsTransactions {
+        pub boc: String,
+    }
+}
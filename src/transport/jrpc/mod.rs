@@ -11,7 +11,7 @@ use crate::external::{self, JrpcConnection};
 
 use super::models::{PollContractState, RawContractState, RawTransaction};
 use super::utils::*;
-use super::{Transport, TransportInfo};
+use super::{Transport, TransportCapabilities, TransportInfo};
 
 use self::models::*;
 
@@ -44,12 +44,28 @@ impl Transport for JrpcTransport {
         }
     }
 
+    fn capabilities(&self) -> TransportCapabilities {
+        TransportCapabilities {
+            blocks: false,
+            code_hash_search: true,
+            batched_states: false,
+            proofs: false,
+            time_sync: true,
+            subscriptions: false,
+            message_lookup: true,
+        }
+    }
+
     async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
         let req = external::JrpcRequest {
             data: make_jrpc_request("sendMessage", &SendMessage { message }),
             requires_db: false,
         };
-        self.connection.post(req).await.map(|_| ())
+        let data = self.connection.post(req).await?;
+
+        // NOTE: only check that the message was not rejected
+        tiny_jsonrpc::parse_response::<serde::de::IgnoredAny>(&data)?;
+        Ok(())
     }
 
     async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
@@ -195,6 +211,16 @@ impl Transport for JrpcTransport {
             .map(|block: GetBlockResponse| block.block)
     }
 
+    async fn get_chain_time(&self) -> Result<u32> {
+        let req = external::JrpcRequest {
+            data: make_jrpc_request("getTimings", &()),
+            requires_db: false,
+        };
+        let data = self.connection.post(req).await?;
+        let response = tiny_jsonrpc::parse_response::<GetTimingsResponse>(&data)?;
+        Ok(response.last_mc_utime)
+    }
+
     async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
         let (capabilities, _) = self
             .config_cache
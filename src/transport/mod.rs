@@ -1,12 +1,17 @@
+use std::sync::Arc;
+
 use anyhow::Result;
-use nekoton_utils::Clock;
+use nekoton_utils::{Clock, ClockWithOffset};
 use serde::{Deserialize, Serialize};
 use ton_block::MsgAddressInt;
 
-use crate::models::{NetworkCapabilities, ReliableBehavior};
+use crate::models::{ContractState, NetworkCapabilities, ReliableBehavior, TransactionsCursor};
 
+pub use self::bounded::{BoundedTransport, CancellationToken, OperationBounds, OperationError};
 use self::models::*;
 
+#[cfg(feature = "adnl_transport")]
+pub mod adnl;
 #[cfg(feature = "gql_transport")]
 pub mod gql;
 #[cfg(feature = "jrpc_transport")]
@@ -14,8 +19,10 @@ pub mod jrpc;
 #[cfg(feature = "proto_transport")]
 pub mod proto;
 
+mod bounded;
 pub mod models;
 #[cfg(any(
+    feature = "adnl_transport",
     feature = "gql_transport",
     feature = "jrpc_transport",
     feature = "proto_transport",
@@ -27,10 +34,65 @@ mod utils;
 pub trait Transport: Send + Sync {
     fn info(&self) -> TransportInfo;
 
+    /// Optional features of the backend.
+    ///
+    /// Default implementation describes the transport which supports
+    /// only the required methods and code hash search
+    fn capabilities(&self) -> TransportCapabilities {
+        TransportCapabilities {
+            blocks: matches!(
+                self.info().reliable_behavior,
+                ReliableBehavior::BlockWalking
+            ),
+            code_hash_search: true,
+            message_lookup: true,
+            ..Default::default()
+        }
+    }
+
     async fn send_message(&self, message: &ton_block::Message) -> Result<()>;
 
+    /// Broadcasts several messages at once.
+    /// Returns the status of each message in the same order.
+    ///
+    /// Default implementation sends messages one by one
+    async fn send_messages(
+        &self,
+        messages: &[ton_block::Message],
+    ) -> Result<Vec<SendMessageStatus>> {
+        let mut statuses = Vec::with_capacity(messages.len());
+        for message in messages {
+            statuses.push(self.send_message(message).await.into());
+        }
+        Ok(statuses)
+    }
+
     async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState>;
 
+    /// Fetches states of several contracts at once.
+    /// Returns results in the same order as the addresses, so that
+    /// the failure of one state doesn't affect the others.
+    ///
+    /// Default implementation fetches states one by one
+    async fn get_contract_states(
+        &self,
+        addresses: &[MsgAddressInt],
+    ) -> Result<Vec<Result<RawContractState>>> {
+        let mut states = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            states.push(self.get_contract_state(address).await);
+        }
+        Ok(states)
+    }
+
+    /// Returns only the balance, status and code hash of the contract.
+    ///
+    /// Can be used as a fallback when [`Transport::get_contract_state`]
+    /// fails with [`StateTooLarge`]
+    async fn get_brief_contract_state(&self, address: &MsgAddressInt) -> Result<ContractState> {
+        Ok(self.get_contract_state(address).await?.brief())
+    }
+
     async fn poll_contract_state(
         &self,
         address: &MsgAddressInt,
@@ -51,6 +113,28 @@ pub trait Transport: Send + Sync {
         count: u8,
     ) -> Result<Vec<RawTransaction>>;
 
+    /// Fetches the page of transactions starting from the cursor (inclusive), newest first.
+    ///
+    /// Returns the cursor of the next page, or `None` if there are no older transactions
+    async fn get_transactions_page(
+        &self,
+        address: &MsgAddressInt,
+        cursor: &TransactionsCursor,
+        count: u8,
+    ) -> Result<(Vec<RawTransaction>, Option<TransactionsCursor>)> {
+        let transactions = self.get_transactions(address, cursor.lt, count).await?;
+        if let Some(first) = transactions.first() {
+            if first.data.lt != cursor.lt || first.hash != cursor.hash {
+                return Err(TransportError::TransactionsCursorMismatch.into());
+            }
+        }
+
+        let next = transactions
+            .last()
+            .and_then(|transaction| TransactionsCursor::next_after(&transaction.data));
+        Ok((transactions, next))
+    }
+
     async fn get_transaction(&self, id: &ton_types::UInt256) -> Result<Option<RawTransaction>>;
 
     async fn get_dst_transaction(
@@ -58,8 +142,41 @@ pub trait Transport: Send + Sync {
         message_hash: &ton_types::UInt256,
     ) -> Result<Option<RawTransaction>>;
 
+    /// Returns contract state at the specified point in the past.
+    ///
+    /// Most backends store only the latest states, so the default
+    /// implementation always returns an error
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: StateAt,
+    ) -> Result<RawContractState> {
+        let _ = (address, at);
+        Err(TransportError::HistoricalStatesNotSupported.into())
+    }
+
     async fn get_latest_key_block(&self) -> Result<ton_block::Block>;
 
+    /// Subscribes to the pushed account state and transactions updates.
+    ///
+    /// Returns `None` if the backend doesn't support subscriptions
+    /// (see [`TransportCapabilities::subscriptions`]), so the account must be polled as usual
+    async fn subscribe_account(
+        &self,
+        address: &MsgAddressInt,
+        handler: Arc<dyn AccountUpdatesHandler>,
+    ) -> Result<Option<Box<dyn AccountUpdatesSubscription>>> {
+        let _ = (address, handler);
+        Ok(None)
+    }
+
+    /// Returns the generation time of the latest known masterchain block.
+    ///
+    /// Default implementation always returns an error
+    async fn get_chain_time(&self) -> Result<u32> {
+        Err(TransportError::UnknownChainTime.into())
+    }
+
     async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities>;
 
     // NOTE: clock is used for caching here
@@ -77,3 +194,103 @@ pub struct TransportInfo {
     pub reliable_behavior: ReliableBehavior,
     pub has_key_blocks: bool,
 }
+
+/// Features which are not supported by all backends
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransportCapabilities {
+    /// Blocks can be fetched and waited for directly
+    pub blocks: bool,
+    /// [`Transport::get_accounts_by_code_hash`] is supported
+    pub code_hash_search: bool,
+    /// [`Transport::get_contract_states`] uses a single request
+    pub batched_states: bool,
+    /// Contract states are verified using the state proofs
+    pub proofs: bool,
+    /// [`Transport::get_chain_time`] is supported
+    pub time_sync: bool,
+    /// Account updates can be pushed by the server instead of polling
+    pub subscriptions: bool,
+    /// [`Transport::get_dst_transaction`] is supported
+    pub message_lookup: bool,
+}
+
+pub trait AccountUpdatesHandler: Send + Sync {
+    /// Called when the account state changes.
+    /// State is `None` if it was omitted (e.g. it is too large), so it must be requested separately
+    fn on_state(&self, state: Option<RawContractState>);
+
+    /// Called for each new transaction of the account
+    fn on_transaction(&self, transaction: RawTransaction);
+
+    /// Called once when the subscription is closed. The account must be polled after that
+    fn on_closed(&self, error: Option<anyhow::Error>);
+}
+
+/// Active account subscription. Unsubscribes on drop
+pub trait AccountUpdatesSubscription: Send + Sync {}
+
+/// Full account state exceeds transport limits (e.g. huge dictionaries in storage).
+/// Contains the brief state of the account
+#[derive(thiserror::Error, Debug, Clone)]
+#[error("Account state is too large")]
+pub struct StateTooLarge(pub ContractState);
+
+/// Synchronizes the clock with the generation time of the latest masterchain state.
+///
+/// Precision is limited by the block generation interval, so the offset is
+/// only updated when the skew is noticeable. Returns the resulting offset in milliseconds
+pub async fn sync_clock(transport: &dyn Transport, clock: &ClockWithOffset) -> Result<i64> {
+    const MIN_SKEW_MS: u64 = 10_000;
+
+    if !transport.capabilities().time_sync {
+        return Err(TransportError::UnknownChainTime.into());
+    }
+
+    let gen_utime = transport.get_chain_time().await?;
+
+    let chain_time_ms = gen_utime as u64 * 1000;
+    if clock.now_ms_u64().abs_diff(chain_time_ms) > MIN_SKEW_MS {
+        clock.sync_with(chain_time_ms);
+    }
+    Ok(clock.offset_ms())
+}
+
+#[derive(thiserror::Error, Debug)]
+enum TransportError {
+    #[error("Historical states are not supported by this transport")]
+    HistoricalStatesNotSupported,
+    #[error("Transaction at the cursor was not found")]
+    TransactionsCursorMismatch,
+    #[error("Chain time is unknown")]
+    UnknownChainTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use nekoton_utils::now_sec_u64;
+
+    use super::*;
+    use crate::testing::ChainSimulator;
+
+    #[tokio::test]
+    async fn sync_clock_with_chain() {
+        const SKEW_SEC: u64 = 3600;
+
+        let simulator = ChainSimulator::new(now_sec_u64() + SKEW_SEC);
+        let transport = simulator.transport().clone() as Arc<dyn Transport>;
+
+        let clock = ClockWithOffset::new(0);
+        let offset = sync_clock(transport.as_ref(), &clock).await.unwrap();
+        assert!((offset - SKEW_SEC as i64 * 1000).abs() < 2000);
+
+        // Small skew doesn't change the offset
+        simulator.clock().advance(std::time::Duration::from_secs(5));
+        assert_eq!(
+            sync_clock(transport.as_ref(), &clock).await.unwrap(),
+            offset
+        );
+    }
+}
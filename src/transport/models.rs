@@ -5,8 +5,18 @@ use ton_block::{Account, AccountStuff, Transaction};
 use ton_types::UInt256;
 
 use crate::models::{ContractState, PendingTransaction};
-use nekoton_abi::{ExecutionContext, GenTimings, LastTransactionId};
-use nekoton_utils::{serde_account_stuff, Clock};
+use nekoton_abi::{ExecutionContext, ExecutionOutput, GenTimings, LastTransactionId};
+use nekoton_utils::{serde_account_stuff, Clock, ConstClock};
+
+/// Point in the past at which the contract state is requested
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+pub enum StateAt {
+    /// State after the masterchain block with the specified seqno
+    Block(u32),
+    /// State at the specified unix timestamp
+    Time(u32),
+}
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -63,6 +73,25 @@ impl From<RawContractState> for Option<ExistingContract> {
     }
 }
 
+/// Result of the message broadcast
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+pub enum SendMessageStatus {
+    Accepted,
+    Rejected { reason: String },
+}
+
+impl From<anyhow::Result<()>> for SendMessageStatus {
+    fn from(result: anyhow::Result<()>) -> Self {
+        match result {
+            Ok(()) => Self::Accepted,
+            Err(e) => Self::Rejected {
+                reason: e.to_string(),
+            },
+        }
+    }
+}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", tag = "type")]
@@ -126,6 +155,32 @@ impl ExistingContract {
             account_stuff: &self.account,
         }
     }
+
+    /// Returns clock which points to the moment when this state was generated.
+    ///
+    /// Getters of historical states must be executed with this clock
+    pub fn gen_clock(&self) -> Option<ConstClock> {
+        match self.timings {
+            GenTimings::Known { gen_utime, .. } => Some(ConstClock::from_secs(gen_utime as u64)),
+            GenTimings::Unknown => None,
+        }
+    }
+
+    /// Executes the getter against this state with the clock from [`ExistingContract::gen_clock`],
+    /// so that historical states (see [`StateAt`]) are executed as of their time.
+    ///
+    /// Specified clock is used only if the state timings are unknown
+    pub fn run_getter(
+        &self,
+        clock: &dyn Clock,
+        function: &ton_abi::Function,
+        input: &[ton_abi::Token],
+    ) -> anyhow::Result<ExecutionOutput> {
+        match self.gen_clock() {
+            Some(gen_clock) => self.as_context(&gen_clock).run_local(function, input),
+            None => self.as_context(clock).run_local(function, input),
+        }
+    }
 }
 
 impl PartialEq for ExistingContract {
@@ -15,7 +15,7 @@ use crate::transport::models::{ExistingContract, PollContractState};
 
 use super::models::{RawContractState, RawTransaction};
 use super::utils::*;
-use super::{Transport, TransportInfo};
+use super::{Transport, TransportCapabilities, TransportInfo};
 
 pub struct ProtoTransport {
     connection: Arc<dyn ProtoConnection>,
@@ -44,6 +44,18 @@ impl Transport for ProtoTransport {
         }
     }
 
+    fn capabilities(&self) -> TransportCapabilities {
+        TransportCapabilities {
+            blocks: false,
+            code_hash_search: true,
+            batched_states: false,
+            proofs: false,
+            time_sync: true,
+            subscriptions: false,
+            message_lookup: true,
+        }
+    }
+
     async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
         let data = rpc::Request {
             call: Some(rpc::request::Call::SendMessage(rpc::request::SendMessage {
@@ -286,6 +298,25 @@ impl Transport for ProtoTransport {
         }
     }
 
+    async fn get_chain_time(&self) -> Result<u32> {
+        let data = rpc::Request {
+            call: Some(rpc::request::Call::GetTimings(())),
+        };
+
+        let req = external::ProtoRequest {
+            data: data.encode_to_vec(),
+            requires_db: false,
+        };
+
+        let data = self.connection.post(req).await?;
+        let response = rpc::Response::decode(Bytes::from(data))?;
+
+        match response.result {
+            Some(rpc::response::Result::GetTimings(timings)) => Ok(timings.last_mc_utime),
+            _ => Err(ProtoClientError::InvalidResponse.into()),
+        }
+    }
+
     async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
         let (capabilities, _) = self
             .config_cache
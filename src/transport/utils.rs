@@ -91,7 +91,7 @@ impl ConfigCache {
 
         Ok(match &*cache {
             None => {
-                let (capabilities, config, key_block_seqno) = fetch_config(transport).await?;
+                let (capabilities, config, key_block_seqno) = fetch_config(transport, None).await?;
                 let phase = compute_next_phase(now, &config, None, key_block_seqno)?;
                 *cache = Some(ConfigCacheState {
                     capabilities,
@@ -102,7 +102,8 @@ impl ConfigCache {
                 (capabilities, config)
             }
             Some(a) if force && !self.use_default_config || cache_expired(now, a.phase) => {
-                let (capabilities, config, key_block_seqno) = fetch_config(transport).await?;
+                let (capabilities, config, key_block_seqno) =
+                    fetch_config(transport, Some(a)).await?;
                 let phase = compute_next_phase(
                     now,
                     &config,
@@ -124,11 +125,29 @@ impl ConfigCache {
 
 async fn fetch_config(
     transport: &dyn Transport,
+    cached: Option<&ConfigCacheState>,
 ) -> Result<(NetworkCapabilities, ton_executor::BlockchainConfig, u32)> {
     let block = transport.get_latest_key_block().await?;
+    parse_config(&block, cached)
+}
 
+fn parse_config(
+    block: &ton_block::Block,
+    cached: Option<&ConfigCacheState>,
+) -> Result<(NetworkCapabilities, ton_executor::BlockchainConfig, u32)> {
     let info = block.info.read_struct()?;
 
+    // Config can only be changed in the new key block
+    if let Some(cached) = cached {
+        if cached.last_key_block_seqno == info.seq_no() {
+            return Ok((
+                cached.capabilities,
+                cached.config.clone(),
+                cached.last_key_block_seqno,
+            ));
+        }
+    }
+
     let extra = block
         .read_extra()
         .map_err(|_| QueryConfigError::InvalidBlock)?;
@@ -208,3 +227,40 @@ enum QueryConfigError {
     #[error("Invalid config")]
     InvalidConfig,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_key_block(seqno: u32) -> ton_block::Block {
+        let mut info = ton_block::BlockInfo::default();
+        info.set_seq_no(seqno).unwrap();
+
+        let mut block = ton_block::Block::default();
+        block.info.write_struct(&info).unwrap();
+        block
+    }
+
+    #[test]
+    fn reuse_config_of_the_same_key_block() {
+        let cached = ConfigCacheState {
+            capabilities: NetworkCapabilities {
+                global_id: 42,
+                raw: 0x4000000,
+            },
+            config: ton_executor::BlockchainConfig::default(),
+            last_key_block_seqno: 10,
+            phase: ConfigCachePhase::WaitingKeyBlock,
+        };
+
+        // Same key block is not parsed
+        let (capabilities, _, seqno) = parse_config(&make_key_block(10), Some(&cached)).unwrap();
+        assert_eq!(capabilities.global_id, 42);
+        assert_eq!(capabilities.raw, 0x4000000);
+        assert_eq!(seqno, 10);
+
+        // New key block is parsed (and this one has no config)
+        assert!(parse_config(&make_key_block(11), Some(&cached)).is_err());
+        assert!(parse_config(&make_key_block(10), None).is_err());
+    }
+}